@@ -0,0 +1,583 @@
+use std::{cell::{Cell, RefCell}, rc::Rc};
+
+use wasm_bindgen::{JsCast, JsValue, prelude::wasm_bindgen};
+use web_sys::{HtmlCanvasElement, HtmlProgressElement, Element};
+
+use crate::{util::{scoped_id, wait_until_visible}, console_log, render::{chunk::TextureFilter, wgpu_context::{WgpuContextBuilder, ContextOptions, MeshStrategy, VertexHeights}, runtime::Runtime, camera::Camera, scene::SceneKind, settings::SettingId, stats::StatsSnapshot, event::{KeyOptions, KeyTarget}, bench::{Benchmark, mesh_bench_requested}, quality::{Quality, QualityBounds}, color_ramp::ColorRamp, thumbnail::{ThumbnailQueue, ThumbnailCallback, Thumbnail, DEFAULT_THUMBNAIL_SIZE}, xr::xr_available}};
+
+type RuntimeSlot = Rc<RefCell<StartState<Rc<RefCell<Runtime>>>>>;
+
+//Where a runtime is in starting up. Startup is async, so a stop can come while it's pending: the start's
+//flag is set and whatever it creates is handed back to be destroyed instead of installed.
+pub enum StartState<T> {
+    Stopped,
+    Starting(Rc<Cell<bool>>),
+    Running(T)
+}
+
+impl<T> StartState<T> {
+    //Returns the new start's cancelled flag, or None if a start is already pending or running
+    pub fn begin(&mut self) -> Option<Rc<Cell<bool>>> {
+        match self {
+            StartState::Stopped => {
+                let cancelled = Rc::new(Cell::new(false));
+                *self = StartState::Starting(cancelled.clone());
+                Some(cancelled)
+            },
+            _ => None
+        }
+    }
+
+    //Cancels a pending start, returns what was running for the caller to tear down
+    pub fn stop(&mut self) -> Option<T> {
+        match std::mem::replace(self, StartState::Stopped) {
+            StartState::Starting(cancelled) => {
+                cancelled.set(true);
+                None
+            },
+            StartState::Running(value) => Some(value),
+            StartState::Stopped => None
+        }
+    }
+
+    //Installs what a start created, or hands it back if the start was cancelled
+    pub fn finish(&mut self, cancelled: &Cell<bool>, value: T) -> Result<(), T> {
+        if cancelled.get() {
+            return Err(value);
+        }
+
+        *self = StartState::Running(value);
+        Ok(())
+    }
+
+    //Lets the next start begin after this one failed
+    pub fn fail(&mut self, cancelled: &Cell<bool>) {
+        if !cancelled.get() {
+            *self = StartState::Stopped;
+        }
+    }
+
+    pub fn running(&self) -> Option<&T> {
+        match self {
+            StartState::Running(value) => Some(value),
+            _ => None
+        }
+    }
+}
+
+//Accepts either a canvas element or the id of one
+pub fn resolve_canvas(canvas: &JsValue) -> Result<HtmlCanvasElement, JsValue> {
+    if let Some(canvas) = canvas.dyn_ref::<HtmlCanvasElement>() {
+        return Ok(canvas.clone());
+    }
+
+    let canvas_id = canvas.as_string().ok_or_else(|| JsValue::from_str("Expected a canvas element or id"))?;
+    let document = web_sys::window()
+        .and_then(|window| window.document())
+        .ok_or_else(|| JsValue::from_str("No document to find the canvas in"))?;
+
+    document.get_element_by_id(&canvas_id)
+        .ok_or_else(|| JsValue::from_str(&format!("Cannot find canvas '{}'", canvas_id)))?
+        .dyn_into()
+        .map_err(|_| JsValue::from_str(&format!("'{}' is not a canvas", canvas_id)))
+}
+
+//Sets up a runtime on the canvas and starts its frame loop. Every runtime owns its own
+//context, surface and event queue, so any number can run on one page.
+//Nothing is created until the canvas is in the DOM and laid out, a page can mount it in a hidden tab.
+pub async fn create_runtime(canvas: HtmlCanvasElement, builder: WgpuContextBuilder) -> Result<Rc<RefCell<Runtime>>, JsValue> {
+    wait_until_visible(&canvas).await;
+
+    let (width, height) = (canvas.width(), canvas.height());
+    console_log!("Got canvas!");
+
+    let camera = Camera::new(
+        cgmath::Point3 { x: 0.0, y: 1.0, z: 0.0 },
+        cgmath::Vector3 { x: 0.0, y: 1.0, z: 0.0 },
+        0.0,
+        0.0,
+        width as f32 / height.max(1) as f32,
+        45.0
+    );
+
+    let mut progress = StartupProgress::new(&canvas);
+    let context = builder.build(&canvas, &camera, &mut |stage, done| progress.report(stage, done)).await;
+    progress.finish();
+    let context = context?;
+    console_log!("Created GPU context!");
+
+    let runtime = Runtime::new(context, canvas, camera, SceneKind::from_location())?;
+    console_log!("Created runtime!");
+
+    if mesh_bench_requested() {
+        runtime.borrow().run_mesh_benchmark();
+    }
+
+    if let Some(bench) = Benchmark::from_location() {
+        runtime.borrow_mut().start_benchmark(bench);
+    }
+    runtime.borrow_mut().request_animation_frame();
+
+    Ok(runtime)
+}
+
+//A <progress> element shown while the context starts up. Uses the page's own element if it has one
+//(id "progress", scoped to the canvas), otherwise one is placed over the canvas and removed afterwards.
+struct StartupProgress {
+    bar: Option<HtmlProgressElement>,
+    created: Option<Element>
+}
+
+impl StartupProgress {
+    fn new(canvas: &HtmlCanvasElement) -> Self {
+        let document = match canvas.owner_document() {
+            Some(document) => document,
+            None => return StartupProgress { bar: None, created: None }
+        };
+        let id = scoped_id(&canvas.id(), "progress");
+
+        if let Some(existing) = document.get_element_by_id(&id) {
+            return StartupProgress { bar: existing.dyn_into().ok(), created: None };
+        }
+
+        let bar = document.create_element("progress").ok()
+            .and_then(|element| element.dyn_into::<HtmlProgressElement>().ok());
+
+        if let Some(bar) = &bar {
+            bar.set_id(&id);
+            bar.set_max(1.0);
+            let _ = bar.set_attribute("style", "position: fixed; top: 50%; left: 25%; width: 50%;");
+
+            if let Some(body) = document.body() {
+                let _ = body.append_child(bar);
+            }
+        }
+
+        let created = bar.clone().map(Element::from);
+        StartupProgress { bar, created }
+    }
+
+    fn report(&mut self, stage: &str, done: f64) {
+        console_log!("Startup: {} ({:.0}%)", stage, done * 100.0);
+
+        if let Some(bar) = &self.bar {
+            bar.set_value(done);
+            bar.set_title(stage);
+        }
+    }
+
+    fn finish(&mut self) {
+        if let Some(created) = self.created.take() {
+            created.remove();
+        }
+    }
+}
+
+//Replaces the page with a message, for when there's nothing to render with
+pub fn show_error(error: &JsValue) {
+    let message = error.as_string().unwrap_or_else(|| format!("{:?}", error));
+    console_log!("Couldn't start: {}", message);
+
+    let body = web_sys::window()
+        .and_then(|window| window.document())
+        .and_then(|document| document.body());
+
+    if let Some(body) = body {
+        body.set_inner_text(&format!("Cacophony couldn't start.\n\n{}", message));
+    }
+}
+
+//Handle for host pages that embed the renderer. Doc comments on the exported items end up in the
+//generated TypeScript definitions, so they're written for page authors.
+
+/// A running Cacophony instance. Returned by `start`; the GPU context is created asynchronously,
+/// so until `is_ready()` is true every method except `is_ready` and `destroy` throws.
+#[wasm_bindgen]
+pub struct CacophonyApp {
+    runtime: RuntimeSlot
+}
+
+/// Starts a renderer on a canvas, given either the element or its id. Keyboard input is only
+/// taken while the canvas has focus. Throws if the canvas can't be found.
+#[wasm_bindgen]
+pub fn start(canvas: JsValue) -> Result<CacophonyApp, JsValue> {
+    start_with(canvas, None)
+}
+
+/// Like `start`, but the canvas is composited with premultiplied alpha, so lowering
+/// `set_background_alpha` lets the page behind it show through the sky. Fails to start (logging
+/// why) if the browser can't composite the canvas that way.
+#[wasm_bindgen]
+pub fn start_transparent(canvas: JsValue) -> Result<CacophonyApp, JsValue> {
+    start_with(canvas, Some(wgpu::CompositeAlphaMode::PreMultiplied))
+}
+
+fn start_with(canvas: JsValue, alpha_mode: Option<wgpu::CompositeAlphaMode>) -> Result<CacophonyApp, JsValue> {
+    let canvas = resolve_canvas(&canvas)?;
+    let mut state = StartState::Stopped;
+    let cancelled = state.begin().expect("a new instance can always start");
+    let runtime: RuntimeSlot = Rc::new(RefCell::new(state));
+
+    let slot = runtime.clone();
+    wasm_bindgen_futures::spawn_local(async move {
+        //Embedded canvases only take keys while focused, the rest of the page keeps its keyboard
+        let options = ContextOptions { keys: KeyOptions { target: KeyTarget::Canvas, ..KeyOptions::default() }, alpha_mode, ..ContextOptions::default() };
+
+        match create_runtime(canvas, WgpuContextBuilder::new().with_options(options)).await {
+            Ok(runtime) => {
+                //Destroyed while starting, it mustn't keep rendering or take keys
+                let destroyed = slot.borrow_mut().finish(&cancelled, runtime).err();
+                if let Some(runtime) = destroyed {
+                    runtime.borrow_mut().destroy();
+                }
+            },
+            Err(e) => console_log!("Failed to start: {:?}", e)
+        }
+    });
+
+    Ok(CacophonyApp { runtime })
+}
+
+impl CacophonyApp {
+    fn with_runtime<R>(&self, f: impl FnOnce(&mut Runtime) -> R) -> Result<R, JsValue> {
+        let runtime = self.runtime.borrow();
+        let runtime = runtime.running().ok_or_else(|| JsValue::from_str("Cacophony is still starting"))?;
+        let mut runtime = runtime.try_borrow_mut().map_err(|_| JsValue::from_str("Cacophony is busy"))?;
+
+        Ok(f(&mut runtime))
+    }
+}
+
+#[wasm_bindgen]
+impl CacophonyApp {
+    /// True once startup has finished and the other methods can be called.
+    pub fn is_ready(&self) -> bool {
+        self.runtime.borrow().running().is_some()
+    }
+
+    /// Regenerates the terrain from a different seed. Seeds are 64 bit, so this takes a `BigInt`.
+    pub fn set_seed(&self, seed: u64) -> Result<(), JsValue> {
+        self.with_runtime(|runtime| runtime.set_seed(seed))
+    }
+
+    /// Sets the terrain height scale, like the slider in the settings panel.
+    pub fn set_height_scale(&self, height_scale: f32) -> Result<(), JsValue> {
+        self.with_runtime(|runtime| runtime.apply_setting(SettingId::HeightScale, height_scale as f64))
+    }
+
+    /// Turns vsync on or off, like the checkbox in the settings panel. Without vsync frames are presented
+    /// as soon as they're ready where the browser allows it, for benchmarking.
+    pub fn set_vsync(&self, vsync: bool) -> Result<(), JsValue> {
+        self.with_runtime(|runtime| runtime.apply_setting(SettingId::Vsync, if vsync { 1.0 } else { 0.0 }))
+    }
+
+    /// The present mode the canvas is currently using, e.g. "Fifo".
+    pub fn present_mode(&self) -> Result<String, JsValue> {
+        self.with_runtime(|runtime| format!("{:?}", runtime.context().present_mode()))
+    }
+
+    /// Moves the camera to a world position, keeping its direction.
+    pub fn teleport(&self, x: f64, y: f64, z: f64) -> Result<(), JsValue> {
+        self.with_runtime(|runtime| runtime.teleport([x, y, z]))
+    }
+
+    /// Same as `teleport`.
+    pub fn set_camera(&self, x: f64, y: f64, z: f64) -> Result<(), JsValue> {
+        self.teleport(x, y, z)
+    }
+
+    /// Turns the camera to face a world position without moving it.
+    pub fn look_at(&self, x: f64, y: f64, z: f64) -> Result<(), JsValue> {
+        self.with_runtime(|runtime| runtime.look_at([x, y, z]))
+    }
+
+    /// Switches between the shaded terrain and a wireframe of its triangles.
+    pub fn toggle_wireframe(&self) -> Result<(), JsValue> {
+        self.with_runtime(|runtime| runtime.toggle_wireframe())
+    }
+
+    /// Meshes the terrain as a regular grid instead of a triangulation of evenly spaced points. The
+    /// grid is much quicker to build when the terrain is regenerated. Every chunk shares one mesh, so
+    /// neither adds extra detail on steep slopes.
+    pub fn set_grid_mesh(&self, enabled: bool) -> Result<(), JsValue> {
+        let strategy = if enabled { MeshStrategy::RegularGrid } else { MeshStrategy::DelaunayScatter };
+        self.with_runtime(|runtime| runtime.set_mesh_strategy(strategy))
+    }
+
+    /// Bakes terrain heights and per-vertex normals into the mesh on the CPU instead of reading them
+    /// from textures on the GPU. Lighting follows the triangles more closely, and it avoids texture
+    /// reads in the vertex shader, which some WebGL2 devices handle badly. Turning it on reloads every
+    /// chunk over the next few frames, the terrain switches over once they're all done.
+    pub fn set_baked_heights(&self, enabled: bool) -> Result<(), JsValue> {
+        let heights = if enabled { VertexHeights::Baked } else { VertexHeights::Texture };
+        self.with_runtime(|runtime| runtime.set_vertex_heights(heights))
+    }
+
+    /// Meshes the terrain from Poisson disk samples, evenly spaced but in no pattern, with no two
+    /// closer than `min_distance` mesh units. `set_grid_mesh(false)` goes back to the default mesh.
+    pub fn set_poisson_mesh(&self, min_distance: f32) -> Result<(), JsValue> {
+        self.with_runtime(|runtime| runtime.set_poisson_mesh(min_distance))
+    }
+
+    /// Samples the terrain's height and normal textures without filtering, for a blocky, pixelated
+    /// look. Off by default.
+    pub fn set_pixelated(&self, enabled: bool) -> Result<(), JsValue> {
+        let filter = if enabled { TextureFilter::Pixelated } else { TextureFilter::Smooth };
+        self.with_runtime(|runtime| runtime.set_texture_filter(filter))
+    }
+
+    /// Sets the light reaching the terrain where the sun doesn't, as linear RGB. Turns off
+    /// hemisphere ambient.
+    pub fn set_ambient_color(&self, r: f32, g: f32, b: f32) -> Result<(), JsValue> {
+        self.with_runtime(|runtime| {
+            runtime.set_hemisphere_ambient(None);
+            runtime.set_ambient_color([r, g, b]);
+        })
+    }
+
+    /// Lights the terrain with the sky colour from above and the ground colour from below,
+    /// blended by which way each surface faces. Both are linear RGB.
+    #[allow(clippy::too_many_arguments)]
+    pub fn set_hemisphere_ambient(&self, sky_r: f32, sky_g: f32, sky_b: f32, ground_r: f32, ground_g: f32, ground_b: f32) -> Result<(), JsValue> {
+        self.with_runtime(|runtime| runtime.set_hemisphere_ambient(Some(([sky_r, sky_g, sky_b], [ground_r, ground_g, ground_b]))))
+    }
+
+    /// Sets the colour and brightness of direct sunlight, as linear RGB.
+    pub fn set_sun_color(&self, r: f32, g: f32, b: f32) -> Result<(), JsValue> {
+        self.with_runtime(|runtime| runtime.set_sun_color([r, g, b]))
+    }
+
+    /// Sets the fog colour as linear RGB. The sky is this colour at the horizon too, so distant
+    /// terrain blends into it. Fog density is in the settings panel.
+    pub fn set_fog_color(&self, r: f32, g: f32, b: f32) -> Result<(), JsValue> {
+        self.with_runtime(|runtime| runtime.set_fog_color([r, g, b]))
+    }
+
+    /// Sets the background colour as linear RGB. It only shows with the sky off and no fog, otherwise
+    /// the background is the fog colour so it matches the horizon.
+    pub fn set_clear_color(&self, r: f32, g: f32, b: f32) -> Result<(), JsValue> {
+        self.with_runtime(|runtime| runtime.set_clear_color([r, g, b]))
+    }
+
+    /// Hides the sky and clears to magenta, so any holes in the terrain from culling or skirts
+    /// stand out.
+    pub fn set_debug_clear(&self, enabled: bool) -> Result<(), JsValue> {
+        self.with_runtime(|runtime| runtime.set_debug_clear(enabled))
+    }
+
+    /// Sets the water colour as linear RGB, and its opacity looking straight down. It gets more
+    /// reflective towards the horizon. The water level is in the settings panel.
+    pub fn set_water_color(&self, r: f32, g: f32, b: f32, a: f32) -> Result<(), JsValue> {
+        self.with_runtime(|runtime| runtime.set_water_color([r, g, b, a]))
+    }
+
+    /// Sets how opaque the sky is, from 0 to 1. Below 1 the page behind the canvas shows through,
+    /// but only for instances made with `start_transparent`.
+    pub fn set_background_alpha(&self, alpha: f32) -> Result<(), JsValue> {
+        self.with_runtime(|runtime| runtime.set_background_alpha(alpha))
+    }
+
+    /// How the canvas is composited with the page, e.g. "Opaque" or "PreMultiplied".
+    pub fn alpha_mode(&self) -> Result<String, JsValue> {
+        self.with_runtime(|runtime| format!("{:?}", runtime.context().alpha_mode()))
+    }
+
+    /// Each render pass's GPU time in milliseconds as a text table, only the header where the device
+    /// has no timestamp queries (WebGL2 never does).
+    pub fn gpu_pass_times(&self) -> Result<String, JsValue> {
+        self.with_runtime(|runtime| runtime.context().gpu_passes().table())
+    }
+
+    /// Whether the browser exposes WebXR (`navigator.xr`). Doesn't mean a session can be started.
+    pub fn xr_available(&self) -> bool {
+        xr_available()
+    }
+
+    /// Draws the terrain in stereo from the views of a WebXR frame, or goes back to the normal
+    /// camera with `undefined`. Call it every XR frame with 36 numbers per view: its
+    /// `transform.inverse.matrix`, its `projectionMatrix` and its viewport as fractions of the
+    /// layer's framebuffer, `[x, y, width, height]`. Head movement is added to the camera's
+    /// position. Frames are drawn to the canvas, not into the session's layer, so the page has to
+    /// present them itself.
+    pub fn set_xr_views(&self, views: Option<Vec<f32>>) -> Result<(), JsValue> {
+        self.with_runtime(|runtime| runtime.set_xr_poses(views))
+    }
+
+    /// Draws the scene at a fixed width / height ratio, centred with bars filling the rest of the
+    /// canvas, e.g. `16 / 9` for consistent screenshots. `undefined` fills the canvas again.
+    pub fn set_aspect_lock(&self, aspect: Option<f32>) -> Result<(), JsValue> {
+        self.with_runtime(|runtime| runtime.set_aspect_lock(aspect))
+    }
+
+    /// Re-reads the canvas size. Resizes are normally picked up on their own; call this after
+    /// changing the canvas's layout in a way the browser doesn't report.
+    pub fn resize(&self) -> Result<(), JsValue> {
+        self.with_runtime(|runtime| runtime.check_resize())
+    }
+
+    /// Stops the frame loop until `resume` is called.
+    pub fn pause(&self) -> Result<(), JsValue> {
+        self.with_runtime(|runtime| runtime.pause())
+    }
+
+    pub fn resume(&self) -> Result<(), JsValue> {
+        self.with_runtime(|runtime| runtime.resume())
+    }
+
+    /// Downloads the current view as a PNG.
+    pub fn screenshot(&self) -> Result<(), JsValue> {
+        self.with_runtime(|runtime| runtime.screenshot())
+    }
+
+    /// Frame statistics: `{ fps, last_frame_time, average_frame_time, p95_frame_time, frames,
+    /// last_events, total_events, last_uploaded_bytes, render, max_render }`, times in milliseconds.
+    /// `render` is the last frame's draw counts and `max_render` the highest of each over recent frames.
+    pub fn get_stats(&self) -> Result<JsValue, JsValue> {
+        let snapshot = self.with_runtime(|runtime| StatsSnapshot::from(runtime.stats()))?;
+        let json = serde_json::to_string(&snapshot).map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+        js_sys::JSON::parse(&json)
+    }
+
+    /// Reads the heights of the chunk under the camera back from the GPU. Resolves to
+    /// `{ coord, size, apron, heights }`: `heights` is `size * size` values from 0 to 1, row by
+    /// row, with an `apron` texel border shared with the neighbouring chunks. Rejects if that chunk
+    /// isn't loaded yet or the readback fails. Only resolves once another frame is drawn.
+    pub fn read_heights(&self) -> Result<js_sys::Promise, JsValue> {
+        let readback = self.with_runtime(|runtime| runtime.context().read_center_heights())?
+            .ok_or_else(|| JsValue::from_str("The chunk under the camera isn't loaded yet"))?;
+
+        Ok(wasm_bindgen_futures::future_to_promise(async move {
+            let heightmap = readback.await.map_err(|e| JsValue::from_str(&format!("Height readback failed: {:?}", e)))?;
+            let json = serde_json::to_string(&heightmap).map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+            js_sys::JSON::parse(&json)
+        }))
+    }
+
+    /// A hash of the terrain noise sampled over one chunk, as 16 hex digits. The same seed and settings
+    /// should give the same hash on every run and in every browser.
+    pub fn terrain_hash(&self) -> Result<String, JsValue> {
+        self.with_runtime(|runtime| format!("{:016x}", runtime.context().terrain_hash()))
+    }
+
+    /// The full camera state: `{ eye, up, pitch, yaw, aspect, fovy, znear, zfar }`, angles in radians
+    /// except `fovy` which is in degrees.
+    pub fn get_camera(&self) -> Result<JsValue, JsValue> {
+        let state = self.with_runtime(|runtime| runtime.camera().state())?;
+        let json = serde_json::to_string(&state).map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+        js_sys::JSON::parse(&json)
+    }
+
+    /// Sets the frame time, in milliseconds, that quality is adjusted to hold. Defaults to 16.6.
+    pub fn set_frame_target(&self, frame_time: f64) -> Result<(), JsValue> {
+        self.with_runtime(|runtime| runtime.set_frame_target(frame_time))
+    }
+
+    /// Limits how far quality is lowered and raised. `min` and `max` are both
+    /// `{ render_scale, chunk_radius, max_subdivisions }`, render scale is clamped to [0.5, 1].
+    /// Quality starts again from `max`.
+    pub fn set_quality_bounds(&self, min: JsValue, max: JsValue) -> Result<(), JsValue> {
+        let parse = |value: &JsValue| -> Result<Quality, JsValue> {
+            let json = js_sys::JSON::stringify(value)?.as_string().unwrap_or_default();
+            serde_json::from_str(&json).map_err(|e| JsValue::from_str(&e.to_string()))
+        };
+        let bounds = QualityBounds { min: parse(&min)?, max: parse(&max)? };
+
+        self.with_runtime(|runtime| runtime.set_quality_bounds(bounds))
+    }
+
+    /// Replaces the terrain's colours by height. `stops` is a list of `{ height, color }` with heights
+    /// from 0 (lowest) to 1 (highest) and linear RGB colours, colours are blended between them.
+    pub fn set_color_ramp(&self, stops: JsValue) -> Result<(), JsValue> {
+        let json = js_sys::JSON::stringify(&stops)?.as_string().unwrap_or_default();
+        let ramp: ColorRamp = serde_json::from_str(&json).map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+        self.with_runtime(|runtime| runtime.set_color_ramp(&ramp))
+    }
+
+    /// The quality currently in use: `{ render_scale, chunk_radius, max_subdivisions }`.
+    pub fn get_quality(&self) -> Result<JsValue, JsValue> {
+        let quality = self.with_runtime(|runtime| runtime.quality().quality())?;
+        let json = serde_json::to_string(&quality).map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+        js_sys::JSON::parse(&json)
+    }
+
+    /// Renders a top-down preview of each seed, one per frame, and calls
+    /// `callback(seed, rgba, size)` for each as it's ready. Seeds are `BigInt`s both ways, `rgba` is a
+    /// `Uint8Array` of `size * size` RGBA pixels. Replaces any thumbnails still queued.
+    pub fn generate_thumbnails(&self, seeds: Vec<u64>, size: Option<u32>, callback: js_sys::Function) -> Result<(), JsValue> {
+        let size = size.unwrap_or(DEFAULT_THUMBNAIL_SIZE);
+        let callback: ThumbnailCallback = Rc::new(move |thumbnail: Thumbnail| {
+            let rgba = js_sys::Uint8Array::from(thumbnail.rgba.as_slice());
+            let _ = callback.call3(&JsValue::NULL, &thumbnail.seed.into(), &rgba, &thumbnail.size.into());
+        });
+
+        self.with_runtime(|runtime| runtime.queue_thumbnails(ThumbnailQueue::new(seeds, size, callback)))
+    }
+
+    /// Stops the frame loop and removes every listener and element the renderer added. The handle
+    /// is unusable afterwards. Called while still starting, the startup is cancelled.
+    pub fn destroy(&self) {
+        let runtime = self.runtime.borrow_mut().stop();
+
+        if let Some(runtime) = runtime {
+            runtime.borrow_mut().destroy();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    fn start_is_idempotent_while_pending() {
+        let mut state = StartState::Stopped;
+        let cancelled = state.begin().unwrap();
+        assert!(state.begin().is_none());
+
+        assert_eq!(state.finish(&cancelled, 1), Ok(()));
+        assert!(state.begin().is_none());
+        assert_eq!(state.running(), Some(&1));
+    }
+
+    #[wasm_bindgen_test]
+    fn stopping_cancels_a_pending_start() {
+        let mut state = StartState::Stopped;
+        let cancelled = state.begin().unwrap();
+
+        assert_eq!(state.stop(), None);
+        assert_eq!(state.finish(&cancelled, 1), Err(1));
+        assert_eq!(state.running(), None);
+    }
+
+    #[wasm_bindgen_test]
+    fn cancelled_start_does_not_replace_a_restart() {
+        let mut state = StartState::Stopped;
+        let first = state.begin().unwrap();
+        state.stop();
+        let second = state.begin().unwrap();
+
+        assert_eq!(state.finish(&first, 1), Err(1));
+        state.fail(&first);
+        assert!(state.begin().is_none());
+
+        assert_eq!(state.finish(&second, 2), Ok(()));
+        assert_eq!(state.stop(), Some(2));
+        assert_eq!(state.stop(), None);
+    }
+
+    #[wasm_bindgen_test]
+    fn failed_start_allows_another() {
+        let mut state: StartState<i32> = StartState::Stopped;
+        let cancelled = state.begin().unwrap();
+        state.fail(&cancelled);
+
+        assert!(state.begin().is_some());
+    }
+}
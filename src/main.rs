@@ -1,88 +1,114 @@
-use std::{future::Future, task::{Context, Poll}, pin::Pin};
-
-use noise::source::TestSource;
-use wasm_bindgen::{JsCast, JsValue};
-use web_sys::{HtmlCanvasElement, CanvasRenderingContext2d};
-
-use crate::{render::{wgpu_context::WgpuContext, runtime::Runtime, event::EventQueue, camera::Camera}, noise::source::NoiseSource};
-
-pub mod util;
-pub mod render;
-pub mod noise;
-
-async fn run_main() -> Result<JsValue, JsValue> {
-    let dom_window = web_sys::window().expect("no global `window` exists");
-    let document = dom_window.document().expect("should have a document on a window");
-    let body = document.body().expect("document should have a body");
-
-    let canvas: HtmlCanvasElement = document.get_element_by_id("wgpu-canvas").expect("Cannot find canvas!").unchecked_into();
-    let (width, height) = (canvas.width(), canvas.height());
-    console_log!("Got canvas!");
-
-    let camera = Camera::new(
-        cgmath::Point3 { x: 0.0, y: 1.0, z: 0.0 },
-        cgmath::Vector3 { x: 0.0, y: 1.0, z: 0.0 },
-        0.0,
-        0.0,
-        width as f32 / height as f32,
-        45.0
-    );
-
-    let context = WgpuContext::new(&canvas, &camera).await;
-    console_log!("Created GPU context!");
-
-    let runtime = Runtime::new(context, canvas, camera);
-    console_log!("Created runtime!");
-    
-    runtime.borrow_mut().request_animation_frame();
-
-    Ok(JsValue::NULL)
-}
-
-async fn noise_test() -> Result<JsValue, JsValue> {
-    let dom_window = web_sys::window().expect("no global `window` exists");
-    let document = dom_window.document().expect("should have a document on a window");
-    let body = document.body().expect("document should have a body");
-
-    let canvas: HtmlCanvasElement = document.get_element_by_id("wgpu-canvas").expect("Cannot find canvas!").unchecked_into();
-    console_log!("Got canvas!");
-
-    let source = TestSource;
-
-    let resoultion: f32 = 1.0 / 20.0;
-    let size = 1000;
-
-    canvas.set_width(size as u32);
-    canvas.set_height(size as u32);
-    canvas.style().set_property("width", &format!("{}px", size)).unwrap();
-    canvas.style().set_property("height", &format!("{}px", size)).unwrap();
-
-    let context: CanvasRenderingContext2d = canvas.get_context("2d").unwrap().unwrap().unchecked_into();
-
-    for xi in 0..size {
-        for yi in 0..size {
-            let x = xi as f32 * resoultion;
-            let y = yi as f32 * resoultion;
-
-            let sample = source.sample(x, y, 0);
-            let sample = sample * 0.5 + 0.5;
-
-            context.set_fill_style(&JsValue::from_str(&format!("rgba({}, {}, {}, 1.0)", sample * 255.0, sample * 255.0, sample * 255.0)));
-            context.fill_rect(xi as _, yi as _, 1.0, 1.0);
-        }
-
-        if (xi + 1) % 10 == 0 {
-            console_log!("{:?} / {}", xi + 1, size);
-        }
-    }
-
-    Ok(JsValue::NULL)
-}
-
-fn main() {
-    console_error_panic_hook::set_once();
-    console_log::init_with_level(log::Level::Warn).expect("Couldn't intialize logger");
-
-    wasm_bindgen_futures::future_to_promise(run_main());
-    //wasm_bindgen_futures::future_to_promise(noise_test());
+use std::{cell::{Cell, RefCell}, rc::Rc};
+
+use wasm_bindgen::{JsValue, prelude::wasm_bindgen};
+
+use crate::{app::StartState, render::runtime::Runtime, util::DEFAULT_CANVAS_ID};
+
+pub mod app;
+pub mod util;
+pub mod render;
+pub mod noise;
+
+thread_local! {
+    static RUNTIME: RefCell<StartState<Rc<RefCell<Runtime>>>> = const { RefCell::new(StartState::Stopped) };
+}
+
+fn with_runtime<R>(f: impl FnOnce(&mut Runtime) -> R) -> Option<R> {
+    RUNTIME.with(|runtime| {
+        runtime.borrow().running().map(|runtime| f(&mut runtime.borrow_mut()))
+    })
+}
+
+//Does nothing while a start is pending or the runtime is up, so listeners are only ever attached once
+#[wasm_bindgen]
+pub fn cacophony_start() {
+    if let Some(cancelled) = RUNTIME.with(|runtime| runtime.borrow_mut().begin()) {
+        let _ = wasm_bindgen_futures::future_to_promise(run_main(cancelled));
+    }
+}
+
+//Also cancels a pending start, the runtime it creates is destroyed as soon as it's ready
+#[wasm_bindgen]
+pub fn cacophony_destroy() {
+    let runtime = RUNTIME.with(|runtime| runtime.borrow_mut().stop());
+
+    if let Some(runtime) = runtime {
+        runtime.borrow_mut().destroy();
+    }
+}
+
+#[wasm_bindgen]
+pub fn pause() {
+    with_runtime(|runtime| runtime.pause());
+}
+
+#[wasm_bindgen]
+pub fn resume() {
+    with_runtime(|runtime| runtime.resume());
+}
+
+#[cfg(feature = "hot-shaders")]
+#[wasm_bindgen]
+pub fn reload_shaders() {
+    with_runtime(|runtime| runtime.reload_shaders());
+}
+
+#[wasm_bindgen]
+pub fn screenshot() {
+    with_runtime(|runtime| runtime.screenshot());
+}
+
+//Logs a table of the per-stage frame timings collected so far
+#[wasm_bindgen]
+pub fn dump_timings() {
+    with_runtime(|runtime| runtime.dump_timings());
+}
+
+#[wasm_bindgen]
+pub fn start_recording() {
+    with_runtime(|runtime| runtime.start_recording());
+}
+
+//Returns the recorded camera path as JSON
+#[wasm_bindgen]
+pub fn stop_recording() -> Result<String, JsValue> {
+    let keyframes = with_runtime(|runtime| runtime.stop_recording()).unwrap_or_default();
+    serde_json::to_string(&keyframes).map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+#[wasm_bindgen]
+pub fn play_recording(path: &str) -> Result<(), JsValue> {
+    let keyframes = serde_json::from_str(path).map_err(|e| JsValue::from_str(&e.to_string()))?;
+    with_runtime(|runtime| runtime.play(keyframes));
+    Ok(())
+}
+
+async fn run_main(cancelled: Rc<Cell<bool>>) -> Result<JsValue, JsValue> {
+    let runtime = match create_default_runtime().await {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            RUNTIME.with(|global| global.borrow_mut().fail(&cancelled));
+            app::show_error(&e);
+            return Err(e);
+        }
+    };
+
+    let cancelled = RUNTIME.with(|global| global.borrow_mut().finish(&cancelled, runtime).err());
+    if let Some(runtime) = cancelled {
+        runtime.borrow_mut().destroy();
+    }
+
+    Ok(JsValue::NULL)
+}
+
+async fn create_default_runtime() -> Result<Rc<RefCell<Runtime>>, JsValue> {
+    let canvas = app::resolve_canvas(&JsValue::from_str(DEFAULT_CANVAS_ID))?;
+    app::create_runtime(canvas, render::wgpu_context::WgpuContextBuilder::new()).await
+}
+
+fn main() {
+    console_error_panic_hook::set_once();
+    console_log::init_with_level(log::Level::Warn).expect("Couldn't intialize logger");
+
+    cacophony_start();
 }
\ No newline at end of file
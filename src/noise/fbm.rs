@@ -0,0 +1,167 @@
+use super::perm::{splitmix64, SPLITMIX_INCREMENT};
+use super::source::{Coord, NoiseSource, Sample, Seed};
+
+//Golden angle in radians, successive octaves never line up again for long
+const GOLDEN_ANGLE: Coord = 2.399_963;
+
+//How each octave gets a pattern of its own from the one seed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OctaveSeeds {
+    //seed + octave. Cheap, but consecutive seeds can give related lattices.
+    Additive,
+    //The octave-th splitmix64 output from seed, unrelated even for neighbouring seeds
+    Hashed,
+    //Every octave keeps seed and samples its coordinates rotated by a further golden angle instead
+    RotatedDomain
+}
+
+impl OctaveSeeds {
+    pub fn seed(self, seed: Seed, octave: u32) -> Seed {
+        match self {
+            OctaveSeeds::Additive => seed.wrapping_add(octave as Seed),
+            OctaveSeeds::Hashed => splitmix64(seed.wrapping_add(SPLITMIX_INCREMENT.wrapping_mul(octave as u64 + 1))),
+            OctaveSeeds::RotatedDomain => seed
+        }
+    }
+
+    //Radians the octave's coordinates are turned by
+    pub fn rotation(self, octave: u32) -> Coord {
+        match self {
+            OctaveSeeds::RotatedDomain => octave as Coord * GOLDEN_ANGLE,
+            _ => 0.0
+        }
+    }
+}
+
+//Fractal Brownian motion: octaves of source, each at lacunarity times the frequency and gain times the
+//amplitude of the last, divided by the total amplitude so the result stays in the source's range
+pub struct Fbm<S> {
+    pub source: S,
+    pub octaves: u32,
+    pub lacunarity: Coord,
+    pub gain: Sample,
+    pub seeds: OctaveSeeds
+}
+
+impl<S: NoiseSource> Fbm<S> {
+    pub fn new(source: S, octaves: u32) -> Self {
+        Fbm {
+            source,
+            octaves,
+            lacunarity: 2.0,
+            gain: 0.5,
+            seeds: OctaveSeeds::Hashed
+        }
+    }
+
+    pub fn with_lacunarity(mut self, lacunarity: Coord) -> Self {
+        self.lacunarity = lacunarity;
+        self
+    }
+
+    pub fn with_gain(mut self, gain: Sample) -> Self {
+        self.gain = gain;
+        self
+    }
+
+    pub fn with_seeds(mut self, seeds: OctaveSeeds) -> Self {
+        self.seeds = seeds;
+        self
+    }
+
+    pub fn into_inner(self) -> S {
+        self.source
+    }
+
+    //The octave on its own, before its amplitude is applied
+    pub fn sample_octave(&self, x: Coord, y: Coord, seed: Seed, octave: u32) -> Sample {
+        let frequency = self.lacunarity.powi(octave as i32);
+        let (sin, cos) = self.seeds.rotation(octave).sin_cos();
+        let (x, y) = (x * frequency, y * frequency);
+
+        self.source.sample(cos * x - sin * y, sin * x + cos * y, self.seeds.seed(seed, octave))
+    }
+}
+
+impl<S: NoiseSource> NoiseSource for Fbm<S> {
+    fn sample(&self, x: Coord, y: Coord, seed: Seed) -> Sample {
+        let mut total = 0.0;
+        let mut total_amplitude = 0.0;
+        let mut amplitude = 1.0;
+
+        for octave in 0..self.octaves {
+            total += self.sample_octave(x, y, seed, octave) * amplitude;
+            total_amplitude += amplitude;
+            amplitude *= self.gain;
+        }
+
+        if total_amplitude == 0.0 {
+            0.0
+        } else {
+            total / total_amplitude
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use crate::noise::perlin::PerlinSource;
+
+    use super::*;
+
+    //Turns the seed into a domain offset, like the simplest seeded sources do, so neighbouring seeds
+    //give nearly the same pattern
+    struct SeedOffset(PerlinSource);
+
+    impl NoiseSource for SeedOffset {
+        fn sample(&self, x: Coord, y: Coord, seed: Seed) -> Sample {
+            self.0.sample(x + (seed & 0xffff) as Coord * 0.01, y, 0)
+        }
+    }
+
+    //Pearson correlation of octaves 0 and 1 over a grid, with lacunarity 1 so only their seeds differ
+    fn octave_correlation(seeds: OctaveSeeds) -> f32 {
+        let fbm = Fbm::new(SeedOffset(PerlinSource::default()), 2).with_lacunarity(1.0).with_seeds(seeds);
+        let samples: Vec<(f32, f32)> = (0..1024).map(|i| {
+            let (x, y) = ((i % 32) as Coord * 0.53, (i / 32) as Coord * 0.47);
+            (fbm.sample_octave(x, y, 12345, 0), fbm.sample_octave(x, y, 12345, 1))
+        }).collect();
+
+        let n = samples.len() as f32;
+        let (mean_a, mean_b) = (samples.iter().map(|s| s.0).sum::<f32>() / n, samples.iter().map(|s| s.1).sum::<f32>() / n);
+        let covariance: f32 = samples.iter().map(|s| (s.0 - mean_a) * (s.1 - mean_b)).sum();
+        let variance_a: f32 = samples.iter().map(|s| (s.0 - mean_a).powi(2)).sum();
+        let variance_b: f32 = samples.iter().map(|s| (s.1 - mean_b).powi(2)).sum();
+
+        covariance / (variance_a * variance_b).sqrt()
+    }
+
+    #[wasm_bindgen_test]
+    fn hashed_octaves_are_uncorrelated_unlike_additive() {
+        let additive = octave_correlation(OctaveSeeds::Additive);
+        let hashed = octave_correlation(OctaveSeeds::Hashed);
+
+        assert!(additive > 0.9, "additive octaves only correlate by {}", additive);
+        assert!(hashed.abs() < 0.2, "hashed octaves correlate by {}", hashed);
+    }
+
+    #[wasm_bindgen_test]
+    fn hashed_seeds_differ_per_octave_and_seed() {
+        let seeds: Vec<Seed> = (0..8).map(|octave| OctaveSeeds::Hashed.seed(1, octave)).collect();
+
+        for (i, a) in seeds.iter().enumerate() {
+            assert!(seeds[i + 1..].iter().all(|b| a != b));
+            assert_ne!(*a, OctaveSeeds::Hashed.seed(2, i as u32));
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn rotated_domain_keeps_the_seed_and_turns_each_octave() {
+        assert_eq!(OctaveSeeds::RotatedDomain.seed(77, 3), 77);
+        assert_eq!(OctaveSeeds::RotatedDomain.rotation(0), 0.0);
+        assert_ne!(OctaveSeeds::RotatedDomain.rotation(1), OctaveSeeds::RotatedDomain.rotation(2));
+        assert_eq!(OctaveSeeds::Hashed.rotation(5), 0.0);
+    }
+}
@@ -0,0 +1,143 @@
+use super::source::{Coord, NoiseSource, Sample, Seed};
+
+pub struct NoiseLayer {
+    pub source: Box<dyn NoiseSource>,
+    pub frequency: Coord,
+    pub amplitude: Sample,
+    //Added after scaling by frequency, so it's in the layer's own noise space
+    pub offset: (Coord, Coord)
+}
+
+impl NoiseLayer {
+    pub fn new(source: Box<dyn NoiseSource>, frequency: Coord, amplitude: Sample, offset: (Coord, Coord)) -> Self {
+        NoiseLayer {
+            source,
+            frequency,
+            amplitude,
+            offset
+        }
+    }
+
+    fn sample(&self, x: Coord, y: Coord, seed: Seed) -> Sample {
+        self.source.sample(x * self.frequency + self.offset.0, y * self.frequency + self.offset.1, seed) * self.amplitude
+    }
+}
+
+//Sum of independently transformed sources, divided by the total amplitude so the result stays
+//in the same range as the layers
+pub struct LayeredSource {
+    layers: Vec<NoiseLayer>
+}
+
+impl LayeredSource {
+    pub fn new() -> Self {
+        LayeredSource {
+            layers: Vec::new()
+        }
+    }
+
+    pub fn with_layer(mut self, layer: NoiseLayer) -> Self {
+        self.add_layer(layer);
+        self
+    }
+
+    //Returns the index of the new layer
+    pub fn add_layer(&mut self, layer: NoiseLayer) -> usize {
+        self.layers.push(layer);
+        self.layers.len() - 1
+    }
+
+    pub fn remove_layer(&mut self, index: usize) -> Option<NoiseLayer> {
+        (index < self.layers.len()).then(|| self.layers.remove(index))
+    }
+
+    pub fn layers(&self) -> &[NoiseLayer] {
+        &self.layers
+    }
+
+    pub fn layers_mut(&mut self) -> &mut [NoiseLayer] {
+        &mut self.layers
+    }
+
+    pub fn total_amplitude(&self) -> Sample {
+        self.layers.iter().map(|layer| layer.amplitude.abs()).sum()
+    }
+}
+
+impl Default for LayeredSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NoiseSource for LayeredSource {
+    fn sample(&self, x: Coord, y: Coord, seed: Seed) -> Sample {
+        let total_amplitude = self.total_amplitude();
+        if total_amplitude == 0.0 {
+            return 0.0;
+        }
+
+        self.layers.iter().map(|layer| layer.sample(x, y, seed)).sum::<Sample>() / total_amplitude
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use crate::noise::source::{TableFilter, TableSource, TestSource};
+
+    use super::*;
+
+    fn constant(value: Sample, amplitude: Sample) -> NoiseLayer {
+        let source = TableSource::new(1, 1, vec![value], TableFilter::Nearest).unwrap();
+        NoiseLayer::new(Box::new(source), 1.0, amplitude, (0.0, 0.0))
+    }
+
+    #[wasm_bindgen_test]
+    fn sum_is_weighted_by_amplitude() {
+        let layered = LayeredSource::new()
+            .with_layer(constant(1.0, 3.0))
+            .with_layer(constant(-1.0, 1.0));
+
+        //(1 * 3 - 1 * 1) / 4
+        assert_eq!(layered.sample(2.0, 5.0, 0), 0.5);
+    }
+
+    #[wasm_bindgen_test]
+    fn scaling_every_amplitude_changes_nothing() {
+        let quiet = LayeredSource::new().with_layer(constant(0.8, 1.0)).with_layer(constant(0.2, 2.0));
+        let loud = LayeredSource::new().with_layer(constant(0.8, 10.0)).with_layer(constant(0.2, 20.0));
+
+        assert!((quiet.sample(0.0, 0.0, 0) - loud.sample(0.0, 0.0, 0)).abs() < 1e-6);
+    }
+
+    #[wasm_bindgen_test]
+    fn single_layer_keeps_its_source_range() {
+        let layered = LayeredSource::new().with_layer(NoiseLayer::new(Box::new(TestSource), 2.0, 5.0, (1.0, 0.0)));
+
+        for &(x, y) in &[(0.0, 0.0), (0.3, 1.7), (-4.0, 2.5)] {
+            assert!((layered.sample(x, y, 0) - TestSource.sample(x * 2.0 + 1.0, y * 2.0, 0)).abs() < 1e-6);
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn empty_or_silent_layers_sample_zero() {
+        let mut layered = LayeredSource::new();
+        assert_eq!(layered.sample(1.0, 1.0, 0), 0.0);
+
+        layered.add_layer(constant(1.0, 0.0));
+        assert_eq!(layered.sample(1.0, 1.0, 0), 0.0);
+    }
+
+    #[wasm_bindgen_test]
+    fn removed_layers_stop_contributing() {
+        let mut layered = LayeredSource::new().with_layer(constant(1.0, 1.0));
+        let index = layered.add_layer(constant(-1.0, 1.0));
+        assert_eq!(layered.sample(0.0, 0.0, 0), 0.0);
+
+        assert!(layered.remove_layer(index).is_some());
+        assert!(layered.remove_layer(index).is_none());
+        assert_eq!(layered.sample(0.0, 0.0, 0), 1.0);
+    }
+}
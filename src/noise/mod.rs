@@ -1 +1,9 @@
-pub mod source;
\ No newline at end of file
+pub mod source;
+pub mod layered;
+pub mod worley;
+pub mod perm;
+pub mod perlin;
+pub mod simplex;
+pub mod transformed;
+pub mod fbm;
+pub mod remap;
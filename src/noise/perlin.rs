@@ -0,0 +1,49 @@
+use std::cell::RefCell;
+
+use super::perm::PermTable;
+use super::source::{Coord, NoiseSource, Sample, Seed};
+
+//Unit gradients can reach sqrt(0.5) between lattice points, this brings that to 1
+const PERLIN_SCALE: Sample = std::f32::consts::SQRT_2;
+
+fn fade(t: Coord) -> Coord {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+//Classic gradient noise on a square lattice, in [-1, 1]
+pub struct PerlinSource {
+    table: RefCell<PermTable>
+}
+
+impl PerlinSource {
+    pub fn new(table: PermTable) -> Self {
+        PerlinSource {
+            table: RefCell::new(table)
+        }
+    }
+}
+
+impl Default for PerlinSource {
+    fn default() -> Self {
+        Self::new(PermTable::default())
+    }
+}
+
+impl NoiseSource for PerlinSource {
+    fn sample(&self, x: Coord, y: Coord, seed: Seed) -> Sample {
+        let table = PermTable::for_seed(&self.table, seed);
+
+        let (cell_x, cell_y) = (x.floor() as i32, y.floor() as i32);
+        let (fx, fy) = (x - x.floor(), y - y.floor());
+        let (u, v) = (fade(fx), fade(fy));
+
+        let a = table.gradient_dot(cell_x, cell_y, fx, fy);
+        let b = table.gradient_dot(cell_x + 1, cell_y, fx - 1.0, fy);
+        let c = table.gradient_dot(cell_x, cell_y + 1, fx, fy - 1.0);
+        let d = table.gradient_dot(cell_x + 1, cell_y + 1, fx - 1.0, fy - 1.0);
+
+        let mix = |a: Sample, b: Sample, t: Coord| a + (b - a) * t;
+
+        (mix(mix(a, b, u), mix(c, d, u), v) * PERLIN_SCALE).clamp(-1.0, 1.0)
+    }
+}
@@ -0,0 +1,176 @@
+use std::cell::{Ref, RefCell};
+use std::f32::consts::FRAC_1_SQRT_2;
+
+use super::source::Seed;
+
+//Unit gradients every 45 degrees
+const GRADIENTS: [[f32; 2]; 8] = [
+    [1.0, 0.0],
+    [FRAC_1_SQRT_2, FRAC_1_SQRT_2],
+    [0.0, 1.0],
+    [-FRAC_1_SQRT_2, FRAC_1_SQRT_2],
+    [-1.0, 0.0],
+    [-FRAC_1_SQRT_2, -FRAC_1_SQRT_2],
+    [0.0, -1.0],
+    [FRAC_1_SQRT_2, -FRAC_1_SQRT_2]
+];
+
+//Golden ratio step between splitmix64 states
+pub const SPLITMIX_INCREMENT: u64 = 0x9e3779b97f4a7c15;
+
+//splitmix64's output mix, which scatters nearby states far apart. Every input bit reaches every
+//output bit, so seeds differing only in their high half still give unrelated streams.
+pub fn splitmix64(state: u64) -> u64 {
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+//The crate's seeded generator: a splitmix64 stream. Plain integer arithmetic, so a seed gives the same
+//numbers on every target, unlike anything built on std's randomly keyed hashers.
+#[derive(Clone, Debug)]
+pub struct SplitMix64 {
+    state: u64
+}
+
+impl SplitMix64 {
+    pub fn new(seed: Seed) -> Self {
+        SplitMix64 { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(SPLITMIX_INCREMENT);
+        splitmix64(self.state)
+    }
+
+    //The high half, splitmix64's low bits are the weaker ones
+    pub fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    //In [0, 1), from the top 53 bits so every value is exact
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+//Fisher-Yates, every seed-derived table is shuffled with this rather than collected out of a hash map
+pub fn shuffle<T>(values: &mut [T], random: &mut SplitMix64) {
+    for i in (1..values.len()).rev() {
+        let j = (random.next_u32() % (i as u32 + 1)) as usize;
+        values.swap(i, j);
+    }
+}
+
+//Permutation of 0..256 shuffled from a seed, shared by every gradient noise so a seed gives the same
+//lattice whichever algorithm is used
+#[derive(Clone, PartialEq, Eq)]
+pub struct PermTable {
+    seed: Seed,
+    //The permutation twice over, so hash can index past 255 without wrapping
+    perm: [u8; 512]
+}
+
+impl PermTable {
+    pub fn from_seed(seed: Seed) -> Self {
+        let mut values: [u8; 256] = std::array::from_fn(|i| i as u8);
+        shuffle(&mut values, &mut SplitMix64::new(seed));
+
+        PermTable {
+            seed,
+            perm: std::array::from_fn(|i| values[i % 256])
+        }
+    }
+
+    pub fn seed(&self) -> Seed {
+        self.seed
+    }
+
+    //Pseudo-random byte for a lattice point, repeats every 256 points along each axis
+    pub fn hash(&self, x: i32, y: i32) -> u8 {
+        let x = (x & 255) as usize;
+        let y = (y & 255) as usize;
+
+        self.perm[self.perm[x] as usize + y]
+    }
+
+    pub fn gradient(&self, hash: u8) -> [f32; 2] {
+        GRADIENTS[(hash & 7) as usize]
+    }
+
+    //Dot product of a lattice point's gradient with the offset from it
+    pub fn gradient_dot(&self, x: i32, y: i32, dx: f32, dy: f32) -> f32 {
+        let [gx, gy] = self.gradient(self.hash(x, y));
+        gx * dx + gy * dy
+    }
+
+    //Sources keep their table in a RefCell and call this with the seed they're sampled with, so the
+    //table is only reshuffled when the seed actually changes
+    pub fn for_seed(table: &RefCell<PermTable>, seed: Seed) -> Ref<'_, PermTable> {
+        if table.borrow().seed != seed {
+            *table.borrow_mut() = PermTable::from_seed(seed);
+        }
+
+        table.borrow()
+    }
+}
+
+impl Default for PermTable {
+    fn default() -> Self {
+        Self::from_seed(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    fn same_seed_gives_the_same_table() {
+        let (a, b) = (PermTable::from_seed(42), PermTable::from_seed(42));
+
+        assert!(a == b);
+        assert_eq!(a.hash(3, 7), b.hash(3, 7));
+    }
+
+    #[wasm_bindgen_test]
+    fn different_seeds_give_different_tables() {
+        let (a, b) = (PermTable::from_seed(1), PermTable::from_seed(2));
+        let differing = (0..256).filter(|&x| a.hash(x, 0) != b.hash(x, 0)).count();
+
+        assert!(a != b);
+        assert!(differing > 200, "only {} of 256 hashes differ", differing);
+    }
+
+    #[wasm_bindgen_test]
+    fn for_seed_follows_the_seed() {
+        let table = RefCell::new(PermTable::from_seed(5));
+        assert!(*PermTable::for_seed(&table, 5) == PermTable::from_seed(5));
+        assert!(*PermTable::for_seed(&table, 6) == PermTable::from_seed(6));
+    }
+
+    #[wasm_bindgen_test]
+    fn table_is_a_permutation_repeated_twice() {
+        let table = PermTable::from_seed(0x5eed);
+
+        let mut seen = [false; 256];
+        for &value in &table.perm[..256] {
+            assert!(!seen[value as usize], "{} appears twice", value);
+            seen[value as usize] = true;
+        }
+        assert_eq!(table.perm[..256], table.perm[256..]);
+    }
+
+    #[wasm_bindgen_test]
+    fn generator_output_is_fixed() {
+        //The first outputs of the reference splitmix64 seeded with 0, a change here changes every seeded table
+        let mut random = SplitMix64::new(0);
+
+        assert_eq!(random.next_u64(), 0xe220a8397b1dcdaf);
+        assert_eq!(random.next_u64(), 0x6e789e6aa1b965f4);
+        assert_eq!(random.next_u64(), 0x06c45d188009454f);
+    }
+}
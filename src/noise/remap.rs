@@ -0,0 +1,121 @@
+use super::source::{Coord, NoiseSource, Sample, Seed};
+
+//The range the terrain expects, displacement::texel_value maps it onto [0, 1]
+pub const TERRAIN_RANGE: (Sample, Sample) = (-1.0, 1.0);
+
+//Linearly maps samples of source from input onto output, so sources with different ranges can be swapped
+//without the terrain washing out or clipping. Samples outside input land outside output unless clamp is set.
+pub struct Remap<S> {
+    pub source: S,
+    pub input: (Sample, Sample),
+    pub output: (Sample, Sample),
+    pub clamp: bool
+}
+
+impl<S: NoiseSource> Remap<S> {
+    pub fn new(source: S, input: (Sample, Sample), output: (Sample, Sample)) -> Self {
+        Remap {
+            source,
+            input,
+            output,
+            clamp: false
+        }
+    }
+
+    //Maps the range estimate_range finds over the given area onto output. Anything the grid missed
+    //goes past output, clamp if that matters.
+    pub fn calibrated(source: S, seed: Seed, area: (Coord, Coord), resolution: u32, output: (Sample, Sample)) -> Self {
+        let input = estimate_range(&source, seed, area, resolution);
+        Remap::new(source, input, output)
+    }
+
+    pub fn clamped(mut self, clamp: bool) -> Self {
+        self.clamp = clamp;
+        self
+    }
+
+    pub fn into_inner(self) -> S {
+        self.source
+    }
+
+    //An empty input range has nothing to stretch, everything goes to the middle of output
+    pub fn remap(&self, value: Sample) -> Sample {
+        let (in_min, in_max) = self.input;
+        let (out_min, out_max) = self.output;
+
+        let t = if in_max != in_min { (value - in_min) / (in_max - in_min) } else { 0.5 };
+        let t = if self.clamp { t.clamp(0.0, 1.0) } else { t };
+
+        out_min + (out_max - out_min) * t
+    }
+}
+
+impl<S: NoiseSource> NoiseSource for Remap<S> {
+    fn sample(&self, x: Coord, y: Coord, seed: Seed) -> Sample {
+        self.remap(self.source.sample(x, y, seed))
+    }
+}
+
+//Smallest and largest sample on a resolution x resolution grid over [0, area.0] x [0, area.1]. Only an
+//estimate, the true extremes can fall between grid points. NaNs are skipped, (0, 0) if every sample is one.
+pub fn estimate_range(source: &dyn NoiseSource, seed: Seed, area: (Coord, Coord), resolution: u32) -> (Sample, Sample) {
+    let resolution = resolution.max(2);
+    let step = |extent: Coord, i: u32| extent * i as Coord / (resolution - 1) as Coord;
+
+    let range = (0..resolution * resolution)
+        .map(|i| source.sample(step(area.0, i % resolution), step(area.1, i / resolution), seed))
+        .filter(|sample| !sample.is_nan())
+        .fold(None, |range: Option<(Sample, Sample)>, sample| match range {
+            Some((min, max)) => Some((min.min(sample), max.max(sample))),
+            None => Some((sample, sample))
+        });
+
+    range.unwrap_or((0.0, 0.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use crate::noise::source::{TableFilter, TableSource, TestSource};
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    fn remap_is_linear() {
+        let remap = Remap::new(TestSource, (0.0, 4.0), TERRAIN_RANGE);
+
+        assert_eq!(remap.remap(0.0), -1.0);
+        assert_eq!(remap.remap(1.0), -0.5);
+        assert_eq!(remap.remap(2.0), 0.0);
+        assert_eq!(remap.remap(4.0), 1.0);
+    }
+
+    #[wasm_bindgen_test]
+    fn clamping_keeps_samples_in_the_output() {
+        let unclamped = Remap::new(TestSource, (0.0, 1.0), (10.0, 20.0));
+        assert_eq!(unclamped.remap(1.5), 25.0);
+        assert_eq!(unclamped.remap(-1.0), 0.0);
+
+        let clamped = unclamped.clamped(true);
+        assert_eq!(clamped.remap(1.5), 20.0);
+        assert_eq!(clamped.remap(-1.0), 10.0);
+        assert_eq!(clamped.remap(0.5), 15.0);
+    }
+
+    #[wasm_bindgen_test]
+    fn reversed_and_empty_ranges() {
+        assert_eq!(Remap::new(TestSource, (-1.0, 1.0), (1.0, 0.0)).remap(-1.0), 1.0);
+        assert_eq!(Remap::new(TestSource, (3.0, 3.0), (0.0, 8.0)).remap(100.0), 4.0);
+    }
+
+    #[wasm_bindgen_test]
+    fn calibration_finds_the_grid_range() {
+        let table = TableSource::new(2, 2, vec![0.25, 0.5, -0.75, 0.0], TableFilter::Bilinear).unwrap();
+        assert_eq!(estimate_range(&table, 0, (1.0, 1.0), 5), (-0.75, 0.5));
+
+        let remap = Remap::calibrated(table, 0, (1.0, 1.0), 5, TERRAIN_RANGE);
+        assert_eq!(remap.sample(0.0, 1.0, 0), -1.0);
+        assert_eq!(remap.sample(1.0, 0.0, 0), 1.0);
+    }
+}
@@ -0,0 +1,131 @@
+use super::source::{Coord, NoiseSource, Sample, Seed};
+
+//Samples source at matrix * (x, y) + translation, so the noise can be rotated, stretched or sheared
+//without touching the source itself. Each builder method applies on top of what's already there,
+//e.g. Transformed::new(source).scale(2.0, 1.0).rotate(angle) scales first, then rotates.
+pub struct Transformed<S> {
+    pub source: S,
+    //Row major
+    pub matrix: [[Coord; 2]; 2],
+    pub translation: (Coord, Coord)
+}
+
+impl<S: NoiseSource> Transformed<S> {
+    //Starts as the identity, sampling exactly like source
+    pub fn new(source: S) -> Self {
+        Transformed {
+            source,
+            matrix: [[1.0, 0.0], [0.0, 1.0]],
+            translation: (0.0, 0.0)
+        }
+    }
+
+    pub fn with_matrix(source: S, matrix: [[Coord; 2]; 2], translation: (Coord, Coord)) -> Self {
+        Transformed {
+            source,
+            matrix,
+            translation
+        }
+    }
+
+    //Rotates the sampled coordinates counterclockwise by angle (radians), which turns the terrain the other way
+    pub fn rotate(self, angle: Coord) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        self.then([[cos, -sin], [sin, cos]], (0.0, 0.0))
+    }
+
+    //Factors above 1 raise the frequency along that axis, squeezing features together
+    pub fn scale(self, sx: Coord, sy: Coord) -> Self {
+        self.then([[sx, 0.0], [0.0, sy]], (0.0, 0.0))
+    }
+
+    pub fn translate(self, dx: Coord, dy: Coord) -> Self {
+        self.then([[1.0, 0.0], [0.0, 1.0]], (dx, dy))
+    }
+
+    pub fn into_inner(self) -> S {
+        self.source
+    }
+
+    //Applies matrix and then translation after the current transform
+    fn then(self, matrix: [[Coord; 2]; 2], translation: (Coord, Coord)) -> Self {
+        let [[a, b], [c, d]] = matrix;
+        let [[e, f], [g, h]] = self.matrix;
+        let (tx, ty) = self.translation;
+
+        Transformed {
+            source: self.source,
+            matrix: [
+                [a * e + b * g, a * f + b * h],
+                [c * e + d * g, c * f + d * h]
+            ],
+            translation: (a * tx + b * ty + translation.0, c * tx + d * ty + translation.1)
+        }
+    }
+
+    pub fn transform(&self, x: Coord, y: Coord) -> (Coord, Coord) {
+        let [[a, b], [c, d]] = self.matrix;
+
+        (a * x + b * y + self.translation.0, c * x + d * y + self.translation.1)
+    }
+}
+
+impl<S: NoiseSource> NoiseSource for Transformed<S> {
+    fn sample(&self, x: Coord, y: Coord, seed: Seed) -> Sample {
+        let (x, y) = self.transform(x, y);
+        self.source.sample(x, y, seed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::{FRAC_PI_2, PI};
+
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use crate::noise::source::TestSource;
+
+    use super::*;
+
+    const POINTS: [(Coord, Coord); 4] = [(0.0, 0.0), (1.5, -2.0), (-7.25, 3.5), (12.0, 40.0)];
+
+    fn close(a: (Coord, Coord), b: (Coord, Coord)) -> bool {
+        (a.0 - b.0).abs() < 1e-4 && (a.1 - b.1).abs() < 1e-4
+    }
+
+    #[wasm_bindgen_test]
+    fn identity_samples_like_the_source() {
+        let transformed = Transformed::new(TestSource);
+
+        for &(x, y) in &POINTS {
+            assert_eq!(transformed.sample(x, y, 9), TestSource.sample(x, y, 9));
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn full_turn_is_nearly_identity() {
+        let transformed = Transformed::new(TestSource).rotate(2.0 * PI);
+
+        for &(x, y) in &POINTS {
+            assert!(close(transformed.transform(x, y), (x, y)), "{}, {}", x, y);
+            assert!((transformed.sample(x, y, 0) - TestSource.sample(x, y, 0)).abs() < 1e-4);
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn quarter_turn_is_counterclockwise() {
+        let transformed = Transformed::new(TestSource).rotate(FRAC_PI_2);
+
+        assert!(close(transformed.transform(1.0, 0.0), (0.0, 1.0)));
+        assert!(close(transformed.transform(0.0, 1.0), (-1.0, 0.0)));
+    }
+
+    #[wasm_bindgen_test]
+    fn builders_apply_in_order() {
+        let scaled_then_moved = Transformed::new(TestSource).scale(2.0, 3.0).translate(1.0, -1.0);
+        let moved_then_scaled = Transformed::new(TestSource).translate(1.0, -1.0).scale(2.0, 3.0);
+
+        assert_eq!(scaled_then_moved.transform(1.0, 1.0), (3.0, 2.0));
+        assert_eq!(moved_then_scaled.transform(1.0, 1.0), (4.0, 0.0));
+    }
+}
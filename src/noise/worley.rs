@@ -0,0 +1,129 @@
+use super::perm::splitmix64;
+use super::source::{Coord, NoiseSource, Sample, Seed};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WorleyOutput {
+    //Distance to the nearest feature point, round cells
+    F1,
+    //Distance to the second nearest
+    F2,
+    //Zero on the boundaries between cells, gives sharp cracks and ridges
+    F2MinusF1
+}
+
+//Cellular noise: one randomly placed feature point per unit cell. Outputs are distances
+//mapped from [0, 1] to the usual [-1, 1], anything further clamps to 1.
+pub struct WorleySource {
+    pub output: WorleyOutput,
+    //How far feature points can stray from their cell's corner, 0 gives a regular grid
+    pub jitter: Coord
+}
+
+impl WorleySource {
+    pub fn new(output: WorleyOutput) -> Self {
+        WorleySource {
+            output,
+            jitter: 1.0
+        }
+    }
+
+    fn feature_point(&self, cell_x: i32, cell_y: i32, seed: Seed) -> (Coord, Coord) {
+        let h = hash(cell_x, cell_y, seed);
+        let (jx, jy) = ((h & 0xffff) as Coord / 65535.0, (h >> 16) as Coord / 65535.0);
+
+        (cell_x as Coord + jx * self.jitter, cell_y as Coord + jy * self.jitter)
+    }
+
+    //Nearest and second nearest feature point distances, searching the 3x3 cells around the point
+    pub fn distances(&self, x: Coord, y: Coord, seed: Seed) -> (Coord, Coord) {
+        let (cell_x, cell_y) = (x.floor() as i32, y.floor() as i32);
+        let (mut f1, mut f2) = (Coord::INFINITY, Coord::INFINITY);
+
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let (px, py) = self.feature_point(cell_x + dx, cell_y + dy, seed);
+                let distance = ((px - x).powi(2) + (py - y).powi(2)).sqrt();
+
+                if distance < f1 {
+                    f2 = f1;
+                    f1 = distance;
+                } else if distance < f2 {
+                    f2 = distance;
+                }
+            }
+        }
+
+        (f1, f2)
+    }
+}
+
+impl Default for WorleySource {
+    fn default() -> Self {
+        Self::new(WorleyOutput::F1)
+    }
+}
+
+impl NoiseSource for WorleySource {
+    fn sample(&self, x: Coord, y: Coord, seed: Seed) -> Sample {
+        let (f1, f2) = self.distances(x, y, seed);
+
+        let value = match self.output {
+            WorleyOutput::F1 => f1,
+            WorleyOutput::F2 => f2,
+            WorleyOutput::F2MinusF1 => f2 - f1
+        };
+
+        value.min(1.0) * 2.0 - 1.0
+    }
+}
+
+//The cell packed into 64 bits and mixed with the whole seed, so no bit of either is folded away
+fn hash(x: i32, y: i32, seed: Seed) -> u32 {
+    let cell = (x as u32 as u64) << 32 | y as u32 as u64;
+    (splitmix64(splitmix64(seed) ^ cell) >> 32) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    fn f2_is_never_nearer_than_f1() {
+        let worley = WorleySource::default();
+
+        for i in 0..400 {
+            let (x, y) = ((i % 20) as Coord * 0.37 - 3.0, (i / 20) as Coord * 0.41 - 4.0);
+            let (f1, f2) = worley.distances(x, y, 11);
+
+            assert!(f2 >= f1, "{} < {} at {}, {}", f2, f1, x, y);
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn cracks_are_zero_on_cell_boundaries() {
+        //Without jitter the feature points sit on the integer grid, so the boundaries are the half-integer lines
+        let worley = WorleySource { jitter: 0.0, ..WorleySource::new(WorleyOutput::F2MinusF1) };
+
+        for &(x, y) in &[(0.5, 0.25), (2.5, -1.125), (-3.0, 0.5), (1.5, 1.5)] {
+            let (f1, f2) = worley.distances(x, y, 5);
+            assert_eq!(f2 - f1, 0.0, "{}, {}", x, y);
+            assert_eq!(worley.sample(x, y, 5), -1.0);
+        }
+
+        let (f1, f2) = worley.distances(0.25, 0.0, 5);
+        assert!(f2 - f1 > 0.0);
+    }
+
+    #[wasm_bindgen_test]
+    fn outputs_pick_their_distance() {
+        let (x, y, seed) = (1.3, -0.7, 2);
+        let (f1, f2) = WorleySource::default().distances(x, y, seed);
+        let sample = |output| WorleySource::new(output).sample(x, y, seed);
+
+        assert_eq!(sample(WorleyOutput::F1), f1.min(1.0) * 2.0 - 1.0);
+        assert_eq!(sample(WorleyOutput::F2), f2.min(1.0) * 2.0 - 1.0);
+        assert_eq!(sample(WorleyOutput::F2MinusF1), (f2 - f1).min(1.0) * 2.0 - 1.0);
+    }
+}
@@ -0,0 +1,120 @@
+use wgpu::util::DeviceExt;
+
+//Per-chunk second vertex stream for VertexHeights::Baked, lined up with the shared chunk mesh.
+//height is normalised like the height textures, the shader scales it and adds detail as usual.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct BakedVertex {
+    height: f32,
+    normal: [f32; 3]
+}
+
+impl BakedVertex {
+    const ATTRIBS: [wgpu::VertexAttribute; 2] = wgpu::vertex_attr_array![3 => Float32, 4 => Float32x3];
+
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<BakedVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBS
+        }
+    }
+}
+
+//CPU copy of the chunk mesh, what baking needs from it. Skirt vertices come after the surface ones,
+//each a copy of the surface vertex in skirt_sources, and their triangles after surface_indices.
+pub struct MeshShape {
+    pub positions: Vec<[f32; 2]>,
+    pub uvs: Vec<[f32; 2]>,
+    pub indices: Vec<u32>,
+    pub surface_indices: usize,
+    pub skirt_sources: Vec<u32>
+}
+
+impl MeshShape {
+    //Heights are a chunk's texels laid out like chunk::noise_buffer, apron texels around tex_size
+    //inner ones. Each vertex gets the height sample_height in shader.wgsl would give it, and the
+    //area weighted average of the normals of the surface triangles around it.
+    pub fn bake(&self, heights: &[f32], stride: usize, tex_size: u32, apron: u32, height_scale: f32) -> Vec<BakedVertex> {
+        let surface_vertices = self.positions.len() - self.skirt_sources.len();
+
+        let sample = |uv: [f32; 2]| {
+            let max = (tex_size - 1) as f32;
+            let (x, y) = (uv[0].clamp(0.0, 1.0) * max, uv[1].clamp(0.0, 1.0) * max);
+            let (base_x, base_y) = ((x.floor() as usize).min(tex_size as usize - 2), (y.floor() as usize).min(tex_size as usize - 2));
+            let (fx, fy) = (x - base_x as f32, y - base_y as f32);
+
+            let texel = |dx: usize, dy: usize| heights[(base_y + dy + apron as usize) * stride + base_x + dx + apron as usize];
+            let mix = |a: f32, b: f32, t: f32| a + (b - a) * t;
+
+            mix(mix(texel(0, 0), texel(1, 0), fx), mix(texel(0, 1), texel(1, 1), fx), fy)
+        };
+
+        let vertex_heights: Vec<f32> = self.uvs[..surface_vertices].iter().map(|&uv| sample(uv)).collect();
+        let position = |i: u32| {
+            let [x, z] = self.positions[i as usize];
+            cgmath::Vector3::new(x, vertex_heights[i as usize] * height_scale, z)
+        };
+
+        let mut normals = vec![cgmath::Vector3::new(0.0, 0.0, 0.0); surface_vertices];
+        for triangle in self.indices[..self.surface_indices].chunks_exact(3) {
+            let (a, b, c) = (position(triangle[0]), position(triangle[1]), position(triangle[2]));
+
+            //Twice the triangle's area long, so bigger triangles count for more. Flipped up whatever the winding.
+            let normal = (b - a).cross(c - a);
+            let normal = if normal.y < 0.0 { -normal } else { normal };
+
+            for &i in triangle {
+                normals[i as usize] += normal;
+            }
+        }
+
+        let surface = (0..surface_vertices).map(|i| {
+            use cgmath::InnerSpace;
+
+            let normal = normals[i];
+            let normal = if normal.magnitude2() > 0.0 { normal.normalize() } else { cgmath::Vector3::unit_y() };
+
+            BakedVertex {
+                height: vertex_heights[i],
+                normal: normal.into()
+            }
+        }).collect::<Vec<_>>();
+
+        let skirt: Vec<_> = self.skirt_sources.iter().map(|&source| surface[source as usize]).collect();
+
+        [surface, skirt].concat()
+    }
+}
+
+//A chunk's baked stream, and which mesh and height scale it was baked for
+pub struct BakedBuffers {
+    pub vertices: wgpu::Buffer,
+    //Expanded by the mesh's indices for unindexed draws, like ChunkBuffers::unindexed_buffer
+    pub unindexed: Option<wgpu::Buffer>,
+    pub version: u64
+}
+
+impl BakedBuffers {
+    pub fn new(device: &wgpu::Device, mesh: &MeshShape, vertices: &[BakedVertex], unindexed: bool, version: u64) -> Self {
+        let unindexed = unindexed.then(|| {
+            let unindexed_vertices: Vec<_> = mesh.indices.iter().map(|&i| vertices[i as usize]).collect();
+
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Unindexed baked vertex buffer"),
+                contents: bytemuck::cast_slice(&unindexed_vertices),
+                usage: wgpu::BufferUsages::VERTEX
+            })
+        });
+
+        BakedBuffers {
+            vertices: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Baked vertex buffer"),
+                contents: bytemuck::cast_slice(vertices),
+                usage: wgpu::BufferUsages::VERTEX
+            }),
+            unindexed,
+            version
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use serde::Serialize;
+use wasm_bindgen::JsCast;
+use web_sys::{Document, HtmlElement};
+
+use crate::{noise::source::TestSource, util::{now, Profiler, scoped_id}};
+
+use super::{camera::Camera, recording::{CameraKeyframe, CameraPath}, wgpu_context::{self, TessellationConfig, WgpuContext, DEFAULT_CHUNK_SIZE, DEFAULT_NOISE_FREQUENCY, DEFAULT_TEX_SIZE}};
+
+pub const DEFAULT_BENCH_FRAMES: u32 = 600;
+
+//Chunk sizes and densities the mesh generators are compared at, and how many times each is built
+const MESH_BENCH_SIZES: [u32; 3] = [50, 100, 200];
+const MESH_BENCH_DENSITIES: [f32; 3] = [0.5, 1.0, 2.0];
+const MESH_BENCH_RUNS: u32 = 5;
+
+//Loop over the terrain chunk, looking along the direction of travel. Times only set the spacing,
+//the benchmark samples the path by frame number so every run sees the same views.
+fn bench_path() -> CameraPath {
+    let keyframe = |time: f64, eye: [f32; 3], pitch: f32, yaw: f32| CameraKeyframe { time, origin: [0.0; 2], eye, pitch, yaw };
+
+    CameraPath::new(vec![
+        keyframe(0.0, [5.0, 20.0, 5.0], -0.3, 0.0),
+        keyframe(2.0, [95.0, 15.0, 5.0], -0.3, 0.8),
+        keyframe(4.0, [95.0, 25.0, 95.0], -0.5, 2.4),
+        keyframe(6.0, [5.0, 15.0, 95.0], -0.3, 3.9),
+        keyframe(8.0, [5.0, 20.0, 5.0], -0.3, 6.3)
+    ])
+}
+
+#[derive(Serialize)]
+pub struct StageTiming {
+    pub name: &'static str,
+    pub average: f64,
+    pub max: f64,
+    pub count: u64
+}
+
+//Times are in milliseconds
+#[derive(Serialize)]
+pub struct BenchSummary {
+    pub frames: usize,
+    pub total: f64,
+    pub average: f64,
+    pub median: f64,
+    pub p95: f64,
+    pub max: f64,
+    pub fps: f64,
+    pub stages: Vec<StageTiming>
+}
+
+//One generator at one size and density, times are in milliseconds
+#[derive(Serialize)]
+pub struct MeshTiming {
+    pub generator: &'static str,
+    pub size: u32,
+    pub density: f32,
+    pub average: f64,
+    pub min: f64,
+    pub triangles: usize
+}
+
+//Both generators at the same size and density. The Delaunay mesh spends its extra triangles on steep
+//terrain, so the triangle counts are as much a part of the comparison as the times.
+#[derive(Serialize)]
+pub struct MeshComparison {
+    pub delaunay: MeshTiming,
+    pub grid: MeshTiming,
+    //delaunay.average / grid.average
+    pub slowdown: f64
+}
+
+fn percentile(sorted: &[f64], percentile: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+
+    let rank = (percentile / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+//Flies a fixed path for a fixed number of frames and collects every frame time.
+//Enabled with ?bench=1, ?bench_frames=N changes the length.
+pub struct Benchmark {
+    path: CameraPath,
+    frames: u32,
+    frame: u32,
+    frame_times: Vec<f64>
+}
+
+impl Benchmark {
+    pub fn new(frames: u32) -> Self {
+        Benchmark {
+            path: bench_path(),
+            frames: frames.max(1),
+            frame: 0,
+            frame_times: Vec::with_capacity(frames as usize)
+        }
+    }
+
+    pub fn from_location() -> Option<Self> {
+        if location_param("bench").as_deref() != Some("1") {
+            return None;
+        }
+
+        let frames = location_param("bench_frames").and_then(|frames| frames.parse().ok()).unwrap_or(DEFAULT_BENCH_FRAMES);
+        Some(Self::new(frames))
+    }
+
+    //Moves the camera to where this frame is on the path
+    pub fn advance(&mut self, camera: &mut Camera) {
+        let t = self.frame as f64 / self.frames as f64;
+        if let Some(keyframe) = self.path.sample_time(t * self.path.duration()) {
+            keyframe.apply(camera);
+        }
+
+        self.frame += 1;
+    }
+
+    pub fn record_frame(&mut self, frame_time: f64) {
+        self.frame_times.push(frame_time);
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.frame >= self.frames
+    }
+
+    pub fn summary(&self, profiler: &Profiler) -> BenchSummary {
+        let mut sorted = self.frame_times.clone();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+
+        let total: f64 = sorted.iter().sum();
+        let average = if sorted.is_empty() { 0.0 } else { total / sorted.len() as f64 };
+
+        BenchSummary {
+            frames: sorted.len(),
+            total,
+            average,
+            median: percentile(&sorted, 50.0),
+            p95: percentile(&sorted, 95.0),
+            max: sorted.last().copied().unwrap_or(0.0),
+            fps: if average > 0.0 { 1000.0 / average } else { 0.0 },
+            stages: profiler.buckets().iter()
+                .map(|bucket| StageTiming { name: bucket.name, average: bucket.average(), max: bucket.max, count: bucket.count })
+                .collect()
+        }
+    }
+}
+
+fn location_param(name: &str) -> Option<String> {
+    let search = web_sys::window()
+        .and_then(|window| window.location().search().ok())
+        .unwrap_or_default();
+
+    search.trim_start_matches('?')
+        .split('&')
+        .find_map(|pair| pair.strip_prefix(name)?.strip_prefix('='))
+        .map(str::to_string)
+}
+
+//Enabled with ?mesh_bench=1
+pub fn mesh_bench_requested() -> bool {
+    location_param("mesh_bench").as_deref() == Some("1")
+}
+
+fn time_mesh(generator: &'static str, size: u32, density: f32, build: impl Fn() -> usize) -> MeshTiming {
+    let mut times = vec![];
+    let mut indices = 0;
+
+    for _ in 0..MESH_BENCH_RUNS {
+        let start = now();
+        indices = build();
+        times.push(now() - start);
+    }
+
+    MeshTiming {
+        generator,
+        size,
+        density,
+        average: times.iter().sum::<f64>() / times.len() as f64,
+        min: times.iter().copied().fold(f64::INFINITY, f64::min),
+        triangles: indices / 3
+    }
+}
+
+//Times building the chunk mesh on the CPU with Delaunay triangulation against a plain grid. Uploading
+//the buffers costs the same for both per vertex, so it's left out.
+pub fn mesh_bench() -> Vec<MeshComparison> {
+    let noise_scale = WgpuContext::chunk_noise_scale(DEFAULT_TEX_SIZE, DEFAULT_CHUNK_SIZE, DEFAULT_NOISE_FREQUENCY);
+    let mut comparisons = vec![];
+
+    for size in MESH_BENCH_SIZES {
+        for density in MESH_BENCH_DENSITIES {
+            let config = TessellationConfig { density, ..TessellationConfig::default() };
+
+            let delaunay = time_mesh("delaunay", size, density, || wgpu_context::delaunay_mesh(size, &TestSource, 0, noise_scale, &config).1.len());
+            let grid = time_mesh("grid", size, density, || wgpu_context::grid_mesh(size, &config).1.len());
+            let slowdown = if grid.average > 0.0 { delaunay.average / grid.average } else { 0.0 };
+
+            comparisons.push(MeshComparison { delaunay, grid, slowdown });
+        }
+    }
+
+    comparisons
+}
+
+//Puts the summary JSON in a <pre id="bench"> (scoped to the canvas id) so it can be read off the page
+pub fn show_summary(document: &Document, canvas_id: &str, json: &str) {
+    let id = scoped_id(canvas_id, "bench");
+
+    let element = document.get_element_by_id(&id).or_else(|| {
+        let element = document.create_element("pre").ok()?;
+        element.set_id(&id);
+        let _ = element.set_attribute("style", "position: fixed; bottom: 0; left: 0; margin: 0; padding: 4px; background: rgba(0, 0, 0, 0.5); color: white;");
+        document.body()?.append_child(&element).ok()?;
+        Some(element)
+    });
+
+    if let Some(element) = element.and_then(|element| element.dyn_into::<HtmlElement>().ok()) {
+        element.set_inner_text(json);
+    }
+}
@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use web_sys::Storage;
+
+use crate::{console_log, util::scoped_id};
+
+use super::{camera::CameraState, event::KeyboardKey};
+
+//Scoped to the canvas, see settings::storage_key
+const STORAGE_KEY: &str = "cacophony-bookmarks";
+
+pub fn storage_key(canvas_id: &str) -> String {
+    scoped_id(canvas_id, STORAGE_KEY)
+}
+
+//Seconds taken to fly to a recalled bookmark
+pub const RECALL_DURATION: f64 = 0.5;
+
+//Saved camera views on the number keys 1-9: Ctrl+number saves, number recalls
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Bookmarks {
+    slots: HashMap<u8, CameraState>
+}
+
+impl Bookmarks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn slot_for_key(key: KeyboardKey) -> Option<u8> {
+        match key {
+            KeyboardKey::Character(c @ '1'..='9') => c.to_digit(10).map(|digit| digit as u8),
+            _ => None
+        }
+    }
+
+    pub fn save_slot(&mut self, slot: u8, state: CameraState) {
+        self.slots.insert(slot, state);
+    }
+
+    pub fn recall(&self, slot: u8) -> Option<&CameraState> {
+        self.slots.get(&slot)
+    }
+
+    pub fn load(storage: Option<&Storage>, canvas_id: &str) -> Self {
+        storage
+            .and_then(|storage| storage.get_item(&storage_key(canvas_id)).ok().flatten())
+            .and_then(|json| serde_json::from_str::<Bookmarks>(&json).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, storage: Option<&Storage>, canvas_id: &str) {
+        if let (Some(storage), Ok(json)) = (storage, serde_json::to_string(self)) {
+            if storage.set_item(&storage_key(canvas_id), &json).is_err() {
+                console_log!("Couldn't save bookmarks");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use crate::{render::camera::Camera, util::DEFAULT_CANVAS_ID};
+
+    use super::*;
+
+    fn state(x: f32, yaw: f32) -> CameraState {
+        Camera::new(cgmath::Point3::new(x, 10.0, 0.0), cgmath::Vector3::unit_y(), -0.2, yaw, 1.5, 45.0).state()
+    }
+
+    #[wasm_bindgen_test]
+    fn saved_slots_recall_their_state() {
+        let mut bookmarks = Bookmarks::new();
+        bookmarks.save_slot(1, state(1.0, 0.5));
+        bookmarks.save_slot(9, state(-4.0, 3.0));
+
+        assert_eq!(bookmarks.recall(1), Some(&state(1.0, 0.5)));
+        assert_eq!(bookmarks.recall(9), Some(&state(-4.0, 3.0)));
+        assert_eq!(bookmarks.recall(2), None);
+
+        bookmarks.save_slot(1, state(7.0, 0.0));
+        assert_eq!(bookmarks.recall(1), Some(&state(7.0, 0.0)));
+    }
+
+    #[wasm_bindgen_test]
+    fn bookmarks_survive_json() {
+        let mut bookmarks = Bookmarks::new();
+        bookmarks.save_slot(3, state(2.5, 1.0));
+
+        let json = serde_json::to_string(&bookmarks).unwrap();
+        assert_eq!(serde_json::from_str::<Bookmarks>(&json).unwrap(), bookmarks);
+    }
+
+    #[wasm_bindgen_test]
+    fn only_digits_one_to_nine_are_slots() {
+        assert_eq!(Bookmarks::slot_for_key(KeyboardKey::Character('1')), Some(1));
+        assert_eq!(Bookmarks::slot_for_key(KeyboardKey::Character('9')), Some(9));
+        assert_eq!(Bookmarks::slot_for_key(KeyboardKey::Character('0')), None);
+        assert_eq!(Bookmarks::slot_for_key(KeyboardKey::Character('a')), None);
+        assert_eq!(Bookmarks::slot_for_key(KeyboardKey::Function(1)), None);
+    }
+
+    #[wasm_bindgen_test]
+    fn storage_keys_are_scoped_to_the_canvas() {
+        assert_eq!(storage_key(DEFAULT_CANVAS_ID), STORAGE_KEY);
+        assert_eq!(storage_key("left"), "left-cacophony-bookmarks");
+        assert_ne!(storage_key("left"), storage_key("right"));
+    }
+}
@@ -1,77 +1,435 @@
-use cgmath::InnerSpace;
-
-use crate::console_log;
-
-#[rustfmt::skip]
-pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
-    1.0, 0.0, 0.0, 0.0,
-    0.0, 1.0, 0.0, 0.0,
-    0.0, 0.0, 0.5, 0.5,
-    0.0, 0.0, 0.0, 1.0,
-);
-
-pub struct Camera {
-    pub eye: cgmath::Point3<f32>,
-    up: cgmath::Vector3<f32>,
-
-    pub pitch: f32,
-    pub yaw: f32,
-
-    pub aspect: f32,
-    pub fovy: f32,
-    znear: f32,
-    zfar: f32
-}
-
-impl Camera {
-    pub fn new(eye: cgmath::Point3<f32>, up: cgmath::Vector3<f32>, pitch: f32, yaw: f32, aspect: f32, fovy: f32) -> Self {
-        Camera {
-            eye,
-            up,
-            pitch,
-            yaw,
-            aspect,
-            fovy,
-            znear: 0.01,
-            zfar: 1000.0
-        }
-    }
-
-    pub fn get_direction(&self) -> cgmath::Vector3<f32> {
-        cgmath::Vector3::new(
-            self.yaw.cos() * self.pitch.cos(),
-            self.pitch.sin(),
-            self.yaw.sin() * self.pitch.cos(),
-        )
-    }
-
-    pub fn get_forward(&self) -> cgmath::Vector3<f32> {
-        cgmath::Vector3::new(
-            self.yaw.cos(),
-            0.0,
-            self.yaw.sin()
-        )
-    }
-
-    pub fn build_view_projection_matrix(&self) -> cgmath::Matrix4<f32> {
-        let view = cgmath::Matrix4::look_to_rh(
-            self.eye,
-            self.get_direction(),
-            self.up
-        );
-
-        let proj = cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
-
-        OPENGL_TO_WGPU_MATRIX * proj * view
-    }
-
-    pub fn do_move(&mut self, forward: f32, right: f32, up: f32) {
-        let forward = self.get_forward() * forward;
-        let right = self.get_forward().cross(self.up).normalize() * right;
-        let up = self.up.normalize() * up;
-
-        self.eye += forward;
-        self.eye += right;
-        self.eye += up;
-    }
-}
\ No newline at end of file
+use std::f32::consts::FRAC_PI_2;
+
+use cgmath::InnerSpace;
+use serde::{Deserialize, Serialize};
+
+#[rustfmt::skip]
+pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 0.5, 0.5,
+    0.0, 0.0, 0.0, 1.0,
+);
+
+//Stay just short of vertical, where look_to_rh degenerates with a fixed up vector
+pub const PITCH_LIMIT: f32 = FRAC_PI_2 - 1e-3;
+
+//How far the camera can get from the floating origin, along x or z, before the origin is moved to it
+pub const RECENTER_DISTANCE: f32 = 1000.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Easing {
+    Linear,
+    //Starts fast and settles into the target
+    EaseOut,
+    //Smoothstep, gentle at both ends
+    EaseInOut
+}
+
+impl Easing {
+    //t is the fraction of the animation done, 0 to 1
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+
+        match self {
+            Easing::Linear => t,
+            Easing::EaseOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct FovAnimation {
+    from: f32,
+    to: f32,
+    //Seconds
+    duration: f32,
+    elapsed: f32,
+    easing: Easing
+}
+
+//Everything needed to restore a camera, in a form that serializes cleanly
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CameraState {
+    //eye is relative to origin, see Camera
+    #[serde(default)]
+    pub origin: [f64; 2],
+    pub eye: [f32; 3],
+    pub up: [f32; 3],
+
+    pub pitch: f32,
+    pub yaw: f32,
+
+    pub aspect: f32,
+    pub fovy: f32,
+    pub znear: f32,
+    pub zfar: f32
+}
+
+//Everything is drawn relative to a floating origin on the xz plane, so positions the GPU sees stay small
+//and keep their f32 precision however far the camera goes. eye is relative to origin, which recenter
+//moves in whole steps (chunks) as the camera travels, keeping chunk offsets from it exact.
+#[derive(Clone)]
+pub struct Camera {
+    pub origin: [f64; 2],
+    pub eye: cgmath::Point3<f32>,
+    up: cgmath::Vector3<f32>,
+
+    pitch: f32,
+    pub yaw: f32,
+
+    pub aspect: f32,
+    pub fovy: f32,
+    znear: f32,
+    zfar: f32,
+
+    fov_animation: Option<FovAnimation>
+}
+
+impl Camera {
+    pub fn new(eye: cgmath::Point3<f32>, up: cgmath::Vector3<f32>, pitch: f32, yaw: f32, aspect: f32, fovy: f32) -> Self {
+        Camera {
+            origin: [0.0; 2],
+            eye,
+            up,
+            pitch: pitch.clamp(-PITCH_LIMIT, PITCH_LIMIT),
+            yaw,
+            aspect,
+            fovy,
+            znear: 0.01,
+            zfar: 1000.0,
+
+            fov_animation: None
+        }
+    }
+
+    pub fn state(&self) -> CameraState {
+        CameraState {
+            origin: self.origin,
+            eye: self.eye.into(),
+            up: self.up.into(),
+            pitch: self.pitch,
+            yaw: self.yaw,
+            aspect: self.aspect,
+            fovy: self.fovy,
+            znear: self.znear,
+            zfar: self.zfar
+        }
+    }
+
+    pub fn set_state(&mut self, state: &CameraState) {
+        self.origin = state.origin;
+        self.eye = state.eye.into();
+        self.up = state.up.into();
+        self.set_pitch(state.pitch);
+        self.yaw = state.yaw;
+        self.aspect = state.aspect;
+        self.fovy = state.fovy;
+        self.znear = state.znear;
+        self.zfar = state.zfar;
+        self.fov_animation = None;
+    }
+
+    //Eases fovy from where it is now to target (degrees) over duration seconds, as update_fov is called.
+    //Replaces any animation already running, starting from wherever that had got to.
+    pub fn animate_fov(&mut self, target: f32, duration: f32, easing: Easing) {
+        self.fov_animation = Some(FovAnimation {
+            from: self.fovy,
+            to: target,
+            duration,
+            elapsed: 0.0,
+            easing
+        });
+        self.update_fov(0.0);
+    }
+
+    //Where fovy is heading, the current fovy if it isn't animating
+    pub fn fov_target(&self) -> f32 {
+        self.fov_animation.map_or(self.fovy, |animation| animation.to)
+    }
+
+    //Advances the fov animation by dt seconds and returns whether fovy changed
+    pub fn update_fov(&mut self, dt: f32) -> bool {
+        let Some(animation) = &mut self.fov_animation else {
+            return false;
+        };
+
+        animation.elapsed += dt;
+        let t = if animation.duration > 0.0 { animation.elapsed / animation.duration } else { 1.0 };
+
+        let previous = self.fovy;
+        self.fovy = animation.from + (animation.to - animation.from) * animation.easing.apply(t);
+        if t >= 1.0 {
+            self.fovy = animation.to;
+            self.fov_animation = None;
+        }
+
+        self.fovy != previous
+    }
+
+    pub fn world_position(&self) -> [f64; 3] {
+        [self.origin[0] + self.eye.x as f64, self.eye.y as f64, self.origin[1] + self.eye.z as f64]
+    }
+
+    //Keeps the current origin, the next recenter moves it if the position is far from it
+    pub fn set_world_position(&mut self, position: [f64; 3]) {
+        self.eye = cgmath::Point3::new((position[0] - self.origin[0]) as f32, position[1] as f32, (position[2] - self.origin[1]) as f32);
+    }
+
+    //Moves the origin by whole steps to the camera once it's more than threshold away along x or z.
+    //Returns whether it moved, everything drawn relative to the origin has to follow.
+    pub fn recenter(&mut self, step: f64, threshold: f32) -> bool {
+        if self.eye.x.abs() <= threshold && self.eye.z.abs() <= threshold {
+            return false;
+        }
+
+        let shift_x = (self.eye.x as f64 / step).round() * step;
+        let shift_z = (self.eye.z as f64 / step).round() * step;
+
+        self.origin = [self.origin[0] + shift_x, self.origin[1] + shift_z];
+        self.eye.x = (self.eye.x as f64 - shift_x) as f32;
+        self.eye.z = (self.eye.z as f64 - shift_z) as f32;
+
+        true
+    }
+
+    pub fn pitch(&self) -> f32 {
+        self.pitch
+    }
+
+    pub fn set_pitch(&mut self, pitch: f32) {
+        self.pitch = pitch.clamp(-PITCH_LIMIT, PITCH_LIMIT);
+    }
+
+    //A camera height above this one looking straight down, turned the way this one faces, for a map-like
+    //second view. Shares the origin, so both can be drawn in the same frame.
+    pub fn top_down(&self, height: f32, aspect: f32) -> Camera {
+        let eye = cgmath::Point3::new(self.eye.x, self.eye.y + height, self.eye.z);
+
+        Camera {
+            origin: self.origin,
+            zfar: self.zfar.max(height * 2.0),
+            ..Camera::new(eye, self.up, -PITCH_LIMIT, self.yaw, aspect, self.fovy)
+        }
+    }
+
+    //Turns to face target, which is relative to the origin like eye. The inverse of get_direction, except
+    //that pitch is kept short of vertical. Looking straight up or down keeps the current yaw, and so does
+    //a target at the eye. The new yaw is the one nearest the old, so turning doesn't spin the long way round.
+    pub fn look_at(&mut self, target: cgmath::Point3<f32>) {
+        let offset = target - self.eye;
+        let length = offset.magnitude();
+        if length <= f32::EPSILON {
+            return;
+        }
+        let direction = offset / length;
+
+        self.set_pitch(direction.y.clamp(-1.0, 1.0).asin());
+
+        if direction.x.abs() > f32::EPSILON || direction.z.abs() > f32::EPSILON {
+            let yaw = direction.z.atan2(direction.x);
+            self.yaw += super::recording::wrap_angle(yaw - self.yaw);
+        }
+    }
+
+    pub fn look_at_world(&mut self, target: [f64; 3]) {
+        self.look_at(cgmath::Point3::new((target[0] - self.origin[0]) as f32, target[1] as f32, (target[2] - self.origin[1]) as f32));
+    }
+
+    pub fn get_direction(&self) -> cgmath::Vector3<f32> {
+        cgmath::Vector3::new(
+            self.yaw.cos() * self.pitch.cos(),
+            self.pitch.sin(),
+            self.yaw.sin() * self.pitch.cos(),
+        )
+    }
+
+    pub fn get_forward(&self) -> cgmath::Vector3<f32> {
+        cgmath::Vector3::new(
+            self.yaw.cos(),
+            0.0,
+            self.yaw.sin()
+        )
+    }
+
+    pub fn build_view_projection_matrix(&self) -> cgmath::Matrix4<f32> {
+        let view = cgmath::Matrix4::look_to_rh(
+            self.eye,
+            self.get_direction(),
+            self.up
+        );
+
+        let proj = cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
+
+        OPENGL_TO_WGPU_MATRIX * proj * view
+    }
+
+    pub fn do_move(&mut self, forward: f32, right: f32, up: f32) {
+        let forward = self.get_forward() * forward;
+        let right = self.get_forward().cross(self.up).normalize() * right;
+        let up = self.up.normalize() * up;
+
+        self.eye += forward;
+        self.eye += right;
+        self.eye += up;
+    }
+}
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use crate::render::chunk::ChunkUniform;
+
+    use super::*;
+
+    fn camera() -> Camera {
+        Camera::new(cgmath::Point3::new(0.0, 0.0, 0.0), cgmath::Vector3::unit_y(), 0.0, 0.0, 1.0, 45.0)
+    }
+
+    #[wasm_bindgen_test]
+    fn pitch_stops_just_short_of_vertical() {
+        let mut camera = camera();
+
+        camera.set_pitch(PI);
+        assert_eq!(camera.pitch(), PITCH_LIMIT);
+        assert!(camera.pitch() < FRAC_PI_2);
+
+        camera.set_pitch(-PI);
+        assert_eq!(camera.pitch(), -PITCH_LIMIT);
+
+        camera.set_pitch(0.5);
+        assert_eq!(camera.pitch(), 0.5);
+    }
+
+    #[wasm_bindgen_test]
+    fn view_is_finite_at_the_pitch_limit() {
+        let mut camera = camera();
+        camera.set_pitch(FRAC_PI_2);
+
+        let matrix: [[f32; 4]; 4] = camera.build_view_projection_matrix().into();
+        assert!(matrix.iter().flatten().all(|value| value.is_finite()));
+    }
+
+    #[wasm_bindgen_test]
+    fn state_round_trips() {
+        let mut original = Camera::new(cgmath::Point3::new(1.5, 20.0, -3.0), cgmath::Vector3::unit_y(), -0.4, 2.1, 1.6, 60.0);
+        original.origin = [128.0, -64.0];
+        let state = original.state();
+
+        let mut restored = camera();
+        restored.set_state(&state);
+
+        assert_eq!(restored.state(), state);
+        assert_eq!(restored.pitch(), -0.4);
+    }
+
+    #[wasm_bindgen_test]
+    fn state_survives_json() {
+        let state = Camera::new(cgmath::Point3::new(0.1, 2.0, 3.3), cgmath::Vector3::unit_y(), 0.25, -1.0, 0.75, 70.0).state();
+        let json = serde_json::to_string(&state).unwrap();
+
+        assert_eq!(serde_json::from_str::<CameraState>(&json).unwrap(), state);
+    }
+
+    //Camera to chunk corner as the GPU sees it, both relative to the floating origin
+    fn to_chunk(camera: &Camera, coord: (i32, i32)) -> [f32; 2] {
+        let offset = ChunkUniform::for_coord(coord, 64.0, camera.origin, 0).offset;
+        [offset[0] - camera.eye.x, offset[1] - camera.eye.z]
+    }
+
+    #[wasm_bindgen_test]
+    fn recentering_keeps_chunks_where_they_were() {
+        let mut camera = camera();
+        camera.set_world_position([1000.25, 30.0, -2000.75]);
+        let before = [to_chunk(&camera, (15, -31)), to_chunk(&camera, (16, -32))];
+        let world = camera.world_position();
+
+        assert!(camera.recenter(64.0, 32.0));
+        assert_eq!(camera.origin, [1024.0, -1984.0]);
+        assert_eq!(camera.world_position(), world);
+        assert_eq!([to_chunk(&camera, (15, -31)), to_chunk(&camera, (16, -32))], before);
+    }
+
+    #[wasm_bindgen_test]
+    fn recentering_waits_for_the_threshold() {
+        let mut camera = camera();
+        camera.set_world_position([20.0, 5.0, -31.0]);
+
+        assert!(!camera.recenter(64.0, 32.0));
+        assert_eq!(camera.origin, [0.0, 0.0]);
+    }
+
+    #[wasm_bindgen_test]
+    fn looking_at_a_point_faces_it() {
+        let mut camera = Camera::new(cgmath::Point3::new(1.0, 2.0, 3.0), cgmath::Vector3::unit_y(), 0.0, 0.0, 1.0, 45.0);
+
+        for &target in &[cgmath::Point3::new(5.0, 2.0, 3.0), cgmath::Point3::new(-4.0, 0.0, 9.0), cgmath::Point3::new(1.5, 10.0, 2.0)] {
+            camera.look_at(target);
+
+            //Every target is more than a unit away, so a step along the direction is still short of it
+            let ahead = camera.eye + camera.get_direction();
+            let remaining = (target - ahead).normalize();
+            assert!((remaining - camera.get_direction()).magnitude() < 1e-5, "{:?} towards {:?}", ahead, target);
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn looking_straight_up_stops_at_the_limit() {
+        let mut camera = camera();
+        camera.look_at(cgmath::Point3::new(0.0, 10.0, 0.0));
+
+        assert_eq!(camera.pitch(), PITCH_LIMIT);
+    }
+
+    //Runs the fov animation in 60fps steps, returning the seconds it took to finish
+    fn finish_fov(camera: &mut Camera) -> f32 {
+        let mut elapsed = 0.0;
+        while camera.fov_animation.is_some() {
+            camera.update_fov(1.0 / 60.0);
+            elapsed += 1.0 / 60.0;
+            assert!(elapsed < 10.0, "fov animation never finished");
+        }
+        elapsed
+    }
+
+    #[wasm_bindgen_test]
+    fn fov_reaches_the_target_and_returns() {
+        let mut camera = camera();
+
+        for easing in [Easing::Linear, Easing::EaseOut, Easing::EaseInOut] {
+            camera.animate_fov(60.0, 0.25, easing);
+            assert_eq!(camera.fov_target(), 60.0);
+            assert!(finish_fov(&mut camera) <= 0.25 + 1.0 / 60.0);
+            assert_eq!(camera.fovy, 60.0);
+
+            camera.animate_fov(45.0, 0.25, easing);
+            assert!(finish_fov(&mut camera) <= 0.25 + 1.0 / 60.0);
+            assert_eq!(camera.fovy, 45.0);
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn fov_moves_towards_the_target_without_overshooting() {
+        let mut camera = camera();
+        camera.animate_fov(75.0, 0.5, Easing::EaseOut);
+
+        let mut previous = camera.fovy;
+        while camera.update_fov(0.05) {
+            assert!(camera.fovy > previous && camera.fovy <= 75.0);
+            previous = camera.fovy;
+        }
+        assert_eq!(camera.fovy, 75.0);
+    }
+
+    #[wasm_bindgen_test]
+    fn easings_start_and_end_in_place() {
+        for easing in [Easing::Linear, Easing::EaseOut, Easing::EaseInOut] {
+            assert_eq!(easing.apply(0.0), 0.0);
+            assert_eq!(easing.apply(1.0), 1.0);
+            assert_eq!(easing.apply(2.0), 1.0);
+        }
+    }
+}
@@ -0,0 +1,850 @@
+use std::rc::Rc;
+
+use crate::noise::source::{Coord, NoiseSource, Seed};
+
+use super::displacement;
+use super::normal_map::{NormalMap, NORMAL_FORMAT};
+use super::mipmap::MipGenerator;
+use super::baked_mesh::{BakedBuffers, MeshShape};
+use super::upload::Uploader;
+use super::height_field::Heightmap;
+use super::screenshot::{self, padded_bytes_per_row};
+use super::grass::{self, GrassInstance, GrassInstances, GrassMask};
+
+//Chunks share one mesh, each is moved into place by its own offset and has its own height and normal
+//textures. Every chunk's texture is a window onto one endless grid of texels (see ChunkSampler), with
+//the last texel of a chunk being the first of its neighbour, so their edges meet without seams.
+
+pub const CHUNK_GRID_RADIUS: i32 = 2;
+//Extra texels around each height texture, so normals along a chunk's edge see the real neighbouring
+//heights instead of clamping and don't crease at the seam
+pub const CHUNK_APRON: u32 = 1;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ChunkUniform {
+    pub offset: [f32; 2],
+    //Blend towards the next coarser level of detail, always 0 until chunks have levels of detail
+    pub morph: f32,
+    //The seed the chunk's heights were sampled with, low half first. WGSL has no 64 bit integers.
+    pub seed: [u32; 2],
+    _padding: u32
+}
+
+//Has to match ChunkSettings in shader.wgsl
+const _: () = assert!(std::mem::size_of::<ChunkUniform>() == 24);
+
+impl ChunkUniform {
+    //extent is the chunk's size in world units. The offset is from the camera's floating origin, worked
+    //out in f64 so it stays exact however far both are from zero.
+    pub fn for_coord(coord: (i32, i32), extent: f32, origin: [f64; 2], seed: Seed) -> Self {
+        ChunkUniform {
+            offset: [
+                (coord.0 as f64 * extent as f64 - origin[0]) as f32,
+                (coord.1 as f64 * extent as f64 - origin[1]) as f32
+            ],
+            morph: 0.0,
+            seed: [seed as u32, (seed >> 32) as u32],
+            _padding: 0
+        }
+    }
+
+    pub fn seed(&self) -> Seed {
+        self.seed[0] as Seed | (self.seed[1] as Seed) << 32
+    }
+
+    pub fn set_seed(&mut self, seed: Seed) {
+        self.seed = [seed as u32, (seed >> 32) as u32];
+    }
+}
+
+//Distance between slots in ChunkUniforms' buffer. Dynamic offsets have to be multiples of the device's
+//min_uniform_buffer_offset_alignment, so each size bytes slot is padded up to one.
+pub fn uniform_stride(size: wgpu::BufferAddress, alignment: u32) -> wgpu::BufferAddress {
+    let alignment = alignment.max(1) as wgpu::BufferAddress;
+    size.div_ceil(alignment) * alignment
+}
+
+const INITIAL_UNIFORM_SLOTS: u32 = 32;
+
+//Every chunk's ChunkUniform in one buffer, bound once as group 2 and pointed at a chunk's slot with a
+//dynamic offset per draw, rather than a buffer and bind group per chunk. A chunk keeps its slot for as
+//long as it exists, in the pool or not. The whole buffer is staged once per frame.
+pub struct ChunkUniforms {
+    buffer: wgpu::Buffer,
+    pub bind_group: wgpu::BindGroup,
+    stride: wgpu::BufferAddress,
+    slots: u32,
+    //CPU copy of the buffer
+    data: Vec<u8>
+}
+
+impl ChunkUniforms {
+    fn new(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, slots: u32) -> Self {
+        let size = std::mem::size_of::<ChunkUniform>() as wgpu::BufferAddress;
+        let stride = uniform_stride(size, device.limits().min_uniform_buffer_offset_alignment);
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Chunk uniform buffer"),
+            size: stride * slots as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: &buffer,
+                        offset: 0,
+                        size: wgpu::BufferSize::new(size)
+                    })
+                }
+            ],
+            label: Some("Chunk bind group")
+        });
+
+        ChunkUniforms {
+            buffer,
+            bind_group,
+            stride,
+            slots,
+            data: vec![0; (stride * slots as wgpu::BufferAddress) as usize]
+        }
+    }
+
+    //Moves everything to a bigger buffer if slot doesn't fit
+    fn reserve(&mut self, device: &wgpu::Device, layout: &wgpu::BindGroupLayout, slot: u32) {
+        if slot < self.slots {
+            return;
+        }
+
+        let mut grown = ChunkUniforms::new(device, layout, (slot + 1).next_power_of_two());
+        grown.data[..self.data.len()].copy_from_slice(&self.data);
+        *self = grown;
+    }
+
+    pub fn offset(&self, chunk: &Chunk) -> wgpu::DynamicOffset {
+        (chunk.slot as wgpu::BufferAddress * self.stride) as wgpu::DynamicOffset
+    }
+
+    //Copies the chunks' uniforms into their slots and records the buffer's upload into encoder,
+    //ahead of the passes that draw them
+    pub fn stage<'a>(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, uploader: &mut Uploader, chunks: impl Iterator<Item = &'a Chunk>) {
+        let size = std::mem::size_of::<ChunkUniform>();
+
+        for chunk in chunks {
+            let start = self.offset(chunk) as usize;
+            self.data[start..start + size].copy_from_slice(bytemuck::bytes_of(&chunk.uniform));
+        }
+
+        uploader.write_buffer(device, encoder, &self.buffer, 0, &self.data);
+    }
+}
+
+//Zeroed height data for a size x size texture, with rows padded for write_texture. Returns the data and its row stride in texels.
+pub fn noise_buffer(size: u32) -> (Vec<f32>, usize) {
+    let pixel_size = std::mem::size_of::<f32>() as u32;
+    let padded_bytes_per_row = padded_bytes_per_row(size, pixel_size);
+
+    if !padded_bytes_per_row.is_multiple_of(pixel_size) {
+        panic!("Padded bytes per row is not a multiple of pixel size");
+    }
+
+    let stride = (padded_bytes_per_row / pixel_size) as usize;
+    (vec![0.0; stride * size as usize], stride)
+}
+
+//Storage for the height textures, best first. The terrain shader samples them with a filtering sampler,
+//and R32Float is only filterable on some adapters (never on WebGL2), so heights fall back to a format
+//with less precision that is. Heights are in [0, 1], the Unorm formats store them as is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeightFormat {
+    R32Float,
+    R16Float,
+    R16Unorm,
+    R8Unorm
+}
+
+impl HeightFormat {
+    //The first format the device can filter. R8Unorm always can, but it terraces visibly.
+    pub fn choose(adapter: &wgpu::Adapter, device: &wgpu::Device) -> Self {
+        let features = device.features();
+        let filterable = |format: HeightFormat| {
+            let format = format.texture_format();
+            if !features.contains(format.required_features()) {
+                return false;
+            }
+
+            //Adapter specific capabilities can only be used with the feature enabled
+            let format_features = if features.contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES) {
+                adapter.get_texture_format_features(format)
+            } else {
+                format.guaranteed_format_features(features)
+            };
+
+            format_features.flags.contains(wgpu::TextureFormatFeatureFlags::FILTERABLE)
+                && format_features.allowed_usages.contains(wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST)
+        };
+
+        [HeightFormat::R32Float, HeightFormat::R16Float, HeightFormat::R16Unorm]
+            .iter()
+            .copied()
+            .find(|&format| filterable(format))
+            .unwrap_or(HeightFormat::R8Unorm)
+    }
+
+    pub fn texture_format(self) -> wgpu::TextureFormat {
+        match self {
+            HeightFormat::R32Float => wgpu::TextureFormat::R32Float,
+            HeightFormat::R16Float => wgpu::TextureFormat::R16Float,
+            HeightFormat::R16Unorm => wgpu::TextureFormat::R16Unorm,
+            HeightFormat::R8Unorm => wgpu::TextureFormat::R8Unorm
+        }
+    }
+
+    fn bytes_per_texel(self) -> u32 {
+        match self {
+            HeightFormat::R32Float => 4,
+            HeightFormat::R16Float | HeightFormat::R16Unorm => 2,
+            HeightFormat::R8Unorm => 1
+        }
+    }
+
+    //Scale and bias turning a sampled texel back into a height, for settings.height_decode
+    pub fn decode(self) -> [f32; 2] {
+        [1.0, 0.0]
+    }
+
+    //Quantizes size x size heights with the given row stride into texel bytes, rows padded for
+    //write_texture. Returns the bytes and bytes per row.
+    pub fn encode(self, heights: &[f32], stride: usize, size: u32) -> (Vec<u8>, u32) {
+        let [scale, bias] = self.decode();
+        let unpadded_bytes_per_row = self.bytes_per_texel() * size;
+        let bytes_per_row = padded_bytes_per_row(size, self.bytes_per_texel());
+
+        let mut data = vec![0; (bytes_per_row * size) as usize];
+        for (row, texels) in data.chunks_exact_mut(bytes_per_row as usize).enumerate() {
+            let values = heights[row * stride..row * stride + size as usize].iter().map(|&height| (height - bias) / scale);
+            let texels = &mut texels[..unpadded_bytes_per_row as usize];
+
+            match self {
+                HeightFormat::R32Float => {
+                    for (bytes, value) in texels.chunks_exact_mut(4).zip(values) {
+                        bytes.copy_from_slice(&value.to_le_bytes());
+                    }
+                },
+                HeightFormat::R16Float => {
+                    for (bytes, value) in texels.chunks_exact_mut(2).zip(values) {
+                        bytes.copy_from_slice(&f32_to_f16_bits(value).to_le_bytes());
+                    }
+                },
+                HeightFormat::R16Unorm => {
+                    for (bytes, value) in texels.chunks_exact_mut(2).zip(values) {
+                        bytes.copy_from_slice(&((value.clamp(0.0, 1.0) * 65535.0).round() as u16).to_le_bytes());
+                    }
+                },
+                HeightFormat::R8Unorm => {
+                    for (byte, value) in texels.iter_mut().zip(values) {
+                        *byte = (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+                    }
+                }
+            }
+        }
+
+        (data, bytes_per_row)
+    }
+
+    //The inverse of encode, for size x size texels read back with rows bytes_per_row apart. Returns
+    //the heights packed without padding, as close to the originals as the format kept them.
+    pub fn decode_texels(self, data: &[u8], size: u32, bytes_per_row: u32) -> Vec<f32> {
+        let [scale, bias] = self.decode();
+        let unpadded_bytes_per_row = (self.bytes_per_texel() * size) as usize;
+
+        let mut heights = Vec::with_capacity((size * size) as usize);
+        for row in data.chunks(bytes_per_row as usize).take(size as usize) {
+            let texels = &row[..unpadded_bytes_per_row];
+
+            match self {
+                HeightFormat::R32Float => heights.extend(texels.chunks_exact(4).map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))),
+                HeightFormat::R16Float => heights.extend(texels.chunks_exact(2).map(|bytes| f16_bits_to_f32(u16::from_le_bytes([bytes[0], bytes[1]])))),
+                HeightFormat::R16Unorm => heights.extend(texels.chunks_exact(2).map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 65535.0)),
+                HeightFormat::R8Unorm => heights.extend(texels.iter().map(|&byte| byte as f32 / 255.0))
+            }
+        }
+
+        heights.iter_mut().for_each(|height| *height = *height * scale + bias);
+        heights
+    }
+}
+
+fn f16_bits_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15)
+    }
+}
+
+//Rounds to the nearest half float. Out of range values become infinity and NaN stays NaN.
+fn f32_to_f16_bits(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exponent == 0xff {
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+
+    if exponent <= 0 {
+        //Subnormal, or too small for even that
+        if exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - exponent) as u32;
+        let half = mantissa >> shift;
+        let round = (mantissa >> (shift - 1)) & 1;
+        return sign | (half + round) as u16;
+    }
+
+    //Rounding can carry into the exponent, which is still the right answer
+    let half = ((exponent as u32) << 10) | (mantissa >> 13);
+    let round = (mantissa >> 12) & 1;
+    sign | (half + round) as u16
+}
+
+//Maps a chunk's texels onto the endless texel grid the noise is sampled on. Chunk (x, z) starts at
+//texel (x, z) * (tex_size - 1), so neighbours share a row of texels and agree on it exactly.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChunkSampler {
+    //Texels from uv 0 to uv 1, not counting the apron
+    pub tex_size: u32,
+    pub noise_frequency: Coord,
+    pub seed: Seed,
+    //How much of each chunk's own pattern is mixed in, see displacement::chunk_texel_value
+    pub variation: f32
+}
+
+impl ChunkSampler {
+    pub fn texture_size(&self) -> u32 {
+        self.tex_size + 2 * CHUNK_APRON
+    }
+
+    //Texel the chunk's uv 0 lands on
+    pub fn origin(&self, coord: (i32, i32)) -> (i64, i64) {
+        let step = (self.tex_size - 1) as i64;
+        (coord.0 as i64 * step, coord.1 as i64 * step)
+    }
+
+    //Fills rows of a chunk's texture, apron included, into data laid out like noise_buffer
+    pub fn fill_rows(&self, source: &dyn NoiseSource, coord: (i32, i32), data: &mut [f32], stride: usize, rows: std::ops::Range<u32>) {
+        let (origin_x, origin_y) = self.origin(coord);
+        let apron = CHUNK_APRON as i64;
+
+        for y in rows {
+            for x in 0..self.texture_size() {
+                data[stride * y as usize + x as usize] = displacement::chunk_texel_value(
+                    source,
+                    self.noise_frequency,
+                    self.seed,
+                    self.variation,
+                    (self.tex_size - 1) as i64,
+                    origin_x + x as i64 - apron,
+                    origin_y + y as i64 - apron
+                );
+            }
+        }
+    }
+}
+
+//How the terrain shaders filter the height and normal textures
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureFilter {
+    Smooth,
+    //Nearest texel, blocky terraces and flat-shaded squares
+    Pixelated
+}
+
+impl TextureFilter {
+    pub fn next(self) -> Self {
+        match self {
+            TextureFilter::Smooth => TextureFilter::Pixelated,
+            TextureFilter::Pixelated => TextureFilter::Smooth
+        }
+    }
+
+    pub fn filter_mode(self) -> wgpu::FilterMode {
+        match self {
+            TextureFilter::Smooth => wgpu::FilterMode::Linear,
+            TextureFilter::Pixelated => wgpu::FilterMode::Nearest
+        }
+    }
+
+    pub fn create_sampler(self, device: &wgpu::Device) -> wgpu::Sampler {
+        device.create_sampler(
+            &wgpu::SamplerDescriptor {
+                label: Some("Noise sampler"),
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                address_mode_w: wgpu::AddressMode::ClampToEdge,
+                mag_filter: self.filter_mode(),
+                min_filter: self.filter_mode(),
+                //Smooth is trilinear, blending between mip levels too
+                mipmap_filter: self.filter_mode(),
+                ..Default::default()
+            }
+        )
+    }
+}
+
+pub struct Chunk {
+    pub coord: (i32, i32),
+    //Where the chunk's uniform lives in ChunkUniforms
+    slot: u32,
+    uniform: ChunkUniform,
+
+    height_texture: wgpu::Texture,
+    height_view: wgpu::TextureView,
+    format: HeightFormat,
+    normal_map: NormalMap,
+    mips: Rc<MipGenerator>,
+    //Group 1 of every scene pipeline, the chunk's height and normal textures
+    pub texture_bind_group: wgpu::BindGroup,
+
+    //A copy of the heights, only kept when the pool is baking vertices (see VertexHeights::Baked)
+    heights: Option<(Vec<f32>, usize)>,
+    keep_heights: bool,
+    pub baked: Option<BakedBuffers>,
+    //Scattered whenever the chunk is filled, see ChunkPool::scatter_grass
+    pub grass: GrassInstances
+}
+
+impl Chunk {
+    //Writes heights filled by ChunkSampler for coord. It's drawn there after the next set_origin.
+    //Returns the bytes written to the height texture.
+    pub fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, coord: (i32, i32), seed: Seed, heights: Vec<f32>, stride: usize) -> u64 {
+        self.coord = coord;
+        self.uniform.set_seed(seed);
+        self.heights = self.keep_heights.then(|| (heights.clone(), stride));
+        self.baked = None;
+
+        let (texels, bytes_per_row) = self.format.encode(&heights, stride, self.height_texture.width());
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.height_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &texels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(bytes_per_row),
+                rows_per_image: Some(self.height_texture.height()),
+            },
+            self.height_texture.size()
+        );
+        self.mips.generate(device, queue, &self.height_texture);
+
+        self.normal_map.set_heights(device, queue, heights, stride);
+
+        texels.len() as u64
+    }
+
+    //Copies the height texture back to the CPU. Resolves once the copy has been mapped, which is after
+    //the frame loop's next submit at the earliest.
+    pub fn read_heights(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> impl std::future::Future<Output = Result<Heightmap, wgpu::BufferAsyncError>> {
+        let size = self.height_texture.width();
+        let bytes_per_row = padded_bytes_per_row(size, self.format.bytes_per_texel());
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Height readback buffer"),
+            size: (bytes_per_row * size) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Height readback encoder")
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.height_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(bytes_per_row),
+                    rows_per_image: Some(size),
+                },
+            },
+            self.height_texture.size()
+        );
+        queue.submit(Some(encoder.finish()));
+
+        let (coord, format) = (self.coord, self.format);
+        async move {
+            screenshot::map_read(&buffer).await?;
+
+            let heights = {
+                let data = buffer.slice(..).get_mapped_range();
+                format.decode_texels(&data, size, bytes_per_row)
+            };
+            buffer.unmap();
+
+            Ok(Heightmap { coord, size, apron: CHUNK_APRON, heights })
+        }
+    }
+
+    //Where the chunk's corner is drawn, relative to the floating origin
+    pub fn offset(&self) -> [f32; 2] {
+        self.uniform.offset
+    }
+
+    //Redraws the chunk in the same place relative to a new floating origin, from the next ChunkUniforms::stage
+    pub fn set_origin(&mut self, extent: f32, origin: [f64; 2]) {
+        self.uniform = ChunkUniform::for_coord(self.coord, extent, origin, self.uniform.seed());
+    }
+
+    pub fn set_height_scale(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, height_scale: f32) {
+        self.normal_map.set_height_scale(device, queue, height_scale);
+    }
+
+    //Bakes the mesh against the chunk's heights unless it's already baked for this version.
+    //Does nothing until the chunk has been uploaded with heights kept.
+    pub fn bake(&mut self, device: &wgpu::Device, mesh: &MeshShape, version: u64, height_scale: f32, unindexed: bool) {
+        let Some((heights, stride)) = &self.heights else {
+            return;
+        };
+        if self.baked.as_ref().is_some_and(|baked| baked.version == version) {
+            return;
+        }
+
+        let tex_size = self.height_texture.width() - 2 * CHUNK_APRON;
+        let vertices = mesh.bake(heights, *stride, tex_size, CHUNK_APRON, height_scale);
+        self.baked = Some(BakedBuffers::new(device, mesh, &vertices, unindexed, version));
+    }
+
+    fn set_keep_heights(&mut self, keep: bool) {
+        self.keep_heights = keep;
+        if !keep {
+            self.heights = None;
+            self.baked = None;
+        }
+    }
+
+    //Rebuilds the texture bind group, for when the pool's sampler changes
+    fn bind_textures(&mut self, device: &wgpu::Device, layout: &wgpu::BindGroupLayout, sampler: &wgpu::Sampler) {
+        self.texture_bind_group = texture_bind_group(device, layout, &self.height_view, sampler, &self.normal_map.view);
+    }
+}
+
+fn texture_bind_group(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, height_view: &wgpu::TextureView, sampler: &wgpu::Sampler, normal_view: &wgpu::TextureView) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(height_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(normal_view),
+            },
+        ],
+        label: Some("Noise texture bind group"),
+    })
+}
+
+//What every chunk has in common, and the chunks that aren't in use. Textures are big, so chunks that
+//scroll out of view are kept here and refilled rather than dropped and reallocated.
+pub struct ChunkPool {
+    pub uniform_layout: wgpu::BindGroupLayout,
+    pub uniforms: ChunkUniforms,
+    next_slot: u32,
+    pub texture_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    filter: TextureFilter,
+    mips: Rc<MipGenerator>,
+    //Height and normal texture size, apron included
+    texture_size: u32,
+    format: HeightFormat,
+    //World units between texels, for the normals
+    texel_size: f32,
+    use_compute: bool,
+    height_scale: f32,
+    keep_heights: bool,
+    grass_mask: GrassMask,
+
+    free: Vec<Chunk>
+}
+
+impl ChunkPool {
+    pub fn new(device: &wgpu::Device, texture_size: u32, format: HeightFormat, texel_size: f32, use_compute: bool) -> Self {
+        let uniform_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<ChunkUniform>() as wgpu::BufferAddress)
+                    },
+                    count: None
+                }
+            ],
+            label: Some("Chunk bind group layout")
+        });
+        let uniforms = ChunkUniforms::new(device, &uniform_layout, INITIAL_UNIFORM_SLOTS);
+
+        let texture_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false
+                    },
+                    count: None,
+                }
+            ],
+            label: Some("Noise texture bind group layout"),
+        });
+
+        let filter = TextureFilter::Smooth;
+        let sampler = filter.create_sampler(device);
+        let mips = Rc::new(MipGenerator::new(device, &[format.texture_format(), NORMAL_FORMAT]));
+
+        ChunkPool {
+            uniform_layout,
+            uniforms,
+            next_slot: 0,
+            texture_layout,
+            sampler,
+            filter,
+            mips,
+            texture_size,
+            format,
+            texel_size,
+            use_compute,
+            height_scale: 1.0,
+            keep_heights: false,
+            grass_mask: GrassMask::default(),
+
+            free: Vec::new()
+        }
+    }
+
+    //A chunk with whatever it last held, call Chunk::upload before drawing it
+    pub fn take(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> Chunk {
+        match self.free.pop() {
+            Some(chunk) => chunk,
+            None => self.create(device, queue)
+        }
+    }
+
+    pub fn give_back(&mut self, mut chunk: Chunk) {
+        chunk.set_keep_heights(self.keep_heights);
+        self.free.push(chunk);
+    }
+
+    //Free chunks are brought up to date too, so they come back out with the right normals
+    pub fn set_height_scale(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, height_scale: f32) {
+        self.height_scale = height_scale;
+
+        for chunk in &mut self.free {
+            chunk.set_height_scale(device, queue, height_scale);
+        }
+    }
+
+    //Chunks only hold on to their heights when they're kept, and they only get them on the next upload
+    pub fn set_keep_heights(&mut self, keep: bool) {
+        self.keep_heights = keep;
+
+        for chunk in &mut self.free {
+            chunk.set_keep_heights(keep);
+        }
+    }
+
+    pub fn keep_heights(&self) -> bool {
+        self.keep_heights
+    }
+
+    //Blades for a chunk about to be uploaded with these heights. Slopes are judged at the current height
+    //scale, chunks filled before it changed keep the blades they had.
+    pub fn scatter_grass(&self, coord: (i32, i32), seed: Seed, heights: &[f32], stride: usize) -> Vec<GrassInstance> {
+        let tex_size = self.texture_size - 2 * CHUNK_APRON;
+        grass::scatter(heights, stride, tex_size, self.texel_size, self.height_scale, &self.grass_mask, coord, seed)
+    }
+
+    pub fn filter(&self) -> TextureFilter {
+        self.filter
+    }
+
+    //Only rebinds the free chunks, the ones in use go through rebind
+    pub fn set_filter(&mut self, device: &wgpu::Device, filter: TextureFilter) {
+        self.filter = filter;
+        self.sampler = filter.create_sampler(device);
+
+        for chunk in &mut self.free {
+            chunk.bind_textures(device, &self.texture_layout, &self.sampler);
+        }
+    }
+
+    //Points a chunk at the current sampler
+    pub fn rebind(&self, device: &wgpu::Device, chunk: &mut Chunk) {
+        chunk.bind_textures(device, &self.texture_layout, &self.sampler);
+    }
+
+    fn create(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> Chunk {
+        let slot = self.next_slot;
+        self.next_slot += 1;
+        self.uniforms.reserve(device, &self.uniform_layout, slot);
+
+        //Far away terrain shimmers without mips, the fragment shader samples a texel or more apart per pixel
+        let mip_level_count = self.mips.level_count(self.format.texture_format(), self.texture_size);
+        //COPY_SRC for read_heights
+        let mut usage = wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::COPY_SRC;
+        if mip_level_count > 1 {
+            usage |= wgpu::TextureUsages::RENDER_ATTACHMENT;
+        }
+
+        let height_texture = device.create_texture(&wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: self.texture_size,
+                height: self.texture_size,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format.texture_format(),
+            usage,
+            label: Some("Noise texture"),
+            view_formats: &[]
+        });
+        let height_view = height_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let mut normal_map = NormalMap::new(device, &height_view, self.texture_size, self.texel_size, self.use_compute, self.mips.clone());
+        normal_map.set_height_scale(device, queue, self.height_scale);
+
+        let texture_bind_group = texture_bind_group(device, &self.texture_layout, &height_view, &self.sampler, &normal_map.view);
+
+        Chunk {
+            coord: (0, 0),
+            slot,
+            uniform: ChunkUniform::for_coord((0, 0), 0.0, [0.0; 2], 0),
+
+            height_texture,
+            height_view,
+            format: self.format,
+            normal_map,
+            mips: self.mips.clone(),
+            texture_bind_group,
+
+            heights: None,
+            keep_heights: self.keep_heights,
+            baked: None,
+            grass: GrassInstances::new(device)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    fn stride_rounds_up_to_the_alignment() {
+        let size = std::mem::size_of::<ChunkUniform>() as wgpu::BufferAddress;
+
+        //256 is WebGPU's default limit, some native adapters allow less
+        assert_eq!(uniform_stride(size, 256), 256);
+        assert_eq!(uniform_stride(size, 64), 64);
+        assert_eq!(uniform_stride(size, 16), 32);
+        assert_eq!(uniform_stride(size, 8), 24);
+    }
+
+    #[wasm_bindgen_test]
+    fn stride_keeps_exact_multiples() {
+        assert_eq!(uniform_stride(256, 256), 256);
+        assert_eq!(uniform_stride(257, 256), 512);
+        assert_eq!(uniform_stride(24, 0), 24);
+    }
+
+    #[wasm_bindgen_test]
+    fn every_slot_offset_is_aligned() {
+        for alignment in [16, 32, 64, 128, 256] {
+            let stride = uniform_stride(std::mem::size_of::<ChunkUniform>() as wgpu::BufferAddress, alignment);
+
+            for slot in 0..INITIAL_UNIFORM_SLOTS as wgpu::BufferAddress {
+                assert_eq!(slot * stride % alignment as wgpu::BufferAddress, 0);
+            }
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn heights_survive_encoding_within_quantization() {
+        let size = 5;
+        let stride = 7;
+        let heights: Vec<f32> = (0..stride * size as usize).map(|i| (i as f32 * 0.37).sin() * 0.5 + 0.5).collect();
+
+        for (format, tolerance) in [(HeightFormat::R32Float, 0.0), (HeightFormat::R16Float, 1e-3), (HeightFormat::R16Unorm, 1.0 / 65535.0), (HeightFormat::R8Unorm, 1.0 / 255.0)] {
+            let (data, bytes_per_row) = format.encode(&heights, stride, size);
+            assert_eq!(bytes_per_row % wgpu::COPY_BYTES_PER_ROW_ALIGNMENT, 0);
+
+            let decoded = format.decode_texels(&data, size, bytes_per_row);
+            assert_eq!(decoded.len(), (size * size) as usize);
+
+            for y in 0..size as usize {
+                for x in 0..size as usize {
+                    let (original, read) = (heights[y * stride + x], decoded[y * size as usize + x]);
+                    assert!((original - read).abs() <= tolerance, "{:?} at {}, {}: {} became {}", format, x, y, original, read);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use super::event::KeyboardKey;
+
+pub const TIME_SLOWER_KEY: KeyboardKey = KeyboardKey::Character('[');
+pub const TIME_FASTER_KEY: KeyboardKey = KeyboardKey::Character(']');
+pub const TIME_FREEZE_KEY: KeyboardKey = KeyboardKey::Character('t');
+
+pub const MAX_TIME_SCALE: f64 = 4.0;
+pub const TIME_SCALE_STEP: f64 = 0.25;
+//Longest step world time takes in one frame, in seconds
+const MAX_STEP: f64 = 0.25;
+
+//World time for animated effects, separate from wall-clock time so it can be slowed down or frozen
+//without affecting the camera
+pub struct WorldClock {
+    time: f64,
+    scale: f64,
+    //Scale to go back to when unfreezing
+    frozen_scale: Option<f64>
+}
+
+impl WorldClock {
+    pub fn new() -> Self {
+        WorldClock {
+            time: 0.0,
+            scale: 1.0,
+            frozen_scale: None
+        }
+    }
+
+    //Seconds of world time so far
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    pub fn scale(&self) -> f64 {
+        self.scale
+    }
+
+    //0 freezes time, 1 is real time
+    pub fn set_scale(&mut self, scale: f64) {
+        self.scale = if scale.is_finite() { scale.clamp(0.0, MAX_TIME_SCALE) } else { 1.0 };
+        self.frozen_scale = None;
+    }
+
+    pub fn toggle_freeze(&mut self) {
+        match self.frozen_scale.take() {
+            Some(scale) => self.scale = scale,
+            None => {
+                self.frozen_scale = Some(self.scale);
+                self.scale = 0.0;
+            }
+        }
+    }
+
+    //dt is wall-clock seconds, returns how far world time moved
+    pub fn advance(&mut self, dt: f64) -> f64 {
+        let dt = if dt.is_finite() { dt.clamp(0.0, MAX_STEP) } else { 0.0 };
+        let step = dt * self.scale;
+
+        self.time += step;
+        step
+    }
+}
+
+impl Default for WorldClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    fn time_follows_the_scale() {
+        let mut clock = WorldClock::new();
+        clock.set_scale(0.5);
+
+        assert_eq!(clock.advance(0.1), 0.05);
+        clock.advance(0.1);
+        assert!((clock.time() - 0.1).abs() < 1e-12);
+    }
+
+    #[wasm_bindgen_test]
+    fn huge_steps_are_clamped() {
+        let mut clock = WorldClock::new();
+
+        assert_eq!(clock.advance(1000.0), MAX_STEP);
+        assert_eq!(clock.advance(-1.0), 0.0);
+        assert_eq!(clock.advance(f64::INFINITY), 0.0);
+        assert_eq!(clock.time(), MAX_STEP);
+    }
+
+    #[wasm_bindgen_test]
+    fn scale_stays_in_range() {
+        let mut clock = WorldClock::new();
+
+        clock.set_scale(100.0);
+        assert_eq!(clock.scale(), MAX_TIME_SCALE);
+        clock.set_scale(-1.0);
+        assert_eq!(clock.scale(), 0.0);
+        clock.set_scale(f64::NAN);
+        assert_eq!(clock.scale(), 1.0);
+    }
+
+    #[wasm_bindgen_test]
+    fn freezing_stops_time_and_restores_the_scale() {
+        let mut clock = WorldClock::new();
+        clock.set_scale(2.0);
+
+        clock.toggle_freeze();
+        assert_eq!(clock.advance(0.1), 0.0);
+        assert_eq!(clock.time(), 0.0);
+
+        clock.toggle_freeze();
+        assert_eq!(clock.scale(), 2.0);
+    }
+}
@@ -0,0 +1,131 @@
+use serde::{Deserialize, Serialize};
+
+//Texels across the lookup texture. It's 2D with one row, WebGL2 has no 1D textures.
+pub const RAMP_WIDTH: u32 = 256;
+
+//Colours are linear RGB, heights are normalised (0..1 before height_scale)
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ColorStop {
+    pub height: f32,
+    pub color: [f32; 3]
+}
+
+//Terrain colour by height, blended linearly between stops. Serialized as just the list of stops.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ColorRamp {
+    pub stops: Vec<ColorStop>
+}
+
+impl Default for ColorRamp {
+    fn default() -> Self {
+        let stop = |height: f32, color: [f32; 3]| ColorStop { height, color };
+
+        ColorRamp {
+            stops: vec![
+                //Deep and shallow water
+                stop(0.0, [0.01, 0.03, 0.12]),
+                stop(0.3, [0.03, 0.12, 0.3]),
+                //Sand
+                stop(0.33, [0.55, 0.48, 0.3]),
+                stop(0.36, [0.5, 0.43, 0.27]),
+                //Grass
+                stop(0.4, [0.1, 0.3, 0.05]),
+                stop(0.6, [0.05, 0.15, 0.03]),
+                //Rock
+                stop(0.72, [0.2, 0.17, 0.15]),
+                stop(0.82, [0.3, 0.28, 0.27]),
+                //Snow
+                stop(0.88, [0.9, 0.9, 0.95])
+            ]
+        }
+    }
+}
+
+impl ColorRamp {
+    //Stops don't have to be in order. Heights outside the stops take the nearest one's colour, an empty ramp is white.
+    pub fn color_at(&self, height: f32) -> [f32; 3] {
+        let below = self.stops.iter().filter(|stop| stop.height <= height).max_by(|a, b| a.height.total_cmp(&b.height));
+        let above = self.stops.iter().filter(|stop| stop.height > height).min_by(|a, b| a.height.total_cmp(&b.height));
+
+        match (below, above) {
+            (Some(below), Some(above)) => {
+                let t = (height - below.height) / (above.height - below.height);
+                std::array::from_fn(|i| below.color[i] + (above.color[i] - below.color[i]) * t)
+            },
+            (Some(stop), None) | (None, Some(stop)) => stop.color,
+            (None, None) => [1.0; 3]
+        }
+    }
+
+    //RGBA8 texels for an sRGB texture, sampling it gives back linear colours
+    pub fn texels(&self) -> Vec<u8> {
+        let encode = |c: f32| (super::wgpu_context::encode_srgb(c) * 255.0).round() as u8;
+
+        (0..RAMP_WIDTH).flat_map(|i| {
+            let [r, g, b] = self.color_at(i as f32 / (RAMP_WIDTH - 1) as f32);
+            [encode(r), encode(g), encode(b), 255]
+        }).collect()
+    }
+}
+
+pub struct ColorRampTexture {
+    texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler
+}
+
+impl ColorRampTexture {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, ramp: &ColorRamp) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Color ramp texture"),
+            size: wgpu::Extent3d {
+                width: RAMP_WIDTH,
+                height: 1,
+                depth_or_array_layers: 1
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[]
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Color ramp sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let ramp_texture = ColorRampTexture { texture, view, sampler };
+        ramp_texture.upload(queue, ramp);
+
+        ramp_texture
+    }
+
+    //Same size every time, so the bind group holding the view stays valid
+    pub fn upload(&self, queue: &wgpu::Queue, ramp: &ColorRamp) {
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &ramp.texels(),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(RAMP_WIDTH * 4),
+                rows_per_image: Some(1),
+            },
+            self.texture.size()
+        );
+    }
+}
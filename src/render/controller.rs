@@ -0,0 +1,201 @@
+use cgmath::{InnerSpace, Vector3, Zero};
+
+use super::{camera::{Camera, Easing}, event::KeyboardKey, input::InputState};
+
+pub const INVERT_Y_KEY: KeyboardKey = KeyboardKey::Character('i');
+pub const SPRINT_KEY: KeyboardKey = KeyboardKey::Character('f');
+
+//Widening the view while sprinting makes the extra speed feel like speed
+#[derive(Clone, Copy, Debug)]
+pub struct SprintConfig {
+    pub speed_multiplier: f32,
+    //Degrees added to fovy while the key is held
+    pub fov_boost: f32,
+    //Seconds to ease into the boost and back out of it
+    pub ease_in: f32,
+    pub ease_out: f32,
+    pub easing: Easing
+}
+
+impl Default for SprintConfig {
+    fn default() -> Self {
+        SprintConfig {
+            speed_multiplier: 3.0,
+            fov_boost: 10.0,
+            ease_in: 0.25,
+            ease_out: 0.4,
+            easing: Easing::EaseOut
+        }
+    }
+}
+
+pub struct CameraController {
+    pub speed: f32,
+    pub look_sensitivity: f32,
+    pub wheel_sensitivity: f32,
+    pub invert_y: bool,
+
+    //Rates (per second) at which velocity approaches the input and decays without it
+    pub acceleration: f32,
+    pub friction: f32,
+
+    pub sprint: SprintConfig,
+    //The fov to go back to, only set while sprinting
+    sprint_base_fov: Option<f32>,
+
+    //In camera space: x = right, y = up, z = forward
+    velocity: Vector3<f32>
+}
+
+impl CameraController {
+    pub fn new() -> Self {
+        CameraController {
+            speed: 0.5,
+            look_sensitivity: 0.002,
+            wheel_sensitivity: 0.001,
+            invert_y: false,
+
+            acceleration: 10.0,
+            friction: 6.0,
+
+            sprint: SprintConfig::default(),
+            sprint_base_fov: None,
+
+            velocity: Vector3::zero()
+        }
+    }
+
+    //Returns the (yaw, pitch) change caused by the look input
+    pub fn look_delta(&self, input: &InputState) -> (f32, f32) {
+        let pitch_sign = if self.invert_y { 1.0 } else { -1.0 };
+
+        (
+            input.look_x * self.look_sensitivity,
+            pitch_sign * input.look_y * self.look_sensitivity
+        )
+    }
+
+    pub fn velocity(&self) -> Vector3<f32> {
+        self.velocity
+    }
+
+    //Exponential approach so the result doesn't depend on how dt is split into frames
+    pub fn step_velocity(&mut self, target: Vector3<f32>, dt: f32) {
+        if target.is_zero() {
+            self.velocity *= (-self.friction * dt).exp();
+        } else {
+            self.velocity += (target - self.velocity) * (1.0 - (-self.acceleration * dt).exp());
+        }
+
+        //Friction never quite reaches zero on its own
+        if self.velocity.magnitude2() < 1e-8 {
+            self.velocity = Vector3::zero();
+        }
+    }
+
+    //Returns whether the camera moved
+    pub fn update(&mut self, camera: &mut Camera, input: &InputState, dt: f32) -> bool {
+        if input.was_pressed(INVERT_Y_KEY) {
+            self.invert_y = !self.invert_y;
+        }
+
+        //Scrolling up speeds up movement, scrolling down slows it down
+        self.speed = (self.speed * (-input.wheel * self.wheel_sensitivity).exp()).clamp(0.05, 50.0);
+
+        let (yaw_delta, pitch_delta) = self.look_delta(input);
+        camera.yaw += yaw_delta;
+        camera.set_pitch(camera.pitch() + pitch_delta);
+
+        let sprinting = input.is_held(SPRINT_KEY);
+        self.update_sprint_fov(camera, sprinting);
+        let fov_changed = camera.update_fov(dt);
+
+        let speed = if sprinting { self.speed * self.sprint.speed_multiplier } else { self.speed };
+        let target = Vector3::new(input.right, input.up, input.forward) * speed;
+        self.step_velocity(target, dt);
+
+        camera.do_move(self.velocity.z * dt, self.velocity.x * dt, self.velocity.y * dt);
+
+        yaw_delta != 0.0 || pitch_delta != 0.0 || !self.velocity.is_zero() || fov_changed
+    }
+
+    //Starts the fov on its way out when sprinting starts, and back when it stops
+    fn update_sprint_fov(&mut self, camera: &mut Camera, sprinting: bool) {
+        match (sprinting, self.sprint_base_fov) {
+            (true, None) => {
+                let base = camera.fov_target();
+                self.sprint_base_fov = Some(base);
+                camera.animate_fov(base + self.sprint.fov_boost, self.sprint.ease_in, self.sprint.easing);
+            },
+            (false, Some(base)) => {
+                self.sprint_base_fov = None;
+                camera.animate_fov(base, self.sprint.ease_out, self.sprint.easing);
+            },
+            _ => {}
+        }
+    }
+}
+
+impl Default for CameraController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    fn looking(look_x: f32, look_y: f32) -> InputState {
+        let mut input = InputState::idle();
+        input.look_x = look_x;
+        input.look_y = look_y;
+        input
+    }
+
+    #[wasm_bindgen_test]
+    fn inversion_flips_the_pitch_delta() {
+        let mut controller = CameraController::new();
+        let input = looking(5.0, 10.0);
+
+        let (yaw, pitch) = controller.look_delta(&input);
+        controller.invert_y = true;
+        let (inverted_yaw, inverted_pitch) = controller.look_delta(&input);
+
+        assert!(pitch < 0.0);
+        assert_eq!(inverted_pitch, -pitch);
+        assert_eq!(inverted_yaw, yaw);
+    }
+
+    #[wasm_bindgen_test]
+    fn velocity_decays_after_input_stops() {
+        let mut controller = CameraController::new();
+        let dt = 1.0 / 60.0;
+
+        for _ in 0..60 {
+            controller.step_velocity(Vector3::new(0.0, 0.0, 1.0), dt);
+        }
+        assert!(controller.velocity().z > 0.9);
+
+        //Two seconds without input
+        for _ in 0..120 {
+            controller.step_velocity(Vector3::zero(), dt);
+        }
+        assert!(controller.velocity().magnitude() < 1e-4);
+    }
+
+    #[wasm_bindgen_test]
+    fn velocity_does_not_depend_on_frame_rate() {
+        let (mut fast, mut slow) = (CameraController::new(), CameraController::new());
+        let target = Vector3::new(1.0, 0.0, 0.0);
+
+        for _ in 0..4 {
+            fast.step_velocity(target, 0.025);
+        }
+        slow.step_velocity(target, 0.1);
+
+        assert!((fast.velocity() - slow.velocity()).magnitude() < 1e-5);
+    }
+}
@@ -0,0 +1,229 @@
+use crate::noise::{perm::splitmix64, source::{Coord, NoiseSource, Seed}};
+
+//The single definition of terrain height, for anything on the CPU (collision, picking) that has to
+//agree with what's drawn. shader.wgsl mirrors it step for step: sample_height is sample_world within one chunk,
+//the textures hold chunk_texel_value, vs_main multiplies by height_scale and adds detail. Change both together.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Displacement {
+    pub tex_size: u32,
+    //Mesh size in world units + 1, the chunk's uv spans [0, chunk_size - 1]
+    pub chunk_size: u32,
+    pub noise_frequency: Coord,
+    pub seed: Seed,
+    //See chunk_texel_value
+    pub chunk_variation: f32,
+    pub height_scale: f32,
+    pub detail_strength: f32,
+    pub detail_frequency: f32
+}
+
+impl Displacement {
+    pub fn texel_value(&self, source: &dyn NoiseSource, x: i64, y: i64) -> f32 {
+        chunk_texel_value(source, self.noise_frequency, self.seed, self.chunk_variation, self.tex_size as i64 - 1, x, y)
+    }
+
+    //Texture value at a world position, blending the four surrounding texels. Every chunk's texture is a
+    //window onto the same endless grid of texels (see ChunkSampler), so this needs no chunk lookup.
+    pub fn sample_world(&self, source: &dyn NoiseSource, x: f32, z: f32) -> f32 {
+        let texels_per_unit = (self.tex_size - 1) as f32 / (self.chunk_size - 1) as f32;
+        let (px, py) = (x * texels_per_unit, z * texels_per_unit);
+        let (tx, ty) = (px.floor(), py.floor());
+        let (fx, fy) = (px - tx, py - ty);
+        let (x, y) = (tx as i64, ty as i64);
+
+        let mix = |a: f32, b: f32, t: f32| a + (b - a) * t;
+        let texel = |x: i64, y: i64| self.texel_value(source, x, y);
+
+        mix(
+            mix(texel(x, y), texel(x + 1, y), fx),
+            mix(texel(x, y + 1), texel(x + 1, y + 1), fx),
+            fy
+        )
+    }
+
+    //World height of the terrain at a world position, in any chunk
+    pub fn height_at(&self, source: &dyn NoiseSource, x: f32, z: f32) -> f32 {
+        self.sample_world(source, x, z) * self.height_scale + self.detail(x, z)
+    }
+
+    pub fn detail(&self, x: f32, z: f32) -> f32 {
+        value_noise(x * self.detail_frequency, z * self.detail_frequency) * self.detail_strength
+    }
+}
+
+//Value of a texel in the endless height texture, in [0, 1]
+pub fn texel_value(source: &dyn NoiseSource, noise_frequency: Coord, seed: Seed, x: i64, y: i64) -> f32 {
+    source.sample(x as Coord * noise_frequency, y as Coord * noise_frequency, seed) * 0.5 + 0.5
+}
+
+//Each chunk's own seed, from the global one and the chunk's coordinates
+pub fn chunk_seed(seed: Seed, coord: (i32, i32)) -> Seed {
+    let cell = (coord.0 as u32 as u64) << 32 | coord.1 as u32 as u64;
+    splitmix64(seed ^ splitmix64(cell))
+}
+
+//texel_value with every chunk's own pattern, sampled with its chunk_seed, mixed in by variation. Each
+//chunk's pattern is strongest at its centre and fades out towards its neighbours' over chunk_texels,
+//the texels from one chunk's uv 0 to the next's. The weights only depend on the texel, not on which
+//chunk is being filled, so chunks agree on the texels they share and the seams stay closed.
+pub fn chunk_texel_value(source: &dyn NoiseSource, noise_frequency: Coord, seed: Seed, variation: f32, chunk_texels: i64, x: i64, y: i64) -> f32 {
+    let base = texel_value(source, noise_frequency, seed, x, y);
+    if variation <= 0.0 {
+        return base;
+    }
+
+    //Measured from chunk centres, so the fraction is how far towards the next centre the texel is
+    let (cx, cy) = (x as f64 / chunk_texels as f64 - 0.5, y as f64 / chunk_texels as f64 - 0.5);
+    let (x0, y0) = (cx.floor(), cy.floor());
+    let smooth = |t: f64| (t * t * (3.0 - 2.0 * t)) as f32;
+    let (tx, ty) = (smooth(cx - x0), smooth(cy - y0));
+
+    let mix = |a: f32, b: f32, t: f32| a + (b - a) * t;
+    let local = |dx: i32, dy: i32| texel_value(source, noise_frequency, chunk_seed(seed, (x0 as i32 + dx, y0 as i32 + dy)), x, y);
+    let local = mix(mix(local(0, 0), local(1, 0), tx), mix(local(0, 1), local(1, 1), tx), ty);
+
+    mix(base, local, variation.min(1.0))
+}
+
+//Same integer hash as shader.wgsl, the i32 -> u32 casts reinterpret the bits like WGSL's u32()
+fn hash(x: i32, y: i32) -> f32 {
+    let mut h = (x as u32).wrapping_mul(0x8da6b343).wrapping_add((y as u32).wrapping_mul(0xd8163841));
+    h = (h ^ (h >> 15)).wrapping_mul(0x2c1b3c6d);
+    h = (h ^ (h >> 12)).wrapping_mul(0x297a2d39);
+    h ^= h >> 15;
+
+    h as f32 / 4294967295.0
+}
+
+//Smoothly interpolated lattice noise in [-1, 1]
+fn value_noise(x: f32, y: f32) -> f32 {
+    let (cell_x, cell_y) = (x.floor() as i32, y.floor() as i32);
+    let (fx, fy) = (x - x.floor(), y - y.floor());
+    let (tx, ty) = (fx * fx * (3.0 - 2.0 * fx), fy * fy * (3.0 - 2.0 * fy));
+
+    let mix = |a: f32, b: f32, t: f32| a + (b - a) * t;
+
+    let a = hash(cell_x, cell_y);
+    let b = hash(cell_x + 1, cell_y);
+    let c = hash(cell_x, cell_y + 1);
+    let d = hash(cell_x + 1, cell_y + 1);
+
+    mix(mix(a, b, tx), mix(c, d, tx), ty) * 2.0 - 1.0
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use crate::noise::perlin::PerlinSource;
+    use crate::noise::source::TestSource;
+    use crate::render::chunk::{ChunkSampler, CHUNK_APRON};
+
+    use super::*;
+
+    fn displacement() -> Displacement {
+        Displacement {
+            tex_size: 17,
+            chunk_size: 9,
+            noise_frequency: 0.1,
+            seed: 3,
+            chunk_variation: 0.0,
+            height_scale: 20.0,
+            detail_strength: 0.0,
+            detail_frequency: 1.0
+        }
+    }
+
+    //vs_main's height for chunk (0, 0): sample_height blends the texels around the uv, then height_scale
+    fn shader_height(d: &Displacement, x: f32, z: f32) -> f32 {
+        let extent = (d.chunk_size - 1) as f32;
+        let (px, py) = (x / extent * (d.tex_size - 1) as f32, z / extent * (d.tex_size - 1) as f32);
+        let (bx, by) = (px.floor().min((d.tex_size - 2) as f32), py.floor().min((d.tex_size - 2) as f32));
+        let (fx, fy) = (px - bx, py - by);
+        let texel = |dx: i64, dy: i64| texel_value(&TestSource, d.noise_frequency, d.seed, bx as i64 + dx, by as i64 + dy);
+
+        let mix = |a: f32, b: f32, t: f32| a + (b - a) * t;
+        mix(mix(texel(0, 0), texel(1, 0), fx), mix(texel(0, 1), texel(1, 1), fx), fy) * d.height_scale
+    }
+
+    #[wasm_bindgen_test]
+    fn cpu_height_matches_the_shader_on_texels() {
+        let d = displacement();
+
+        //Two texels per world unit, so half units land on texels
+        for &(x, z) in &[(0.0, 0.0), (0.5, 0.0), (3.0, 2.5), (8.0, 8.0)] {
+            let expected = texel_value(&TestSource, d.noise_frequency, d.seed, (x * 2.0) as i64, (z * 2.0) as i64) * d.height_scale;
+
+            assert!((d.height_at(&TestSource, x, z) - expected).abs() < 1e-5, "{}, {}", x, z);
+            assert!((d.height_at(&TestSource, x, z) - shader_height(&d, x, z)).abs() < 1e-5, "{}, {}", x, z);
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn cpu_height_matches_the_shader_between_texels() {
+        let d = displacement();
+
+        for &(x, z) in &[(0.2, 0.7), (1.3, 4.9), (7.9, 0.1)] {
+            assert!((d.height_at(&TestSource, x, z) - shader_height(&d, x, z)).abs() < 1e-5, "{}, {}", x, z);
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn detail_is_the_hashed_lattice_at_whole_cells() {
+        let d = Displacement { detail_strength: 2.0, ..displacement() };
+
+        //value_noise at a lattice point is just that point's hash
+        assert_eq!(d.detail(0.0, 0.0), (hash(0, 0) * 2.0 - 1.0) * 2.0);
+        assert_eq!(d.detail(3.0, -2.0), (hash(3, -2) * 2.0 - 1.0) * 2.0);
+    }
+
+    fn filled(sampler: &ChunkSampler, source: &dyn NoiseSource, coord: (i32, i32)) -> Vec<f32> {
+        let size = sampler.texture_size();
+        let mut data = vec![0.0; (size * size) as usize];
+        sampler.fill_rows(source, coord, &mut data, size as usize, 0..size);
+        data
+    }
+
+    #[wasm_bindgen_test]
+    fn neighbouring_chunks_share_their_edges() {
+        let sampler = ChunkSampler { tex_size: 17, noise_frequency: 0.13, seed: 99, variation: 1.0 };
+        let source = PerlinSource::default();
+        let size = sampler.texture_size() as usize;
+        //Chunk uv 1 and the apron past it are the next chunk's apron and uv 0
+        let last = (CHUNK_APRON + sampler.tex_size - 1) as usize;
+        let overlap = 2 * CHUNK_APRON as usize + 1;
+
+        for &(coord, right, below) in &[((0, 0), (1, 0), (0, 1)), ((-1, -1), (0, -1), (-1, 0)), ((5, -3), (6, -3), (5, -2))] {
+            let (chunk, right, below) = (filled(&sampler, &source, coord), filled(&sampler, &source, right), filled(&sampler, &source, below));
+
+            for i in 0..size {
+                for o in 0..overlap {
+                    let (a, b) = (chunk[i * size + last - CHUNK_APRON as usize + o], right[i * size + o]);
+                    assert_eq!(a.to_bits(), b.to_bits(), "{:?} and its right neighbour differ at row {}", coord, i);
+
+                    let (a, b) = (chunk[(last - CHUNK_APRON as usize + o) * size + i], below[o * size + i]);
+                    assert_eq!(a.to_bits(), b.to_bits(), "{:?} and the chunk below differ at column {}", coord, i);
+                }
+            }
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn variation_changes_the_inside_of_chunks() {
+        let source = PerlinSource::default();
+        let base = filled(&ChunkSampler { tex_size: 17, noise_frequency: 0.13, seed: 99, variation: 0.0 }, &source, (2, 2));
+        let varied = filled(&ChunkSampler { tex_size: 17, noise_frequency: 0.13, seed: 99, variation: 1.0 }, &source, (2, 2));
+
+        assert_ne!(base, varied);
+    }
+
+    #[wasm_bindgen_test]
+    fn chunk_seeds_depend_on_both_coordinates() {
+        assert_ne!(chunk_seed(1, (0, 1)), chunk_seed(1, (1, 0)));
+        assert_ne!(chunk_seed(1, (-1, 0)), chunk_seed(1, (1, 0)));
+        assert_ne!(chunk_seed(1, (0, -1)), chunk_seed(1, (-1, 0)));
+        assert_ne!(chunk_seed(1, (-1, -1)), chunk_seed(1, (1, 1)));
+        assert_ne!(chunk_seed(1, (3, 4)), chunk_seed(2, (3, 4)));
+        assert_eq!(chunk_seed(1, (3, 4)), chunk_seed(1, (3, 4)));
+    }
+}
@@ -1,279 +1,748 @@
-use std::{cell::RefCell, rc::Rc, collections::{VecDeque, HashMap}};
-
-use wasm_bindgen::{JsCast, prelude::Closure, JsValue};
-use web_sys::{HtmlCanvasElement, EventTarget, KeyboardEvent, MouseEvent};
-
-use crate::{console_log, util::get_expected_size};
-
-#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
-pub enum KeyboardKey {
-    Character(char),
-    Alt, 
-    AltGr,
-    CapsLock,
-    Control,
-    Fn,
-    FnLock,
-    Hyper,
-    Meta,
-    NumLock,
-    ScrollLock,
-    Shift,
-    Super,
-    Symbol,
-    SymbolLock,
-    Dead,
-
-    Unidentified
-}
-
-impl KeyboardKey {
-    pub fn extract(key: &str) -> Self {
-        match key {
-            "Alt" => KeyboardKey::Alt,
-            "AltGraph" => KeyboardKey::AltGr,
-            "CapsLock" => KeyboardKey::CapsLock,
-            "Control" => KeyboardKey::Control,
-            "Fn" => KeyboardKey::Fn,
-            "FnLock" => KeyboardKey::FnLock,
-            "Hyper" => KeyboardKey::Hyper,
-            "Meta" => KeyboardKey::Meta,
-            "NumLock" => KeyboardKey::Meta,
-            "ScrollLock" => KeyboardKey::ScrollLock,
-            "Shift" => KeyboardKey::Shift,
-            "Super" => KeyboardKey::Super,
-            "Symbol" => KeyboardKey::Symbol,
-            "SymbolLock" => KeyboardKey::Symbol,
-            "Dead" => KeyboardKey::Dead,
-
-            s if s.len() == 1 => KeyboardKey::Character(s.chars().next().unwrap()),
-
-            _ => KeyboardKey::Unidentified
-        }
-    }
-}
-
-#[derive(Debug, Clone)]
-pub struct KeyboardEventData {
-    pub alt_key: bool,
-    pub ctrl_key: bool,
-    pub shift_key: bool,
-    pub meta_key: bool,
-
-    pub key: KeyboardKey
-}
-
-impl KeyboardEventData {
-    pub fn extract(event: &KeyboardEvent) -> Self {
-        KeyboardEventData { 
-            alt_key: event.alt_key(), 
-            ctrl_key: event.ctrl_key(), 
-            shift_key: event.shift_key(), 
-            meta_key: event.meta_key(), 
-            key: KeyboardKey::extract(&event.key())
-        }
-    }
-}
-
-#[derive(Debug, Clone)]
-pub enum MouseButton {
-    Left, // Main button
-    Middle, // Auxiliary button
-    Right, // Secondary button
-
-    OtherButton(u8)
-}
-
-impl MouseButton {
-    pub fn extract(button: u8) -> Self {
-        match button {
-            0 => MouseButton::Left,
-            1 => MouseButton::Middle,
-            2 => MouseButton::Right,
-            _ => MouseButton::OtherButton(button)
-        }
-    }
-}
-
-#[derive(Debug, Clone)]
-pub struct MouseEventData {
-    pub alt_key: bool,
-    pub ctrl_key: bool,
-    pub shift_key: bool,
-    pub meta_key: bool,
-
-    pub button: MouseButton,
-
-    pub movement_x: i32,
-    pub movement_y: i32,
-
-    pub x: i32,
-    pub y: i32
-}
-
-impl MouseEventData {
-    pub fn extract(event: &MouseEvent) -> Self {
-        MouseEventData {
-            alt_key: event.alt_key(),
-            ctrl_key: event.ctrl_key(),
-            shift_key: event.shift_key(),
-            meta_key: event.meta_key(),
-
-            button: MouseButton::extract(event.button() as u8),
-
-            movement_x: event.movement_x(),
-            movement_y: event.movement_y(),
-
-            x: event.x(),
-            y: event.y()
-        }
-    }
-}
-
-#[derive(Debug)]
-pub struct CanvasResizeData {
-    pub old_width: u32,
-    pub old_height: u32,
-
-    pub new_width: u32,
-    pub new_height: u32
-}
-
-#[derive(Debug)]
-pub enum Event {
-    KeyDown(KeyboardEventData),
-    KeyUp(KeyboardEventData),
-
-    MouseDown(MouseEventData),
-    MouseUp(MouseEventData),
-    MouseMove(MouseEventData),
-
-    CanvasResize(CanvasResizeData)
-}
-
-pub struct EventQueue {
-    pub events: VecDeque<Event>,
-    canvas: HtmlCanvasElement
-}
-
-impl EventQueue {
-    pub fn for_canvas(canvas: HtmlCanvasElement) -> Result<Rc<RefCell<EventQueue>>, JsValue> {
-        let event_target: EventTarget = canvas.clone().into();
-        let document: EventTarget = canvas.owner_document().unwrap().into();
-
-        let queue = Rc::new(RefCell::new(EventQueue {
-            events: VecDeque::new(),
-            canvas
-        }));
-
-        let queue_clone = queue.clone();
-        let keydown_handler = move |event: web_sys::Event| {
-            let key_data = KeyboardEventData::extract(&event.unchecked_into());
-            queue_clone.borrow_mut().enqueue(Event::KeyDown(key_data));
-        };
-
-        let queue_clone = queue.clone();
-        let keyup_handler = move |event: web_sys::Event| {
-            let key_data = KeyboardEventData::extract(&event.unchecked_into());
-            queue_clone.borrow_mut().enqueue(Event::KeyUp(key_data));
-        };
-
-        let queue_clone = queue.clone();
-        let mousedown_handler = move |event: web_sys::Event| {
-            let mouse_data = MouseEventData::extract(&event.unchecked_into());
-            queue_clone.borrow_mut().enqueue(Event::MouseDown(mouse_data));
-        };
-
-        let queue_clone = queue.clone();
-        let mouseup_handler = move |event: web_sys::Event| {
-            let mouse_data = MouseEventData::extract(&event.unchecked_into());
-            queue_clone.borrow_mut().enqueue(Event::MouseUp(mouse_data));
-        };
-
-        let queue_clone = queue.clone();
-        let mousemove_handler = move |event: web_sys::Event| {
-            let mouse_data = MouseEventData::extract(&event.unchecked_into());
-            queue_clone.borrow_mut().enqueue(Event::MouseMove(mouse_data));
-        };
-
-        
-        let keydown_handler: Closure<dyn FnMut(_)> = Closure::new(keydown_handler);
-        let keyup_handler: Closure<dyn FnMut(_)> = Closure::new(keyup_handler);
-        let mousedown_handler: Closure<dyn FnMut(_)> = Closure::new(mousedown_handler);
-        let mouseup_handler: Closure<dyn FnMut(_)> = Closure::new(mouseup_handler);
-        let mousemove_handler: Closure<dyn FnMut(_)> = Closure::new(mousemove_handler);
-
-        document.add_event_listener_with_callback("keydown", &keydown_handler.as_ref().unchecked_ref())?;
-        document.add_event_listener_with_callback("keyup", &keyup_handler.as_ref().unchecked_ref())?;
-        event_target.add_event_listener_with_callback("mousedown", &mousedown_handler.as_ref().unchecked_ref())?;
-        event_target.add_event_listener_with_callback("mouseup", &mouseup_handler.as_ref().unchecked_ref())?;
-        event_target.add_event_listener_with_callback("mousemove", &mousemove_handler.as_ref().unchecked_ref())?;
-
-        Box::leak(Box::new(keydown_handler));
-        Box::leak(Box::new(keyup_handler));
-        Box::leak(Box::new(mousedown_handler));
-        Box::leak(Box::new(mouseup_handler));
-        Box::leak(Box::new(mousemove_handler));
-
-        Ok(queue)
-    }
-
-    pub fn detect_resize(&mut self) {
-        let (new_width, new_height) = get_expected_size(&self.canvas);
-
-        if new_width != self.canvas.width() || new_height!= self.canvas.height()  {
-            self.canvas.set_width(new_width);
-            self.canvas.set_height(new_height);
-
-            self.enqueue_inner(Event::CanvasResize(CanvasResizeData { 
-                old_width: self.canvas.width(),
-                old_height: self.canvas.height(),
-
-                new_width,
-                new_height
-            }));
-        }
-    }
-
-    pub fn enqueue(&mut self, event: Event) {
-        self.detect_resize();
-        self.enqueue_inner(event);
-    }
-
-    fn enqueue_inner(&mut self, event: Event) {
-        self.events.push_back(event);
-    }
-
-    pub fn pop(&mut self) -> Option<Event> {
-        self.events.pop_front()
-    }
-
-    pub fn empty(&mut self) -> bool {
-        self.events.is_empty()
-    }
-}
-
-pub struct KeyTracker {
-    keys: HashMap<KeyboardKey, bool>
-}
-
-impl KeyTracker {
-    pub fn new() -> Self {
-        KeyTracker {
-            keys: HashMap::new()
-        }
-    }
-
-    pub fn set_key_down(&mut self, key: KeyboardKey) {
-        self.keys.insert(key, true);
-    }
-
-    pub fn set_key_up(&mut self, key: KeyboardKey) {
-        console_log!("Key up {:?}", key);
-        self.keys.insert(key, false);
-    }
-
-    pub fn is_key_down(&self, key: KeyboardKey) -> bool {
-        *self.keys.get(&key).unwrap_or(&false)
-    }
-}
\ No newline at end of file
+use std::{cell::{Cell, RefCell}, rc::Rc, collections::{VecDeque, HashMap}};
+
+use wasm_bindgen::{JsCast, prelude::Closure, JsValue};
+use web_sys::{HtmlCanvasElement, HtmlElement, EventTarget, KeyboardEvent, MouseEvent, WheelEvent, ResizeObserver, Document};
+
+use crate::{console_log, util::{get_expected_size, has_layout_size}};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum KeyboardKey {
+    Character(char),
+    Alt, 
+    AltGr,
+    CapsLock,
+    Control,
+    Fn,
+    FnLock,
+    Hyper,
+    Meta,
+    NumLock,
+    ScrollLock,
+    Shift,
+    Super,
+    Symbol,
+    SymbolLock,
+    Dead,
+    Function(u8),
+
+    Unidentified
+}
+
+impl KeyboardKey {
+    pub fn extract(key: &str) -> Self {
+        match key {
+            "Alt" => KeyboardKey::Alt,
+            "AltGraph" => KeyboardKey::AltGr,
+            "CapsLock" => KeyboardKey::CapsLock,
+            "Control" => KeyboardKey::Control,
+            "Fn" => KeyboardKey::Fn,
+            "FnLock" => KeyboardKey::FnLock,
+            "Hyper" => KeyboardKey::Hyper,
+            "Meta" => KeyboardKey::Meta,
+            "NumLock" => KeyboardKey::Meta,
+            "ScrollLock" => KeyboardKey::ScrollLock,
+            "Shift" => KeyboardKey::Shift,
+            "Super" => KeyboardKey::Super,
+            "Symbol" => KeyboardKey::Symbol,
+            "SymbolLock" => KeyboardKey::Symbol,
+            "Dead" => KeyboardKey::Dead,
+
+            s if s.len() == 1 => KeyboardKey::Character(s.chars().next().unwrap()),
+            s if s.starts_with('F') => s[1..].parse().map(KeyboardKey::Function).unwrap_or(KeyboardKey::Unidentified),
+
+            _ => KeyboardKey::Unidentified
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct KeyboardEventData {
+    pub alt_key: bool,
+    pub ctrl_key: bool,
+    pub shift_key: bool,
+    pub meta_key: bool,
+
+    pub key: KeyboardKey,
+    //Set on the keydowns the OS sends while a key is held, never on the first
+    pub repeat: bool
+}
+
+impl KeyboardEventData {
+    pub fn extract(event: &KeyboardEvent) -> Self {
+        KeyboardEventData { 
+            alt_key: event.alt_key(), 
+            ctrl_key: event.ctrl_key(), 
+            shift_key: event.shift_key(), 
+            meta_key: event.meta_key(), 
+            key: KeyboardKey::extract(&event.key()),
+            repeat: event.repeat()
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum MouseButton {
+    Left, // Main button
+    Middle, // Auxiliary button
+    Right, // Secondary button
+
+    OtherButton(u8)
+}
+
+impl MouseButton {
+    pub fn extract(button: u8) -> Self {
+        match button {
+            0 => MouseButton::Left,
+            1 => MouseButton::Middle,
+            2 => MouseButton::Right,
+            _ => MouseButton::OtherButton(button)
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MouseEventData {
+    pub alt_key: bool,
+    pub ctrl_key: bool,
+    pub shift_key: bool,
+    pub meta_key: bool,
+
+    pub button: MouseButton,
+
+    pub movement_x: i32,
+    pub movement_y: i32,
+
+    pub x: i32,
+    pub y: i32
+}
+
+impl MouseEventData {
+    pub fn extract(event: &MouseEvent) -> Self {
+        MouseEventData {
+            alt_key: event.alt_key(),
+            ctrl_key: event.ctrl_key(),
+            shift_key: event.shift_key(),
+            meta_key: event.meta_key(),
+
+            button: MouseButton::extract(event.button() as u8),
+
+            movement_x: event.movement_x(),
+            movement_y: event.movement_y(),
+
+            x: event.x(),
+            y: event.y()
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WheelEventData {
+    pub delta_x: f64,
+    pub delta_y: f64
+}
+
+impl WheelEventData {
+    pub fn extract(event: &WheelEvent) -> Self {
+        WheelEventData {
+            delta_x: event.delta_x(),
+            delta_y: event.delta_y()
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct CanvasResizeData {
+    pub old_width: u32,
+    pub old_height: u32,
+
+    pub new_width: u32,
+    pub new_height: u32
+}
+
+//Events held while nothing drains the queue, see enforce_cap
+pub const DEFAULT_EVENT_CAP: usize = 256;
+
+#[derive(Debug)]
+pub enum Event {
+    KeyDown(KeyboardEventData),
+    KeyUp(KeyboardEventData),
+
+    MouseDown(MouseEventData),
+    MouseUp(MouseEventData),
+    MouseMove(MouseEventData),
+    MouseWheel(WheelEventData),
+
+    CanvasResize(CanvasResizeData),
+
+    //Keys released while unfocused never send a keyup, so everything held should be let go
+    FocusLost
+}
+
+impl Event {
+    //Events that leave state behind if they're lost: a key or button stuck down, or a stale canvas size
+    pub fn is_critical(&self) -> bool {
+        matches!(self, Event::KeyUp(_) | Event::MouseUp(_) | Event::CanvasResize(_) | Event::FocusLost)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyTarget {
+    //Keys anywhere on the page, for demos where the canvas is the whole page
+    Document,
+    //Only while the canvas has focus, for canvases embedded in a larger page
+    Canvas
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct KeyOptions {
+    pub target: KeyTarget,
+    //Leave keys alone while the user is typing in an input, textarea or contenteditable element
+    pub ignore_editable: bool,
+    //Drop auto-repeat keydowns before they're queued, for pages that only use one-shot keys
+    pub skip_repeats: bool
+}
+
+impl KeyOptions {
+    //Whether a keydown is dropped before it's queued
+    pub fn skips(&self, key: &KeyboardEventData) -> bool {
+        key.repeat && self.skip_repeats
+    }
+}
+
+impl Default for KeyOptions {
+    fn default() -> Self {
+        KeyOptions {
+            target: KeyTarget::Document,
+            ignore_editable: true,
+            skip_repeats: false
+        }
+    }
+}
+
+fn is_editing(document: &Document) -> bool {
+    match document.active_element() {
+        Some(element) => {
+            let tag = element.tag_name();
+            tag.eq_ignore_ascii_case("input")
+                || tag.eq_ignore_ascii_case("textarea")
+                || tag.eq_ignore_ascii_case("select")
+                || element.dyn_ref::<HtmlElement>().map(|element| element.is_content_editable()).unwrap_or(false)
+        },
+        None => false
+    }
+}
+
+thread_local! {
+    static NEXT_QUEUE_ID: Cell<u32> = const { Cell::new(0) };
+    //The queue taking document key events, see EventQueue::active
+    static KEY_OWNER: Cell<Option<u32>> = const { Cell::new(None) };
+}
+
+//A new queue only takes keys if no other queue does, so a second canvas on the page doesn't start
+//reacting to the first one's keys
+fn register_key_queue() -> u32 {
+    let id = NEXT_QUEUE_ID.with(|next| {
+        let id = next.get();
+        next.set(id.wrapping_add(1));
+        id
+    });
+
+    KEY_OWNER.with(|owner| if owner.get().is_none() {
+        owner.set(Some(id));
+    });
+
+    id
+}
+
+fn owns_keys(id: u32) -> bool {
+    KEY_OWNER.with(|owner| owner.get() == Some(id))
+}
+
+//Every queue sees every click on the page. Clicking a queue's canvas hands it the keys, clicking
+//anywhere else only takes them away from the queue that had them.
+fn key_queue_clicked(id: u32, on_canvas: bool) {
+    KEY_OWNER.with(|owner| if on_canvas {
+        owner.set(Some(id));
+    } else if owner.get() == Some(id) {
+        owner.set(None);
+    });
+}
+
+fn unregister_key_queue(id: u32) {
+    KEY_OWNER.with(|owner| if owner.get() == Some(id) {
+        owner.set(None);
+    });
+}
+
+struct Listener {
+    target: EventTarget,
+    name: &'static str,
+    handler: Closure<dyn FnMut(web_sys::Event)>
+}
+
+struct SizeObserver {
+    observer: ResizeObserver,
+    #[allow(dead_code)]
+    handler: Closure<dyn FnMut(js_sys::Array)>
+}
+
+pub struct EventQueue {
+    pub events: VecDeque<Event>,
+    //Longest events gets before it's cut back, see enforce_cap
+    cap: usize,
+    canvas: HtmlCanvasElement,
+    min_canvas_size: u32,
+    listeners: Vec<Listener>,
+    //With keyboard listeners on the document and several canvases on a page, only one queue takes
+    //key presses: the first one created, then the last one whose canvas was clicked
+    id: u32,
+    keys: KeyOptions,
+    //None if the browser has no ResizeObserver, the size is polled instead
+    resize_observer: Option<SizeObserver>
+}
+
+impl EventQueue {
+    pub fn for_canvas(canvas: HtmlCanvasElement, min_canvas_size: u32, keys: KeyOptions, cap: usize) -> Result<Rc<RefCell<EventQueue>>, JsValue> {
+        let event_target: EventTarget = canvas.clone().into();
+        let document: EventTarget = canvas.owner_document().unwrap().into();
+
+        let key_target = match keys.target {
+            KeyTarget::Document => document.clone(),
+            KeyTarget::Canvas => {
+                //A canvas can only take focus with a tabindex
+                if !canvas.has_attribute("tabindex") {
+                    canvas.set_tab_index(0);
+                }
+                event_target.clone()
+            }
+        };
+
+        let queue = Rc::new(RefCell::new(EventQueue {
+            events: VecDeque::new(),
+            cap: cap.max(1),
+            canvas,
+            min_canvas_size,
+            listeners: Vec::new(),
+            id: register_key_queue(),
+            keys,
+            resize_observer: None
+        }));
+
+        Self::observe_resize(&queue);
+
+        Self::add_listener(&queue, &key_target, "keydown", |queue, event| {
+            if !queue.accepts_keys() {
+                return;
+            }
+
+            let key_data = KeyboardEventData::extract(event.unchecked_ref());
+
+            //Function keys and Ctrl+number (bookmarks) are used as shortcuts, don't let the browser act on them too
+            match key_data.key {
+                KeyboardKey::Function(_) => event.prevent_default(),
+                KeyboardKey::Character('1'..='9') if key_data.ctrl_key => event.prevent_default(),
+                _ => {}
+            }
+
+            //After prevent_default, the browser shouldn't act on a held shortcut either
+            if queue.keys.skips(&key_data) {
+                return;
+            }
+
+            queue.enqueue(Event::KeyDown(key_data));
+        })?;
+        //Key ups always go through so keys held while focus moves elsewhere don't get stuck
+        Self::listen(&queue, &key_target, "keyup", |event| Event::KeyUp(KeyboardEventData::extract(&event.unchecked_into())))?;
+        Self::add_listener(&queue, &document, "mousedown", |queue, event| {
+            let canvas: &EventTarget = queue.canvas.as_ref();
+            let on_canvas = event.target().as_ref() == Some(canvas);
+            key_queue_clicked(queue.id, on_canvas);
+
+            if on_canvas && queue.keys.target == KeyTarget::Canvas {
+                let _ = queue.canvas.focus();
+            }
+        })?;
+        if keys.target == KeyTarget::Canvas {
+            Self::listen(&queue, &event_target, "blur", |_| Event::FocusLost)?;
+        }
+        Self::listen(&queue, &event_target, "mousedown", |event| Event::MouseDown(MouseEventData::extract(&event.unchecked_into())))?;
+        Self::listen(&queue, &event_target, "mouseup", |event| Event::MouseUp(MouseEventData::extract(&event.unchecked_into())))?;
+        Self::listen(&queue, &event_target, "mousemove", |event| Event::MouseMove(MouseEventData::extract(&event.unchecked_into())))?;
+        Self::listen(&queue, &event_target, "wheel", |event| Event::MouseWheel(WheelEventData::extract(&event.unchecked_into())))?;
+
+        Ok(queue)
+    }
+
+    fn listen(queue: &Rc<RefCell<EventQueue>>, target: &EventTarget, name: &'static str, convert: fn(web_sys::Event) -> Event) -> Result<(), JsValue> {
+        Self::add_listener(queue, target, name, move |queue, event| queue.enqueue(convert(event)))
+    }
+
+    fn add_listener(queue: &Rc<RefCell<EventQueue>>, target: &EventTarget, name: &'static str, mut handle: impl FnMut(&mut EventQueue, web_sys::Event) + 'static) -> Result<(), JsValue> {
+        let queue_clone = queue.clone();
+        let handler: Closure<dyn FnMut(_)> = Closure::new(move |event: web_sys::Event| {
+            handle(&mut queue_clone.borrow_mut(), event);
+        });
+
+        target.add_event_listener_with_callback(name, handler.as_ref().unchecked_ref())?;
+
+        queue.borrow_mut().listeners.push(Listener {
+            target: target.clone(),
+            name,
+            handler
+        });
+
+        Ok(())
+    }
+
+    fn observe_resize(queue: &Rc<RefCell<EventQueue>>) {
+        let queue_clone = queue.clone();
+        let handler: Closure<dyn FnMut(_)> = Closure::new(move |_entries: js_sys::Array| {
+            queue_clone.borrow_mut().detect_resize();
+        });
+
+        match ResizeObserver::new(handler.as_ref().unchecked_ref()) {
+            Ok(observer) => {
+                observer.observe(&queue.borrow().canvas);
+                queue.borrow_mut().resize_observer = Some(SizeObserver { observer, handler });
+            },
+            Err(_) => console_log!("ResizeObserver unavailable, polling the canvas size instead")
+        }
+    }
+
+    fn accepts_keys(&self) -> bool {
+        if self.keys.target == KeyTarget::Document && !owns_keys(self.id) {
+            return false;
+        }
+
+        !(self.keys.ignore_editable && self.canvas.owner_document().map(|document| is_editing(&document)).unwrap_or(false))
+    }
+
+    pub fn canvas(&self) -> &HtmlCanvasElement {
+        &self.canvas
+    }
+
+    //Removes every listener, which also breaks the reference cycle between the queue and its handlers
+    pub fn detach(&mut self) {
+        for listener in self.listeners.drain(..) {
+            let _ = listener.target.remove_event_listener_with_callback(listener.name, listener.handler.as_ref().unchecked_ref());
+        }
+
+        if let Some(resize_observer) = self.resize_observer.take() {
+            resize_observer.observer.disconnect();
+        }
+
+        unregister_key_queue(self.id);
+
+        self.events.clear();
+    }
+
+    pub fn detect_resize(&mut self) {
+        //Hidden, keep the last real size. The runtime suspends until the canvas is shown again.
+        if !has_layout_size(self.canvas.client_width(), self.canvas.client_height()) {
+            return;
+        }
+
+        let (new_width, new_height) = get_expected_size(&self.canvas, self.min_canvas_size);
+        let (old_width, old_height) = (self.canvas.width(), self.canvas.height());
+
+        if new_width != old_width || new_height != old_height {
+            self.canvas.set_width(new_width);
+            self.canvas.set_height(new_height);
+
+            self.enqueue_inner(Event::CanvasResize(CanvasResizeData { 
+                old_width,
+                old_height,
+
+                new_width,
+                new_height
+            }));
+        }
+    }
+
+    //Only does anything without a ResizeObserver, which otherwise reports resizes as they happen
+    pub fn poll_resize(&mut self) {
+        if self.resize_observer.is_none() {
+            self.detect_resize();
+        }
+    }
+
+    pub fn enqueue(&mut self, event: Event) {
+        self.poll_resize();
+        self.enqueue_inner(event);
+    }
+
+    fn enqueue_inner(&mut self, event: Event) {
+        self.events.push_back(event);
+
+        if self.events.len() > self.cap {
+            enforce_cap(&mut self.events, self.cap);
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<Event> {
+        self.events.pop_front()
+    }
+
+    //Takes every queued event, merging runs of consecutive MouseMove events into one.
+    //Movement deltas are summed, everything else comes from the latest move.
+    pub fn drain_coalesced(&mut self) -> Vec<Event> {
+        coalesce_moves(self.events.drain(..))
+    }
+
+    pub fn empty(&mut self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+//When rendering stalls (a throttled tab) the queue would otherwise grow for as long as the user keeps
+//moving, then replay everything at once. Mouse moves are merged first, which loses nothing but their
+//timing, then the oldest events that aren't critical are dropped. Critical ones are always kept, so a
+//flood of only those can still go past the cap.
+fn enforce_cap(events: &mut VecDeque<Event>, cap: usize) {
+    *events = coalesce_moves(events.drain(..)).into();
+
+    let mut excess = events.len().saturating_sub(cap);
+    events.retain(|event| {
+        let drop = excess > 0 && !event.is_critical();
+        excess -= drop as usize;
+        !drop
+    });
+}
+
+pub fn coalesce_moves(events: impl IntoIterator<Item = Event>) -> Vec<Event> {
+    let mut coalesced: Vec<Event> = Vec::new();
+
+    for event in events {
+        match (coalesced.last_mut(), event) {
+            (Some(Event::MouseMove(previous)), Event::MouseMove(mut next)) => {
+                next.movement_x += previous.movement_x;
+                next.movement_y += previous.movement_y;
+                *previous = next;
+            },
+            (_, event) => coalesced.push(event)
+        }
+    }
+
+    coalesced
+}
+
+pub struct KeyTracker {
+    keys: HashMap<KeyboardKey, bool>,
+    pub debug_logging: bool
+}
+
+impl Default for KeyTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KeyTracker {
+    pub fn new() -> Self {
+        KeyTracker {
+            keys: HashMap::new(),
+            debug_logging: false
+        }
+    }
+
+    pub fn set_key_down(&mut self, key: KeyboardKey) {
+        self.keys.insert(key, true);
+    }
+
+    pub fn set_key_up(&mut self, key: KeyboardKey) {
+        if self.debug_logging {
+            console_log!("Key up {:?}", key);
+        }
+        self.keys.insert(key, false);
+    }
+
+    pub fn is_key_down(&self, key: KeyboardKey) -> bool {
+        *self.keys.get(&key).unwrap_or(&false)
+    }
+
+    pub fn release_all(&mut self) {
+        self.keys.clear();
+    }
+
+    pub fn keys_down(&self) -> impl Iterator<Item = KeyboardKey> + '_ {
+        self.keys.iter().filter(|(_, down)| **down).map(|(key, _)| *key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    fn mouse(movement_x: i32, movement_y: i32, x: i32, y: i32) -> MouseEventData {
+        MouseEventData {
+            alt_key: false,
+            ctrl_key: false,
+            shift_key: false,
+            meta_key: false,
+
+            button: MouseButton::Left,
+
+            movement_x,
+            movement_y,
+
+            x,
+            y
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn consecutive_moves_coalesce() {
+        let events = vec![
+            Event::MouseMove(mouse(1, 2, 10, 10)),
+            Event::MouseMove(mouse(3, -1, 13, 9)),
+            Event::MouseMove(mouse(-2, 4, 11, 13))
+        ];
+
+        match coalesce_moves(events).as_slice() {
+            [Event::MouseMove(moved)] => {
+                assert_eq!((moved.movement_x, moved.movement_y), (2, 5));
+                assert_eq!((moved.x, moved.y), (11, 13));
+            },
+            other => panic!("expected one move, got {:?}", other)
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn other_events_split_runs_of_moves() {
+        let events = vec![
+            Event::MouseMove(mouse(1, 0, 0, 0)),
+            Event::MouseDown(mouse(0, 0, 0, 0)),
+            Event::MouseMove(mouse(1, 0, 0, 0)),
+            Event::MouseMove(mouse(1, 0, 0, 0))
+        ];
+
+        match coalesce_moves(events).as_slice() {
+            [Event::MouseMove(first), Event::MouseDown(_), Event::MouseMove(second)] => {
+                assert_eq!(first.movement_x, 1);
+                assert_eq!(second.movement_x, 2);
+            },
+            other => panic!("moves merged across a click: {:?}", other)
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn only_one_queue_takes_document_keys() {
+        KEY_OWNER.with(|owner| owner.set(None));
+        let first = register_key_queue();
+        let second = register_key_queue();
+
+        assert_ne!(first, second);
+        assert!(owns_keys(first) && !owns_keys(second));
+
+        //Clicking the second canvas, the first queue sees the click land elsewhere
+        key_queue_clicked(second, true);
+        key_queue_clicked(first, false);
+        assert!(!owns_keys(first) && owns_keys(second));
+
+        //Clicking outside both canvases
+        key_queue_clicked(first, false);
+        key_queue_clicked(second, false);
+        assert!(!owns_keys(first) && !owns_keys(second));
+
+        key_queue_clicked(first, true);
+        unregister_key_queue(second);
+        assert!(owns_keys(first));
+        unregister_key_queue(first);
+        assert!(!owns_keys(first));
+    }
+
+    fn key(key: char) -> KeyboardEventData {
+        KeyboardEventData {
+            alt_key: false,
+            ctrl_key: false,
+            shift_key: false,
+            meta_key: false,
+
+            key: KeyboardKey::Character(key),
+            repeat: false
+        }
+    }
+
+    //Pushes events one at a time, capping like EventQueue::enqueue does
+    fn flood(cap: usize, events: impl IntoIterator<Item = Event>) -> VecDeque<Event> {
+        let mut queue = VecDeque::new();
+        for event in events {
+            queue.push_back(event);
+            if queue.len() > cap {
+                enforce_cap(&mut queue, cap);
+            }
+        }
+        queue
+    }
+
+    #[wasm_bindgen_test]
+    fn flooding_past_the_cap_bounds_the_queue() {
+        let queue = flood(16, (0..1000).map(|i| if i % 2 == 0 { Event::KeyDown(key('w')) } else { Event::MouseMove(mouse(1, 1, 0, 0)) }));
+
+        assert!(queue.len() <= 16, "{} events queued", queue.len());
+    }
+
+    #[wasm_bindgen_test]
+    fn capped_moves_keep_their_total_movement() {
+        let queue = flood(4, (0..100).map(|_| Event::MouseMove(mouse(1, -2, 0, 0))));
+
+        let total = queue.iter().fold((0, 0), |(x, y), event| match event {
+            Event::MouseMove(data) => (x + data.movement_x, y + data.movement_y),
+            _ => (x, y)
+        });
+        assert_eq!(total, (100, -200));
+    }
+
+    #[wasm_bindgen_test]
+    fn critical_events_survive_the_cap() {
+        let events = (0..50).map(|_| Event::KeyDown(key('a'))).chain([Event::KeyUp(key('a')), Event::FocusLost]).chain((0..50).map(|_| Event::KeyDown(key('b'))));
+        let queue = flood(8, events);
+
+        assert!(queue.len() <= 8);
+        assert!(queue.iter().any(|event| matches!(event, Event::KeyUp(_))));
+        assert!(queue.iter().any(|event| matches!(event, Event::FocusLost)));
+    }
+
+    #[wasm_bindgen_test]
+    fn keys_are_extracted_from_their_names() {
+        assert_eq!(KeyboardKey::extract("w"), KeyboardKey::Character('w'));
+        assert_eq!(KeyboardKey::extract(" "), KeyboardKey::Character(' '));
+        assert_eq!(KeyboardKey::extract("Shift"), KeyboardKey::Shift);
+        assert_eq!(KeyboardKey::extract("Control"), KeyboardKey::Control);
+        assert_eq!(KeyboardKey::extract("AltGraph"), KeyboardKey::AltGr);
+        assert_eq!(KeyboardKey::extract("F11"), KeyboardKey::Function(11));
+        assert_eq!(KeyboardKey::extract("Foo"), KeyboardKey::Unidentified);
+        assert_eq!(KeyboardKey::extract("ArrowUp"), KeyboardKey::Unidentified);
+    }
+
+    #[wasm_bindgen_test]
+    fn mouse_buttons_are_extracted_from_their_numbers() {
+        assert!(matches!(MouseButton::extract(0), MouseButton::Left));
+        assert!(matches!(MouseButton::extract(1), MouseButton::Middle));
+        assert!(matches!(MouseButton::extract(2), MouseButton::Right));
+        assert!(matches!(MouseButton::extract(4), MouseButton::OtherButton(4)));
+    }
+
+    #[wasm_bindgen_test]
+    fn only_releases_and_resizes_are_critical() {
+        let repeat = KeyboardEventData { repeat: true, ..key('w') };
+
+        assert!(!Event::KeyDown(repeat).is_critical());
+        assert!(!Event::MouseMove(mouse(0, 0, 0, 0)).is_critical());
+        assert!(Event::KeyUp(key('w')).is_critical());
+        assert!(Event::MouseUp(mouse(0, 0, 0, 0)).is_critical());
+        assert!(Event::FocusLost.is_critical());
+    }
+
+    #[wasm_bindgen_test]
+    fn repeats_are_skipped_only_when_asked() {
+        let (first, repeat) = (key('e'), KeyboardEventData { repeat: true, ..key('e') });
+        let skipping = KeyOptions { skip_repeats: true, ..KeyOptions::default() };
+
+        assert!(!KeyOptions::default().skips(&repeat));
+        assert!(skipping.skips(&repeat));
+        assert!(!skipping.skips(&first));
+    }
+}
@@ -0,0 +1,310 @@
+use super::wgpu_context::WgpuContext;
+
+//Records the draws of one pass. Gets the context for the pass's lifetime so it can bind anything it owns.
+pub type RecordFn = for<'pass> fn(&'pass WgpuContext, &mut wgpu::RenderPass<'pass>);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorLoad {
+    Clear(wgpu::Color),
+    //Cleared to WgpuContext::background_color, which follows the fog
+    Background,
+    Keep,
+    //The pass draws over every pixel of its viewport, so what was there doesn't matter. WebGPU has no
+    //don't-care load, so it loads, unless there's a viewport and the rest still needs clearing.
+    Covered
+}
+
+//What a pass draws into. Scene passes share the offscreen scene target and depth buffer at the scaled
+//resolution; output passes run after all of them, on the surface at full size with no depth.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PassTarget {
+    Scene,
+    Output
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DepthLoad {
+    Clear(f32),
+    Keep
+}
+
+//Part of the target a pass draws to, in pixels
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Viewport {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32
+}
+
+impl Viewport {
+    //The largest rectangle of the given aspect ratio (width / height) centred in the target, None when
+    //that's the whole target anyway. The bars either side are whatever the passes cleared them to.
+    pub fn letterbox(target_width: u32, target_height: u32, aspect: f32) -> Option<Viewport> {
+        if target_width == 0 || target_height == 0 || !(aspect.is_finite() && aspect > 0.0) {
+            return None;
+        }
+
+        let (width, height) = if target_width as f32 / target_height as f32 > aspect {
+            (((target_height as f32 * aspect).round() as u32).clamp(1, target_width), target_height)
+        } else {
+            (target_width, ((target_width as f32 / aspect).round() as u32).clamp(1, target_height))
+        };
+
+        if (width, height) == (target_width, target_height) {
+            return None;
+        }
+
+        Some(Viewport {
+            x: (target_width - width) / 2,
+            y: (target_height - height) / 2,
+            width,
+            height
+        })
+    }
+}
+
+//Part of a target as fractions of its size, from the top left, so it stays put when the target is resized
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ViewRect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32
+}
+
+impl ViewRect {
+    //In pixels of a target_width x target_height target, clipped to it. Each edge is rounded on its own,
+    //so rects sharing an edge tile without a gap or an overlap. None if nothing of it is left.
+    pub fn to_pixels(&self, target_width: u32, target_height: u32) -> Option<Viewport> {
+        let edge = |fraction: f32, size: u32| (fraction.clamp(0.0, 1.0) * size as f32).round() as u32;
+        let (left, right) = (edge(self.x, target_width), edge(self.x + self.width, target_width));
+        let (top, bottom) = (edge(self.y, target_height), edge(self.y + self.height, target_height));
+
+        (right > left && bottom > top).then_some(Viewport { x: left, y: top, width: right - left, height: bottom - top })
+    }
+
+    //Width / height in pixels of a target_width x target_height target, what Camera::aspect should be for
+    //a camera drawn into it. 1 if it has no area there.
+    pub fn aspect(&self, target_width: u32, target_height: u32) -> f32 {
+        self.to_pixels(target_width, target_height).map_or(1.0, |viewport| viewport.width as f32 / viewport.height as f32)
+    }
+}
+
+//One render pass of a frame. Every node writes its target's colour and optionally the shared depth
+//buffer; nodes of a target run in the order they were added, each seeing what the previous ones wrote.
+//Nodes are recorded into a RenderPass for their target, see WgpuContext::encode_scene and encode_output.
+#[derive(Clone, Copy)]
+pub struct RenderPassNode {
+    pub label: &'static str,
+    pub color: ColorLoad,
+    pub depth: Option<DepthLoad>,
+    pub target: PassTarget,
+    active: fn(&WgpuContext) -> bool,
+    record: RecordFn
+}
+
+impl RenderPassNode {
+    pub fn new(label: &'static str, color: ColorLoad, record: RecordFn) -> Self {
+        RenderPassNode {
+            label,
+            color,
+            depth: None,
+            target: PassTarget::Scene,
+            active: |_| true,
+            record
+        }
+    }
+
+    pub fn with_depth(mut self, depth: DepthLoad) -> Self {
+        self.depth = Some(depth);
+        self
+    }
+
+    //Draw to the surface after the scene passes, reading what they left in the scene target
+    pub fn to_output(mut self) -> Self {
+        self.target = PassTarget::Output;
+        self.depth = None;
+        self
+    }
+
+    //Skip the whole pass on frames where this returns false
+    pub fn when(mut self, active: fn(&WgpuContext) -> bool) -> Self {
+        self.active = active;
+        self
+    }
+
+    pub fn is_active(&self, context: &WgpuContext) -> bool {
+        (self.active)(context)
+    }
+
+    //The same pass drawing over what's there, for another viewport of a target that's already been cleared
+    pub fn keeping_color(&self) -> Self {
+        RenderPassNode {
+            color: ColorLoad::Keep,
+            ..*self
+        }
+    }
+
+    //background is what ColorLoad::Background clears to
+    pub fn color_load(&self, background: wgpu::Color, viewport: Option<Viewport>) -> wgpu::LoadOp<wgpu::Color> {
+        match self.color {
+            ColorLoad::Clear(color) => wgpu::LoadOp::Clear(color),
+            ColorLoad::Background => wgpu::LoadOp::Clear(background),
+            ColorLoad::Covered if viewport.is_some() => wgpu::LoadOp::Clear(background),
+            ColorLoad::Keep | ColorLoad::Covered => wgpu::LoadOp::Load
+        }
+    }
+
+    //None when the pass doesn't use the depth buffer
+    pub fn depth_load(&self) -> Option<wgpu::LoadOp<f32>> {
+        self.depth.map(|depth| match depth {
+            DepthLoad::Clear(value) => wgpu::LoadOp::Clear(value),
+            DepthLoad::Keep => wgpu::LoadOp::Load
+        })
+    }
+
+    pub fn color_attachment<'a>(&self, context: &WgpuContext, view: &'a wgpu::TextureView, viewport: Option<Viewport>) -> wgpu::RenderPassColorAttachment<'a> {
+        wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: self.color_load(context.background_color(), viewport),
+                store: true
+            }
+        }
+    }
+
+    pub fn depth_attachment<'a>(&self, view: &'a wgpu::TextureView) -> Option<wgpu::RenderPassDepthStencilAttachment<'a>> {
+        self.depth_load().map(|load| wgpu::RenderPassDepthStencilAttachment {
+            view,
+            depth_ops: Some(wgpu::Operations {
+                load,
+                store: true
+            }),
+            stencil_ops: None
+        })
+    }
+
+    //Clears always cover the whole target, drawing is limited to the viewport if there is one. The pass
+    //is timed under its label, see WgpuContext::time_pass.
+    pub fn encode<'a>(&self, context: &'a WgpuContext, encoder: &mut wgpu::CommandEncoder, color_view: &'a wgpu::TextureView, depth_view: &'a wgpu::TextureView, viewport: Option<Viewport>) {
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some(self.label),
+                color_attachments: &[Some(self.color_attachment(context, color_view, viewport))],
+                depth_stencil_attachment: self.depth_attachment(depth_view)
+            });
+
+            if let Some(Viewport { x, y, width, height }) = viewport {
+                render_pass.set_viewport(x as f32, y as f32, width as f32, height as f32, 0.0, 1.0);
+                render_pass.set_scissor_rect(x, y, width, height);
+            }
+
+            (self.record)(context, &mut render_pass);
+        }
+
+        context.time_pass(encoder, self.label);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    const BACKGROUND: wgpu::Color = wgpu::Color { r: 0.5, g: 0.6, b: 0.7, a: 1.0 };
+    const VIEWPORT: Viewport = Viewport { x: 10, y: 0, width: 100, height: 50 };
+
+    fn node(color: ColorLoad) -> RenderPassNode {
+        RenderPassNode::new("Test", color, |_, _| {})
+    }
+
+    #[wasm_bindgen_test]
+    fn color_loads() {
+        assert_eq!(node(ColorLoad::Clear(wgpu::Color::RED)).color_load(BACKGROUND, None), wgpu::LoadOp::Clear(wgpu::Color::RED));
+        assert_eq!(node(ColorLoad::Background).color_load(BACKGROUND, None), wgpu::LoadOp::Clear(BACKGROUND));
+        assert_eq!(node(ColorLoad::Keep).color_load(BACKGROUND, Some(VIEWPORT)), wgpu::LoadOp::Load);
+    }
+
+    #[wasm_bindgen_test]
+    fn covered_clears_only_around_a_viewport() {
+        let covered = node(ColorLoad::Covered);
+
+        assert_eq!(covered.color_load(BACKGROUND, None), wgpu::LoadOp::Load);
+        assert_eq!(covered.color_load(BACKGROUND, Some(VIEWPORT)), wgpu::LoadOp::Clear(BACKGROUND));
+    }
+
+    #[wasm_bindgen_test]
+    fn depth_loads() {
+        assert_eq!(node(ColorLoad::Keep).depth_load(), None);
+        assert_eq!(node(ColorLoad::Keep).with_depth(DepthLoad::Clear(1.0)).depth_load(), Some(wgpu::LoadOp::Clear(1.0)));
+        assert_eq!(node(ColorLoad::Keep).with_depth(DepthLoad::Keep).depth_load(), Some(wgpu::LoadOp::Load));
+    }
+
+    #[wasm_bindgen_test]
+    fn output_passes_have_no_depth() {
+        let pass = node(ColorLoad::Background).with_depth(DepthLoad::Clear(1.0)).to_output();
+
+        assert_eq!(pass.target, PassTarget::Output);
+        assert_eq!(pass.depth_load(), None);
+    }
+
+    #[wasm_bindgen_test]
+    fn later_viewports_keep_the_first_ones_drawing() {
+        let pass = node(ColorLoad::Background).with_depth(DepthLoad::Clear(1.0)).keeping_color();
+
+        assert_eq!(pass.color_load(BACKGROUND, Some(VIEWPORT)), wgpu::LoadOp::Load);
+        assert_eq!(pass.depth_load(), Some(wgpu::LoadOp::Clear(1.0)));
+    }
+
+    #[wasm_bindgen_test]
+    fn wide_canvases_get_bars_at_the_sides() {
+        assert_eq!(Viewport::letterbox(2000, 900, 16.0 / 9.0), Some(Viewport { x: 200, y: 0, width: 1600, height: 900 }));
+    }
+
+    #[wasm_bindgen_test]
+    fn tall_canvases_get_bars_top_and_bottom() {
+        assert_eq!(Viewport::letterbox(800, 800, 16.0 / 9.0), Some(Viewport { x: 0, y: 175, width: 800, height: 450 }));
+        assert_eq!(Viewport::letterbox(400, 900, 1.0), Some(Viewport { x: 0, y: 250, width: 400, height: 400 }));
+    }
+
+    #[wasm_bindgen_test]
+    fn matching_canvases_need_no_letterbox() {
+        assert_eq!(Viewport::letterbox(1920, 1080, 16.0 / 9.0), None);
+        assert_eq!(Viewport::letterbox(0, 1080, 16.0 / 9.0), None);
+        assert_eq!(Viewport::letterbox(1920, 1080, 0.0), None);
+        assert_eq!(Viewport::letterbox(1920, 1080, f32::NAN), None);
+    }
+
+    #[wasm_bindgen_test]
+    fn view_rects_scale_to_the_target() {
+        let left = ViewRect { x: 0.0, y: 0.0, width: 0.5, height: 1.0 };
+        assert_eq!(left.to_pixels(1280, 720), Some(Viewport { x: 0, y: 0, width: 640, height: 720 }));
+
+        let inset = ViewRect { x: 0.75, y: 0.05, width: 0.2, height: 0.3 };
+        assert_eq!(inset.to_pixels(1000, 800), Some(Viewport { x: 750, y: 40, width: 200, height: 240 }));
+    }
+
+    #[wasm_bindgen_test]
+    fn neighbouring_rects_tile_without_gaps() {
+        let (left, right) = (ViewRect { x: 0.0, y: 0.0, width: 1.0 / 3.0, height: 1.0 }, ViewRect { x: 1.0 / 3.0, y: 0.0, width: 2.0 / 3.0, height: 1.0 });
+
+        for width in [100, 101, 333, 1919] {
+            let (a, b) = (left.to_pixels(width, 10).unwrap(), right.to_pixels(width, 10).unwrap());
+            assert_eq!(a.x + a.width, b.x, "gap at width {}", width);
+            assert_eq!(b.x + b.width, width);
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn rects_are_clipped_to_the_target() {
+        let overhanging = ViewRect { x: 0.8, y: -0.5, width: 0.5, height: 1.0 };
+        assert_eq!(overhanging.to_pixels(100, 100), Some(Viewport { x: 80, y: 0, width: 20, height: 50 }));
+
+        let outside = ViewRect { x: 1.2, y: 0.0, width: 0.5, height: 1.0 };
+        assert_eq!(outside.to_pixels(100, 100), None);
+        assert_eq!(outside.aspect(100, 100), 1.0);
+    }
+}
@@ -0,0 +1,160 @@
+use std::{cell::{Cell, RefCell}, rc::Rc};
+
+//Most timestamps a frame can write, the start, one after each pass and the end. Passes past the limit
+//aren't marked, their time goes to the frame's total only.
+const MAX_QUERIES: u32 = 32;
+const TIMESTAMP_SIZE: wgpu::BufferAddress = std::mem::size_of::<u64>() as wgpu::BufferAddress;
+
+//A frame's GPU time in milliseconds, overall and per pass. Passes that ran more than once, like one per
+//view, are summed under their label.
+#[derive(Clone, Debug, Default)]
+pub struct GpuTimes {
+    pub total: f64,
+    pub passes: Vec<(&'static str, f64)>
+}
+
+//Times a command encoder with timestamp queries, one at the start, one after each pass that's marked and
+//one at the end. Only one readback is in flight at a time, frames submitted while it's mapping just
+//aren't measured.
+pub struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    readback_buffer: Rc<wgpu::Buffer>,
+    //Nanoseconds per timestamp tick
+    period: f32,
+
+    active: bool,
+    //Written while passes are recorded, which only get the context by reference
+    queries: Cell<u32>,
+    labels: RefCell<Vec<&'static str>>,
+    mapping: Rc<Cell<bool>>,
+    result: Rc<RefCell<Option<GpuTimes>>>
+}
+
+impl GpuTimer {
+    //None if the device wasn't created with TIMESTAMP_QUERY
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("Frame timestamps"),
+            ty: wgpu::QueryType::Timestamp,
+            count: MAX_QUERIES
+        });
+
+        let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Timestamp resolve buffer"),
+            size: MAX_QUERIES as wgpu::BufferAddress * TIMESTAMP_SIZE,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false
+        });
+
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Timestamp readback buffer"),
+            size: MAX_QUERIES as wgpu::BufferAddress * TIMESTAMP_SIZE,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false
+        });
+
+        Some(GpuTimer {
+            query_set,
+            resolve_buffer,
+            readback_buffer: Rc::new(readback_buffer),
+            period: queue.get_timestamp_period(),
+
+            active: false,
+            queries: Cell::new(0),
+            labels: RefCell::new(Vec::new()),
+            mapping: Rc::new(Cell::new(false)),
+            result: Rc::new(RefCell::new(None))
+        })
+    }
+
+    pub fn begin(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        self.active = !self.mapping.get();
+
+        if self.active {
+            encoder.write_timestamp(&self.query_set, 0);
+            self.queries.set(1);
+            self.labels.borrow_mut().clear();
+        }
+    }
+
+    //Everything recorded since begin or the last mark is timed as label. Call between passes, timestamps
+    //can't be written inside one.
+    pub fn mark(&self, encoder: &mut wgpu::CommandEncoder, label: &'static str) {
+        let query = self.queries.get();
+        //The last query is kept for end
+        if !self.active || query + 1 >= MAX_QUERIES {
+            return;
+        }
+
+        encoder.write_timestamp(&self.query_set, query);
+        self.queries.set(query + 1);
+        self.labels.borrow_mut().push(label);
+    }
+
+    pub fn end(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if !self.active {
+            return;
+        }
+
+        let count = self.queries.get() + 1;
+        let size = count as wgpu::BufferAddress * TIMESTAMP_SIZE;
+        encoder.write_timestamp(&self.query_set, count - 1);
+        encoder.resolve_query_set(&self.query_set, 0..count, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(&self.resolve_buffer, 0, &self.readback_buffer, 0, size);
+        self.queries.set(count);
+    }
+
+    //Call once the encoder passed to begin/end has been submitted
+    pub fn read_back(&mut self) {
+        if !std::mem::take(&mut self.active) {
+            return;
+        }
+        self.mapping.set(true);
+
+        let buffer = self.readback_buffer.clone();
+        let mapping = self.mapping.clone();
+        let result = self.result.clone();
+        let labels = self.labels.take();
+        let size = self.queries.get() as wgpu::BufferAddress * TIMESTAMP_SIZE;
+        let period = self.period as f64;
+
+        self.readback_buffer.slice(..size).map_async(wgpu::MapMode::Read, move |status| {
+            if status.is_ok() {
+                {
+                    let data = buffer.slice(..size).get_mapped_range();
+                    let timestamps: &[u64] = bytemuck::cast_slice(&data);
+                    let to_millis = |start: u64, end: u64| end.wrapping_sub(start) as f64 * period / 1_000_000.0;
+
+                    let mut times = GpuTimes {
+                        total: to_millis(timestamps[0], timestamps[timestamps.len() - 1]),
+                        passes: Vec::new()
+                    };
+
+                    for (label, pair) in labels.into_iter().zip(timestamps.windows(2)) {
+                        let time = to_millis(pair[0], pair[1]);
+
+                        match times.passes.iter_mut().find(|(name, _)| *name == label) {
+                            Some((_, total)) => *total += time,
+                            None => times.passes.push((label, time))
+                        }
+                    }
+
+                    result.replace(Some(times));
+                }
+                buffer.unmap();
+            }
+
+            mapping.set(false);
+        });
+    }
+
+    //Most recent frame's times, each measurement is only returned once
+    pub fn take_result(&self) -> Option<GpuTimes> {
+        self.result.take()
+    }
+}
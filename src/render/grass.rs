@@ -0,0 +1,337 @@
+use wgpu::util::DeviceExt;
+
+use crate::noise::{perm::{shuffle, SplitMix64}, source::Seed};
+
+use super::chunk::CHUNK_APRON;
+use super::displacement::chunk_seed;
+use super::splat::{DEFAULT_GRASS_COLOR, DEFAULT_ROCK_SLOPE, DEFAULT_SAND_COLOR, DEFAULT_SAND_LINE, DEFAULT_SNOW_LINE};
+
+//Fraction of the scattered blades drawn
+pub const DEFAULT_GRASS_DENSITY: f32 = 0.5;
+//World units from the camera, blades shrink away over the last quarter of it
+pub const DEFAULT_GRASS_DISTANCE: f32 = 40.0;
+
+//Candidate blades along each side of a chunk, one per cell at a random spot in it
+const GRASS_CELLS: u32 = 96;
+const MAX_GRASS_INSTANCES: u32 = GRASS_CELLS * GRASS_CELLS;
+//World units, before each blade's own scale
+const BLADE_HEIGHT: f32 = 0.35;
+const BLADE_WIDTH: f32 = 0.25;
+//Two crossed quads of two triangles each, see vs_grass in shader.wgsl
+const BLADE_VERTICES: u32 = 12;
+pub const BLADE_TRIANGLES: u32 = BLADE_VERTICES / 3;
+//How far the height bands are blurred, in normalised height, so grass thins out towards them
+const BAND_FADE: f32 = 0.04;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GrassInstance {
+    //Where in the chunk, the vertex shader finds the height there
+    uv: [f32; 2],
+    //Radians about the vertical
+    rotation: f32,
+    scale: f32,
+    //Linear RGB
+    tint: [f32; 3]
+}
+
+impl GrassInstance {
+    const ATTRIBS: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32, 2 => Float32, 3 => Float32x3];
+
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<GrassInstance>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBS
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct GrassSettings {
+    distance: f32,
+    blade_height: f32,
+    blade_width: f32,
+    //Chunk size in world units, turning an instance's uv into a position
+    extent: f32
+}
+
+//Has to match GrassSettings in shader.wgsl
+const _: () = assert!(std::mem::size_of::<GrassSettings>() == 16);
+
+//Where blades can grow, from a chunk's normalised heights. Between the sand and snow lines and flatter
+//than rock, like the grass band of the splat. The masks are the splat's defaults rather than its live
+//settings, changing those would mean scattering every chunk again.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GrassMask {
+    pub min_height: f32,
+    pub max_height: f32,
+    //1 - normal.y, like SplatSettings::rock_slope
+    pub max_slope: f32
+}
+
+impl Default for GrassMask {
+    fn default() -> Self {
+        GrassMask {
+            min_height: DEFAULT_SAND_LINE,
+            max_height: DEFAULT_SNOW_LINE,
+            max_slope: DEFAULT_ROCK_SLOPE
+        }
+    }
+}
+
+impl GrassMask {
+    //Chance of a blade at a height and slope
+    fn weight(&self, height: f32, slope: f32) -> f32 {
+        if slope > self.max_slope {
+            return 0.0;
+        }
+
+        let above = ((height - self.min_height) / BAND_FADE).clamp(0.0, 1.0);
+        let below = ((self.max_height - height) / BAND_FADE).clamp(0.0, 1.0);
+        above * below
+    }
+}
+
+//Blades for the chunk at coord, from its texels laid out like chunk::noise_buffer. Each cell of a
+//GRASS_CELLS grid gets a candidate at a random spot, kept with the mask's chance there. They come back
+//shuffled, so drawing any number of the first ones thins the grass out evenly. texel_size and
+//height_scale are in world units and only used for the slope.
+#[allow(clippy::too_many_arguments)]
+pub fn scatter(heights: &[f32], stride: usize, tex_size: u32, texel_size: f32, height_scale: f32, mask: &GrassMask, coord: (i32, i32), seed: Seed) -> Vec<GrassInstance> {
+    let mut random = SplitMix64::new(chunk_seed(seed, coord));
+    let mut next = || random.next_f64() as f32;
+
+    //The apron has the neighbours of the texels along the edge
+    let apron = CHUNK_APRON as i32;
+    let texel = |x: i32, y: i32| heights[(y + apron) as usize * stride + (x + apron) as usize];
+    let last = (tex_size - 1) as f32;
+
+    let mut instances = Vec::new();
+    for cell in 0..MAX_GRASS_INSTANCES {
+        let u = ((cell % GRASS_CELLS) as f32 + next()) / GRASS_CELLS as f32;
+        let v = ((cell / GRASS_CELLS) as f32 + next()) / GRASS_CELLS as f32;
+
+        //The nearest texel is close enough for the masks
+        let (x, y) = ((u * last).round() as i32, (v * last).round() as i32);
+        let height = texel(x, y);
+        let gradient = [texel(x + 1, y) - texel(x - 1, y), texel(x, y + 1) - texel(x, y - 1)]
+            .map(|difference| difference * height_scale / (2.0 * texel_size));
+        let slope = 1.0 - 1.0 / (1.0 + gradient[0] * gradient[0] + gradient[1] * gradient[1]).sqrt();
+
+        if next() >= mask.weight(height, slope) {
+            continue;
+        }
+
+        let brightness = 0.75 + 0.5 * next();
+        let dryness = next() * next() * 0.6;
+        let tint = [0, 1, 2].map(|i| (DEFAULT_GRASS_COLOR[i] + (DEFAULT_SAND_COLOR[i] - DEFAULT_GRASS_COLOR[i]) * dryness) * brightness);
+
+        instances.push(GrassInstance {
+            uv: [u, v],
+            rotation: next() * std::f32::consts::TAU,
+            scale: 0.6 + 0.8 * next(),
+            tint
+        });
+    }
+
+    shuffle(&mut instances, &mut random);
+    instances
+}
+
+//A chunk's blades. The buffer holds as many as a chunk can have, so it's reused as chunks are refilled.
+pub struct GrassInstances {
+    buffer: wgpu::Buffer,
+    count: u32
+}
+
+impl GrassInstances {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Grass instance buffer"),
+            size: MAX_GRASS_INSTANCES as wgpu::BufferAddress * std::mem::size_of::<GrassInstance>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false
+        });
+
+        GrassInstances {
+            buffer,
+            count: 0
+        }
+    }
+
+    //Returns the bytes written
+    pub fn write(&mut self, queue: &wgpu::Queue, instances: &[GrassInstance]) -> u64 {
+        let instances = &instances[..instances.len().min(MAX_GRASS_INSTANCES as usize)];
+        self.count = instances.len() as u32;
+
+        let bytes: &[u8] = bytemuck::cast_slice(instances);
+        if !bytes.is_empty() {
+            queue.write_buffer(&self.buffer, 0, bytes);
+        }
+
+        bytes.len() as u64
+    }
+}
+
+//Small crossed-quad blades scattered over the chunks (see scatter), one instanced draw per chunk with
+//the chunk's height texture bound so the vertex shader can stand them on the terrain. Only the nearest
+//chunks are drawn and the blades shrink away towards the distance, but it's still heavy, so it's off
+//unless the setting turns it on.
+pub struct GrassRenderer {
+    layout: wgpu::PipelineLayout,
+    pipeline: wgpu::RenderPipeline,
+    settings: GrassSettings,
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    density: f32
+}
+
+impl GrassRenderer {
+    //Groups 0 to 2 are the terrain's, the shader is the terrain's too
+    pub fn new(device: &wgpu::Device, shader: &wgpu::ShaderModule, format: wgpu::TextureFormat, depth_format: wgpu::TextureFormat, terrain_layouts: [&wgpu::BindGroupLayout; 3], extent: f32) -> Self {
+        let settings = GrassSettings {
+            distance: DEFAULT_GRASS_DISTANCE,
+            blade_height: BLADE_HEIGHT,
+            blade_width: BLADE_WIDTH,
+            extent
+        };
+
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Grass uniform buffer"),
+            contents: bytemuck::cast_slice(&[settings]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("Grass bind group layout"),
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("Grass bind group"),
+        });
+
+        let [settings_layout, texture_layout, chunk_layout] = terrain_layouts;
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Grass Pipeline Layout"),
+            bind_group_layouts: &[settings_layout, texture_layout, chunk_layout, &bind_group_layout],
+            push_constant_ranges: &[]
+        });
+
+        let pipeline = Self::create_pipeline(device, &layout, shader, format, depth_format);
+
+        GrassRenderer {
+            layout,
+            pipeline,
+            settings,
+            buffer,
+            bind_group,
+            density: DEFAULT_GRASS_DENSITY
+        }
+    }
+
+    fn create_pipeline(device: &wgpu::Device, layout: &wgpu::PipelineLayout, shader: &wgpu::ShaderModule, format: wgpu::TextureFormat, depth_format: wgpu::TextureFormat) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Grass Pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_grass",
+                buffers: &[GrassInstance::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "fs_grass",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                //Blades are seen from both sides
+                cull_mode: None,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: depth_format,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default()
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None
+        })
+    }
+
+    //For when the terrain shader is reloaded, see WgpuContext::finish_rebuild
+    pub fn rebuild(&mut self, device: &wgpu::Device, shader: &wgpu::ShaderModule, format: wgpu::TextureFormat, depth_format: wgpu::TextureFormat) {
+        self.pipeline = Self::create_pipeline(device, &self.layout, shader, format, depth_format);
+    }
+
+    pub fn density(&self) -> f32 {
+        self.density
+    }
+
+    //Only changes how many of each chunk's blades are drawn, nothing is scattered again
+    pub fn set_density(&mut self, density: f32) {
+        self.density = density.clamp(0.0, 1.0);
+    }
+
+    pub fn distance(&self) -> f32 {
+        self.settings.distance
+    }
+
+    pub fn set_distance(&mut self, queue: &wgpu::Queue, distance: f32) {
+        self.settings.distance = distance.max(0.0);
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.settings]));
+    }
+
+    //Whether any of a chunk at offset (from the floating origin) is within the distance of the camera
+    pub fn in_range(&self, offset: [f32; 2], camera_position: [f32; 3]) -> bool {
+        let half = self.settings.extent / 2.0;
+        let (dx, dz) = (offset[0] + half - camera_position[0], offset[1] + half - camera_position[2]);
+
+        (dx * dx + dz * dz).sqrt() - half * std::f32::consts::SQRT_2 <= self.settings.distance
+    }
+
+    //Sets the pipeline and the groups every chunk shares, before draw
+    pub fn bind<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, settings_bind_group: &'a wgpu::BindGroup) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, settings_bind_group, &[]);
+        render_pass.set_bind_group(3, &self.bind_group, &[]);
+    }
+
+    //The chunk's own groups have to be bound already. Returns how many blades were drawn.
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, instances: &'a GrassInstances) -> u32 {
+        let count = (instances.count as f32 * self.density).round() as u32;
+        if count > 0 {
+            render_pass.set_vertex_buffer(0, instances.buffer.slice(..));
+            render_pass.draw(0..BLADE_VERTICES, 0..count);
+        }
+
+        count
+    }
+}
@@ -0,0 +1,179 @@
+use wgpu::util::DeviceExt;
+
+use super::camera::Camera;
+use super::wgpu_context::BlendMode;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LineVertex {
+    position: [f32; 3],
+    //Alpha only counts with a transparent pipeline
+    color: [f32; 4]
+}
+
+impl LineVertex {
+    const ATTRIBS: [wgpu::VertexAttribute; 2] = wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x4];
+
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<LineVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBS
+        }
+    }
+}
+
+//Faint enough that the terrain shows through, the axes are drawn solid
+const GRID_COLOR: [f32; 4] = [0.4, 0.4, 0.4, 0.5];
+const AXIS_LENGTH: f32 = 10.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GridConfig {
+    pub spacing: f32,
+    //Number of lines drawn on each side of the centre line
+    pub half_lines: u32
+}
+
+impl Default for GridConfig {
+    fn default() -> Self {
+        GridConfig {
+            spacing: 1.0,
+            half_lines: 50
+        }
+    }
+}
+
+impl GridConfig {
+    fn vertex_count(&self) -> usize {
+        let lines = 2 * (2 * self.half_lines as usize + 1);
+        2 * lines + 6
+    }
+
+    //The grid cell the camera is in, the grid is only rebuilt when this changes
+    pub fn center_cell(&self, camera: &Camera) -> (i32, i32) {
+        (
+            (camera.eye.x / self.spacing).round() as i32,
+            (camera.eye.z / self.spacing).round() as i32
+        )
+    }
+
+    //XZ grid lines around the given cell followed by RGB axis lines at the origin
+    pub fn vertices(&self, (cell_x, cell_z): (i32, i32)) -> Vec<LineVertex> {
+        let mut vertices = Vec::with_capacity(self.vertex_count());
+
+        let half = self.half_lines as i32;
+        let extent = half as f32 * self.spacing;
+        let (center_x, center_z) = (cell_x as f32 * self.spacing, cell_z as f32 * self.spacing);
+
+        for i in -half..=half {
+            let offset = i as f32 * self.spacing;
+
+            vertices.push(LineVertex { position: [center_x + offset, 0.0, center_z - extent], color: GRID_COLOR });
+            vertices.push(LineVertex { position: [center_x + offset, 0.0, center_z + extent], color: GRID_COLOR });
+
+            vertices.push(LineVertex { position: [center_x - extent, 0.0, center_z + offset], color: GRID_COLOR });
+            vertices.push(LineVertex { position: [center_x + extent, 0.0, center_z + offset], color: GRID_COLOR });
+        }
+
+        let axes = [
+            ([AXIS_LENGTH, 0.0, 0.0], [1.0, 0.0, 0.0, 1.0]),
+            ([0.0, AXIS_LENGTH, 0.0], [0.0, 1.0, 0.0, 1.0]),
+            ([0.0, 0.0, AXIS_LENGTH], [0.0, 0.0, 1.0, 1.0])
+        ];
+        for (end, color) in axes {
+            vertices.push(LineVertex { position: [0.0, 0.0, 0.0], color });
+            vertices.push(LineVertex { position: end, color });
+        }
+
+        vertices
+    }
+}
+
+pub struct GridRenderer {
+    pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    num_vertices: u32,
+
+    config: GridConfig,
+    center: Option<(i32, i32)>
+}
+
+impl GridRenderer {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, depth_format: wgpu::TextureFormat, settings_layout: &wgpu::BindGroupLayout, config: GridConfig, blend: BlendMode) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Line shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/lines.wgsl").into())
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Line Pipeline Layout"),
+            bind_group_layouts: &[settings_layout],
+            push_constant_ranges: &[]
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Line Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[
+                    LineVertex::desc()
+                ],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(blend.color_target(format))],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false
+            },
+            depth_stencil: Some(blend.depth_stencil(depth_format, wgpu::CompareFunction::LessEqual)),
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false
+            },
+            multiview: None
+        });
+
+        let vertices = config.vertices((0, 0));
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Grid vertex buffer"),
+            contents: bytemuck::cast_slice(&vertices),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        });
+
+        Self {
+            pipeline,
+            vertex_buffer,
+            num_vertices: vertices.len() as u32,
+
+            config,
+            center: Some((0, 0))
+        }
+    }
+
+    pub fn update(&mut self, queue: &wgpu::Queue, camera: &Camera) {
+        let center = self.config.center_cell(camera);
+
+        if self.center != Some(center) {
+            queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&self.config.vertices(center)));
+            self.center = Some(center);
+        }
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, settings_bind_group: &'a wgpu::BindGroup) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, settings_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.draw(0..self.num_vertices, 0..1);
+    }
+}
@@ -0,0 +1,186 @@
+use std::rc::Rc;
+
+use serde::Serialize;
+
+use crate::noise::source::NoiseSource;
+
+use super::displacement::Displacement;
+
+//Cached terrain heights on a coarse grid over the chunk, for anything that queries the height a lot
+//(collision, picking, spawning). Queries between grid nodes are bilinearly interpolated, so they're
+//exact on the nodes and close elsewhere. Rebuilt whenever the displacement changes, e.g. on reseed.
+pub struct HeightField {
+    source: Rc<dyn NoiseSource>,
+    //Nodes along each side
+    resolution: usize,
+    displacement: Option<Displacement>,
+    heights: Vec<f32>
+}
+
+impl HeightField {
+    pub fn new(source: Rc<dyn NoiseSource>, resolution: usize) -> Self {
+        HeightField {
+            source,
+            resolution: resolution.max(2),
+            displacement: None,
+            heights: Vec::new()
+        }
+    }
+
+    //Rebuilds the grid if the displacement is different from the one it was built for
+    pub fn update(&mut self, displacement: Displacement) {
+        if self.displacement == Some(displacement) {
+            return;
+        }
+
+        let spacing = self.spacing(&displacement);
+        let resolution = self.resolution;
+        let source = self.source.as_ref();
+
+        self.heights = (0..resolution * resolution)
+            .map(|i| displacement.height_at(source, (i % resolution) as f32 * spacing, (i / resolution) as f32 * spacing))
+            .collect();
+        self.displacement = Some(displacement);
+    }
+
+    pub fn invalidate(&mut self) {
+        self.displacement = None;
+    }
+
+    pub fn resolution(&self) -> usize {
+        self.resolution
+    }
+
+    //World units between grid nodes
+    fn spacing(&self, displacement: &Displacement) -> f32 {
+        (displacement.chunk_size - 1) as f32 / (self.resolution - 1) as f32
+    }
+
+    //World position of a grid node
+    pub fn node_position(&self, i: usize, j: usize) -> Option<(f32, f32)> {
+        let spacing = self.spacing(self.displacement.as_ref()?);
+        Some((i as f32 * spacing, j as f32 * spacing))
+    }
+
+    //Interpolated height at a world position, clamped to the chunk. None until update has been called.
+    pub fn height_at(&self, x: f32, z: f32) -> Option<f32> {
+        let displacement = self.displacement.as_ref()?;
+        let spacing = self.spacing(displacement);
+        let last = (self.resolution - 1) as f32;
+
+        let gx = (x / spacing).clamp(0.0, last);
+        let gz = (z / spacing).clamp(0.0, last);
+
+        let (i, j) = ((gx.floor() as usize).min(self.resolution - 2), (gz.floor() as usize).min(self.resolution - 2));
+        let (tx, tz) = (gx - i as f32, gz - j as f32);
+
+        let height = |i: usize, j: usize| self.heights[j * self.resolution + i];
+        let mix = |a: f32, b: f32, t: f32| a + (b - a) * t;
+
+        Some(mix(
+            mix(height(i, j), height(i + 1, j), tx),
+            mix(height(i, j + 1), height(i + 1, j + 1), tx),
+            tz
+        ))
+    }
+}
+
+//A chunk's heights as its texture holds them, read back from the GPU (see Chunk::read_heights). size x
+//size texels, apron included, in rows with nothing between them.
+#[derive(Clone, Debug, Serialize)]
+pub struct Heightmap {
+    pub coord: (i32, i32),
+    pub size: u32,
+    pub apron: u32,
+    pub heights: Vec<f32>
+}
+
+impl Heightmap {
+    //Texels from uv 0 to uv 1, the apron left out
+    pub fn inner_size(&self) -> u32 {
+        self.size - 2 * self.apron
+    }
+
+    //Normalised height at a chunk uv, filtered like sample_height in shader.wgsl so it matches what's drawn
+    pub fn height_at_uv(&self, u: f32, v: f32) -> f32 {
+        let max = (self.inner_size() - 1) as f32;
+        let (x, y) = (u.clamp(0.0, 1.0) * max, v.clamp(0.0, 1.0) * max);
+        let (base_x, base_y) = ((x.floor() as u32).min(self.inner_size() - 2), (y.floor() as u32).min(self.inner_size() - 2));
+        let (fx, fy) = (x - base_x as f32, y - base_y as f32);
+
+        let texel = |dx: u32, dy: u32| self.heights[((base_y + dy + self.apron) * self.size + base_x + dx + self.apron) as usize];
+        let mix = |a: f32, b: f32, t: f32| a + (b - a) * t;
+
+        mix(mix(texel(0, 0), texel(1, 0), fx), mix(texel(0, 1), texel(1, 1), fx), fy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use crate::noise::source::TestSource;
+
+    use super::*;
+
+    fn displacement(seed: u64) -> Displacement {
+        Displacement {
+            tex_size: 33,
+            chunk_size: 17,
+            noise_frequency: 0.2,
+            seed,
+            chunk_variation: 0.0,
+            height_scale: 30.0,
+            detail_strength: 0.5,
+            detail_frequency: 0.3
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn nodes_match_direct_sampling() {
+        let mut field = HeightField::new(Rc::new(TestSource), 5);
+        assert_eq!(field.height_at(0.0, 0.0), None);
+
+        let displacement = displacement(1);
+        field.update(displacement);
+
+        for j in 0..field.resolution() {
+            for i in 0..field.resolution() {
+                let (x, z) = field.node_position(i, j).unwrap();
+                let direct = displacement.height_at(&TestSource, x, z);
+
+                assert!((field.height_at(x, z).unwrap() - direct).abs() < 1e-4, "node {}, {}", i, j);
+            }
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn between_nodes_is_interpolated() {
+        let mut field = HeightField::new(Rc::new(TestSource), 5);
+        field.update(displacement(1));
+
+        let (x0, z) = field.node_position(1, 2).unwrap();
+        let (x1, _) = field.node_position(2, 2).unwrap();
+        let expected = (field.height_at(x0, z).unwrap() + field.height_at(x1, z).unwrap()) / 2.0;
+
+        assert!((field.height_at((x0 + x1) / 2.0, z).unwrap() - expected).abs() < 1e-4);
+    }
+
+    #[wasm_bindgen_test]
+    fn queries_outside_clamp_to_the_edge() {
+        let mut field = HeightField::new(Rc::new(TestSource), 4);
+        field.update(displacement(1));
+
+        assert_eq!(field.height_at(-10.0, 3.0), field.height_at(0.0, 3.0));
+        assert_eq!(field.height_at(100.0, 100.0), field.height_at(16.0, 16.0));
+    }
+
+    #[wasm_bindgen_test]
+    fn invalidate_forgets_the_grid() {
+        let mut field = HeightField::new(Rc::new(TestSource), 4);
+        field.update(displacement(1));
+        field.invalidate();
+
+        assert_eq!(field.height_at(1.0, 1.0), None);
+    }
+}
@@ -0,0 +1,232 @@
+use std::collections::HashSet;
+
+use super::event::{Event, KeyTracker, KeyboardKey, KeyboardEventData, MouseEventData, WheelEventData};
+
+pub struct InputState {
+    pub forward: f32,
+    pub right: f32,
+    pub up: f32,
+
+    pub look_x: f32,
+    pub look_y: f32,
+
+    pub wheel: f32,
+
+    held: HashSet<KeyboardKey>,
+    pressed: HashSet<KeyboardKey>
+}
+
+impl InputState {
+    //No movement and no keys, for frames where input is ignored
+    pub fn idle() -> Self {
+        InputState {
+            forward: 0.0,
+            right: 0.0,
+            up: 0.0,
+
+            look_x: 0.0,
+            look_y: 0.0,
+
+            wheel: 0.0,
+
+            held: HashSet::new(),
+            pressed: HashSet::new()
+        }
+    }
+
+    pub fn is_held(&self, key: KeyboardKey) -> bool {
+        self.held.contains(&key)
+    }
+
+    //True only on the first frame the key is down
+    pub fn was_pressed(&self, key: KeyboardKey) -> bool {
+        self.pressed.contains(&key)
+    }
+
+    pub fn pressed_keys(&self) -> impl Iterator<Item = KeyboardKey> + '_ {
+        self.pressed.iter().copied()
+    }
+}
+
+//Accumulates events between frames and turns them into an InputState snapshot
+pub struct InputTracker {
+    keyboard: KeyTracker,
+    pressed: HashSet<KeyboardKey>,
+
+    look_x: f32,
+    look_y: f32,
+    wheel: f32
+}
+
+impl InputTracker {
+    pub fn new() -> Self {
+        InputTracker {
+            keyboard: KeyTracker::new(),
+            pressed: HashSet::new(),
+
+            look_x: 0.0,
+            look_y: 0.0,
+            wheel: 0.0
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &Event) {
+        match event {
+            Event::KeyDown(KeyboardEventData {key, repeat, ..}) => {
+                //Auto-repeats are flagged, so a key whose keyup was missed still counts when pressed again
+                if !*repeat {
+                    self.pressed.insert(*key);
+                }
+
+                self.keyboard.set_key_down(*key);
+            },
+            Event::KeyUp(KeyboardEventData {key, ..}) => self.keyboard.set_key_up(*key),
+
+            Event::MouseMove(MouseEventData {movement_x, movement_y, ..}) => {
+                self.look_x += *movement_x as f32;
+                self.look_y += *movement_y as f32;
+            },
+
+            Event::MouseWheel(WheelEventData {delta_y, ..}) => self.wheel += *delta_y as f32,
+
+            Event::FocusLost => self.keyboard.release_all(),
+
+            _ => {}
+        }
+    }
+
+    fn axis(&self, positive: KeyboardKey, negative: KeyboardKey) -> f32 {
+        let mut value = 0.0;
+
+        if self.keyboard.is_key_down(positive) {
+            value += 1.0;
+        }
+        if self.keyboard.is_key_down(negative) {
+            value -= 1.0;
+        }
+
+        value
+    }
+
+    pub fn snapshot(&mut self) -> InputState {
+        let held = self.keyboard.keys_down().collect();
+
+        let state = InputState {
+            forward: self.axis(KeyboardKey::Character('w'), KeyboardKey::Character('s')),
+            right: self.axis(KeyboardKey::Character('d'), KeyboardKey::Character('a')),
+            up: self.axis(KeyboardKey::Character(' '), KeyboardKey::Shift),
+
+            look_x: self.look_x,
+            look_y: self.look_y,
+
+            wheel: self.wheel,
+
+            held,
+            pressed: std::mem::take(&mut self.pressed)
+        };
+
+        self.look_x = 0.0;
+        self.look_y = 0.0;
+        self.wheel = 0.0;
+
+        state
+    }
+}
+
+impl Default for InputTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+    use crate::render::event::MouseButton;
+
+    fn key(key: KeyboardKey, repeat: bool) -> KeyboardEventData {
+        KeyboardEventData { alt_key: false, ctrl_key: false, shift_key: false, meta_key: false, key, repeat }
+    }
+
+    const W: KeyboardKey = KeyboardKey::Character('w');
+
+    #[wasm_bindgen_test]
+    fn pressed_only_on_the_first_frame() {
+        let mut tracker = InputTracker::new();
+        tracker.handle_event(&Event::KeyDown(key(W, false)));
+
+        let first = tracker.snapshot();
+        assert!(first.was_pressed(W));
+        assert!(first.is_held(W));
+
+        let second = tracker.snapshot();
+        assert!(!second.was_pressed(W));
+        assert!(second.is_held(W));
+        assert_eq!(second.forward, 1.0);
+    }
+
+    #[wasm_bindgen_test]
+    fn repeats_are_not_presses() {
+        let mut tracker = InputTracker::new();
+        tracker.handle_event(&Event::KeyDown(key(W, false)));
+        tracker.snapshot();
+
+        tracker.handle_event(&Event::KeyDown(key(W, true)));
+        let state = tracker.snapshot();
+        assert!(!state.was_pressed(W));
+        assert!(state.is_held(W));
+    }
+
+    #[wasm_bindgen_test]
+    fn press_and_release_within_a_frame_still_counts() {
+        let mut tracker = InputTracker::new();
+        tracker.handle_event(&Event::KeyDown(key(W, false)));
+        tracker.handle_event(&Event::KeyUp(key(W, false)));
+
+        let state = tracker.snapshot();
+        assert!(state.was_pressed(W));
+        assert!(!state.is_held(W));
+        assert_eq!(state.forward, 0.0);
+    }
+
+    #[wasm_bindgen_test]
+    fn pressed_again_after_release() {
+        let mut tracker = InputTracker::new();
+        tracker.handle_event(&Event::KeyDown(key(W, false)));
+        tracker.snapshot();
+        tracker.handle_event(&Event::KeyUp(key(W, false)));
+        assert!(!tracker.snapshot().is_held(W));
+
+        tracker.handle_event(&Event::KeyDown(key(W, false)));
+        assert!(tracker.snapshot().was_pressed(W));
+    }
+
+    #[wasm_bindgen_test]
+    fn focus_lost_releases_held_keys() {
+        let mut tracker = InputTracker::new();
+        tracker.handle_event(&Event::KeyDown(key(W, false)));
+        tracker.handle_event(&Event::FocusLost);
+
+        assert!(!tracker.snapshot().is_held(W));
+    }
+
+    #[wasm_bindgen_test]
+    fn look_is_reset_between_frames() {
+        let mut tracker = InputTracker::new();
+        let movement = |x, y| Event::MouseMove(MouseEventData {
+            alt_key: false, ctrl_key: false, shift_key: false, meta_key: false,
+            button: MouseButton::Left,
+            movement_x: x, movement_y: y, x: 0, y: 0
+        });
+        tracker.handle_event(&movement(3, -2));
+        tracker.handle_event(&movement(1, 1));
+
+        let state = tracker.snapshot();
+        assert_eq!((state.look_x, state.look_y), (4.0, -1.0));
+
+        let state = tracker.snapshot();
+        assert_eq!((state.look_x, state.look_y), (0.0, 0.0));
+    }
+}
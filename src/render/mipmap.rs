@@ -0,0 +1,148 @@
+//Levels in a full mip chain for a size x size texture, down to 1x1
+pub fn mip_level_count(size: u32) -> u32 {
+    32 - size.max(1).leading_zeros()
+}
+
+//Fills in a texture's mip chain from its first level, each level drawn from the one above it with
+//downsample.wgsl. wgpu has no generator of its own. Every texel is a plain average of the 2x2 above it,
+//for heights too: the fragment shader only shades with the far away levels, and an average keeps the
+//mean height (and brightness) of a patch where a min or max would darken or brighten distant terrain.
+pub struct MipGenerator {
+    layout: wgpu::BindGroupLayout,
+    //One per format, pipelines are tied to their target's format
+    pipelines: Vec<(wgpu::TextureFormat, wgpu::RenderPipeline)>
+}
+
+impl MipGenerator {
+    //Formats supports turns down get no pipeline, textures in them keep a single level
+    pub fn new(device: &wgpu::Device, formats: &[wgpu::TextureFormat]) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Downsample shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/downsample.wgsl").into())
+        });
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false
+                    },
+                    count: None,
+                }
+            ],
+            label: Some("Downsample bind group layout"),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Downsample Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[]
+        });
+
+        let pipelines = formats.iter().copied().filter(|&format| Self::supports(device, format)).map(|format| {
+            let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("Downsample Pipeline"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None
+            });
+
+            (format, pipeline)
+        }).collect();
+
+        MipGenerator {
+            layout,
+            pipelines
+        }
+    }
+
+    //Levels are drawn to, so the format has to be renderable
+    pub fn supports(device: &wgpu::Device, format: wgpu::TextureFormat) -> bool {
+        format.guaranteed_format_features(device.features()).allowed_usages.contains(wgpu::TextureUsages::RENDER_ATTACHMENT)
+    }
+
+    //How many levels to give a size x size texture of format, 1 if its chain can't be generated
+    pub fn level_count(&self, format: wgpu::TextureFormat, size: u32) -> u32 {
+        if self.pipeline(format).is_some() {
+            mip_level_count(size)
+        } else {
+            1
+        }
+    }
+
+    fn pipeline(&self, format: wgpu::TextureFormat) -> Option<&wgpu::RenderPipeline> {
+        self.pipelines.iter().find(|(pipeline_format, _)| *pipeline_format == format).map(|(_, pipeline)| pipeline)
+    }
+
+    //Call after the first level has been written. Does nothing for textures without a chain.
+    pub fn generate(&self, device: &wgpu::Device, queue: &wgpu::Queue, texture: &wgpu::Texture) {
+        let Some(pipeline) = self.pipeline(texture.format()).filter(|_| texture.mip_level_count() > 1) else {
+            return;
+        };
+
+        let level_view = |level: u32| texture.create_view(&wgpu::TextureViewDescriptor {
+            base_mip_level: level,
+            mip_level_count: Some(1),
+            ..Default::default()
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Mipmap Encoder")
+        });
+
+        for level in 1..texture.mip_level_count() {
+            let source = level_view(level - 1);
+            let target = level_view(level);
+
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &self.layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&source)
+                    }
+                ],
+                label: Some("Downsample bind group")
+            });
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Downsample pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true
+                    }
+                })],
+                depth_stencil_attachment: None
+            });
+
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+
+        queue.submit(Some(encoder.finish()));
+    }
+}
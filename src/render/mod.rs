@@ -1,4 +1,37 @@
-pub mod wgpu_context;
-pub mod runtime;
-pub mod event;
-pub mod camera;
\ No newline at end of file
+pub mod wgpu_context;
+pub mod runtime;
+pub mod event;
+pub mod camera;
+pub mod input;
+pub mod controller;
+pub mod stats;
+pub mod recording;
+pub mod grid;
+pub mod screenshot;
+pub mod settings;
+pub mod gpu_timer;
+pub mod normal_map;
+pub mod scene;
+pub mod render_scale;
+pub mod clock;
+pub mod displacement;
+pub mod bookmarks;
+pub mod frame_graph;
+pub mod bench;
+pub mod height_field;
+pub mod thumbnail;
+pub mod chunk;
+pub mod streaming;
+pub mod quality;
+pub mod sky;
+pub mod color_ramp;
+pub mod water;
+pub mod baked_mesh;
+pub mod poisson;
+pub mod upload;
+pub mod xr;
+pub mod mipmap;
+pub mod splat;
+pub mod post;
+pub mod shadow;
+pub mod grass;
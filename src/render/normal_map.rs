@@ -0,0 +1,316 @@
+use std::rc::Rc;
+
+use wgpu::util::DeviceExt;
+
+use super::screenshot::padded_bytes_per_row;
+use super::mipmap::MipGenerator;
+
+const WORKGROUP_SIZE: u32 = 8;
+
+pub const NORMAL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct NormalParams {
+    height_scale: f32,
+    texel_size: f32,
+    _padding: [f32; 2]
+}
+
+//Central difference normal of a heightmap stored with `stride` floats per row, clamped at the edges
+pub fn normal_at(heights: &[f32], stride: usize, size: usize, x: usize, y: usize, height_scale: f32, texel_size: f32) -> [f32; 3] {
+    let height = |x: usize, y: usize| heights[y * stride + x] * height_scale;
+
+    let dx = (height((x + 1).min(size - 1), y) - height(x.saturating_sub(1), y)) / (2.0 * texel_size);
+    let dz = (height(x, (y + 1).min(size - 1)) - height(x, y.saturating_sub(1))) / (2.0 * texel_size);
+
+    let length = (dx * dx + 1.0 + dz * dz).sqrt();
+    [-dx / length, 1.0 / length, -dz / length]
+}
+
+//Same packing as normals.wgsl
+pub fn encode_normal(normal: [f32; 3]) -> [u8; 4] {
+    let channel = |v: f32| ((v * 0.5 + 0.5).clamp(0.0, 1.0) * 255.0).round() as u8;
+
+    [channel(normal[0]), channel(normal[1]), channel(normal[2]), 255]
+}
+
+enum Generator {
+    Compute {
+        pipeline: wgpu::ComputePipeline,
+        params_buffer: wgpu::Buffer,
+        bind_group: wgpu::BindGroup
+    },
+    //WebGL2 has no compute shaders, so the normals are worked out from a copy of the heights instead
+    Cpu {
+        heights: Vec<f32>,
+        stride: usize
+    }
+}
+
+//Normal texture derived from the heightmap, regenerated whenever the heights or height scale change
+pub struct NormalMap {
+    texture: wgpu::Texture,
+    //Every mip level, for sampling
+    pub view: wgpu::TextureView,
+    size: u32,
+    mips: Rc<MipGenerator>,
+
+    params: NormalParams,
+    generator: Generator
+}
+
+impl NormalMap {
+    pub fn new(device: &wgpu::Device, height_view: &wgpu::TextureView, size: u32, texel_size: f32, use_compute: bool, mips: Rc<MipGenerator>) -> Self {
+        let mut usage = wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST;
+        if use_compute {
+            usage |= wgpu::TextureUsages::STORAGE_BINDING;
+        }
+
+        let mip_level_count = mips.level_count(NORMAL_FORMAT, size);
+        if mip_level_count > 1 {
+            usage |= wgpu::TextureUsages::RENDER_ATTACHMENT;
+        }
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: NORMAL_FORMAT,
+            usage,
+            label: Some("Normal texture"),
+            view_formats: &[]
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let params = NormalParams {
+            height_scale: 1.0,
+            texel_size,
+            _padding: [0.0; 2]
+        };
+
+        let generator = if use_compute {
+            //Storage bindings can only see one level, the compute pass writes the first
+            let first_level = texture.create_view(&wgpu::TextureViewDescriptor {
+                mip_level_count: Some(1),
+                ..Default::default()
+            });
+            Self::create_compute(device, height_view, &first_level, &params)
+        } else {
+            Generator::Cpu {
+                heights: vec![0.0; (size * size) as usize],
+                stride: size as usize
+            }
+        };
+
+        NormalMap {
+            texture,
+            view,
+            size,
+            mips,
+
+            params,
+            generator
+        }
+    }
+
+    fn create_compute(device: &wgpu::Device, height_view: &wgpu::TextureView, normal_view: &wgpu::TextureView, params: &NormalParams) -> Generator {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Normal shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/normals.wgsl").into())
+        });
+
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Normal params buffer"),
+            contents: bytemuck::cast_slice(&[*params]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: NORMAL_FORMAT,
+                        view_dimension: wgpu::TextureViewDimension::D2
+                    },
+                    count: None,
+                }
+            ],
+            label: Some("Normal bind group layout"),
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(height_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(normal_view),
+                },
+            ],
+            label: Some("Normal bind group"),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Normal Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[]
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Normal Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "cs_main"
+        });
+
+        Generator::Compute {
+            pipeline,
+            params_buffer,
+            bind_group
+        }
+    }
+
+    //Call after the height texture has been written. The compute path reads the texture itself,
+    //the heights passed in are only kept by the CPU path.
+    pub fn set_heights(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, new_heights: Vec<f32>, new_stride: usize) {
+        if let Generator::Cpu { heights, stride } = &mut self.generator {
+            *heights = new_heights;
+            *stride = new_stride;
+        }
+
+        self.regenerate(device, queue);
+    }
+
+    pub fn set_height_scale(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, height_scale: f32) {
+        if height_scale != self.params.height_scale {
+            self.params.height_scale = height_scale;
+            self.regenerate(device, queue);
+        }
+    }
+
+    fn regenerate(&self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        match &self.generator {
+            Generator::Compute { pipeline, params_buffer, bind_group } => {
+                queue.write_buffer(params_buffer, 0, bytemuck::cast_slice(&[self.params]));
+
+                let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Normal Encoder")
+                });
+
+                {
+                    let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                        label: Some("Normal Pass")
+                    });
+
+                    let workgroups = self.size.div_ceil(WORKGROUP_SIZE);
+                    compute_pass.set_pipeline(pipeline);
+                    compute_pass.set_bind_group(0, bind_group, &[]);
+                    compute_pass.dispatch_workgroups(workgroups, workgroups, 1);
+                }
+
+                queue.submit(Some(encoder.finish()));
+            },
+            Generator::Cpu { heights, stride } => {
+                let size = self.size as usize;
+                let bytes_per_row = padded_bytes_per_row(self.size, 4);
+                let mut data = vec![0u8; bytes_per_row as usize * size];
+
+                for y in 0..size {
+                    for x in 0..size {
+                        let normal = normal_at(heights, *stride, size, x, y, self.params.height_scale, self.params.texel_size);
+
+                        let idx = y * bytes_per_row as usize + x * 4;
+                        data[idx..idx + 4].copy_from_slice(&encode_normal(normal));
+                    }
+                }
+
+                queue.write_texture(
+                    wgpu::ImageCopyTexture {
+                        texture: &self.texture,
+                        mip_level: 0,
+                        origin: wgpu::Origin3d::ZERO,
+                        aspect: wgpu::TextureAspect::All,
+                    },
+                    &data,
+                    wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(bytes_per_row),
+                        rows_per_image: Some(self.size),
+                    },
+                    self.texture.size()
+                );
+            }
+        }
+
+        self.mips.generate(device, queue, &self.texture);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    fn flat_heightmap_points_up() {
+        let size = 4;
+        let stride = 6;
+        let heights = vec![0.7; stride * size];
+
+        for y in 0..size {
+            for x in 0..size {
+                assert_eq!(normal_at(&heights, stride, size, x, y, 50.0, 0.5), [0.0, 1.0, 0.0]);
+            }
+        }
+
+        assert_eq!(encode_normal([0.0, 1.0, 0.0]), [128, 255, 128, 255]);
+    }
+
+    #[wasm_bindgen_test]
+    fn slope_tilts_away_from_the_rise() {
+        //Rising along x, the same in every row
+        let heights = [0.0, 1.0, 2.0, 0.0, 1.0, 2.0, 0.0, 1.0, 2.0];
+        let normal = normal_at(&heights, 3, 3, 1, 1, 1.0, 1.0);
+
+        let expected = 0.5f32.sqrt();
+        assert!((normal[0] + expected).abs() < 1e-6 && (normal[1] - expected).abs() < 1e-6);
+        assert_eq!(normal[2], 0.0);
+    }
+}
@@ -0,0 +1,109 @@
+use std::f64::consts::TAU;
+
+use crate::noise::{perm::SplitMix64, source::Seed};
+
+//Candidates tried around each point before it's given up on, Bridson's k
+const ATTEMPTS: u32 = 30;
+
+//Points in [0, width] x [0, height], no two closer than min_distance, filling the area until there's no
+//room for another (Bridson's algorithm). The same seed always gives the same points.
+pub fn poisson_disk(width: f64, height: f64, min_distance: f64, seed: Seed) -> Vec<[f64; 2]> {
+    if !(width > 0.0 && height > 0.0 && min_distance > 0.0) {
+        return Vec::new();
+    }
+
+    //Small enough that a cell holds at most one point
+    let cell = min_distance / std::f64::consts::SQRT_2;
+    let columns = (width / cell).ceil() as usize + 1;
+    let rows = (height / cell).ceil() as usize + 1;
+    let cell_of = |p: [f64; 2]| ((p[0] / cell) as usize, (p[1] / cell) as usize);
+
+    let mut grid: Vec<Option<usize>> = vec![None; columns * rows];
+    let mut points = Vec::new();
+    let mut active = Vec::new();
+    let mut random = SplitMix64::new(seed);
+
+    let first = [random.next_f64() * width, random.next_f64() * height];
+    let (x, y) = cell_of(first);
+    grid[y * columns + x] = Some(0);
+    points.push(first);
+    active.push(0);
+
+    while !active.is_empty() {
+        let index = (random.next_f64() * active.len() as f64) as usize;
+        let centre = points[active[index]];
+
+        let fits = |p: [f64; 2]| {
+            if !(0.0..=width).contains(&p[0]) || !(0.0..=height).contains(&p[1]) {
+                return false;
+            }
+
+            let (x, y) = cell_of(p);
+            (y.saturating_sub(2)..(y + 3).min(rows)).all(|ny| {
+                (x.saturating_sub(2)..(x + 3).min(columns)).all(|nx| match grid[ny * columns + nx] {
+                    Some(other) => {
+                        let (dx, dy) = (points[other][0] - p[0], points[other][1] - p[1]);
+                        dx * dx + dy * dy >= min_distance * min_distance
+                    },
+                    None => true
+                })
+            })
+        };
+
+        let found = (0..ATTEMPTS).map(|_| {
+            //Uniform over the annulus between min_distance and twice that
+            let angle = random.next_f64() * TAU;
+            let radius = min_distance * (1.0 + 3.0 * random.next_f64()).sqrt();
+            [centre[0] + radius * angle.cos(), centre[1] + radius * angle.sin()]
+        }).find(|&p| fits(p));
+
+        match found {
+            Some(p) => {
+                let (x, y) = cell_of(p);
+                grid[y * columns + x] = Some(points.len());
+                active.push(points.len());
+                points.push(p);
+            },
+            None => {
+                active.swap_remove(index);
+            }
+        }
+    }
+
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    fn no_two_points_are_too_close() {
+        let min_distance = 1.5;
+        let points = poisson_disk(30.0, 20.0, min_distance, 4);
+        assert!(points.len() > 50);
+
+        for (i, a) in points.iter().enumerate() {
+            assert!((0.0..=30.0).contains(&a[0]) && (0.0..=20.0).contains(&a[1]), "{:?} outside", a);
+
+            for b in &points[i + 1..] {
+                let distance = ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2)).sqrt();
+                assert!(distance >= min_distance, "{:?} and {:?} are {} apart", a, b, distance);
+            }
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn seed_makes_it_reproducible() {
+        assert_eq!(poisson_disk(16.0, 16.0, 1.0, 7), poisson_disk(16.0, 16.0, 1.0, 7));
+        assert_ne!(poisson_disk(16.0, 16.0, 1.0, 7), poisson_disk(16.0, 16.0, 1.0, 8));
+    }
+
+    #[wasm_bindgen_test]
+    fn empty_areas_have_no_points() {
+        assert!(poisson_disk(0.0, 10.0, 1.0, 0).is_empty());
+        assert!(poisson_disk(10.0, 10.0, 0.0, 0).is_empty());
+    }
+}
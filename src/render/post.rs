@@ -0,0 +1,255 @@
+use wgpu::util::DeviceExt;
+
+pub const DEFAULT_EXPOSURE: f32 = 1.0;
+pub const DEFAULT_VIGNETTE: f32 = 0.0;
+
+//Format of the target the scene is drawn into. A float target keeps colours above 1 for the tonemap
+//to roll off; it has to be renderable and filterable, which WebGL2 only manages with an extension. The
+//fallback clips at 1 like the surface would, but still stores linear colour at sRGB precision.
+pub fn choose_scene_format(adapter: &wgpu::Adapter, device: &wgpu::Device) -> wgpu::TextureFormat {
+    let features = device.features();
+    let format = wgpu::TextureFormat::Rgba16Float;
+
+    //Adapter specific capabilities can only be used with the feature enabled, see HeightFormat::choose
+    let format_features = if features.contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES) {
+        adapter.get_texture_format_features(format)
+    } else {
+        format.guaranteed_format_features(features)
+    };
+
+    let usable = format_features.flags.contains(wgpu::TextureFormatFeatureFlags::FILTERABLE)
+        && format_features.allowed_usages.contains(wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING);
+
+    if usable {
+        format
+    } else {
+        wgpu::TextureFormat::Rgba8UnormSrgb
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct PostSettings {
+    //Multiplies the scene's colour before the tonemap
+    exposure: f32,
+    //How much the corners are darkened, 0 for none
+    vignette: f32,
+    //Set when the output isn't sRGB, post.wgsl encodes the colour itself then
+    encode_srgb: u32,
+    _padding: u32
+}
+
+//Has to match PostSettings in post.wgsl
+const _: () = assert!(std::mem::size_of::<PostSettings>() == 16);
+
+//Offscreen colour target the scene is drawn into, sized to the scaled resolution
+pub struct SceneTarget {
+    pub view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup
+}
+
+//Draws a SceneTarget onto the output with exposure, a filmic tonemap and a vignette. Sampling with a
+//linear filter also upsamples it when the render scale is below 1.
+pub struct PostProcessor {
+    pipeline: wgpu::RenderPipeline,
+    layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    scene_format: wgpu::TextureFormat,
+    settings: PostSettings,
+    buffer: wgpu::Buffer
+}
+
+impl PostProcessor {
+    pub fn new(device: &wgpu::Device, scene_format: wgpu::TextureFormat, output_format: wgpu::TextureFormat) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Post shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/post.wgsl").into())
+        });
+
+        let settings = PostSettings {
+            exposure: DEFAULT_EXPOSURE,
+            vignette: DEFAULT_VIGNETTE,
+            encode_srgb: !output_format.is_srgb() as u32,
+            _padding: 0
+        };
+
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Post uniform buffer"),
+            contents: bytemuck::cast_slice(&[settings]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }
+            ],
+            label: Some("Post bind group layout"),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Post Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[]
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Post Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: output_format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        PostProcessor {
+            pipeline,
+            layout,
+            sampler,
+            scene_format,
+            settings,
+            buffer
+        }
+    }
+
+    //What every pipeline drawing into a SceneTarget has to target
+    pub fn scene_format(&self) -> wgpu::TextureFormat {
+        self.scene_format
+    }
+
+    pub fn exposure(&self) -> f32 {
+        self.settings.exposure
+    }
+
+    pub fn set_exposure(&mut self, queue: &wgpu::Queue, exposure: f32) {
+        self.settings.exposure = exposure.max(0.0);
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.settings]));
+    }
+
+    pub fn vignette(&self) -> f32 {
+        self.settings.vignette
+    }
+
+    pub fn set_vignette(&mut self, queue: &wgpu::Queue, vignette: f32) {
+        self.settings.vignette = vignette.clamp(0.0, 1.0);
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.settings]));
+    }
+
+    //Has to be recreated whenever the size the scene is drawn at changes
+    pub fn create_target(&self, device: &wgpu::Device, width: u32, height: u32) -> SceneTarget {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Scene colour texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.scene_format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[]
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("Post bind group"),
+        });
+
+        SceneTarget {
+            view,
+            bind_group
+        }
+    }
+
+    //Records the post pass into one the frame graph began, see WgpuContext::record_post
+    pub fn draw<'pass>(&'pass self, render_pass: &mut wgpu::RenderPass<'pass>, source: &'pass SceneTarget) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &source.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    //The post pass on its own, for targets drawn outside the frame graph
+    pub fn apply(&self, encoder: &mut wgpu::CommandEncoder, source: &SceneTarget, target: &wgpu::TextureView) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Post Pass"),
+            color_attachments: &[
+                Some(wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true
+                    }
+                })
+            ],
+            depth_stencil_attachment: None
+        });
+
+        self.draw(&mut render_pass, source);
+    }
+}
@@ -0,0 +1,305 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use super::chunk::CHUNK_GRID_RADIUS;
+use super::render_scale::{MAX_RENDER_SCALE, MIN_RENDER_SCALE};
+use super::wgpu_context::TessellationConfig;
+
+const SCALE_STEP: f32 = 0.1;
+
+//Frame times are in milliseconds
+const DEFAULT_TARGET_FRAME_TIME: f64 = 16.6;
+const AVERAGE_WINDOW: usize = 30;
+//Averages above target * DECREASE_THRESHOLD lower the quality, it's only raised again after
+//staying below target * INCREASE_THRESHOLD for a while. The gap stops it flip-flopping.
+const DECREASE_THRESHOLD: f64 = 1.2;
+const INCREASE_THRESHOLD: f64 = 1.05;
+const INITIAL_INCREASE_DELAY: u32 = 120;
+const MAX_INCREASE_DELAY: u32 = 1920;
+
+//Every knob the controller can turn
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Quality {
+    pub render_scale: f32,
+    //Chunks loaded on each side of the one the camera is over
+    pub chunk_radius: i32,
+    //TessellationConfig::max_subdivisions. The shared chunk mesh doesn't use it (see TessellationConfig::shared),
+    //so lowering it saves nothing there.
+    pub max_subdivisions: u32
+}
+
+impl Default for Quality {
+    fn default() -> Self {
+        Quality {
+            render_scale: MAX_RENDER_SCALE,
+            chunk_radius: CHUNK_GRID_RADIUS,
+            max_subdivisions: TessellationConfig::default().max_subdivisions
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct QualityBounds {
+    pub min: Quality,
+    pub max: Quality
+}
+
+impl Default for QualityBounds {
+    fn default() -> Self {
+        QualityBounds {
+            min: Quality {
+                render_scale: MIN_RENDER_SCALE,
+                chunk_radius: 1,
+                max_subdivisions: 0
+            },
+            max: Quality::default()
+        }
+    }
+}
+
+impl QualityBounds {
+    //Every quality from max down to min, one knob at a time: render scale goes first since it's the
+    //cheapest to change back, then the view distance, then the mesh
+    fn levels(&self) -> Vec<Quality> {
+        let min_scale = self.min.render_scale.clamp(MIN_RENDER_SCALE, MAX_RENDER_SCALE);
+        let mut quality = Quality {
+            render_scale: self.max.render_scale.clamp(min_scale, MAX_RENDER_SCALE),
+            chunk_radius: self.max.chunk_radius.max(self.min.chunk_radius).max(0),
+            max_subdivisions: self.max.max_subdivisions.max(self.min.max_subdivisions)
+        };
+        let mut levels = vec![quality];
+
+        while quality.render_scale > min_scale + f32::EPSILON {
+            quality.render_scale = (quality.render_scale - SCALE_STEP).max(min_scale);
+            levels.push(quality);
+        }
+
+        while quality.chunk_radius > self.min.chunk_radius.max(0) {
+            quality.chunk_radius -= 1;
+            levels.push(quality);
+        }
+
+        while quality.max_subdivisions > self.min.max_subdivisions {
+            quality.max_subdivisions -= 1;
+            levels.push(quality);
+        }
+
+        levels
+    }
+}
+
+//Trades quality for frame time using a rolling average, within bounds
+pub struct QualityController {
+    pub enabled: bool,
+    pub target_frame_time: f64,
+
+    bounds: QualityBounds,
+    //Highest quality first
+    levels: Vec<Quality>,
+    level: usize,
+    frame_times: VecDeque<f64>,
+    //Frames the average has to stay on target before quality is raised. Doubles every time
+    //raising it made frames too slow again, so a level that can't be held isn't retried constantly.
+    increase_delay: u32,
+    steady_frames: u32,
+    just_increased: bool
+}
+
+impl QualityController {
+    pub fn new(target_frame_time: f64, bounds: QualityBounds) -> Self {
+        QualityController {
+            enabled: true,
+            target_frame_time,
+
+            bounds,
+            levels: bounds.levels(),
+            level: 0,
+            frame_times: VecDeque::with_capacity(AVERAGE_WINDOW),
+            increase_delay: INITIAL_INCREASE_DELAY,
+            steady_frames: 0,
+            just_increased: false
+        }
+    }
+
+    pub fn quality(&self) -> Quality {
+        self.levels[self.level]
+    }
+
+    pub fn bounds(&self) -> QualityBounds {
+        self.bounds
+    }
+
+    //Starts again from the top of the new bounds, returns the quality to apply
+    pub fn set_bounds(&mut self, bounds: QualityBounds) -> Quality {
+        self.bounds = bounds;
+        self.levels = bounds.levels();
+        self.level = 0;
+        self.increase_delay = INITIAL_INCREASE_DELAY;
+        self.reset();
+
+        self.quality()
+    }
+
+    //Returns the new quality when it changes
+    pub fn record(&mut self, frame_time: f64) -> Option<Quality> {
+        if !self.enabled {
+            return None;
+        }
+
+        if self.frame_times.len() == AVERAGE_WINDOW {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(frame_time);
+
+        if self.frame_times.len() < AVERAGE_WINDOW {
+            return None;
+        }
+
+        let average = self.frame_times.iter().sum::<f64>() / self.frame_times.len() as f64;
+
+        if average > self.target_frame_time * DECREASE_THRESHOLD {
+            if self.level + 1 >= self.levels.len() {
+                return None;
+            }
+
+            if self.just_increased {
+                self.increase_delay = (self.increase_delay * 2).min(MAX_INCREASE_DELAY);
+            }
+            return Some(self.change_level(self.level + 1, false));
+        }
+        self.just_increased = false;
+
+        if average <= self.target_frame_time * INCREASE_THRESHOLD {
+            self.steady_frames += 1;
+
+            if self.steady_frames >= self.increase_delay && self.level > 0 {
+                return Some(self.change_level(self.level - 1, true));
+            }
+        } else {
+            self.steady_frames = 0;
+        }
+
+        None
+    }
+
+    //Forgets the frame times so far, for when the next frame's time will be meaningless (resuming, mode changes)
+    pub fn reset(&mut self) {
+        self.frame_times.clear();
+        self.steady_frames = 0;
+    }
+
+    fn change_level(&mut self, level: usize, increased: bool) -> Quality {
+        self.level = level;
+        //Frame times from before the change say nothing about the new level
+        self.frame_times.clear();
+        self.steady_frames = 0;
+        self.just_increased = increased;
+
+        self.quality()
+    }
+}
+
+impl Default for QualityController {
+    fn default() -> Self {
+        Self::new(DEFAULT_TARGET_FRAME_TIME, QualityBounds::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    //Feeds the same frame time n times, returning every quality change
+    fn run(controller: &mut QualityController, frame_time: f64, frames: usize) -> Vec<Quality> {
+        (0..frames).filter_map(|_| controller.record(frame_time)).collect()
+    }
+
+    #[wasm_bindgen_test]
+    fn slow_frames_lower_the_render_scale() {
+        let mut controller = QualityController::new(16.6, QualityBounds::default());
+
+        assert!(run(&mut controller, 30.0, AVERAGE_WINDOW - 1).is_empty());
+        let lowered = controller.record(30.0).unwrap();
+
+        assert!((lowered.render_scale - (MAX_RENDER_SCALE - SCALE_STEP)).abs() < 1e-6);
+    }
+
+    #[wasm_bindgen_test]
+    fn frames_between_the_thresholds_change_nothing() {
+        let mut controller = QualityController::new(16.6, QualityBounds::default());
+        run(&mut controller, 30.0, AVERAGE_WINDOW);
+        let lowered = controller.quality();
+
+        //Too slow to raise the quality, not slow enough to lower it
+        assert!(run(&mut controller, 18.5, 1000).is_empty());
+        assert_eq!(controller.quality(), lowered);
+    }
+
+    #[wasm_bindgen_test]
+    fn quality_is_raised_only_after_staying_on_target() {
+        let mut controller = QualityController::new(16.6, QualityBounds::default());
+        run(&mut controller, 30.0, AVERAGE_WINDOW);
+
+        let frames = AVERAGE_WINDOW + INITIAL_INCREASE_DELAY as usize - 1;
+        assert!(run(&mut controller, 10.0, frames - 1).is_empty());
+        assert_eq!(controller.record(10.0), Some(Quality::default()));
+    }
+
+    #[wasm_bindgen_test]
+    fn alternating_frames_settle() {
+        let mut controller = QualityController::new(16.6, QualityBounds::default());
+
+        //A trace swinging either side of the target averages out between the two thresholds
+        let changes = (0..2000).filter_map(|i| controller.record(if i % 2 == 0 { 12.0 } else { 23.0 })).count();
+        assert_eq!(changes, 0);
+    }
+
+    //Every knob at most what it was, and at least one lower
+    fn lower(a: Quality, b: Quality) -> bool {
+        a.render_scale <= b.render_scale && a.chunk_radius <= b.chunk_radius && a.max_subdivisions <= b.max_subdivisions && a != b
+    }
+
+    #[wasm_bindgen_test]
+    fn slow_frames_lower_quality_step_by_step_to_the_bounds() {
+        let bounds = QualityBounds::default();
+        let mut controller = QualityController::new(16.6, bounds);
+        let mut previous = controller.quality();
+
+        let changes = run(&mut controller, 50.0, 5000);
+        assert!(!changes.is_empty());
+        for quality in changes {
+            assert!(lower(quality, previous), "{:?} after {:?}", quality, previous);
+            previous = quality;
+        }
+
+        assert_eq!(controller.quality(), bounds.min);
+    }
+
+    #[wasm_bindgen_test]
+    fn fast_frames_raise_quality_step_by_step_to_the_bounds() {
+        let bounds = QualityBounds::default();
+        let mut controller = QualityController::new(16.6, bounds);
+        run(&mut controller, 50.0, 5000);
+        let mut previous = controller.quality();
+
+        let changes = run(&mut controller, 8.0, 50000);
+        assert!(!changes.is_empty());
+        for quality in changes {
+            assert!(lower(previous, quality), "{:?} after {:?}", quality, previous);
+            previous = quality;
+        }
+
+        assert_eq!(controller.quality(), bounds.max);
+    }
+
+    #[wasm_bindgen_test]
+    fn disabled_controller_changes_nothing() {
+        let mut controller = QualityController { enabled: false, ..QualityController::default() };
+
+        assert!(run(&mut controller, 100.0, 1000).is_empty());
+        assert_eq!(controller.quality(), Quality::default());
+    }
+}
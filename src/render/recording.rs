@@ -0,0 +1,291 @@
+use std::f32::consts::PI;
+
+use cgmath::{Quaternion, Rad, Rotation3, Vector3};
+use serde::{Deserialize, Serialize};
+
+use super::camera::Camera;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CameraKeyframe {
+    //Seconds since the start of the recording
+    pub time: f64,
+
+    //eye is relative to origin, like Camera's
+    #[serde(default)]
+    pub origin: [f64; 2],
+    pub eye: [f32; 3],
+    pub pitch: f32,
+    pub yaw: f32
+}
+
+impl CameraKeyframe {
+    pub fn capture(camera: &Camera, time: f64) -> Self {
+        CameraKeyframe {
+            time,
+
+            origin: camera.origin,
+            eye: camera.eye.into(),
+            pitch: camera.pitch(),
+            yaw: camera.yaw
+        }
+    }
+
+    pub fn apply(&self, camera: &mut Camera) {
+        camera.origin = self.origin;
+        camera.eye = self.eye.into();
+        camera.set_pitch(self.pitch);
+        camera.yaw = self.yaw;
+    }
+
+    //The eye moved to another origin, so keyframes recorded either side of a recenter can be blended
+    pub fn eye_relative_to(&self, origin: [f64; 2]) -> Vector3<f32> {
+        Vector3::new(
+            (self.eye[0] as f64 + self.origin[0] - origin[0]) as f32,
+            self.eye[1],
+            (self.eye[2] as f64 + self.origin[1] - origin[1]) as f32
+        )
+    }
+
+    //Yaw is never wrapped, so plain linear interpolation follows the recorded turn
+    pub fn lerp(&self, other: &CameraKeyframe, t: f32) -> CameraKeyframe {
+        let mix = |a: f32, b: f32| a + (b - a) * t;
+        let other_eye = other.eye_relative_to(self.origin);
+
+        CameraKeyframe {
+            time: self.time + (other.time - self.time) * t as f64,
+
+            origin: self.origin,
+            eye: [
+                mix(self.eye[0], other_eye.x),
+                mix(self.eye[1], other_eye.y),
+                mix(self.eye[2], other_eye.z)
+            ],
+            pitch: mix(self.pitch, other.pitch),
+            yaw: mix(self.yaw, other.yaw)
+        }
+    }
+}
+
+//Smooth path through a list of keyframes sorted by time: Catmull-Rom for position, slerp for orientation
+pub struct CameraPath {
+    points: Vec<CameraKeyframe>
+}
+
+impl CameraPath {
+    pub fn new(points: Vec<CameraKeyframe>) -> Self {
+        CameraPath { points }
+    }
+
+    pub fn points(&self) -> &[CameraKeyframe] {
+        &self.points
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.points.last().map(|point| point.time).unwrap_or(0.0)
+    }
+
+    //t is in control point space: t = i lands exactly on point i
+    pub fn sample(&self, t: f32) -> Option<CameraKeyframe> {
+        let last = self.points.len().checked_sub(1)?;
+        let t = t.clamp(0.0, last as f32);
+
+        let i = (t.floor() as usize).min(last.saturating_sub(1));
+        let local = t - i as f32;
+
+        let point = |index: isize| &self.points[index.clamp(0, last as isize) as usize];
+        let (p0, p1, p2, p3) = (point(i as isize - 1), point(i as isize), point(i as isize + 1), point(i as isize + 2));
+
+        let origin = p1.origin;
+        let eye = catmull_rom(p0.eye_relative_to(origin), p1.eye.into(), p2.eye_relative_to(origin), p3.eye_relative_to(origin), local);
+
+        //Only use the slerp for the direction, and pick the yaw closest to the linear one so it stays unwrapped
+        let linear = p1.lerp(p2, local);
+        let direction = orientation(p1).slerp(orientation(p2), local) * Vector3::unit_x();
+
+        let yaw = direction.z.atan2(direction.x);
+        let yaw = linear.yaw + wrap_angle(yaw - linear.yaw);
+
+        Some(CameraKeyframe {
+            time: linear.time,
+
+            origin,
+            eye: eye.into(),
+            pitch: direction.y.clamp(-1.0, 1.0).asin(),
+            yaw
+        })
+    }
+
+    pub fn sample_time(&self, time: f64) -> Option<CameraKeyframe> {
+        let first = self.points.first()?;
+        if time <= first.time {
+            return self.sample(0.0);
+        }
+
+        let next = self.points.partition_point(|point| point.time <= time);
+        if next >= self.points.len() {
+            return self.sample((self.points.len() - 1) as f32);
+        }
+
+        let (a, b) = (&self.points[next - 1], &self.points[next]);
+        let span = b.time - a.time;
+        let local = if span > 0.0 { (time - a.time) / span } else { 0.0 };
+
+        self.sample((next - 1) as f32 + local as f32)
+    }
+}
+
+fn catmull_rom(p0: Vector3<f32>, p1: Vector3<f32>, p2: Vector3<f32>, p3: Vector3<f32>, t: f32) -> Vector3<f32> {
+    let t2 = t * t;
+    let t3 = t2 * t;
+
+    (p1 * 2.0
+        + (p2 - p0) * t
+        + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2
+        + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3) * 0.5
+}
+
+//Rotation taking +X to the keyframe's view direction (see Camera::get_direction)
+fn orientation(keyframe: &CameraKeyframe) -> Quaternion<f32> {
+    Quaternion::from_angle_y(Rad(-keyframe.yaw)) * Quaternion::from_angle_z(Rad(keyframe.pitch))
+}
+
+pub fn wrap_angle(angle: f32) -> f32 {
+    (angle + PI).rem_euclid(2.0 * PI) - PI
+}
+
+pub struct Recorder {
+    start: Option<f64>,
+    keyframes: Vec<CameraKeyframe>
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Recorder {
+            start: None,
+            keyframes: Vec::new()
+        }
+    }
+
+    //Time is the rAF timestamp in milliseconds
+    pub fn record(&mut self, camera: &Camera, time: f64) {
+        let start = *self.start.get_or_insert(time);
+        self.keyframes.push(CameraKeyframe::capture(camera, (time - start) / 1000.0));
+    }
+
+    pub fn finish(self) -> Vec<CameraKeyframe> {
+        self.keyframes
+    }
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct Playback {
+    start: Option<f64>,
+    path: CameraPath
+}
+
+impl Playback {
+    pub fn new(keyframes: Vec<CameraKeyframe>) -> Self {
+        Playback {
+            start: None,
+            path: CameraPath::new(keyframes)
+        }
+    }
+
+    //Moves the camera to where the path is at this time and returns false once the path has ended
+    pub fn update(&mut self, camera: &mut Camera, time: f64) -> bool {
+        let start = *self.start.get_or_insert(time);
+        let elapsed = (time - start) / 1000.0;
+
+        if let Some(keyframe) = self.path.sample_time(elapsed) {
+            keyframe.apply(camera);
+        }
+
+        elapsed < self.path.duration()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    fn keyframe(time: f64, eye: [f32; 3], pitch: f32, yaw: f32) -> CameraKeyframe {
+        CameraKeyframe { time, origin: [0.0; 2], eye, pitch, yaw }
+    }
+
+    #[wasm_bindgen_test]
+    fn lerp_hits_both_ends_and_the_middle() {
+        let a = keyframe(0.0, [0.0, 1.0, 2.0], 0.0, 0.0);
+        let b = keyframe(2.0, [4.0, 3.0, -2.0], 0.5, 1.0);
+
+        assert_eq!(a.lerp(&b, 0.0), a);
+        assert_eq!(a.lerp(&b, 1.0), b);
+
+        let middle = a.lerp(&b, 0.5);
+        assert_eq!(middle.time, 1.0);
+        assert_eq!(middle.eye, [2.0, 2.0, 0.0]);
+        assert_eq!((middle.pitch, middle.yaw), (0.25, 0.5));
+    }
+
+    #[wasm_bindgen_test]
+    fn lerp_across_a_recenter() {
+        let a = keyframe(0.0, [990.0, 0.0, 0.0], 0.0, 0.0);
+        //The same spot 10 units on, recorded after the origin moved 1000 along x
+        let b = CameraKeyframe { origin: [1000.0, 0.0], ..keyframe(1.0, [0.0, 0.0, 0.0], 0.0, 0.0) };
+
+        let middle = a.lerp(&b, 0.5);
+        assert_eq!(middle.origin, a.origin);
+        assert_eq!(middle.eye, [995.0, 0.0, 0.0]);
+    }
+
+    #[wasm_bindgen_test]
+    fn keyframes_survive_json() {
+        let a = keyframe(1.5, [1.0, 2.0, 3.0], 0.25, -2.0);
+        let json = serde_json::to_string(&a).unwrap();
+
+        assert_eq!(serde_json::from_str::<CameraKeyframe>(&json).unwrap(), a);
+    }
+
+    fn path() -> CameraPath {
+        CameraPath::new(vec![
+            keyframe(0.0, [0.0, 0.0, 0.0], 0.0, 0.0),
+            keyframe(1.0, [3.0, 1.0, 0.0], 0.2, 0.5),
+            keyframe(3.0, [5.0, 2.0, 4.0], -0.3, 2.0),
+            keyframe(4.0, [6.0, 0.0, 9.0], 0.1, 3.5)
+        ])
+    }
+
+    #[wasm_bindgen_test]
+    fn spline_passes_through_control_points() {
+        let path = path();
+
+        for (i, point) in path.points().iter().enumerate() {
+            let sample = path.sample(i as f32).unwrap();
+
+            for axis in 0..3 {
+                assert!((sample.eye[axis] - point.eye[axis]).abs() < 1e-4, "point {} axis {}", i, axis);
+            }
+            assert!((sample.pitch - point.pitch).abs() < 1e-4, "point {}", i);
+            assert!((sample.yaw - point.yaw).abs() < 1e-4, "point {}", i);
+            assert_eq!(sample.time, point.time);
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn sampling_by_time_finds_the_control_points() {
+        let path = path();
+
+        assert_eq!(path.duration(), 4.0);
+        assert!((path.sample_time(3.0).unwrap().eye[2] - 4.0).abs() < 1e-4);
+        //Clamped to the ends
+        assert_eq!(path.sample_time(-1.0).unwrap().eye, [0.0, 0.0, 0.0]);
+        assert!((path.sample_time(10.0).unwrap().eye[2] - 9.0).abs() < 1e-4);
+        assert!(CameraPath::new(Vec::new()).sample(0.0).is_none());
+    }
+}
@@ -0,0 +1,29 @@
+pub const MIN_RENDER_SCALE: f32 = 0.5;
+pub const MAX_RENDER_SCALE: f32 = 1.0;
+
+pub fn scaled_size(width: u32, height: u32, scale: f32) -> (u32, u32) {
+    (
+        ((width as f32 * scale).round() as u32).max(1),
+        ((height as f32 * scale).round() as u32).max(1)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    fn scaled_size_rounds_to_whole_pixels() {
+        assert_eq!(scaled_size(1920, 1080, 1.0), (1920, 1080));
+        assert_eq!(scaled_size(1920, 1080, 0.5), (960, 540));
+        assert_eq!(scaled_size(801, 601, 0.5), (401, 301));
+    }
+
+    #[wasm_bindgen_test]
+    fn scaled_size_never_reaches_zero() {
+        assert_eq!(scaled_size(1, 1, MIN_RENDER_SCALE), (1, 1));
+        assert_eq!(scaled_size(0, 3, 0.1), (1, 1));
+    }
+}
@@ -1,139 +1,888 @@
-use std::{time::Duration, rc::Rc, cell::RefCell};
-
-use wasm_bindgen::prelude::{Closure, wasm_bindgen};
-use web_sys::HtmlCanvasElement;
-use winit::dpi::PhysicalSize;
-
-use crate::{console_log, util::Interval};
-
-use super::{wgpu_context::WgpuContext, event::{EventQueue, Event, CanvasResizeData, MouseEventData, KeyTracker, KeyboardEventData, KeyboardKey}, camera::Camera};
-
-#[wasm_bindgen]
-extern "C" {
-    fn requestAnimationFrame(callback: &Closure<dyn FnMut(f64)>) -> u32;
-}
-
-pub struct Runtime {
-    context: WgpuContext,
-    canvas: HtmlCanvasElement,
-    event_queue: Rc<RefCell<EventQueue>>,
-
-    self_ref: Option<Rc<RefCell<Runtime>>>,
-    render_closure: Option<Closure<dyn FnMut(f64)>>,
-
-    frames: u128,
-    last_frame: f64,
-
-    camera: Camera,
-    keyboard: KeyTracker,
-}
-
-impl Runtime {
-    pub fn new(context: WgpuContext, canvas: HtmlCanvasElement, camera: Camera) -> Rc<RefCell<Self>> {
-        let (width, height) = (canvas.width(), canvas.height());
-
-        let base = Rc::new(RefCell::new(Runtime {
-            context,
-            canvas: canvas.clone(),
-            event_queue: EventQueue::for_canvas(canvas).unwrap(),
-
-            self_ref: None,
-            render_closure: None,
-
-            frames: 0,
-            last_frame: 0.0,
-
-            camera,
-            keyboard: KeyTracker::new()
-        }));
-        let base_clone = base.clone();
-
-        base.borrow_mut().self_ref = Some(base.clone());
-        base.borrow_mut().render_closure = Some(Closure::wrap(Box::new(move |time| {
-            base_clone.borrow_mut().render(time);
-        })));
-
-        base
-    }
-
-    pub fn request_animation_frame(&mut self) {
-        if let Some(closure) = &mut self.render_closure {
-            requestAnimationFrame(closure);
-        }
-    }
-
-    pub fn render(&mut self, time: f64) {
-        let dt = (time - self.last_frame) / 1000.0;
-        self.last_frame = time;
-
-        self.event_queue.borrow_mut().detect_resize();
-        while let Some(event) = { let x = self.event_queue.borrow_mut().pop(); x } {
-            self.handle_event(event);
-        }
-
-        let mut forward = 0.0;
-        let mut right = 0.0;
-        let mut up = 0.0;
-
-        if self.keyboard.is_key_down(KeyboardKey::Character('w')) {
-            forward += 1.0;
-        } 
-        if self.keyboard.is_key_down(KeyboardKey::Character('s')) {
-            forward -= 1.0;
-        } 
-        if self.keyboard.is_key_down(KeyboardKey::Character('a')) {
-            right -= 1.0;
-        } 
-        if self.keyboard.is_key_down(KeyboardKey::Character('d')) {
-            right += 1.0;
-        }
-        if self.keyboard.is_key_down(KeyboardKey::Shift) {
-            up -= 1.0;
-        }
-        if self.keyboard.is_key_down(KeyboardKey::Character(' ')) {
-            up += 1.0;
-        }
-
-        const SPEED: f32 = 0.5;
-
-        self.camera.do_move(SPEED * forward * dt as f32, SPEED * right * dt as f32, SPEED * up * dt as f32);
-
-        self.context.render(dt, &self.camera).unwrap();
-
-        self.frames += 1;
-
-        self.request_animation_frame();
-    }
-
-    pub fn handle_event(&mut self, event: Event) {
-        match event {
-            Event::CanvasResize(CanvasResizeData {new_width, new_height, ..}) => {
-                self.context.resize(PhysicalSize::new(new_width, new_height));
-                self.camera.aspect = new_width as f32 / new_height as f32;
-            },
-
-            Event::MouseMove(MouseEventData {movement_x, movement_y,..}) => {
-                self.camera.yaw += movement_x as f32 * 0.002;
-                self.camera.pitch -= movement_y as f32 * 0.002;
-
-                if self.camera.pitch > 3.14 / 2.0 {
-                    self.camera.pitch = 3.14 / 2.0;
-                } else if self.camera.pitch < -3.14 / 2.0 {
-                    self.camera.pitch = -3.14 / 2.0;
-                }
-                
-                //console_log!("Camera move: {},{}", self.camera.yaw, self.camera.pitch);
-            },
-
-            Event::KeyDown(KeyboardEventData {key,..}) => self.keyboard.set_key_down(key),
-            Event::KeyUp(KeyboardEventData {key,..}) => self.keyboard.set_key_up(key),
-
-            _ => {}
-        }
-    }
-
-    pub fn cleanup(&mut self) {
-        self.self_ref = None;
-        self.render_closure = None;
-    }
-}
\ No newline at end of file
+use std::{rc::Rc, cell::RefCell};
+
+use wasm_bindgen::{JsValue, prelude::{Closure, wasm_bindgen}};
+use web_sys::HtmlCanvasElement;
+use winit::dpi::PhysicalSize;
+
+use crate::{console_log, util::{Interval, now, Profiler, get_expected_size, canvas_visible}, noise::source::Seed};
+
+use super::{wgpu_context::{WgpuContext, DebugMode, PresentPreference, MeshStrategy, TessellationConfig, VertexHeights}, event::{EventQueue, Event, CanvasResizeData, KeyboardKey}, camera::{Camera, RECENTER_DISTANCE}, input::{InputTracker, InputState}, controller::CameraController, stats::{FrameStats, StatsOverlay, DeltaFilter, STATS_TOGGLE_KEY}, recording::{Recorder, Playback, CameraKeyframe, wrap_angle}, bookmarks::{Bookmarks, RECALL_DURATION}, chunk::TextureFilter, bench::{Benchmark, mesh_bench, show_summary}, thumbnail::ThumbnailQueue, color_ramp::ColorRamp, settings::{Settings, SettingsPanel, SettingId, DESCRIPTORS, local_storage}, scene::{Scene, SceneKind}, quality::{Quality, QualityBounds, QualityController}, clock::{WorldClock, TIME_SLOWER_KEY, TIME_FASTER_KEY, TIME_FREEZE_KEY, TIME_SCALE_STEP}, xr::{XrView, XR_VIEW_LENGTH}, frame_graph::ViewRect};
+
+#[wasm_bindgen]
+extern "C" {
+    fn requestAnimationFrame(callback: &Closure<dyn FnMut(f64)>) -> u32;
+    fn cancelAnimationFrame(handle: u32);
+}
+
+pub const PAUSE_KEY: KeyboardKey = KeyboardKey::Character('p');
+pub const GRID_TOGGLE_KEY: KeyboardKey = KeyboardKey::Character('g');
+pub const SCREENSHOT_KEY: KeyboardKey = KeyboardKey::Function(2);
+pub const CULL_MODE_KEY: KeyboardKey = KeyboardKey::Character('c');
+pub const DEBUG_MODE_KEY: KeyboardKey = KeyboardKey::Character('v');
+pub const TEXTURE_FILTER_KEY: KeyboardKey = KeyboardKey::Character('n');
+pub const SCENE_KEY: KeyboardKey = KeyboardKey::Character('m');
+pub const SUN_LEFT_KEY: KeyboardKey = KeyboardKey::Character('j');
+pub const SUN_RIGHT_KEY: KeyboardKey = KeyboardKey::Character('l');
+pub const SUN_DOWN_KEY: KeyboardKey = KeyboardKey::Character('k');
+pub const SUN_UP_KEY: KeyboardKey = KeyboardKey::Character('u');
+//Degrees per key press
+const SUN_STEP: f64 = 5.0;
+//'+' is '=' with shift held, either works
+pub const HEIGHT_DOWN_KEY: KeyboardKey = KeyboardKey::Character('-');
+pub const HEIGHT_UP_KEYS: [KeyboardKey; 2] = [KeyboardKey::Character('='), KeyboardKey::Character('+')];
+const HEIGHT_SCALE_STEP: f64 = 0.1;
+pub const MESH_DETAIL_DOWN_KEY: KeyboardKey = KeyboardKey::Character(',');
+pub const MESH_DETAIL_UP_KEY: KeyboardKey = KeyboardKey::Character('.');
+const MESH_DETAIL_STEP: f64 = 0.1;
+//Splits the canvas between the camera and a top-down view of the terrain around it
+pub const SPLIT_VIEW_KEY: KeyboardKey = KeyboardKey::Function(4);
+const MAIN_VIEW: ViewRect = ViewRect { x: 0.0, y: 0.0, width: 0.5, height: 1.0 };
+const TOP_DOWN_VIEW: ViewRect = ViewRect { x: 0.5, y: 0.0, width: 0.5, height: 1.0 };
+//Above the camera
+const TOP_DOWN_HEIGHT: f32 = 50.0;
+
+#[cfg(feature = "hot-shaders")]
+pub const RELOAD_SHADERS_KEY: KeyboardKey = KeyboardKey::Function(5);
+#[cfg(feature = "hot-shaders")]
+const HOT_SHADER_URL: &str = "src/render/shaders/shader.wgsl";
+
+//How often events are drained while the render loop is paused
+const PAUSED_POLL_INTERVAL: u32 = 100;
+
+//Slack for rAF timestamp jitter when capping the frame rate, in milliseconds
+const FRAME_CAP_TOLERANCE: f64 = 1.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RenderMode {
+    Continuous,
+    Capped(f64),
+    //Only render when an event arrived, the camera moved or a redraw was requested
+    OnDemand
+}
+
+pub struct Runtime {
+    context: WgpuContext,
+    scene: Box<dyn Scene>,
+    scene_kind: SceneKind,
+    event_queue: Rc<RefCell<EventQueue>>,
+
+    self_ref: Option<Rc<RefCell<Runtime>>>,
+    render_closure: Option<Closure<dyn FnMut(f64)>>,
+    animation_frame: Option<u32>,
+    paused_poll: Option<Interval>,
+    //Polls for the canvas being put back into the page after it was removed
+    detached_poll: Option<Interval>,
+
+    //None until the first frame, which then has a delta of 0
+    last_frame: Option<f64>,
+    delta_filter: DeltaFilter,
+    clock: WorldClock,
+    last_render: f64,
+    render_mode: RenderMode,
+    redraw_requested: bool,
+    skipped_events: usize,
+    stats: FrameStats,
+    profiler: Profiler,
+    quality: QualityController,
+    overlay: StatsOverlay,
+
+    camera: Camera,
+    controller: CameraController,
+    input: InputTracker,
+
+    recorder: Option<Recorder>,
+    playback: Option<Playback>,
+    //Input is ignored while a benchmark runs so every run renders the same frames
+    bench: Option<Benchmark>,
+    thumbnails: Option<ThumbnailQueue>,
+
+    //Scopes the saved settings and bookmarks, see settings::storage_key
+    canvas_id: String,
+    bookmarks: Bookmarks,
+
+    settings: Settings,
+    settings_panel: Option<SettingsPanel>,
+
+    //The latest per-eye poses from the host page's XR session, XR_VIEW_LENGTH numbers per eye.
+    //Frames are drawn in stereo while there are some.
+    xr_poses: Option<Vec<f32>>,
+    split_view: bool
+}
+
+impl Runtime {
+    pub fn new(mut context: WgpuContext, canvas: HtmlCanvasElement, camera: Camera, scene_kind: SceneKind) -> Result<Rc<RefCell<Self>>, JsValue> {
+        let document = canvas.owner_document().unwrap();
+        let canvas_id = canvas.id();
+        let overlay = StatsOverlay::new(document.clone(), &canvas);
+        let settings = Settings::load(local_storage().as_ref(), &canvas_id);
+        let panel_canvas = canvas.clone();
+        let scene = scene_kind.create(&mut context);
+        let event_queue = EventQueue::for_canvas(canvas, context.options.min_canvas_size, context.options.keys, context.options.event_cap)?;
+
+        let base = Rc::new(RefCell::new(Runtime {
+            context,
+            scene,
+            scene_kind,
+            event_queue,
+
+            self_ref: None,
+            render_closure: None,
+            animation_frame: None,
+            paused_poll: None,
+            detached_poll: None,
+
+            last_frame: None,
+            delta_filter: DeltaFilter::default(),
+            clock: WorldClock::new(),
+            last_render: 0.0,
+            render_mode: RenderMode::Continuous,
+            redraw_requested: true,
+            skipped_events: 0,
+            stats: FrameStats::new(),
+            profiler: Profiler::new(),
+            quality: QualityController::default(),
+            overlay,
+
+            camera,
+            controller: CameraController::new(),
+            input: InputTracker::new(),
+
+            recorder: None,
+            playback: None,
+            bench: None,
+            thumbnails: None,
+
+            canvas_id: canvas_id.clone(),
+            bookmarks: Bookmarks::load(local_storage().as_ref(), &canvas_id),
+
+            settings: settings.clone(),
+            settings_panel: None,
+
+            xr_poses: None,
+            split_view: false
+        }));
+        let base_clone = base.clone();
+
+        //An explicit present mode in the context's options wins over the stored vsync toggle until it's changed
+        let explicit_present = matches!(base.borrow().context.options.present, PresentPreference::Mode(_));
+        for descriptor in DESCRIPTORS.iter().filter(|descriptor| !(explicit_present && descriptor.id == SettingId::Vsync)) {
+            base.borrow_mut().apply_setting(descriptor.id, settings.get(descriptor.id));
+        }
+
+        match SettingsPanel::bind_dom_controls(&panel_canvas, &settings, Rc::downgrade(&base)) {
+            Ok(panel) => base.borrow_mut().settings_panel = Some(panel),
+            Err(e) => console_log!("Couldn't create settings controls: {:?}", e)
+        }
+
+        base.borrow_mut().self_ref = Some(base.clone());
+        base.borrow_mut().render_closure = Some(Closure::wrap(Box::new(move |time| {
+            base_clone.borrow_mut().render(time);
+        })));
+
+        Ok(base)
+    }
+
+    pub fn request_animation_frame(&mut self) {
+        if let Some(closure) = &self.render_closure {
+            self.animation_frame = Some(requestAnimationFrame(closure));
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused_poll.is_some()
+    }
+
+    pub fn pause(&mut self) {
+        if self.is_paused() {
+            return;
+        }
+
+        if let Some(handle) = self.animation_frame.take() {
+            cancelAnimationFrame(handle);
+        }
+
+        //Keep draining the queue so it doesn't grow while paused, and so the pause key can resume
+        let runtime = self.self_ref.as_ref().map(Rc::downgrade);
+        self.paused_poll = Some(Interval::new(move || {
+            if let Some(runtime) = runtime.as_ref().and_then(|runtime| runtime.upgrade()) {
+                runtime.borrow_mut().poll_paused();
+            }
+        }, PAUSED_POLL_INTERVAL));
+    }
+
+    pub fn resume(&mut self) {
+        if self.paused_poll.take().is_none() || self.detached_poll.is_some() {
+            return;
+        }
+
+        //Don't count the time spent paused as a frame
+        self.last_frame = Some(now());
+        self.last_render = now();
+        self.delta_filter.reset();
+        self.quality.reset();
+        self.request_animation_frame();
+    }
+
+    pub fn set_render_mode(&mut self, mode: RenderMode) {
+        self.render_mode = mode;
+        self.quality.reset();
+        self.request_redraw();
+    }
+
+    pub fn request_redraw(&mut self) {
+        self.redraw_requested = true;
+    }
+
+    fn should_render(&self, time: f64, dirty: bool) -> bool {
+        match self.render_mode {
+            RenderMode::Continuous => true,
+            RenderMode::Capped(max_fps) => time - self.last_render >= 1000.0 / max_fps - FRAME_CAP_TOLERANCE,
+            RenderMode::OnDemand => dirty || self.redraw_requested
+        }
+    }
+
+    pub fn screenshot(&mut self) {
+        self.scene.update(&mut self.context, &self.camera, 0.0);
+        self.context.screenshot(self.scene.as_ref());
+    }
+
+    pub fn scene_kind(&self) -> SceneKind {
+        self.scene_kind
+    }
+
+    //The old scene is dropped before the new one is created, so only one scene's resources exist at a time
+    pub fn set_scene(&mut self, kind: SceneKind) {
+        if kind == self.scene_kind {
+            return;
+        }
+
+        //TerrainScene holds nothing, it only stands in so the old scene is freed before create allocates the new one
+        drop(std::mem::replace(&mut self.scene, Box::new(super::scene::TerrainScene)));
+        self.scene = kind.create(&mut self.context);
+        self.scene_kind = kind;
+        self.request_redraw();
+
+        console_log!("Scene: {:?}", kind);
+    }
+
+    //Fetches the terrain shader from the dev server and swaps it in if it compiles
+    #[cfg(feature = "hot-shaders")]
+    pub fn reload_shaders(&mut self) {
+        let runtime = self.self_ref.as_ref().map(Rc::downgrade);
+
+        wasm_bindgen_futures::spawn_local(async move {
+            let source = match crate::util::fetch_text(HOT_SHADER_URL).await {
+                Ok(source) => source,
+                Err(e) => {
+                    console_log!("Couldn't fetch shader: {:?}", e);
+                    return;
+                }
+            };
+
+            let Some(runtime) = runtime.and_then(|runtime| runtime.upgrade()) else { return };
+
+            let validation = runtime.borrow_mut().context.rebuild_pipeline(&source);
+            let error = validation.await;
+
+            runtime.borrow_mut().context.finish_rebuild(error);
+            runtime.borrow_mut().request_redraw();
+        });
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    //Applies and saves a setting, keeping the settings panel in step
+    pub fn apply_setting(&mut self, id: SettingId, value: f64) {
+        self.preview_setting(id, value);
+        self.settings.save(local_storage().as_ref(), &self.canvas_id);
+    }
+
+    //Applies a setting without saving it, for values that are still being dragged
+    pub fn preview_setting(&mut self, id: SettingId, value: f64) {
+        let value = self.settings.set(id, value);
+        if let Some(panel) = &self.settings_panel {
+            panel.sync(id, value);
+        }
+
+        match id {
+            SettingId::HeightScale => self.context.set_height_scale(self.settings.height_scale),
+            SettingId::NoiseFrequency => self.context.set_noise_frequency(self.settings.noise_frequency),
+            SettingId::DetailStrength | SettingId::DetailFrequency => self.context.set_detail(self.settings.detail_strength, self.settings.detail_frequency),
+            SettingId::CameraSpeed => self.controller.speed = self.settings.camera_speed,
+            SettingId::InvertY => self.controller.invert_y = self.settings.invert_y,
+            SettingId::SunAzimuth | SettingId::SunElevation => self.context.set_sun_angles(self.settings.sun_azimuth, self.settings.sun_elevation),
+            SettingId::FogDensity => self.context.set_fog_density(self.settings.fog_density),
+            SettingId::WaterLevel => self.context.set_water_level(self.settings.water_level),
+            SettingId::MeshDetail => self.context.set_mesh_density(self.settings.mesh_detail),
+            SettingId::SplatStrength | SettingId::RockSlope | SettingId::SandLine | SettingId::SnowLine => {
+                let mut splat = self.context.splat_settings();
+                splat.strength = self.settings.splat_strength;
+                splat.rock_slope = self.settings.rock_slope;
+                splat.sand_line = self.settings.sand_line;
+                splat.snow_line = self.settings.snow_line;
+                self.context.set_splat_settings(splat);
+            },
+            SettingId::Exposure => self.context.set_exposure(self.settings.exposure),
+            SettingId::Vignette => self.context.set_vignette(self.settings.vignette),
+            SettingId::ShadowBias | SettingId::ShadowSlopeBias => self.context.set_shadow_bias(self.settings.shadow_bias, self.settings.shadow_slope_bias),
+            SettingId::Grass => self.context.set_show_grass(self.settings.grass),
+            SettingId::GrassDensity => self.context.set_grass_density(self.settings.grass_density),
+            SettingId::GrassDistance => self.context.set_grass_distance(self.settings.grass_distance),
+            SettingId::Sky => self.context.set_show_sky(self.settings.sky),
+            SettingId::ChunkVariation => self.context.set_chunk_variation(self.settings.chunk_variation),
+            SettingId::Vsync => {
+                self.context.set_present_mode(if self.settings.vsync { PresentPreference::AutoVsync } else { PresentPreference::AutoNoVsync });
+            }
+        }
+
+        self.request_redraw();
+    }
+
+    pub fn set_seed(&mut self, seed: Seed) {
+        self.context.set_seed(seed);
+        self.request_redraw();
+    }
+
+    pub fn teleport(&mut self, position: [f64; 3]) {
+        self.camera.set_world_position(position);
+        self.camera.recenter(self.context.chunk_extent() as f64, RECENTER_DISTANCE);
+        self.request_redraw();
+    }
+
+    pub fn look_at(&mut self, target: [f64; 3]) {
+        self.camera.look_at_world(target);
+        self.request_redraw();
+    }
+
+    pub fn set_ambient_color(&mut self, color: [f32; 3]) {
+        self.context.set_ambient_color(color);
+        self.request_redraw();
+    }
+
+    pub fn set_water_color(&mut self, color: [f32; 4]) {
+        self.context.set_water_color(color);
+        self.request_redraw();
+    }
+
+    pub fn set_background_alpha(&mut self, alpha: f32) {
+        self.context.set_background_alpha(alpha);
+        self.request_redraw();
+    }
+
+    //The camera and a top-down view from above it, side by side
+    fn split_views(&self) -> [(Camera, ViewRect); 2] {
+        let (width, height) = (self.context.config.width, self.context.config.height);
+
+        let mut main = self.camera.clone();
+        main.aspect = MAIN_VIEW.aspect(width, height);
+        let top_down = self.camera.top_down(TOP_DOWN_HEIGHT, TOP_DOWN_VIEW.aspect(width, height));
+
+        [(main, MAIN_VIEW), (top_down, TOP_DOWN_VIEW)]
+    }
+
+    //None goes back to drawing the camera's view. Poses that aren't a whole number of eyes are ignored.
+    pub fn set_xr_poses(&mut self, poses: Option<Vec<f32>>) {
+        if let Some(poses) = &poses {
+            if poses.is_empty() || !poses.len().is_multiple_of(XR_VIEW_LENGTH) {
+                console_log!("Expected {} numbers per XR view, got {}", XR_VIEW_LENGTH, poses.len());
+                return;
+            }
+        }
+
+        self.xr_poses = poses;
+        self.request_redraw();
+    }
+
+    pub fn set_aspect_lock(&mut self, aspect: Option<f32>) {
+        self.context.set_aspect_lock(aspect);
+        self.camera.aspect = self.context.camera_aspect();
+        self.request_redraw();
+    }
+
+    pub fn set_color_ramp(&mut self, ramp: &ColorRamp) {
+        self.context.set_color_ramp(ramp);
+        self.request_redraw();
+    }
+
+    pub fn set_sun_color(&mut self, color: [f32; 3]) {
+        self.context.set_sun_color(color);
+        self.request_redraw();
+    }
+
+    pub fn set_fog_color(&mut self, color: [f32; 3]) {
+        self.context.set_fog_color(color);
+        self.request_redraw();
+    }
+
+    pub fn set_clear_color(&mut self, color: [f32; 3]) {
+        self.context.set_clear_color(color);
+        self.request_redraw();
+    }
+
+    pub fn set_debug_clear(&mut self, enabled: bool) {
+        self.context.set_debug_clear(enabled);
+        self.request_redraw();
+    }
+
+    pub fn set_hemisphere_ambient(&mut self, colors: Option<([f32; 3], [f32; 3])>) {
+        self.context.set_hemisphere_ambient(colors);
+        self.request_redraw();
+    }
+
+    pub fn toggle_wireframe(&mut self) {
+        let mode = match self.context.debug_mode() {
+            DebugMode::Wireframe => DebugMode::Shaded,
+            _ => DebugMode::Wireframe
+        };
+
+        self.context.set_debug_mode(mode);
+        self.request_redraw();
+    }
+
+    pub fn set_texture_filter(&mut self, filter: TextureFilter) {
+        self.context.set_texture_filter(filter);
+        self.request_redraw();
+    }
+
+    //Measures the canvas now instead of waiting for the resize observer or the next poll
+    pub fn check_resize(&mut self) {
+        self.event_queue.borrow_mut().detect_resize();
+        self.request_redraw();
+    }
+
+    pub fn camera(&self) -> &Camera {
+        &self.camera
+    }
+
+    pub fn context(&self) -> &WgpuContext {
+        &self.context
+    }
+
+    pub fn stats(&self) -> &FrameStats {
+        &self.stats
+    }
+
+    pub fn dump_timings(&self) {
+        console_log!("{}", self.profiler.table());
+    }
+
+    pub fn start_recording(&mut self) {
+        self.recorder = Some(Recorder::new());
+    }
+
+    pub fn stop_recording(&mut self) -> Vec<CameraKeyframe> {
+        self.recorder.take().map(Recorder::finish).unwrap_or_default()
+    }
+
+    //Input is ignored while a path is playing
+    pub fn play(&mut self, keyframes: Vec<CameraKeyframe>) {
+        self.playback = Some(Playback::new(keyframes));
+    }
+
+    pub fn save_bookmark(&mut self, slot: u8) {
+        self.bookmarks.save_slot(slot, self.camera.state());
+        self.bookmarks.save(local_storage().as_ref(), &self.canvas_id);
+        console_log!("Saved bookmark {}", slot);
+    }
+
+    //Flies to the bookmark instead of jumping. The aspect ratio stays whatever the canvas needs.
+    pub fn recall_bookmark(&mut self, slot: u8) {
+        let mut state = match self.bookmarks.recall(slot) {
+            Some(state) => *state,
+            None => return
+        };
+        state.aspect = self.camera.aspect;
+
+        let from = CameraKeyframe::capture(&self.camera, 0.0);
+        self.camera.set_state(&state);
+        let mut to = CameraKeyframe::capture(&self.camera, RECALL_DURATION);
+        to.yaw = from.yaw + wrap_angle(to.yaw - from.yaw);
+        from.apply(&mut self.camera);
+
+        self.play(vec![from, to]);
+    }
+
+    //Replaces any thumbnails still waiting
+    pub fn queue_thumbnails(&mut self, queue: ThumbnailQueue) {
+        self.thumbnails = Some(queue);
+    }
+
+    fn render_next_thumbnail(&mut self) {
+        let Some(queue) = &mut self.thumbnails else {
+            return;
+        };
+
+        if let Some(seed) = queue.next_seed() {
+            let (size, callback) = (queue.size, queue.callback.clone());
+            self.context.render_thumbnail(seed, size, callback);
+        }
+
+        if queue.is_empty() {
+            self.thumbnails = None;
+        }
+    }
+
+    fn apply_quality(&mut self, quality: Quality) {
+        self.context.set_render_scale(quality.render_scale);
+        self.context.set_chunk_radius(quality.chunk_radius);
+        self.context.set_tessellation(TessellationConfig {
+            max_subdivisions: quality.max_subdivisions,
+            ..*self.context.tessellation()
+        });
+        self.request_redraw();
+    }
+
+    pub fn set_mesh_strategy(&mut self, strategy: MeshStrategy) {
+        self.context.set_tessellation(TessellationConfig {
+            strategy,
+            ..*self.context.tessellation()
+        });
+        self.request_redraw();
+    }
+
+    pub fn set_poisson_mesh(&mut self, min_distance: f32) {
+        self.context.set_tessellation(TessellationConfig {
+            strategy: MeshStrategy::PoissonScatter,
+            min_distance,
+            ..*self.context.tessellation()
+        });
+        self.request_redraw();
+    }
+
+    pub fn set_vertex_heights(&mut self, heights: VertexHeights) {
+        self.context.set_vertex_heights(heights);
+        self.request_redraw();
+    }
+
+    pub fn quality(&self) -> &QualityController {
+        &self.quality
+    }
+
+    //Frame time in milliseconds the quality controller aims for
+    pub fn set_frame_target(&mut self, frame_time: f64) {
+        self.quality.target_frame_time = frame_time;
+        self.quality.reset();
+    }
+
+    pub fn set_quality_bounds(&mut self, bounds: QualityBounds) {
+        let quality = self.quality.set_bounds(bounds);
+        self.apply_quality(quality);
+    }
+
+    pub fn start_benchmark(&mut self, bench: Benchmark) {
+        console_log!("Starting benchmark");
+
+        self.set_render_mode(RenderMode::Continuous);
+        self.quality.enabled = false;
+        self.apply_quality(self.quality.bounds().max);
+
+        self.stats = FrameStats::new();
+        self.profiler.reset();
+        self.bench = Some(bench);
+    }
+
+    //Runs straight away rather than over frames, it doesn't draw anything
+    pub fn run_mesh_benchmark(&self) {
+        console_log!("Starting mesh benchmark");
+        let json = serde_json::to_string(&mesh_bench()).unwrap();
+        console_log!("Mesh benchmark results: {}", json);
+
+        let canvas = self.event_queue.borrow().canvas().clone();
+        if let Some(document) = canvas.owner_document() {
+            show_summary(&document, &canvas.id(), &json);
+        }
+    }
+
+    fn finish_benchmark(&mut self) {
+        let bench = match self.bench.take() {
+            Some(bench) => bench,
+            None => return
+        };
+
+        let json = serde_json::to_string(&bench.summary(&self.profiler)).unwrap();
+        console_log!("Benchmark results: {}", json);
+
+        let canvas = self.event_queue.borrow().canvas().clone();
+        if let Some(document) = canvas.owner_document() {
+            show_summary(&document, &canvas.id(), &json);
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playback.is_some()
+    }
+
+    fn poll_paused(&mut self) {
+        self.drain_events();
+
+        if self.input.snapshot().was_pressed(PAUSE_KEY) {
+            self.resume();
+        }
+    }
+
+    fn drain_events(&mut self) -> usize {
+        self.event_queue.borrow_mut().poll_resize();
+
+        let events = self.event_queue.borrow_mut().drain_coalesced();
+        let count = events.len();
+
+        for event in events {
+            self.handle_event(event);
+        }
+
+        count
+    }
+
+    //Stops the frame loop while the canvas is out of the DOM, presenting into a detached canvas errors eventually.
+    //A canvas hidden with display: none is treated the same, it has no size to render at.
+    fn suspend_detached(&mut self) {
+        self.context.suspend();
+
+        let runtime = self.self_ref.as_ref().map(Rc::downgrade);
+        self.detached_poll = Some(Interval::new(move || {
+            if let Some(runtime) = runtime.as_ref().and_then(|runtime| runtime.upgrade()) {
+                runtime.borrow_mut().poll_detached();
+            }
+        }, PAUSED_POLL_INTERVAL));
+    }
+
+    fn poll_detached(&mut self) {
+        if !canvas_visible(self.event_queue.borrow().canvas()) {
+            return;
+        }
+        self.detached_poll = None;
+
+        let canvas = self.event_queue.borrow().canvas().clone();
+        let (width, height) = get_expected_size(&canvas, self.context.options.min_canvas_size);
+        canvas.set_width(width);
+        canvas.set_height(height);
+
+        self.context.resume_surface(PhysicalSize::new(width, height));
+        self.camera.aspect = self.context.camera_aspect();
+
+        self.last_frame = None;
+        self.delta_filter.reset();
+        self.quality.reset();
+        self.request_redraw();
+
+        if !self.is_paused() {
+            self.request_animation_frame();
+        }
+    }
+
+    pub fn render(&mut self, time: f64) {
+        self.animation_frame = None;
+
+        if !canvas_visible(self.event_queue.borrow().canvas()) {
+            self.suspend_detached();
+            return;
+        }
+
+        let raw_dt = self.last_frame.map(|last_frame| (time - last_frame) / 1000.0).unwrap_or(0.0);
+        self.last_frame = Some(time);
+        let dt = self.delta_filter.filter(raw_dt);
+
+        let start = now();
+        let events = self.drain_events();
+        let start = self.profiler.span("events", start);
+
+        let input = self.input.snapshot();
+        let input = if self.bench.is_some() { InputState::idle() } else { input };
+        if input.was_pressed(STATS_TOGGLE_KEY) {
+            self.overlay.toggle();
+        }
+        if input.was_pressed(PAUSE_KEY) {
+            self.pause();
+            return;
+        }
+        if input.was_pressed(SCREENSHOT_KEY) {
+            self.screenshot();
+        }
+        if input.was_pressed(SPLIT_VIEW_KEY) {
+            self.split_view = !self.split_view;
+            self.request_redraw();
+        }
+        #[cfg(feature = "hot-shaders")]
+        if input.was_pressed(RELOAD_SHADERS_KEY) {
+            self.reload_shaders();
+        }
+        if input.was_pressed(CULL_MODE_KEY) {
+            self.context.set_cull_mode(self.context.cull_mode().next());
+            self.request_redraw();
+        }
+        if input.was_pressed(TIME_SLOWER_KEY) {
+            self.clock.set_scale(self.clock.scale() - TIME_SCALE_STEP);
+            console_log!("Time scale: {}", self.clock.scale());
+        }
+        if input.was_pressed(TIME_FASTER_KEY) {
+            self.clock.set_scale(self.clock.scale() + TIME_SCALE_STEP);
+            console_log!("Time scale: {}", self.clock.scale());
+        }
+        if input.was_pressed(TIME_FREEZE_KEY) {
+            self.clock.toggle_freeze();
+            console_log!("Time scale: {}", self.clock.scale());
+        }
+        if input.was_pressed(SCENE_KEY) {
+            self.set_scene(self.scene_kind.next());
+        }
+        if input.was_pressed(DEBUG_MODE_KEY) {
+            self.context.set_debug_mode(self.context.debug_mode().next());
+            self.request_redraw();
+        }
+        if input.was_pressed(TEXTURE_FILTER_KEY) {
+            self.set_texture_filter(self.context.texture_filter().next());
+            console_log!("Texture filter: {:?}", self.context.texture_filter());
+        }
+        if input.was_pressed(SUN_LEFT_KEY) || input.was_pressed(SUN_RIGHT_KEY) {
+            let step = if input.was_pressed(SUN_LEFT_KEY) { -SUN_STEP } else { SUN_STEP };
+            let azimuth = (self.settings.sun_azimuth as f64 + step).rem_euclid(360.0);
+            self.apply_setting(SettingId::SunAzimuth, azimuth);
+        }
+        if input.was_pressed(SUN_DOWN_KEY) || input.was_pressed(SUN_UP_KEY) {
+            let step = if input.was_pressed(SUN_DOWN_KEY) { -SUN_STEP } else { SUN_STEP };
+            self.apply_setting(SettingId::SunElevation, self.settings.sun_elevation as f64 + step);
+        }
+        let height_up = HEIGHT_UP_KEYS.iter().any(|key| input.was_pressed(*key));
+        if input.was_pressed(HEIGHT_DOWN_KEY) || height_up {
+            let step = if height_up { HEIGHT_SCALE_STEP } else { -HEIGHT_SCALE_STEP };
+            self.apply_setting(SettingId::HeightScale, self.settings.height_scale as f64 + step);
+        }
+        if input.was_pressed(MESH_DETAIL_DOWN_KEY) || input.was_pressed(MESH_DETAIL_UP_KEY) {
+            let step = if input.was_pressed(MESH_DETAIL_DOWN_KEY) { -MESH_DETAIL_STEP } else { MESH_DETAIL_STEP };
+            self.apply_setting(SettingId::MeshDetail, self.settings.mesh_detail as f64 + step);
+        }
+        if input.was_pressed(GRID_TOGGLE_KEY) {
+            self.context.show_grid = !self.context.show_grid;
+            self.request_redraw();
+        }
+        if let Some(slot) = input.pressed_keys().find_map(Bookmarks::slot_for_key) {
+            if input.is_held(KeyboardKey::Control) {
+                self.save_bookmark(slot);
+            } else {
+                self.recall_bookmark(slot);
+            }
+        }
+
+        let camera_changed = if let Some(bench) = &mut self.bench {
+            bench.advance(&mut self.camera);
+            self.scene.uses_camera()
+        } else if !self.scene.uses_camera() {
+            false
+        } else if let Some(playback) = &mut self.playback {
+            if !playback.update(&mut self.camera, time) {
+                self.playback = None;
+            }
+            true
+        } else {
+            self.controller.update(&mut self.camera, &input, dt as f32)
+        };
+        //Keeps the camera near the origin, the context follows the move in prepare
+        self.camera.recenter(self.context.chunk_extent() as f64, RECENTER_DISTANCE);
+        let start = self.profiler.span("camera", start);
+
+        //World time is independent of the camera, freezing it doesn't stop movement
+        self.clock.advance(dt);
+        self.context.set_time(self.clock.time() as f32);
+
+        let scene_changed = self.scene.update(&mut self.context, &self.camera, dt as f32);
+        self.profiler.span("uploads", start);
+
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(&self.camera, time);
+        }
+
+        if self.should_render(time, events > 0 || camera_changed || scene_changed) {
+            let result = match &self.xr_poses {
+                Some(poses) => {
+                    let views: Vec<_> = poses.chunks_exact(XR_VIEW_LENGTH).filter_map(|pose| XrView::from_pose(&self.camera, pose)).collect();
+                    self.context.render_xr(&views, &mut self.profiler)
+                },
+                None if self.split_view => {
+                    let views = self.split_views();
+                    self.context.render_views(&views, &mut self.profiler)
+                },
+                None => self.context.render(self.scene.as_ref(), &mut self.profiler)
+            };
+
+            match result {
+                Ok(render) => self.stats.record_render(render),
+                //The surface needs configuring again, the next frame picks it back up
+                Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                    self.context.resume_surface(self.context.size);
+                    self.request_redraw();
+                },
+                Err(e) => console_log!("Couldn't render frame: {:?}", e)
+            }
+
+            //The first frame has nothing to measure from, its time would be the whole page load
+            let has_previous = self.last_render > 0.0;
+            let frame_time = time - self.last_render;
+            if has_previous {
+                self.stats.record_frame(frame_time, self.skipped_events + events, self.context.uploaded_bytes());
+            }
+
+            //Frame times only reflect how long rendering takes when nothing is holding frames back
+            if self.render_mode == RenderMode::Continuous && has_previous {
+                if let Some(quality) = self.quality.record(frame_time) {
+                    self.apply_quality(quality);
+                }
+            }
+
+            if let Some(bench) = &mut self.bench {
+                if has_previous {
+                    bench.record_frame(frame_time);
+                }
+
+                if bench.is_done() {
+                    self.finish_benchmark();
+                }
+            }
+
+            self.last_render = time;
+            self.redraw_requested = false;
+            self.skipped_events = 0;
+        } else {
+            self.skipped_events += events;
+        }
+
+        self.render_next_thumbnail();
+        self.overlay.update(&self.stats, &self.profiler, &self.context.mesh_stats(), time);
+
+        self.request_animation_frame();
+    }
+
+    pub fn handle_event(&mut self, event: Event) {
+        self.input.handle_event(&event);
+        self.scene.handle_event(&mut self.context, &event);
+
+        if let Event::CanvasResize(CanvasResizeData {new_width, new_height, ..}) = event {
+            self.context.resize(PhysicalSize::new(new_width, new_height));
+            self.camera.aspect = self.context.camera_aspect();
+
+            if let Some(panel) = &self.settings_panel {
+                panel.reposition();
+            }
+        }
+    }
+
+    //Stops rendering and releases everything that would otherwise keep the runtime alive.
+    //GPU resources are freed when the last reference to the runtime is dropped.
+    pub fn destroy(&mut self) {
+        if let Some(handle) = self.animation_frame.take() {
+            cancelAnimationFrame(handle);
+        }
+        self.paused_poll = None;
+        self.detached_poll = None;
+
+        self.event_queue.borrow_mut().detach();
+        self.overlay.remove();
+        if let Some(mut panel) = self.settings_panel.take() {
+            panel.remove();
+        }
+
+        self.render_closure = None;
+        self.self_ref = None;
+    }
+}
@@ -0,0 +1,169 @@
+use super::{camera::Camera, event::Event, wgpu_context::WgpuContext};
+
+//Something the runtime can draw. Scenes are created fresh when switched to and dropped when
+//switched away from, so anything they own (pipelines, buffers) goes with them.
+pub trait Scene {
+    fn init(&mut self, context: &mut WgpuContext);
+
+    fn handle_event(&mut self, _context: &mut WgpuContext, _event: &Event) {}
+
+    //Per-frame uploads, returns whether the scene changed and needs redrawing
+    fn update(&mut self, context: &mut WgpuContext, camera: &Camera, dt: f32) -> bool;
+
+    fn render(&self, context: &WgpuContext, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView);
+
+    //Whether the camera controller and playback should run while this scene is active
+    fn uses_camera(&self) -> bool {
+        true
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SceneKind {
+    Terrain,
+    NoisePreview
+}
+
+impl SceneKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "terrain" => Some(SceneKind::Terrain),
+            "noise" => Some(SceneKind::NoisePreview),
+            _ => None
+        }
+    }
+
+    //Reads ?scene= from the page URL, defaulting to the terrain
+    pub fn from_location() -> Self {
+        let search = web_sys::window()
+            .and_then(|window| window.location().search().ok())
+            .unwrap_or_default();
+
+        search.trim_start_matches('?')
+            .split('&')
+            .filter_map(|pair| pair.strip_prefix("scene="))
+            .find_map(Self::from_name)
+            .unwrap_or(SceneKind::Terrain)
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            SceneKind::Terrain => SceneKind::NoisePreview,
+            SceneKind::NoisePreview => SceneKind::Terrain
+        }
+    }
+
+    pub fn create(self, context: &mut WgpuContext) -> Box<dyn Scene> {
+        let mut scene: Box<dyn Scene> = match self {
+            SceneKind::Terrain => Box::new(TerrainScene),
+            SceneKind::NoisePreview => Box::new(NoisePreviewScene::new())
+        };
+
+        scene.init(context);
+        scene
+    }
+}
+
+//The 3D heightmap, drawn with the terrain pipeline the context owns
+pub struct TerrainScene;
+
+impl Scene for TerrainScene {
+    fn init(&mut self, _context: &mut WgpuContext) {}
+
+    fn update(&mut self, context: &mut WgpuContext, camera: &Camera, _dt: f32) -> bool {
+        context.prepare(camera);
+        context.stream_chunks(camera)
+    }
+
+    fn render(&self, context: &WgpuContext, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        context.encode_scene(encoder, view);
+    }
+}
+
+//The noise texture drawn flat over the whole canvas
+pub struct NoisePreviewScene {
+    pipeline: Option<wgpu::RenderPipeline>
+}
+
+impl NoisePreviewScene {
+    pub fn new() -> Self {
+        NoisePreviewScene {
+            pipeline: None
+        }
+    }
+}
+
+impl Default for NoisePreviewScene {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scene for NoisePreviewScene {
+    fn init(&mut self, context: &mut WgpuContext) {
+        let shader = context.device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Noise preview shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/noise_preview.wgsl").into())
+        });
+
+        self.pipeline = Some(context.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Noise preview Pipeline"),
+            layout: Some(context.scene_pipeline_layout()),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: context.scene_format(),
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None
+        }));
+    }
+
+    fn update(&mut self, _context: &mut WgpuContext, _camera: &Camera, _dt: f32) -> bool {
+        false
+    }
+
+    fn render(&self, context: &WgpuContext, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        let Some(pipeline) = &self.pipeline else { return };
+        let Some(textures) = context.noise_texture_bind_group() else { return };
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Noise preview Pass"),
+                color_attachments: &[
+                    Some(wgpu::RenderPassColorAttachment {
+                        view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                            store: true
+                        }
+                    })
+                ],
+                depth_stencil_attachment: None
+            });
+
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, context.render_settings_bind_group(), &[]);
+            render_pass.set_bind_group(1, textures, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+
+        context.time_pass(encoder, "Noise preview Pass");
+    }
+
+    fn uses_camera(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,118 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{Blob, BlobPropertyBag, HtmlAnchorElement, Url};
+
+//Rows copied into or out of a texture have to be padded to COPY_BYTES_PER_ROW_ALIGNMENT
+pub fn padded_bytes_per_row(width: u32, pixel_size: u32) -> u32 {
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let unpadded_bytes_per_row = pixel_size * width;
+    let padding = (align - unpadded_bytes_per_row % align) % align;
+
+    unpadded_bytes_per_row + padding
+}
+
+//Maps a MAP_READ buffer, resolving once its contents can be read. The callback only runs when wgpu
+//next polls the device, which the frame loop's submits do.
+pub async fn map_read(buffer: &wgpu::Buffer) -> Result<(), wgpu::BufferAsyncError> {
+    let result = Rc::new(Cell::new(None));
+
+    let promise = js_sys::Promise::new(&mut |resolve, _reject| {
+        let result = result.clone();
+        buffer.slice(..).map_async(wgpu::MapMode::Read, move |mapped| {
+            result.set(Some(mapped));
+            let _ = resolve.call0(&JsValue::NULL);
+        });
+    });
+    let _ = wasm_bindgen_futures::JsFuture::from(promise).await;
+
+    result.take().unwrap_or(Err(wgpu::BufferAsyncError))
+}
+
+//Strips row padding and reorders channels to RGBA. The bytes of *Srgb formats are already
+//gamma encoded, and the browser shows plain unorm formats as-is, so neither needs converting
+//for the PNG to match what's on screen.
+pub fn to_rgba8(format: wgpu::TextureFormat, data: &[u8], width: u32, height: u32, bytes_per_row: u32) -> Option<Vec<u8>> {
+    let swap_red_blue = match format {
+        wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+        _ => return None
+    };
+
+    let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+
+    for row in data.chunks(bytes_per_row as usize).take(height as usize) {
+        for pixel in row[..(width * 4) as usize].chunks_exact(4) {
+            if swap_red_blue {
+                pixels.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]);
+            } else {
+                pixels.extend_from_slice(pixel);
+            }
+        }
+    }
+
+    Some(pixels)
+}
+
+pub fn encode_png(pixels: &[u8], width: u32, height: u32) -> Result<Vec<u8>, png::EncodingError> {
+    let mut png_data = Vec::new();
+
+    {
+        let mut encoder = png::Encoder::new(&mut png_data, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(pixels)?;
+    }
+
+    Ok(png_data)
+}
+
+pub fn download(data: &[u8], mime_type: &str, file_name: &str) -> Result<(), JsValue> {
+    let document = web_sys::window().and_then(|window| window.document()).ok_or("No document")?;
+
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(data));
+    let options = BlobPropertyBag::new();
+    options.set_type(mime_type);
+    let blob = Blob::new_with_u8_array_sequence_and_options(&parts, &options)?;
+
+    let url = Url::create_object_url_with_blob(&blob)?;
+
+    let anchor: HtmlAnchorElement = document.create_element("a")?.unchecked_into();
+    anchor.set_href(&url);
+    anchor.set_download(file_name);
+    anchor.click();
+
+    Url::revoke_object_url(&url)
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    fn rows_are_padded_to_the_copy_alignment() {
+        assert_eq!(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT, 256);
+
+        assert_eq!(padded_bytes_per_row(64, 4), 256);
+        assert_eq!(padded_bytes_per_row(65, 4), 512);
+        assert_eq!(padded_bytes_per_row(1, 1), 256);
+        assert_eq!(padded_bytes_per_row(257, 2), 768);
+        assert_eq!(padded_bytes_per_row(0, 4), 0);
+    }
+
+    #[wasm_bindgen_test]
+    fn padding_and_channel_order_are_undone() {
+        //Two rows of two pixels, padded to eight bytes
+        let data = [1, 2, 3, 4, 5, 6, 7, 8, 0, 0, 0, 0, 0, 0, 0, 0, 9, 10, 11, 12, 13, 14, 15, 16, 0, 0, 0, 0, 0, 0, 0, 0];
+
+        assert_eq!(to_rgba8(wgpu::TextureFormat::Rgba8Unorm, &data, 2, 2, 16).unwrap(), [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16]);
+        assert_eq!(to_rgba8(wgpu::TextureFormat::Bgra8UnormSrgb, &data, 2, 2, 16).unwrap(), [3, 2, 1, 4, 7, 6, 5, 8, 11, 10, 9, 12, 15, 14, 13, 16]);
+        assert_eq!(to_rgba8(wgpu::TextureFormat::R32Float, &data, 2, 2, 16), None);
+    }
+}
@@ -0,0 +1,470 @@
+use std::{cell::RefCell, rc::Weak};
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::{prelude::Closure, JsCast, JsValue};
+use web_sys::{Element, HtmlCanvasElement, HtmlElement, HtmlInputElement, Storage};
+
+use crate::{console_log, util::{scoped_id, insert_after_canvas, pin_to_canvas, Corner}};
+
+use super::runtime::Runtime;
+use super::wgpu_context::{DEFAULT_FOG_DENSITY, DEFAULT_SUN_AZIMUTH, DEFAULT_SUN_ELEVATION, MAX_MESH_DENSITY, MIN_MESH_DENSITY};
+use super::water::DEFAULT_WATER_LEVEL;
+use super::splat::{DEFAULT_ROCK_SLOPE, DEFAULT_SAND_LINE, DEFAULT_SNOW_LINE, DEFAULT_SPLAT_STRENGTH};
+use super::post::{DEFAULT_EXPOSURE, DEFAULT_VIGNETTE};
+use super::shadow::{DEFAULT_SHADOW_BIAS, DEFAULT_SHADOW_SLOPE_BIAS};
+use super::grass::{DEFAULT_GRASS_DENSITY, DEFAULT_GRASS_DISTANCE};
+
+//Scoped to the canvas like element ids, so instances on one page keep their own settings
+const STORAGE_KEY: &str = "cacophony-settings";
+
+pub fn storage_key(canvas_id: &str) -> String {
+    scoped_id(canvas_id, STORAGE_KEY)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SettingId {
+    HeightScale,
+    NoiseFrequency,
+    DetailStrength,
+    DetailFrequency,
+    CameraSpeed,
+    InvertY,
+    SunAzimuth,
+    SunElevation,
+    FogDensity,
+    WaterLevel,
+    MeshDetail,
+    Vsync,
+    SplatStrength,
+    RockSlope,
+    SandLine,
+    SnowLine,
+    Exposure,
+    Vignette,
+    ShadowBias,
+    ShadowSlopeBias,
+    Grass,
+    GrassDensity,
+    GrassDistance,
+    Sky,
+    ChunkVariation
+}
+
+impl SettingId {
+    //Settings that refill the chunk textures or rebuild the mesh when changed
+    pub fn is_expensive(self) -> bool {
+        matches!(self, SettingId::NoiseFrequency | SettingId::ChunkVariation | SettingId::MeshDetail)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum SettingKind {
+    Range { min: f64, max: f64, step: f64 },
+    Toggle
+}
+
+pub struct SettingDescriptor {
+    pub id: SettingId,
+    pub key: &'static str,
+    pub label: &'static str,
+    pub kind: SettingKind
+}
+
+impl SettingDescriptor {
+    pub fn clamp(&self, value: f64) -> f64 {
+        match self.kind {
+            SettingKind::Range { min, max, .. } if value.is_finite() => value.clamp(min, max),
+            SettingKind::Range { min, .. } => min,
+            SettingKind::Toggle => if value != 0.0 { 1.0 } else { 0.0 }
+        }
+    }
+
+    //The input events a control listens to, and whether each commits the value (saves it) or only previews it.
+    //Sliders fire "input" on every pixel of a drag, so expensive settings wait for "change" when it's released.
+    pub fn control_events(&self) -> &'static [(&'static str, bool)] {
+        match self.kind {
+            SettingKind::Range { .. } if !self.id.is_expensive() => &[("input", false), ("change", true)],
+            _ => &[("change", true)]
+        }
+    }
+}
+
+pub const DESCRIPTORS: &[SettingDescriptor] = &[
+    SettingDescriptor { id: SettingId::HeightScale, key: "height_scale", label: "Height scale", kind: SettingKind::Range { min: 0.0, max: 20.0, step: 0.1 } },
+    SettingDescriptor { id: SettingId::NoiseFrequency, key: "noise_frequency", label: "Noise frequency", kind: SettingKind::Range { min: 0.01, max: 1.0, step: 0.01 } },
+    SettingDescriptor { id: SettingId::DetailStrength, key: "detail_strength", label: "Detail strength", kind: SettingKind::Range { min: 0.0, max: 0.5, step: 0.005 } },
+    SettingDescriptor { id: SettingId::DetailFrequency, key: "detail_frequency", label: "Detail frequency", kind: SettingKind::Range { min: 0.5, max: 32.0, step: 0.5 } },
+    SettingDescriptor { id: SettingId::CameraSpeed, key: "camera_speed", label: "Camera speed", kind: SettingKind::Range { min: 0.05, max: 50.0, step: 0.05 } },
+    SettingDescriptor { id: SettingId::InvertY, key: "invert_y", label: "Invert Y", kind: SettingKind::Toggle },
+    SettingDescriptor { id: SettingId::SunAzimuth, key: "sun_azimuth", label: "Sun azimuth", kind: SettingKind::Range { min: 0.0, max: 360.0, step: 1.0 } },
+    SettingDescriptor { id: SettingId::SunElevation, key: "sun_elevation", label: "Sun elevation", kind: SettingKind::Range { min: 0.0, max: 90.0, step: 1.0 } },
+    SettingDescriptor { id: SettingId::FogDensity, key: "fog_density", label: "Fog density", kind: SettingKind::Range { min: 0.0, max: 0.05, step: 0.0005 } },
+    SettingDescriptor { id: SettingId::WaterLevel, key: "water_level", label: "Water level", kind: SettingKind::Range { min: 0.0, max: 1.0, step: 0.01 } },
+    SettingDescriptor { id: SettingId::MeshDetail, key: "mesh_detail", label: "Mesh detail", kind: SettingKind::Range { min: MIN_MESH_DENSITY as f64, max: MAX_MESH_DENSITY as f64, step: 0.1 } },
+    SettingDescriptor { id: SettingId::Vsync, key: "vsync", label: "Vsync", kind: SettingKind::Toggle },
+    SettingDescriptor { id: SettingId::SplatStrength, key: "splat_strength", label: "Splat strength", kind: SettingKind::Range { min: 0.0, max: 1.0, step: 0.05 } },
+    SettingDescriptor { id: SettingId::RockSlope, key: "rock_slope", label: "Rock slope", kind: SettingKind::Range { min: 0.0, max: 1.0, step: 0.01 } },
+    SettingDescriptor { id: SettingId::SandLine, key: "sand_line", label: "Sand line", kind: SettingKind::Range { min: 0.0, max: 1.0, step: 0.01 } },
+    SettingDescriptor { id: SettingId::SnowLine, key: "snow_line", label: "Snow line", kind: SettingKind::Range { min: 0.0, max: 1.0, step: 0.01 } },
+    SettingDescriptor { id: SettingId::Exposure, key: "exposure", label: "Exposure", kind: SettingKind::Range { min: 0.1, max: 4.0, step: 0.05 } },
+    SettingDescriptor { id: SettingId::Vignette, key: "vignette", label: "Vignette", kind: SettingKind::Range { min: 0.0, max: 1.0, step: 0.05 } },
+    SettingDescriptor { id: SettingId::ShadowBias, key: "shadow_bias", label: "Shadow bias", kind: SettingKind::Range { min: 0.0, max: 1.0, step: 0.01 } },
+    SettingDescriptor { id: SettingId::ShadowSlopeBias, key: "shadow_slope_bias", label: "Shadow slope bias", kind: SettingKind::Range { min: 0.0, max: 1.0, step: 0.01 } },
+    SettingDescriptor { id: SettingId::Grass, key: "grass", label: "Grass", kind: SettingKind::Toggle },
+    SettingDescriptor { id: SettingId::GrassDensity, key: "grass_density", label: "Grass density", kind: SettingKind::Range { min: 0.0, max: 1.0, step: 0.05 } },
+    SettingDescriptor { id: SettingId::GrassDistance, key: "grass_distance", label: "Grass distance", kind: SettingKind::Range { min: 5.0, max: 100.0, step: 1.0 } },
+    SettingDescriptor { id: SettingId::Sky, key: "sky", label: "Sky", kind: SettingKind::Toggle },
+    SettingDescriptor { id: SettingId::ChunkVariation, key: "chunk_variation", label: "Chunk variation", kind: SettingKind::Range { min: 0.0, max: 1.0, step: 0.05 } },
+];
+
+pub fn descriptor(id: SettingId) -> &'static SettingDescriptor {
+    DESCRIPTORS.iter().find(|descriptor| descriptor.id == id).expect("Every setting needs a descriptor")
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub height_scale: f32,
+    pub noise_frequency: f32,
+    pub detail_strength: f32,
+    pub detail_frequency: f32,
+    pub camera_speed: f32,
+    pub invert_y: bool,
+    //Degrees
+    pub sun_azimuth: f32,
+    pub sun_elevation: f32,
+    pub fog_density: f32,
+    //Normalised height, 0 is below all the terrain
+    pub water_level: f32,
+    //Inner mesh points per unit, see TessellationConfig::density
+    pub mesh_detail: f32,
+    //Off presents as soon as a frame is ready where the surface allows it, see PresentPreference
+    pub vsync: bool,
+    //See SplatSettings, the lines are normalised heights like water_level
+    pub splat_strength: f32,
+    pub rock_slope: f32,
+    pub sand_line: f32,
+    pub snow_line: f32,
+    //Applied by the post pass, see PostProcessor
+    pub exposure: f32,
+    pub vignette: f32,
+    //World units, see ShadowMap::set_bias
+    pub shadow_bias: f32,
+    pub shadow_slope_bias: f32,
+    //Off by default, it's heavy on weak GPUs. See GrassRenderer.
+    pub grass: bool,
+    pub grass_density: f32,
+    //World units
+    pub grass_distance: f32,
+    //Off clears to the fog colour, or the clear colour without fog. See WgpuContext::background_color.
+    pub sky: bool,
+    //0 is one pattern everywhere, see WgpuContext::set_chunk_variation
+    pub chunk_variation: f32
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            height_scale: 1.0,
+            noise_frequency: 0.1,
+            detail_strength: 0.02,
+            detail_frequency: 4.0,
+            camera_speed: 0.5,
+            invert_y: false,
+            sun_azimuth: DEFAULT_SUN_AZIMUTH,
+            sun_elevation: DEFAULT_SUN_ELEVATION,
+            fog_density: DEFAULT_FOG_DENSITY,
+            water_level: DEFAULT_WATER_LEVEL,
+            mesh_detail: 1.0,
+            vsync: true,
+            splat_strength: DEFAULT_SPLAT_STRENGTH,
+            rock_slope: DEFAULT_ROCK_SLOPE,
+            sand_line: DEFAULT_SAND_LINE,
+            snow_line: DEFAULT_SNOW_LINE,
+            exposure: DEFAULT_EXPOSURE,
+            vignette: DEFAULT_VIGNETTE,
+            shadow_bias: DEFAULT_SHADOW_BIAS,
+            shadow_slope_bias: DEFAULT_SHADOW_SLOPE_BIAS,
+            grass: false,
+            grass_density: DEFAULT_GRASS_DENSITY,
+            grass_distance: DEFAULT_GRASS_DISTANCE,
+            sky: true,
+            chunk_variation: 0.0
+        }
+    }
+}
+
+impl Settings {
+    pub fn get(&self, id: SettingId) -> f64 {
+        match id {
+            SettingId::HeightScale => self.height_scale as f64,
+            SettingId::NoiseFrequency => self.noise_frequency as f64,
+            SettingId::DetailStrength => self.detail_strength as f64,
+            SettingId::DetailFrequency => self.detail_frequency as f64,
+            SettingId::CameraSpeed => self.camera_speed as f64,
+            SettingId::InvertY => if self.invert_y { 1.0 } else { 0.0 },
+            SettingId::SunAzimuth => self.sun_azimuth as f64,
+            SettingId::SunElevation => self.sun_elevation as f64,
+            SettingId::FogDensity => self.fog_density as f64,
+            SettingId::WaterLevel => self.water_level as f64,
+            SettingId::MeshDetail => self.mesh_detail as f64,
+            SettingId::Vsync => if self.vsync { 1.0 } else { 0.0 },
+            SettingId::SplatStrength => self.splat_strength as f64,
+            SettingId::RockSlope => self.rock_slope as f64,
+            SettingId::SandLine => self.sand_line as f64,
+            SettingId::SnowLine => self.snow_line as f64,
+            SettingId::Exposure => self.exposure as f64,
+            SettingId::Vignette => self.vignette as f64,
+            SettingId::ShadowBias => self.shadow_bias as f64,
+            SettingId::ShadowSlopeBias => self.shadow_slope_bias as f64,
+            SettingId::Grass => if self.grass { 1.0 } else { 0.0 },
+            SettingId::GrassDensity => self.grass_density as f64,
+            SettingId::GrassDistance => self.grass_distance as f64,
+            SettingId::Sky => if self.sky { 1.0 } else { 0.0 },
+            SettingId::ChunkVariation => self.chunk_variation as f64
+        }
+    }
+
+    //Clamps the value to the setting's descriptor and returns what was actually stored
+    pub fn set(&mut self, id: SettingId, value: f64) -> f64 {
+        let value = descriptor(id).clamp(value);
+
+        match id {
+            SettingId::HeightScale => self.height_scale = value as f32,
+            SettingId::NoiseFrequency => self.noise_frequency = value as f32,
+            SettingId::DetailStrength => self.detail_strength = value as f32,
+            SettingId::DetailFrequency => self.detail_frequency = value as f32,
+            SettingId::CameraSpeed => self.camera_speed = value as f32,
+            SettingId::InvertY => self.invert_y = value != 0.0,
+            SettingId::SunAzimuth => self.sun_azimuth = value as f32,
+            SettingId::SunElevation => self.sun_elevation = value as f32,
+            SettingId::FogDensity => self.fog_density = value as f32,
+            SettingId::WaterLevel => self.water_level = value as f32,
+            SettingId::MeshDetail => self.mesh_detail = value as f32,
+            SettingId::Vsync => self.vsync = value != 0.0,
+            SettingId::SplatStrength => self.splat_strength = value as f32,
+            SettingId::RockSlope => self.rock_slope = value as f32,
+            SettingId::SandLine => self.sand_line = value as f32,
+            SettingId::SnowLine => self.snow_line = value as f32,
+            SettingId::Exposure => self.exposure = value as f32,
+            SettingId::Vignette => self.vignette = value as f32,
+            SettingId::ShadowBias => self.shadow_bias = value as f32,
+            SettingId::ShadowSlopeBias => self.shadow_slope_bias = value as f32,
+            SettingId::Grass => self.grass = value != 0.0,
+            SettingId::GrassDensity => self.grass_density = value as f32,
+            SettingId::GrassDistance => self.grass_distance = value as f32,
+            SettingId::Sky => self.sky = value != 0.0,
+            SettingId::ChunkVariation => self.chunk_variation = value as f32
+        }
+
+        value
+    }
+
+    //Runs every stored value back through the descriptors, in case storage was edited by hand
+    pub fn validated(mut self) -> Self {
+        for descriptor in DESCRIPTORS {
+            self.set(descriptor.id, self.get(descriptor.id));
+        }
+
+        self
+    }
+
+    pub fn load(storage: Option<&Storage>, canvas_id: &str) -> Self {
+        storage
+            .and_then(|storage| storage.get_item(&storage_key(canvas_id)).ok().flatten())
+            .and_then(|json| serde_json::from_str::<Settings>(&json).ok())
+            .unwrap_or_default()
+            .validated()
+    }
+
+    pub fn save(&self, storage: Option<&Storage>, canvas_id: &str) {
+        if let (Some(storage), Ok(json)) = (storage, serde_json::to_string(self)) {
+            if storage.set_item(&storage_key(canvas_id), &json).is_err() {
+                console_log!("Couldn't save settings");
+            }
+        }
+    }
+}
+
+pub fn local_storage() -> Option<Storage> {
+    web_sys::window()?.local_storage().ok().flatten()
+}
+
+type Listener = Closure<dyn FnMut(web_sys::Event)>;
+
+struct Control {
+    id: SettingId,
+    input: HtmlInputElement,
+    //Event name and handler of each listener, see SettingDescriptor::control_events
+    listeners: Vec<(&'static str, Listener)>,
+    //Only set when the control was created here rather than found in the page
+    created: Option<Element>
+}
+
+impl Control {
+    fn show(&self, value: f64) {
+        if self.input.type_() == "checkbox" {
+            self.input.set_checked(value != 0.0);
+        } else {
+            self.input.set_value_as_number(value);
+        }
+    }
+}
+
+//Range/checkbox inputs in <div id="controls"> wired to Runtime::apply_setting and preview_setting.
+//A container made here is pinned to the canvas's top right corner.
+pub struct SettingsPanel {
+    controls: Vec<Control>,
+    canvas: HtmlCanvasElement,
+    created_container: Option<HtmlElement>
+}
+
+impl SettingsPanel {
+    pub fn bind_dom_controls(canvas: &HtmlCanvasElement, settings: &Settings, runtime: Weak<RefCell<Runtime>>) -> Result<Self, JsValue> {
+        let document = canvas.owner_document().ok_or("Canvas has no document")?;
+        let canvas_id = canvas.id();
+        let container_id = scoped_id(&canvas_id, "controls");
+        let (container, created_container) = match document.get_element_by_id(&container_id) {
+            Some(container) => (container, None),
+            None => {
+                let container: HtmlElement = document.create_element("div")?.unchecked_into();
+                container.set_id(&container_id);
+                container.set_attribute("style", "padding: 4px; background: rgba(0, 0, 0, 0.5); color: white; font-family: monospace;")?;
+                insert_after_canvas(&container, canvas)?;
+                pin_to_canvas(&container, canvas, Corner::TopRight);
+                (container.clone().into(), Some(container))
+            }
+        };
+
+        let mut controls = Vec::new();
+
+        for descriptor in DESCRIPTORS {
+            let id = scoped_id(&canvas_id, &format!("setting-{}", descriptor.key));
+
+            let (input, created) = match document.get_element_by_id(&id) {
+                Some(existing) => (existing.dyn_into::<HtmlInputElement>()?, None),
+                None => {
+                    let label = document.create_element("label")?;
+                    label.set_attribute("style", "display: block;")?;
+                    label.set_text_content(Some(descriptor.label));
+
+                    let input: HtmlInputElement = document.create_element("input")?.unchecked_into();
+                    input.set_id(&id);
+                    label.append_child(&input)?;
+                    container.append_child(&label)?;
+
+                    (input, Some(label))
+                }
+            };
+
+            match descriptor.kind {
+                SettingKind::Range { min, max, step } => {
+                    input.set_type("range");
+                    input.set_min(&min.to_string());
+                    input.set_max(&max.to_string());
+                    input.set_step(&step.to_string());
+                },
+                SettingKind::Toggle => input.set_type("checkbox")
+            }
+            let mut control = Control { id: descriptor.id, input, listeners: Vec::new(), created };
+            control.show(settings.get(descriptor.id));
+
+            for &(event, commit) in descriptor.control_events() {
+                let setting = descriptor.id;
+                let kind = descriptor.kind;
+                let runtime = runtime.clone();
+                let input = control.input.clone();
+                let handler: Listener = Closure::new(move |_event: web_sys::Event| {
+                    let value = match kind {
+                        SettingKind::Range { .. } => input.value_as_number(),
+                        SettingKind::Toggle => if input.checked() { 1.0 } else { 0.0 }
+                    };
+
+                    if let Some(runtime) = runtime.upgrade() {
+                        if commit {
+                            runtime.borrow_mut().apply_setting(setting, value);
+                        } else {
+                            runtime.borrow_mut().preview_setting(setting, value);
+                        }
+                    }
+                });
+                control.input.add_event_listener_with_callback(event, handler.as_ref().unchecked_ref())?;
+                control.listeners.push((event, handler));
+            }
+
+            controls.push(control);
+        }
+
+        Ok(SettingsPanel {
+            controls,
+            canvas: canvas.clone(),
+            created_container
+        })
+    }
+
+    //Follows the canvas after it's resized or moved
+    pub fn reposition(&self) {
+        if let Some(container) = &self.created_container {
+            pin_to_canvas(container, &self.canvas, Corner::TopRight);
+        }
+    }
+
+    //Shows a value that changed some other way, e.g. by key. Setting an input's value doesn't fire its listeners.
+    pub fn sync(&self, id: SettingId, value: f64) {
+        if let Some(control) = self.controls.iter().find(|control| control.id == id) {
+            control.show(value);
+        }
+    }
+
+    pub fn remove(&mut self) {
+        for control in self.controls.drain(..) {
+            for (event, handler) in &control.listeners {
+                let _ = control.input.remove_event_listener_with_callback(event, handler.as_ref().unchecked_ref());
+            }
+
+            if let Some(created) = control.created {
+                created.remove();
+            }
+        }
+
+        if let Some(container) = self.created_container.take() {
+            container.remove();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use crate::{render::bookmarks, util::DEFAULT_CANVAS_ID};
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    fn expensive_settings_only_apply_on_release() {
+        for descriptor in DESCRIPTORS.iter().filter(|descriptor| descriptor.id.is_expensive()) {
+            assert_eq!(descriptor.control_events(), &[("change", true)], "{}", descriptor.key);
+        }
+
+        assert_eq!(descriptor(SettingId::HeightScale).control_events(), &[("input", false), ("change", true)]);
+        assert_eq!(descriptor(SettingId::Sky).control_events(), &[("change", true)]);
+    }
+
+    #[wasm_bindgen_test]
+    fn every_control_commits_on_change() {
+        for descriptor in DESCRIPTORS {
+            let events = descriptor.control_events();
+
+            assert!(events.contains(&("change", true)), "{}", descriptor.key);
+            assert!(events.iter().all(|&(event, commit)| commit == (event == "change")), "{}", descriptor.key);
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn storage_keys_are_scoped_to_the_canvas() {
+        assert_eq!(storage_key(DEFAULT_CANVAS_ID), STORAGE_KEY);
+        assert_ne!(storage_key("left"), storage_key("right"));
+        assert_ne!(storage_key("left"), bookmarks::storage_key("left"));
+    }
+}
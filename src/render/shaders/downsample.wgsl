@@ -0,0 +1,26 @@
+//One mip level of the source, a level above the one being drawn
+@group(0) @binding(0)
+var t_source: texture_2d<f32>;
+
+//One triangle covering the whole level, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    return vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+}
+
+//The average of the 2x2 texels the target texel covers. Loaded rather than sampled so it works for
+//formats that can't be filtered, and so an odd sized level's last column and row aren't blended past
+//the edge but repeat it.
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let last = vec2<i32>(textureDimensions(t_source)) - 1;
+    let base = vec2<i32>(position.xy) * 2;
+
+    let a = textureLoad(t_source, min(base, last), 0);
+    let b = textureLoad(t_source, min(base + vec2<i32>(1, 0), last), 0);
+    let c = textureLoad(t_source, min(base + vec2<i32>(0, 1), last), 0);
+    let d = textureLoad(t_source, min(base + vec2<i32>(1, 1), last), 0);
+
+    return (a + b + c + d) * 0.25;
+}
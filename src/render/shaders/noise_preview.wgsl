@@ -0,0 +1,78 @@
+struct RenderSettings {
+    view_proj: mat4x4<f32>,
+
+    height_scale: f32,
+    tex_size: u32,
+    detail_strength: f32,
+    detail_frequency: f32,
+
+    encode_srgb: u32,
+    debug_mode: u32,
+    time: f32,
+    skirt_depth: f32,
+
+    ambient_color: vec3<f32>,
+    hemisphere_ambient: u32,
+    ground_color: vec3<f32>,
+
+    sun_direction: vec3<f32>,
+    sun_color: vec3<f32>,
+
+    camera_position: vec3<f32>,
+    fog_density: f32,
+    fog_color: vec3<f32>,
+    background_alpha: f32,
+
+    detail_cell: vec2<i32>,
+    detail_offset: vec2<f32>,
+
+    inverse_view_proj: mat4x4<f32>,
+
+    height_decode: vec2<f32>
+};
+
+@group(0) @binding(0)
+var<uniform> settings: RenderSettings;
+
+//Colours are computed in linear space, this is only needed when the surface isn't sRGB
+fn output_color(linear: vec3<f32>) -> vec4<f32> {
+    if (settings.encode_srgb == 0u) {
+        return vec4<f32>(linear, 1.0);
+    }
+
+    let c = clamp(linear, vec3<f32>(0.0), vec3<f32>(1.0));
+    let low = c * 12.92;
+    let high = 1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055;
+
+    return vec4<f32>(select(high, low, c <= vec3<f32>(0.0031308)), 1.0);
+}
+
+@group(1) @binding(0)
+var t_noise: texture_2d<f32>;
+@group(1) @binding(1)
+var s_noise: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+//One triangle covering the whole screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    var out: VertexOutput;
+
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    //Same decoding as decode_height in shader.wgsl
+    let sample = textureSample(t_noise, s_noise, in.uv).x * settings.height_decode.x + settings.height_decode.y;
+
+    return output_color(vec3<f32>(sample));
+}
@@ -0,0 +1,32 @@
+struct NormalParams {
+    height_scale: f32,
+    //World distance between neighbouring texels
+    texel_size: f32,
+};
+
+@group(0) @binding(0)
+var<uniform> params: NormalParams;
+@group(0) @binding(1)
+var heights: texture_2d<f32>;
+@group(0) @binding(2)
+var normals: texture_storage_2d<rgba8unorm, write>;
+
+fn height(p: vec2<i32>, size: vec2<i32>) -> f32 {
+    return textureLoad(heights, clamp(p, vec2<i32>(0), size - 1), 0).x * params.height_scale;
+}
+
+@compute @workgroup_size(8, 8)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = vec2<i32>(textureDimensions(heights));
+    let p = vec2<i32>(id.xy);
+
+    if (p.x >= size.x || p.y >= size.y) {
+        return;
+    }
+
+    let dx = (height(p + vec2<i32>(1, 0), size) - height(p - vec2<i32>(1, 0), size)) / (2.0 * params.texel_size);
+    let dz = (height(p + vec2<i32>(0, 1), size) - height(p - vec2<i32>(0, 1), size)) / (2.0 * params.texel_size);
+
+    let normal = normalize(vec3<f32>(-dx, 1.0, -dz));
+    textureStore(normals, p, vec4<f32>(normal * 0.5 + 0.5, 1.0));
+}
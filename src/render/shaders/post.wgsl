@@ -0,0 +1,61 @@
+@group(0) @binding(0)
+var t_scene: texture_2d<f32>;
+@group(0) @binding(1)
+var s_scene: sampler;
+
+struct PostSettings {
+    exposure: f32,
+    vignette: f32,
+    //Set when the output isn't sRGB, the colour is encoded here instead of on write
+    encode_srgb: u32
+};
+
+@group(0) @binding(2)
+var<uniform> post: PostSettings;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+//One triangle covering the whole screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    var out: VertexOutput;
+
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+
+    return out;
+}
+
+//Narkowicz's fit of the ACES filmic curve, rolls highlights off instead of clipping them
+fn tonemap(x: vec3<f32>) -> vec3<f32> {
+    return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+fn encode_srgb(linear: vec3<f32>) -> vec3<f32> {
+    let low = linear * 12.92;
+    let high = 1.055 * pow(linear, vec3<f32>(1.0 / 2.4)) - 0.055;
+
+    return select(high, low, linear <= vec3<f32>(0.0031308));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    //The scene is premultiplied where the background is transparent, the tonemap works on the colour itself
+    let scene = textureSample(t_scene, s_scene, in.uv);
+    let alpha = scene.a;
+    var color = tonemap(scene.rgb / max(alpha, 1e-4) * post.exposure);
+
+    //1 at the centre, falling to 1 - vignette in the corners
+    let from_centre = length(in.uv - 0.5) * sqrt(2.0);
+    color *= 1.0 - post.vignette * from_centre * from_centre;
+
+    if (post.encode_srgb != 0u) {
+        color = encode_srgb(color);
+    }
+
+    return vec4<f32>(color * alpha, alpha);
+}
@@ -1,59 +1,440 @@
-struct RenderSettings {
-    view_proj: mat4x4<f32>,
-
-    height_scale: f32,
-    tex_size: u32
-};
-
-@group(0) @binding(0)
-var<uniform> settings: RenderSettings;
-
-@group(1) @binding(0)
-var t_noise: texture_2d<f32>;
-@group(1) @binding(1)
-var s_noise: sampler;
-
-struct VertexInput {
-    @location(0) position: vec2<f32>,
-    @location(1) uv: vec2<f32>,
-};
-
-struct VertexOutput {
-    @builtin(position) clip_position: vec4<f32>,
-    @location(0) uv: vec2<f32>,
-};
-
-fn uv_to_i(uv: f32) -> u32 {
-    var res = u32(uv * f32(settings.tex_size));
-
-    if (res < 0u) {
-        return 0u;
-    } else if (res > settings.tex_size) {
-        return settings.tex_size - 1u;
-    }
-
-    return res;
-}
-
-@vertex
-fn vs_main(
-    model: VertexInput
-) -> VertexOutput {
-    var out: VertexOutput;
-
-    var tex_x = uv_to_i(model.uv.x);
-    var tex_y = uv_to_i(model.uv.y);
-
-    var raw_height = textureLoad(t_noise, vec2<u32>(tex_x, tex_y), 0).x;
-    var height = raw_height * settings.height_scale;
-
-    out.clip_position = settings.view_proj * vec4<f32>(model.position.x, height, model.position.y, 1.0);
-    out.uv = model.uv;
-
-    return out;
-}
-
-@fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return vec4<f32>(textureSample(t_noise, s_noise, in.uv).x, 0.0, 0.0, 1.0);
-}
\ No newline at end of file
+struct RenderSettings {
+    view_proj: mat4x4<f32>,
+
+    height_scale: f32,
+    tex_size: u32,
+    detail_strength: f32,
+    detail_frequency: f32,
+
+    encode_srgb: u32,
+    debug_mode: u32,
+    time: f32,
+    skirt_depth: f32,
+
+    ambient_color: vec3<f32>,
+    hemisphere_ambient: u32,
+    ground_color: vec3<f32>,
+
+    sun_direction: vec3<f32>,
+    sun_color: vec3<f32>,
+
+    camera_position: vec3<f32>,
+    fog_density: f32,
+    fog_color: vec3<f32>,
+    background_alpha: f32,
+
+    detail_cell: vec2<i32>,
+    detail_offset: vec2<f32>,
+
+    inverse_view_proj: mat4x4<f32>,
+
+    height_decode: vec2<f32>
+};
+
+@group(0) @binding(0)
+var<uniform> settings: RenderSettings;
+
+//Terrain colour by normalised height, built from a ColorRamp in color_ramp.rs
+@group(0) @binding(1)
+var t_ramp: texture_2d<f32>;
+@group(0) @binding(2)
+var s_ramp: sampler;
+
+//Material colours blended over the ramp by height band and slope, see splat.rs
+struct SplatSettings {
+    sand: vec3<f32>,
+    strength: f32,
+    grass: vec3<f32>,
+    rock_slope: f32,
+    rock: vec3<f32>,
+    sand_line: f32,
+    snow: vec3<f32>,
+    snow_line: f32,
+
+    blend: f32,
+    tiling: f32,
+    variation: f32,
+
+    cell: vec2<i32>,
+    offset: vec2<f32>
+};
+
+@group(0) @binding(3)
+var<uniform> splat: SplatSettings;
+
+//Depth of the terrain seen from the sun, see shadow.rs
+struct ShadowSettings {
+    light_view_proj: mat4x4<f32>,
+    texel_size: f32,
+    bias: f32,
+    slope_bias: f32,
+    enabled: u32
+};
+
+@group(0) @binding(4)
+var t_shadow: texture_depth_2d;
+@group(0) @binding(5)
+var s_shadow: sampler_comparison;
+@group(0) @binding(6)
+var<uniform> shadow: ShadowSettings;
+
+//Colours are computed in linear space, this is only needed when the surface isn't sRGB
+fn output_color(linear: vec3<f32>) -> vec4<f32> {
+    if (settings.encode_srgb == 0u) {
+        return vec4<f32>(linear, 1.0);
+    }
+
+    let c = clamp(linear, vec3<f32>(0.0), vec3<f32>(1.0));
+    let low = c * 12.92;
+    let high = 1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055;
+
+    return vec4<f32>(select(high, low, c <= vec3<f32>(0.0031308)), 1.0);
+}
+
+const DEBUG_SHADED: u32 = 0u;
+const DEBUG_WIREFRAME: u32 = 1u;
+const DEBUG_UV: u32 = 2u;
+const DEBUG_HEIGHT: u32 = 3u;
+
+@group(1) @binding(0)
+var t_noise: texture_2d<f32>;
+@group(1) @binding(1)
+var s_noise: sampler;
+@group(1) @binding(2)
+var t_normal: texture_2d<f32>;
+
+struct ChunkSettings {
+    offset: vec2<f32>,
+    morph: f32,
+    //Low and high halves of the 64 bit seed
+    seed_low: u32,
+    seed_high: u32
+};
+
+@group(2) @binding(0)
+var<uniform> chunk: ChunkSettings;
+
+fn ramp_color(height: f32) -> vec3<f32> {
+    //Heights 0 and 1 land on the centres of the first and last texels
+    let width = f32(textureDimensions(t_ramp).x);
+    let u = (clamp(height, 0.0, 1.0) * (width - 1.0) + 0.5) / width;
+
+    return textureSample(t_ramp, s_ramp, vec2<f32>(u, 0.5)).rgb;
+}
+
+//Weights of sand, grass, snow and rock, summing to 1. Rock takes every slope steeper than rock_slope
+//whatever its height, what's left is split between the height bands.
+fn splat_weights(height: f32, normal: vec3<f32>) -> vec4<f32> {
+    let rock = smoothstep(splat.rock_slope - splat.blend, splat.rock_slope + splat.blend, 1.0 - normal.y);
+    let sand = 1.0 - smoothstep(splat.sand_line - splat.blend, splat.sand_line + splat.blend, height);
+    let snow = smoothstep(splat.snow_line - splat.blend, splat.snow_line + splat.blend, height);
+
+    let bands = vec3<f32>(sand, max(1.0 - sand - snow, 0.0), snow);
+    return vec4<f32>(bands / max(bands.x + bands.y + bands.z, 1e-4) * (1.0 - rock), rock);
+}
+
+fn splat_color(xz: vec2<f32>, height: f32, normal: vec3<f32>) -> vec3<f32> {
+    let weights = splat_weights(height, normal);
+    let color = splat.sand * weights.x + splat.grass * weights.y + splat.snow * weights.z + splat.rock * weights.w;
+
+    //Tiled in world units rather than chunk uv, so it's the same size on any chunk
+    let variation = value_noise(xz * splat.tiling + splat.offset, splat.cell) * splat.variation;
+    return color * (1.0 + variation);
+}
+
+//Fraction of the sun reaching a point, from 3x3 comparisons around it. Points outside the map are lit.
+fn sun_visibility(world_position: vec3<f32>, normal: vec3<f32>) -> f32 {
+    if (shadow.enabled == 0u) {
+        return 1.0;
+    }
+
+    let clip = shadow.light_view_proj * vec4<f32>(world_position, 1.0);
+    let ndc = clip.xyz / clip.w;
+    let uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+    if (any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || ndc.z > 1.0) {
+        return 1.0;
+    }
+
+    //Surfaces at a grazing angle to the sun need the most bias, capped so it doesn't run off to infinity
+    let cos_angle = clamp(dot(normal, settings.sun_direction), 0.1, 1.0);
+    let tan_angle = sqrt(1.0 - cos_angle * cos_angle) / cos_angle;
+    let depth = ndc.z - shadow.bias - shadow.slope_bias * tan_angle;
+
+    var lit = 0.0;
+    for (var y = -1; y <= 1; y += 1) {
+        for (var x = -1; x <= 1; x += 1) {
+            let offset = vec2<f32>(f32(x), f32(y)) * shadow.texel_size;
+            lit += textureSampleCompareLevel(t_shadow, s_shadow, uv + offset, depth);
+        }
+    }
+
+    return lit / 9.0;
+}
+
+fn ambient(normal: vec3<f32>) -> vec3<f32> {
+    if (settings.hemisphere_ambient == 0u) {
+        return settings.ambient_color;
+    }
+
+    return mix(settings.ground_color, settings.ambient_color, normal.y * 0.5 + 0.5);
+}
+
+//Exponential squared, so the ground near the camera stays clear. The sky is the fog colour at the
+//horizon, so distant terrain fades into the background instead of ending in a hard edge.
+fn apply_fog(color: vec3<f32>, world_position: vec3<f32>) -> vec3<f32> {
+    if (settings.fog_density <= 0.0) {
+        return color;
+    }
+
+    let amount = settings.fog_density * distance(world_position, settings.camera_position);
+    let visibility = exp(-amount * amount);
+
+    return mix(settings.fog_color, color, visibility);
+}
+
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) skirt: f32,
+};
+
+//Per chunk, only for the baked pipeline (vs_baked and fs_baked), see baked_mesh.rs
+struct BakedInput {
+    @location(3) height: f32,
+    @location(4) normal: vec3<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) world_xz: vec2<f32>,
+    @location(2) height: f32,
+    //Only meaningful for unindexed draws, where every three vertices are one triangle
+    @location(3) barycentric: vec3<f32>,
+    @location(4) world_position: vec3<f32>,
+    //Only set by vs_baked, fs_main reads normals from the normal texture
+    @location(5) normal: vec3<f32>,
+};
+
+fn hash(p: vec2<i32>) -> f32 {
+    var h = u32(p.x) * 0x8da6b343u + u32(p.y) * 0xd8163841u;
+    h = (h ^ (h >> 15u)) * 0x2c1b3c6du;
+    h = (h ^ (h >> 12u)) * 0x297a2d39u;
+    h = h ^ (h >> 15u);
+
+    return f32(h) / 4294967295.0;
+}
+
+//Smoothly interpolated lattice noise in [-1, 1]. p is in lattice units from cell_offset, so a far away
+//lattice cell can be given exactly while p stays small.
+fn value_noise(p: vec2<f32>, cell_offset: vec2<i32>) -> f32 {
+    let cell = vec2<i32>(floor(p)) + cell_offset;
+    let f = fract(p);
+    let t = f * f * (3.0 - 2.0 * f);
+
+    let a = hash(cell);
+    let b = hash(cell + vec2<i32>(1, 0));
+    let c = hash(cell + vec2<i32>(0, 1));
+    let d = hash(cell + vec2<i32>(1, 1));
+
+    return mix(mix(a, b, t.x), mix(c, d, t.x), t.y) * 2.0 - 1.0;
+}
+
+fn detail(xz: vec2<f32>) -> f32 {
+    //xz is relative to the camera's floating origin, the detail noise is fixed to the world
+    return value_noise(xz * settings.detail_frequency + settings.detail_offset, settings.detail_cell) * settings.detail_strength;
+}
+
+//Terrain height is defined by Displacement in displacement.rs, keep these in step with it
+//Each chunk's texture has an apron of CHUNK_APRON texels around it, see chunk.rs
+const APRON: u32 = 1u;
+
+//Height textures can be stored in a few formats, see HeightFormat in chunk.rs
+fn decode_height(texel: f32) -> f32 {
+    return texel * settings.height_decode.x + settings.height_decode.y;
+}
+
+//uv 0 and 1 land exactly on the first and last texels inside the apron, which neighbouring chunks
+//share. In between the four surrounding texels are blended by hand: WebGL2 only allows textureLoad
+//in the vertex stage, and it keeps full precision whatever the texture format.
+fn sample_height(uv: vec2<f32>) -> f32 {
+    let p = clamp(uv, vec2<f32>(0.0), vec2<f32>(1.0)) * f32(settings.tex_size - 1u);
+    let base = min(vec2<u32>(floor(p)), vec2<u32>(settings.tex_size - 2u));
+    let f = p - vec2<f32>(base);
+    let texel = base + vec2<u32>(APRON);
+
+    let a = textureLoad(t_noise, texel, 0).x;
+    let b = textureLoad(t_noise, texel + vec2<u32>(1u, 0u), 0).x;
+    let c = textureLoad(t_noise, texel + vec2<u32>(0u, 1u), 0).x;
+    let d = textureLoad(t_noise, texel + vec2<u32>(1u, 1u), 0).x;
+
+    return decode_height(mix(mix(a, b, f.x), mix(c, d, f.x), f.y));
+}
+
+//The sampler puts texel i at (i + 0.5) / size, sample_height puts it at i / (tex_size - 1) past the apron
+fn texel_center_uv(uv: vec2<f32>) -> vec2<f32> {
+    let size = f32(settings.tex_size + 2u * APRON);
+    return (uv * f32(settings.tex_size - 1u) + f32(APRON) + 0.5) / size;
+}
+
+//Everything but where the height comes from is shared by vs_main and vs_baked
+fn terrain_vertex(model: VertexInput, raw_height: f32, vertex_index: u32) -> VertexOutput {
+    var out: VertexOutput;
+
+    let uv = model.uv;
+    let world_xz = model.position + chunk.offset;
+
+    var height = raw_height * settings.height_scale + detail(world_xz);
+    height -= model.skirt * settings.skirt_depth * settings.height_scale;
+
+    out.world_position = vec3<f32>(world_xz.x, height, world_xz.y);
+    out.clip_position = settings.view_proj * vec4<f32>(out.world_position, 1.0);
+    out.uv = uv;
+    out.world_xz = world_xz;
+    out.height = raw_height;
+    out.normal = vec3<f32>(0.0, 1.0, 0.0);
+
+    let corner = vertex_index % 3u;
+    out.barycentric = vec3<f32>(f32(corner == 0u), f32(corner == 1u), f32(corner == 2u));
+
+    return out;
+}
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+    @builtin(vertex_index) vertex_index: u32
+) -> VertexOutput {
+    return terrain_vertex(model, sample_height(model.uv), vertex_index);
+}
+
+@vertex
+fn vs_baked(
+    model: VertexInput,
+    baked: BakedInput,
+    @builtin(vertex_index) vertex_index: u32
+) -> VertexOutput {
+    var out = terrain_vertex(model, baked.height, vertex_index);
+    out.normal = baked.normal;
+
+    return out;
+}
+
+//Depth only, for the shadow pass: the same terrain placed with the light's matrix
+@vertex
+fn vs_shadow(
+    model: VertexInput,
+    @builtin(vertex_index) vertex_index: u32
+) -> @builtin(position) vec4<f32> {
+    let out = terrain_vertex(model, sample_height(model.uv), vertex_index);
+    return shadow.light_view_proj * vec4<f32>(out.world_position, 1.0);
+}
+
+@vertex
+fn vs_shadow_baked(
+    model: VertexInput,
+    baked: BakedInput,
+    @builtin(vertex_index) vertex_index: u32
+) -> @builtin(position) vec4<f32> {
+    let out = terrain_vertex(model, baked.height, vertex_index);
+    return shadow.light_view_proj * vec4<f32>(out.world_position, 1.0);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    //The mesh is too coarse for high frequency detail, so it also shades the surface between vertices
+    let shade = decode_height(textureSample(t_noise, s_noise, texel_center_uv(in.uv)).x) + detail(in.world_xz);
+    //Normals are precomputed from the heightmap whenever it or the height scale changes
+    let normal = normalize(textureSample(t_normal, s_noise, texel_center_uv(in.uv)).xyz * 2.0 - 1.0);
+
+    return shade_terrain(in, shade, normal);
+}
+
+//Heights and normals are interpolated from the vertices, so the lighting matches the mesh exactly
+@fragment
+fn fs_baked(in: VertexOutput) -> @location(0) vec4<f32> {
+    return shade_terrain(in, in.height + detail(in.world_xz), normalize(in.normal));
+}
+
+fn shade_terrain(in: VertexOutput, shade: f32, normal: vec3<f32>) -> vec4<f32> {
+    if (settings.debug_mode == DEBUG_WIREFRAME) {
+        //Distance to the nearest edge in pixels, so lines stay about a pixel wide at any distance
+        let width = fwidth(in.barycentric);
+        let edge = smoothstep(vec3<f32>(0.0), width * 1.5, in.barycentric);
+        let line = 1.0 - min(edge.x, min(edge.y, edge.z));
+
+        return output_color(mix(vec3<f32>(shade * 0.25, 0.0, 0.0), vec3<f32>(1.0), line));
+    } else if (settings.debug_mode == DEBUG_UV) {
+        return output_color(vec3<f32>(in.uv, 0.0));
+    } else if (settings.debug_mode == DEBUG_HEIGHT) {
+        return output_color(vec3<f32>(in.height));
+    }
+
+    let sun = max(dot(normal, settings.sun_direction), 0.0) * sun_visibility(in.world_position, normal);
+    let light = ambient(normal) + settings.sun_color * sun;
+
+    let albedo = mix(ramp_color(shade), splat_color(in.world_xz, shade, normal), splat.strength);
+
+    return output_color(apply_fog(albedo * light, in.world_position));
+}
+//Blades scattered over the chunks, see grass.rs. Drawn with the terrain's groups and one of its own.
+struct GrassSettings {
+    distance: f32,
+    blade_height: f32,
+    blade_width: f32,
+    extent: f32
+};
+
+@group(3) @binding(0)
+var<uniform> grass: GrassSettings;
+
+struct GrassInstance {
+    @location(0) uv: vec2<f32>,
+    @location(1) rotation: f32,
+    @location(2) scale: f32,
+    @location(3) tint: vec3<f32>,
+};
+
+struct GrassOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+    @location(1) world_position: vec3<f32>,
+};
+
+//Two quads crossed at right angles, six vertices each, narrowing towards the tip. Blades are stood on
+//the terrain where the instance's uv lands and shrink to nothing over the last quarter of the distance.
+@vertex
+fn vs_grass(instance: GrassInstance, @builtin(vertex_index) vertex_index: u32) -> GrassOutput {
+    var out: GrassOutput;
+
+    //Corners (0, 0) (1, 0) (1, 1) and (0, 0) (1, 1) (0, 1)
+    let corner = vertex_index % 6u;
+    let x = f32(corner == 1u || corner == 2u || corner == 4u);
+    let y = f32(corner == 2u || corner == 4u || corner == 5u);
+    let angle = instance.rotation + f32(vertex_index / 6u) * 1.5707964;
+
+    let world_xz = instance.uv * grass.extent + chunk.offset;
+    let ground = sample_height(instance.uv) * settings.height_scale + detail(world_xz);
+    let root = vec3<f32>(world_xz.x, ground, world_xz.y);
+
+    let fade = 1.0 - smoothstep(grass.distance * 0.75, grass.distance, distance(root, settings.camera_position));
+    let size = instance.scale * fade;
+    let across = (x - 0.5) * grass.blade_width * mix(1.0, 0.3, y) * size;
+
+    out.world_position = root + vec3<f32>(cos(angle) * across, y * grass.blade_height * size, sin(angle) * across);
+    out.clip_position = settings.view_proj * vec4<f32>(out.world_position, 1.0);
+    //Darker at the root, where the blades shade each other
+    out.color = instance.tint * mix(0.5, 1.0, y);
+
+    return out;
+}
+
+//Lit as if facing up, blades are too thin for their own normals to read
+@fragment
+fn fs_grass(in: GrassOutput) -> @location(0) vec4<f32> {
+    let up = vec3<f32>(0.0, 1.0, 0.0);
+    let sun = max(dot(up, settings.sun_direction), 0.0) * sun_visibility(in.world_position, up);
+    let light = ambient(up) + settings.sun_color * sun;
+
+    return output_color(apply_fog(in.color * light, in.world_position));
+}
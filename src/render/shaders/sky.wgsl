@@ -0,0 +1,101 @@
+struct RenderSettings {
+    view_proj: mat4x4<f32>,
+
+    height_scale: f32,
+    tex_size: u32,
+    detail_strength: f32,
+    detail_frequency: f32,
+
+    encode_srgb: u32,
+    debug_mode: u32,
+    time: f32,
+    skirt_depth: f32,
+
+    ambient_color: vec3<f32>,
+    hemisphere_ambient: u32,
+    ground_color: vec3<f32>,
+
+    sun_direction: vec3<f32>,
+    sun_color: vec3<f32>,
+
+    camera_position: vec3<f32>,
+    fog_density: f32,
+    fog_color: vec3<f32>,
+    background_alpha: f32,
+
+    detail_cell: vec2<i32>,
+    detail_offset: vec2<f32>,
+
+    inverse_view_proj: mat4x4<f32>,
+
+    height_decode: vec2<f32>
+};
+
+@group(0) @binding(0)
+var<uniform> settings: RenderSettings;
+
+//Colours are computed in linear space, this is only needed when the surface isn't sRGB
+fn output_color(linear: vec3<f32>) -> vec4<f32> {
+    if (settings.encode_srgb == 0u) {
+        return vec4<f32>(linear, 1.0);
+    }
+
+    let c = clamp(linear, vec3<f32>(0.0), vec3<f32>(1.0));
+    let low = c * 12.92;
+    let high = 1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055;
+
+    return vec4<f32>(select(high, low, c <= vec3<f32>(0.0031308)), 1.0);
+}
+
+//How much of the fog colour is left straight up, the sky darkens and turns bluer towards the zenith
+const ZENITH_TINT: vec3<f32> = vec3<f32>(0.35, 0.55, 1.0);
+//Cosine of the sun's angular radius, about 1.5 degrees so it's visible at any resolution
+const SUN_DISC_COS: f32 = 0.99966;
+const SUN_DISC_INTENSITY: f32 = 4.0;
+const SUN_GLOW_POWER: f32 = 64.0;
+const SUN_GLOW_INTENSITY: f32 = 0.3;
+//The sun fades out over this much of the view direction's height above the horizon, so the horizon
+//itself is always exactly the fog colour distant terrain fades into
+const HORIZON_FADE: f32 = 0.05;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+};
+
+//One triangle covering the whole screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    var out: VertexOutput;
+
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    out.ndc = vec2<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+    out.clip_position = vec4<f32>(out.ndc, 0.0, 1.0);
+
+    return out;
+}
+
+//Direction the camera looks through a point on the screen, from the near plane to the far one
+fn view_ray(ndc: vec2<f32>) -> vec3<f32> {
+    let near = settings.inverse_view_proj * vec4<f32>(ndc, 0.0, 1.0);
+    let far = settings.inverse_view_proj * vec4<f32>(ndc, 1.0, 1.0);
+
+    return normalize(far.xyz / far.w - near.xyz / near.w);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let ray = view_ray(in.ndc);
+    let up = max(ray.y, 0.0);
+
+    let zenith = settings.fog_color * ZENITH_TINT;
+    var color = mix(settings.fog_color, zenith, sqrt(up));
+
+    let facing = dot(ray, settings.sun_direction);
+    let disc = smoothstep(SUN_DISC_COS - 0.0002, SUN_DISC_COS, facing) * SUN_DISC_INTENSITY;
+    let glow = pow(max(facing, 0.0), SUN_GLOW_POWER) * SUN_GLOW_INTENSITY;
+    color += settings.sun_color * (disc + glow) * smoothstep(0.0, HORIZON_FADE, up);
+
+    //Premultiplied, so the page behind a transparent canvas shows through, see background_alpha
+    return vec4<f32>(output_color(color).rgb * settings.background_alpha, settings.background_alpha);
+}
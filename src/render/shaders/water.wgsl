@@ -0,0 +1,140 @@
+struct RenderSettings {
+    view_proj: mat4x4<f32>,
+
+    height_scale: f32,
+    tex_size: u32,
+    detail_strength: f32,
+    detail_frequency: f32,
+
+    encode_srgb: u32,
+    debug_mode: u32,
+    time: f32,
+    skirt_depth: f32,
+
+    ambient_color: vec3<f32>,
+    hemisphere_ambient: u32,
+    ground_color: vec3<f32>,
+
+    sun_direction: vec3<f32>,
+    sun_color: vec3<f32>,
+
+    camera_position: vec3<f32>,
+    fog_density: f32,
+    fog_color: vec3<f32>,
+    background_alpha: f32,
+
+    detail_cell: vec2<i32>,
+    detail_offset: vec2<f32>,
+
+    inverse_view_proj: mat4x4<f32>,
+
+    height_decode: vec2<f32>
+};
+
+@group(0) @binding(0)
+var<uniform> settings: RenderSettings;
+
+//Colours are computed in linear space, this is only needed when the surface isn't sRGB
+fn output_color(linear: vec3<f32>) -> vec4<f32> {
+    if (settings.encode_srgb == 0u) {
+        return vec4<f32>(linear, 1.0);
+    }
+
+    let c = clamp(linear, vec3<f32>(0.0), vec3<f32>(1.0));
+    let low = c * 12.92;
+    let high = 1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055;
+
+    return vec4<f32>(select(high, low, c <= vec3<f32>(0.0031308)), 1.0);
+}
+
+struct Water {
+    color: vec4<f32>,
+    height: f32,
+    extent: f32,
+    wave_offset: vec2<f32>
+};
+
+@group(1) @binding(0)
+var<uniform> water: Water;
+
+//Same as the sky's, for the reflected sky colour
+const ZENITH_TINT: vec3<f32> = vec3<f32>(0.35, 0.55, 1.0);
+//The waves repeat every WAVE_TILE world units, WaterRenderer wraps the origin to it. Every wave's
+//direction is a whole number of cycles across the tile so the tiling is seamless.
+const WAVE_TILE: f32 = 64.0;
+const TAU: f32 = 6.2831853;
+//Fraction of light reflected looking straight down, water's is about 2%
+const BASE_REFLECTANCE: f32 = 0.02;
+const SPECULAR_POWER: f32 = 256.0;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+};
+
+//Two triangles around the camera, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, 1.0)
+    );
+
+    var out: VertexOutput;
+
+    let xz = settings.camera_position.xz + corners[vertex_index] * water.extent;
+    out.world_position = vec3<f32>(xz.x, water.height, xz.y);
+    out.clip_position = settings.view_proj * vec4<f32>(out.world_position, 1.0);
+
+    return out;
+}
+
+//Slope of one travelling wave, cycles is how many times it repeats across the tile along x and z
+fn wave_slope(p: vec2<f32>, cycles: vec2<f32>, amplitude: f32, speed: f32) -> vec2<f32> {
+    let k = cycles * (TAU / WAVE_TILE);
+
+    return amplitude * k * cos(dot(k, p) + speed * settings.time);
+}
+
+fn wave_normal(xz: vec2<f32>) -> vec3<f32> {
+    let p = xz + water.wave_offset;
+
+    let slope = wave_slope(p, vec2<f32>(5.0, 3.0), 0.08, 1.1)
+        + wave_slope(p, vec2<f32>(-4.0, 7.0), 0.05, 1.7)
+        + wave_slope(p, vec2<f32>(9.0, -2.0), 0.03, 2.3)
+        + wave_slope(p, vec2<f32>(2.0, 13.0), 0.02, 3.1);
+
+    return normalize(vec3<f32>(-slope.x, 1.0, -slope.y));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let normal = wave_normal(in.world_position.xz);
+    let to_camera = normalize(settings.camera_position - in.world_position);
+
+    //Schlick's approximation, abs so it still works looking up from under the surface
+    let fresnel = BASE_REFLECTANCE + (1.0 - BASE_REFLECTANCE) * pow(1.0 - abs(dot(normal, to_camera)), 5.0);
+
+    let reflected = reflect(-to_camera, normal);
+    let sky = mix(settings.fog_color, settings.fog_color * ZENITH_TINT, sqrt(max(reflected.y, 0.0)));
+    let specular = settings.sun_color * pow(max(dot(reflected, settings.sun_direction), 0.0), SPECULAR_POWER);
+
+    let lit = water.color.rgb * (settings.ambient_color + settings.sun_color * max(settings.sun_direction.y, 0.0));
+    var color = mix(lit, sky, fresnel) + specular;
+    var alpha = mix(water.color.a, 1.0, fresnel);
+
+    //Same fog as the terrain, fully fogged water is opaque so it meets the sky like the ground does
+    if (settings.fog_density > 0.0) {
+        let amount = settings.fog_density * distance(in.world_position, settings.camera_position);
+        let visibility = exp(-amount * amount);
+
+        color = mix(settings.fog_color, color, visibility);
+        alpha = mix(1.0, alpha, visibility);
+    }
+
+    return vec4<f32>(output_color(color).rgb, alpha);
+}
@@ -0,0 +1,244 @@
+use cgmath::{InnerSpace, Matrix4, Point3, Vector3, Vector4};
+use wgpu::util::DeviceExt;
+
+use super::camera::{Camera, OPENGL_TO_WGPU_MATRIX};
+
+pub const SHADOW_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+//The largest size WebGL2 guarantees
+pub const SHADOW_MAP_SIZE: u32 = 2048;
+//World units in front of the camera the map covers, terrain further away is always lit
+pub const DEFAULT_SHADOW_DISTANCE: f32 = 150.0;
+//World units along the sun direction, see ShadowMap::set_bias
+pub const DEFAULT_SHADOW_BIAS: f32 = 0.05;
+pub const DEFAULT_SHADOW_SLOPE_BIAS: f32 = 0.1;
+//Keeps the light's frustum from running along the ground when the sun is at the horizon
+const MIN_SUN_HEIGHT: f32 = 0.2;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct ShadowSettings {
+    light_view_proj: [[f32; 4]; 4],
+    //1 / the map's size, the step between PCF taps
+    texel_size: f32,
+    //Both in the light's depth, converted from world units whenever the light is fitted
+    bias: f32,
+    slope_bias: f32,
+    //0 when the device can't sample the map, every fragment is lit
+    enabled: u32
+}
+
+//Has to match ShadowSettings in shader.wgsl
+const _: () = assert!(std::mem::size_of::<ShadowSettings>() == 80);
+
+//A single depth map of the terrain from the sun, refitted to the camera every frame. The terrain shader
+//compares against it with a 3x3 PCF filter, so shadow edges are a few texels soft.
+pub struct ShadowMap {
+    supported: bool,
+    pub view: wgpu::TextureView,
+    //1x1 and cleared to the far plane, so everything compared against it is lit. Bound in the map's
+    //place while the map is drawn, and for anything the map doesn't cover, like thumbnails.
+    pub placeholder: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+    pub buffer: wgpu::Buffer,
+    settings: ShadowSettings,
+    //World units, see set_bias
+    bias: f32,
+    slope_bias: f32,
+    //Light depth per world unit as of the last fit
+    depth_scale: f32
+}
+
+impl ShadowMap {
+    //Sampling a depth texture through a comparison sampler, WebGL2 has both but not every backend does
+    pub fn supported(adapter: &wgpu::Adapter, device: &wgpu::Device) -> bool {
+        let comparison = adapter.get_downlevel_capabilities().flags.contains(wgpu::DownlevelFlags::COMPARISON_SAMPLERS);
+        let usages = SHADOW_FORMAT.guaranteed_format_features(device.features()).allowed_usages;
+
+        comparison && usages.contains(wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING)
+    }
+
+    //Without support the map is a second placeholder, so the bind group layout is the same either way
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, supported: bool) -> Self {
+        let view = Self::create_map(device, if supported { SHADOW_MAP_SIZE } else { 1 }, "Shadow map");
+        let placeholder = Self::create_map(device, 1, "Shadow placeholder");
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Shadow clear Encoder")
+        });
+        for target in [&view, &placeholder] {
+            encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Shadow clear pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(Self::depth_attachment(target))
+            });
+        }
+        queue.submit(Some(encoder.finish()));
+
+        //Linear filtering makes each tap a 2x2 comparison on top of the PCF taps where it's supported
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Shadow sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        use cgmath::SquareMatrix;
+        let settings = ShadowSettings {
+            light_view_proj: Matrix4::identity().into(),
+            texel_size: 1.0 / SHADOW_MAP_SIZE as f32,
+            bias: 0.0,
+            slope_bias: 0.0,
+            enabled: supported as u32
+        };
+
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Shadow uniform buffer"),
+            contents: bytemuck::cast_slice(&[settings]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        ShadowMap {
+            supported,
+            view,
+            placeholder,
+            sampler,
+            buffer,
+            settings,
+            bias: DEFAULT_SHADOW_BIAS,
+            slope_bias: DEFAULT_SHADOW_SLOPE_BIAS,
+            depth_scale: 0.0
+        }
+    }
+
+    fn create_map(device: &wgpu::Device, size: u32, label: &'static str) -> wgpu::TextureView {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: SHADOW_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[]
+        });
+
+        texture.create_view(&wgpu::TextureViewDescriptor::default())
+    }
+
+    pub fn depth_attachment(view: &wgpu::TextureView) -> wgpu::RenderPassDepthStencilAttachment<'_> {
+        wgpu::RenderPassDepthStencilAttachment {
+            view,
+            depth_ops: Some(wgpu::Operations {
+                load: wgpu::LoadOp::Clear(1.0),
+                store: true
+            }),
+            stencil_ops: None
+        }
+    }
+
+    pub fn is_supported(&self) -> bool {
+        self.supported
+    }
+
+    pub fn bias(&self) -> (f32, f32) {
+        (self.bias, self.slope_bias)
+    }
+
+    //How far each fragment is pushed towards the sun before it's compared, in world units: bias
+    //everywhere plus slope_bias per unit of tan(angle to the sun). Too little and surfaces shadow
+    //themselves in stripes (acne), too much and shadows come away from their casters (peter-panning).
+    pub fn set_bias(&mut self, queue: &wgpu::Queue, bias: f32, slope_bias: f32) {
+        self.bias = bias.max(0.0);
+        self.slope_bias = slope_bias.max(0.0);
+        self.write(queue);
+    }
+
+    //Points the light down sun_direction (towards the sun) at the part of the view within
+    //DEFAULT_SHADOW_DISTANCE of the camera. caster_height is how high the terrain can reach, so
+    //ridges outside the view still cast into it.
+    pub fn fit(&mut self, queue: &wgpu::Queue, camera: &Camera, sun_direction: [f32; 3], caster_height: f32) {
+        if !self.supported {
+            return;
+        }
+
+        let (light_view_proj, depth_range) = fit_light(camera, Vector3::from(sun_direction), DEFAULT_SHADOW_DISTANCE, caster_height);
+        self.settings.light_view_proj = light_view_proj.into();
+        self.depth_scale = 1.0 / depth_range;
+        self.write(queue);
+    }
+
+    fn write(&mut self, queue: &wgpu::Queue) {
+        self.settings.bias = self.bias * self.depth_scale;
+        self.settings.slope_bias = self.slope_bias * self.depth_scale;
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.settings]));
+    }
+}
+
+//The light's view projection and the depth it spans in world units. The box is fitted to a sphere
+//around the slice of the view frustum, so it doesn't change size as the camera turns, and its centre
+//is snapped to whole texels, so shadow edges don't crawl as the camera moves.
+pub fn fit_light(camera: &Camera, sun_direction: Vector3<f32>, distance: f32, caster_height: f32) -> (Matrix4<f32>, f32) {
+    let forward = camera.get_direction();
+    let tan_y = (camera.fovy / 2.0).to_radians().tan();
+    let tan_x = tan_y * camera.aspect;
+
+    //Smallest sphere along forward holding the eye and the slice's far corners
+    let far_extent = distance * (tan_x * tan_x + tan_y * tan_y).sqrt();
+    let along = ((distance * distance + far_extent * far_extent) / (2.0 * distance)).min(distance);
+    let radius = along.max(((distance - along).powi(2) + far_extent * far_extent).sqrt());
+    let centre = camera.eye + forward * along;
+
+    let to_sun = if sun_direction.magnitude2() > 0.0 { sun_direction.normalize() } else { Vector3::unit_y() };
+    let up = if to_sun.y.abs() > 0.99 { Vector3::unit_z() } else { Vector3::unit_y() };
+    let view = Matrix4::look_to_rh(Point3::new(0.0, 0.0, 0.0), -to_sun, up);
+
+    let texel = 2.0 * radius / SHADOW_MAP_SIZE as f32;
+    let light_centre = view * Vector4::new(centre.x, centre.y, centre.z, 1.0);
+    let (x, y) = ((light_centre.x / texel).round() * texel, (light_centre.y / texel).round() * texel);
+
+    //Casters up to caster_height above anything in the sphere can sit further towards the sun than it
+    let extra = caster_height.max(0.0) / to_sun.y.max(MIN_SUN_HEIGHT);
+    let depth = -light_centre.z;
+    let (near, far) = (depth - radius - extra, depth + radius);
+    let proj = cgmath::ortho(x - radius, x + radius, y - radius, y + radius, near, far);
+
+    (OPENGL_TO_WGPU_MATRIX * proj * view, far - near)
+}
+
+//Depth only, from the vertex entry point that places the terrain with the light's matrix. Nothing is
+//culled, the terrain is one sided and its back faces are what the sun sees at low angles.
+pub fn create_pipeline(device: &wgpu::Device, layout: &wgpu::PipelineLayout, shader: &wgpu::ShaderModule, vertex_entry: &str, buffers: &[wgpu::VertexBufferLayout]) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Shadow Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: vertex_entry,
+            buffers,
+        },
+        fragment: None,
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            cull_mode: None,
+            ..Default::default()
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: SHADOW_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default()
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None
+    })
+}
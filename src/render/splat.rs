@@ -0,0 +1,123 @@
+use wgpu::util::DeviceExt;
+
+//Linear RGB, roughly the colour ramp's own bands so blending between the two doesn't shift the hue much
+pub const DEFAULT_SAND_COLOR: [f32; 3] = [0.55, 0.48, 0.32];
+pub const DEFAULT_GRASS_COLOR: [f32; 3] = [0.08, 0.22, 0.04];
+pub const DEFAULT_ROCK_COLOR: [f32; 3] = [0.22, 0.2, 0.18];
+pub const DEFAULT_SNOW_COLOR: [f32; 3] = [0.85, 0.87, 0.9];
+
+pub const DEFAULT_SPLAT_STRENGTH: f32 = 0.5;
+//1 - normal.y, about 40 degrees
+pub const DEFAULT_ROCK_SLOPE: f32 = 0.25;
+//Normalised heights like the colour ramp's stops, sand starts just above the default water level
+pub const DEFAULT_SAND_LINE: f32 = 0.36;
+pub const DEFAULT_SNOW_LINE: f32 = 0.85;
+const DEFAULT_SPLAT_BLEND: f32 = 0.04;
+//Cells of the variation noise per world unit, in world space so it's the same size whatever the chunk size
+const DEFAULT_SPLAT_TILING: f32 = 0.5;
+const DEFAULT_SPLAT_VARIATION: f32 = 0.2;
+//smoothstep needs its edges apart
+const MIN_SPLAT_BLEND: f32 = 1e-3;
+
+//Material colours the terrain is splatted with, weighted by height band and slope, see splat_color in
+//shader.wgsl. The result is mixed over the colour ramp by strength.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SplatSettings {
+    pub sand: [f32; 3],
+    pub strength: f32,
+    pub grass: [f32; 3],
+    //Slopes steeper than this (1 - normal.y) are rock at any height
+    pub rock_slope: f32,
+    pub rock: [f32; 3],
+    //Below this normalised height is sand
+    pub sand_line: f32,
+    pub snow: [f32; 3],
+    //Above this normalised height is snow
+    pub snow_line: f32,
+    //Half the width of every transition, in the units of whatever it blends
+    pub blend: f32,
+    pub tiling: f32,
+    //How far the variation noise brightens or darkens the colours, as a fraction of them
+    pub variation: f32,
+    _padding: u32,
+    //The floating origin in variation noise cells, split like RenderSettings::detail_cell
+    cell: [i32; 2],
+    offset: [f32; 2]
+}
+
+//Has to match SplatSettings in shader.wgsl
+const _: () = assert!(std::mem::size_of::<SplatSettings>() == 96);
+
+impl Default for SplatSettings {
+    fn default() -> Self {
+        SplatSettings {
+            sand: DEFAULT_SAND_COLOR,
+            strength: DEFAULT_SPLAT_STRENGTH,
+            grass: DEFAULT_GRASS_COLOR,
+            rock_slope: DEFAULT_ROCK_SLOPE,
+            rock: DEFAULT_ROCK_COLOR,
+            sand_line: DEFAULT_SAND_LINE,
+            snow: DEFAULT_SNOW_COLOR,
+            snow_line: DEFAULT_SNOW_LINE,
+            blend: DEFAULT_SPLAT_BLEND,
+            tiling: DEFAULT_SPLAT_TILING,
+            variation: DEFAULT_SPLAT_VARIATION,
+            _padding: 0,
+            cell: [0; 2],
+            offset: [0.0; 2]
+        }
+    }
+}
+
+//Bound next to the render settings, the terrain shader is the only one that reads it
+pub struct SplatUniform {
+    settings: SplatSettings,
+    origin: [f64; 2],
+    pub buffer: wgpu::Buffer
+}
+
+impl SplatUniform {
+    pub fn new(device: &wgpu::Device, origin: [f64; 2]) -> Self {
+        let mut settings = SplatSettings::default();
+        Self::place(&mut settings, origin);
+
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Splat uniform buffer"),
+            contents: bytemuck::cast_slice(&[settings]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        SplatUniform {
+            settings,
+            origin,
+            buffer
+        }
+    }
+
+    pub fn settings(&self) -> SplatSettings {
+        self.settings
+    }
+
+    pub fn set(&mut self, queue: &wgpu::Queue, settings: SplatSettings) {
+        self.settings = SplatSettings {
+            strength: settings.strength.clamp(0.0, 1.0),
+            blend: settings.blend.max(MIN_SPLAT_BLEND),
+            tiling: settings.tiling.max(0.0),
+            ..settings
+        };
+        self.set_origin(queue, self.origin);
+    }
+
+    pub fn set_origin(&mut self, queue: &wgpu::Queue, origin: [f64; 2]) {
+        self.origin = origin;
+        Self::place(&mut self.settings, origin);
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.settings]));
+    }
+
+    fn place(settings: &mut SplatSettings, origin: [f64; 2]) {
+        let scaled = origin.map(|value| value * settings.tiling as f64);
+        settings.cell = scaled.map(|value| value.floor() as i32);
+        settings.offset = scaled.map(|value| (value - value.floor()) as f32);
+    }
+}
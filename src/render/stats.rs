@@ -0,0 +1,536 @@
+use std::collections::VecDeque;
+
+use serde::Serialize;
+use wasm_bindgen::JsCast;
+use web_sys::{Document, HtmlCanvasElement, HtmlElement};
+
+use crate::util::{Profiler, scoped_id, insert_after_canvas, pin_to_canvas, Corner};
+
+use super::event::KeyboardKey;
+
+pub const STATS_TOGGLE_KEY: KeyboardKey = KeyboardKey::Function(3);
+
+const HISTORY_LENGTH: usize = 120;
+const OVERLAY_UPDATE_INTERVAL: f64 = 1000.0;
+
+const DELTA_WINDOW: usize = 5;
+//Longest step the camera will take in one frame, in seconds
+const MAX_DELTA: f64 = 0.1;
+//Frames after a (re)start whose delta is reported as 0. The first one has no previous frame to
+//measure against, and a 0 in the window would drag the median down for the next few frames.
+const WARMUP_FRAMES: usize = 1;
+
+pub struct FrameStats {
+    frame_times: VecDeque<f64>,
+    //Over the same frames as frame_times, for the rolling maxima
+    render_stats: VecDeque<RenderStats>,
+
+    pub frames: u64,
+    pub last_events: usize,
+    pub total_events: u64,
+    //Bytes sent to the GPU for the last frame, see WgpuContext::uploaded_bytes
+    pub last_uploaded_bytes: u64
+}
+
+impl FrameStats {
+    pub fn new() -> Self {
+        FrameStats {
+            frame_times: VecDeque::with_capacity(HISTORY_LENGTH),
+            render_stats: VecDeque::with_capacity(HISTORY_LENGTH),
+
+            frames: 0,
+            last_events: 0,
+            total_events: 0,
+            last_uploaded_bytes: 0
+        }
+    }
+
+    //Frame time is in milliseconds
+    pub fn record_frame(&mut self, frame_time: f64, events: usize, uploaded_bytes: u64) {
+        if self.frame_times.len() == HISTORY_LENGTH {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(frame_time);
+
+        self.frames += 1;
+        self.last_events = events;
+        self.total_events += events as u64;
+        self.last_uploaded_bytes = uploaded_bytes;
+    }
+
+    //What WgpuContext::render counted for the frame, separate from record_frame as not every frame draws
+    pub fn record_render(&mut self, stats: RenderStats) {
+        if self.render_stats.len() == HISTORY_LENGTH {
+            self.render_stats.pop_front();
+        }
+        self.render_stats.push_back(stats);
+    }
+
+    pub fn last_render(&self) -> RenderStats {
+        self.render_stats.back().copied().unwrap_or_default()
+    }
+
+    //Each counter's highest value over the history, not necessarily all from the same frame
+    pub fn max_render(&self) -> RenderStats {
+        self.render_stats.iter().fold(RenderStats::default(), |max, stats| max.max(stats))
+    }
+
+    pub fn last_frame_time(&self) -> f64 {
+        self.frame_times.back().copied().unwrap_or(0.0)
+    }
+
+    pub fn average_frame_time(&self) -> f64 {
+        if self.frame_times.is_empty() {
+            return 0.0;
+        }
+
+        self.frame_times.iter().sum::<f64>() / self.frame_times.len() as f64
+    }
+
+    pub fn percentile_frame_time(&self, percentile: f64) -> f64 {
+        if self.frame_times.is_empty() {
+            return 0.0;
+        }
+
+        let mut sorted: Vec<f64> = self.frame_times.iter().copied().collect();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+
+        let rank = (percentile / 100.0 * sorted.len() as f64).ceil() as usize;
+        sorted[rank.clamp(1, sorted.len()) - 1]
+    }
+
+    pub fn p95_frame_time(&self) -> f64 {
+        self.percentile_frame_time(95.0)
+    }
+
+    pub fn fps(&self) -> f64 {
+        let average = self.average_frame_time();
+
+        if average > 0.0 {
+            1000.0 / average
+        } else {
+            0.0
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "{:.1} fps | frame {:.2} ms (avg {:.2}, p95 {:.2}) | events {} ({} total) | uploads {:.1} KiB",
+            self.fps(),
+            self.last_frame_time(),
+            self.average_frame_time(),
+            self.p95_frame_time(),
+            self.last_events,
+            self.total_events,
+            self.last_uploaded_bytes as f64 / 1024.0
+        )
+    }
+
+    pub fn render_summary(&self) -> String {
+        format!("{} | max {}", self.last_render().summary(), self.max_render().summary())
+    }
+}
+
+//Counted while a frame is encoded, see WgpuContext::render. Chunks are counted once per pass that draws
+//them, the shadow pass included.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct RenderStats {
+    pub chunks_drawn: u32,
+    //There's no frustum culling yet, these are chunks skipped because they aren't ready to draw
+    pub chunks_culled: u32,
+    pub triangles: u64,
+    pub bind_group_switches: u32,
+    //Bytes written to buffers and uploaded to textures, see Uploader
+    pub buffer_bytes: u64,
+    pub texture_bytes: u64
+}
+
+impl RenderStats {
+    //The larger of each counter
+    pub fn max(&self, other: &RenderStats) -> RenderStats {
+        RenderStats {
+            chunks_drawn: self.chunks_drawn.max(other.chunks_drawn),
+            chunks_culled: self.chunks_culled.max(other.chunks_culled),
+            triangles: self.triangles.max(other.triangles),
+            bind_group_switches: self.bind_group_switches.max(other.bind_group_switches),
+            buffer_bytes: self.buffer_bytes.max(other.buffer_bytes),
+            texture_bytes: self.texture_bytes.max(other.texture_bytes)
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "{} chunks ({} culled), {} triangles, {} bind groups, buffers {:.1} KiB, textures {:.1} KiB",
+            self.chunks_drawn,
+            self.chunks_culled,
+            self.triangles,
+            self.bind_group_switches,
+            self.buffer_bytes as f64 / 1024.0,
+            self.texture_bytes as f64 / 1024.0
+        )
+    }
+}
+
+//What the host page gets back from CacophonyApp::get_stats
+#[derive(Serialize)]
+pub struct StatsSnapshot {
+    pub fps: f64,
+    pub last_frame_time: f64,
+    pub average_frame_time: f64,
+    pub p95_frame_time: f64,
+    pub frames: u64,
+    pub last_events: usize,
+    pub total_events: u64,
+    pub last_uploaded_bytes: u64,
+    pub render: RenderStats,
+    pub max_render: RenderStats
+}
+
+impl From<&FrameStats> for StatsSnapshot {
+    fn from(stats: &FrameStats) -> Self {
+        StatsSnapshot {
+            fps: stats.fps(),
+            last_frame_time: stats.last_frame_time(),
+            average_frame_time: stats.average_frame_time(),
+            p95_frame_time: stats.p95_frame_time(),
+            frames: stats.frames,
+            last_events: stats.last_events,
+            total_events: stats.total_events,
+            last_uploaded_bytes: stats.last_uploaded_bytes,
+            render: stats.last_render(),
+            max_render: stats.max_render()
+        }
+    }
+}
+
+//The chunk mesh every terrain draw shares, and how many draws there are (one per chunk)
+#[derive(Clone, Copy, Debug)]
+pub struct MeshStats {
+    pub vertices: usize,
+    pub indices: u32,
+    pub index_format: wgpu::IndexFormat,
+    pub bytes: u64,
+    pub draw_calls: usize
+}
+
+impl MeshStats {
+    pub fn summary(&self) -> String {
+        let index_bits = match self.index_format {
+            wgpu::IndexFormat::Uint16 => 16,
+            wgpu::IndexFormat::Uint32 => 32
+        };
+
+        format!(
+            "mesh {} vertices, {} indices ({}-bit), {:.1} KiB | {} draw calls",
+            self.vertices,
+            self.indices,
+            index_bits,
+            self.bytes as f64 / 1024.0,
+            self.draw_calls
+        )
+    }
+}
+
+impl Default for FrameStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+//Median of the last few frame deltas, so single long or short frames don't jerk the camera.
+//Deltas are in seconds and clamped to MAX_DELTA before filtering.
+pub struct DeltaFilter {
+    deltas: VecDeque<f64>,
+    window: usize,
+    max_delta: f64,
+    warmup: usize,
+    warmup_left: usize
+}
+
+impl DeltaFilter {
+    pub fn new(window: usize, max_delta: f64) -> Self {
+        DeltaFilter {
+            deltas: VecDeque::with_capacity(window),
+            window: window.max(1),
+            max_delta,
+            warmup: WARMUP_FRAMES,
+            warmup_left: WARMUP_FRAMES
+        }
+    }
+
+    pub fn with_warmup(mut self, frames: usize) -> Self {
+        self.warmup = frames;
+        self.warmup_left = frames;
+        self
+    }
+
+    pub fn filter(&mut self, raw: f64) -> f64 {
+        if self.warmup_left > 0 {
+            self.warmup_left -= 1;
+            return 0.0;
+        }
+
+        let delta = if raw.is_finite() { raw.clamp(0.0, self.max_delta) } else { 0.0 };
+
+        if self.deltas.len() == self.window {
+            self.deltas.pop_front();
+        }
+        self.deltas.push_back(delta);
+
+        let mut sorted: Vec<f64> = self.deltas.iter().copied().collect();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+
+        let middle = sorted.len() / 2;
+        if sorted.len().is_multiple_of(2) {
+            (sorted[middle - 1] + sorted[middle]) / 2.0
+        } else {
+            sorted[middle]
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.deltas.clear();
+        self.warmup_left = self.warmup;
+    }
+}
+
+impl Default for DeltaFilter {
+    fn default() -> Self {
+        Self::new(DELTA_WINDOW, MAX_DELTA)
+    }
+}
+
+//Shows FrameStats in a <div id="stats"> (scoped to the canvas id), or in the page title if no element can be created
+pub struct StatsOverlay {
+    document: Document,
+    canvas: HtmlCanvasElement,
+    element: Option<HtmlElement>,
+    created_element: bool,
+    original_title: String,
+
+    visible: bool,
+    last_update: f64
+}
+
+impl StatsOverlay {
+    pub fn new(document: Document, canvas: &HtmlCanvasElement) -> Self {
+        let id = scoped_id(&canvas.id(), "stats");
+        let existing = document.get_element_by_id(&id);
+        let created_element = existing.is_none();
+
+        let element = existing
+            .or_else(|| {
+                let element = document.create_element("div").ok()?;
+                element.set_id(&id);
+                insert_after_canvas(&element, canvas).ok()?;
+                Some(element)
+            })
+            .and_then(|element| element.dyn_into::<HtmlElement>().ok());
+
+        if let Some(element) = &element {
+            let style = element.style();
+            pin_to_canvas(element, canvas, Corner::TopLeft);
+            let _ = style.set_property("padding", "4px");
+            let _ = style.set_property("background", "rgba(0, 0, 0, 0.5)");
+            let _ = style.set_property("color", "white");
+            let _ = style.set_property("font-family", "monospace");
+            let _ = style.set_property("display", "none");
+        }
+
+        let original_title = document.title();
+
+        StatsOverlay {
+            document,
+            canvas: canvas.clone(),
+            element,
+            created_element,
+            original_title,
+
+            visible: false,
+            last_update: f64::NEG_INFINITY
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+
+        match &self.element {
+            Some(element) => {
+                let _ = element.style().set_property("display", if self.visible { "block" } else { "none" });
+            },
+            None if !self.visible => self.document.set_title(&self.original_title),
+            None => {}
+        }
+
+        //Show the current numbers straight away instead of waiting for the next interval
+        self.last_update = f64::NEG_INFINITY;
+    }
+
+    //Puts the page back the way it was found
+    pub fn remove(&mut self) {
+        if self.visible {
+            self.toggle();
+        }
+
+        if let Some(element) = self.element.take() {
+            if self.created_element {
+                element.remove();
+            }
+        }
+    }
+
+    pub fn update(&mut self, stats: &FrameStats, profiler: &Profiler, mesh: &MeshStats, time: f64) {
+        if !self.visible || time - self.last_update < OVERLAY_UPDATE_INTERVAL {
+            return;
+        }
+        self.last_update = time;
+
+        match &self.element {
+            Some(element) => {
+                //Follows the canvas if it moved since the last update
+                pin_to_canvas(element, &self.canvas, Corner::TopLeft);
+                element.set_inner_text(&format!("{}\n{}\n{}\n{}", stats.summary(), stats.render_summary(), mesh.summary(), profiler.summary()));
+            },
+            None => self.document.set_title(&stats.summary())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    fn stats_with(frame_times: &[f64]) -> FrameStats {
+        let mut stats = FrameStats::new();
+        for &frame_time in frame_times {
+            stats.record_frame(frame_time, 0, 0);
+        }
+        stats
+    }
+
+    #[wasm_bindgen_test]
+    fn empty_stats_are_zero() {
+        let stats = FrameStats::new();
+        assert_eq!(stats.average_frame_time(), 0.0);
+        assert_eq!(stats.p95_frame_time(), 0.0);
+        assert_eq!(stats.fps(), 0.0);
+    }
+
+    #[wasm_bindgen_test]
+    fn average_and_fps() {
+        let stats = stats_with(&[10.0, 20.0, 30.0]);
+        assert_eq!(stats.average_frame_time(), 20.0);
+        assert_eq!(stats.last_frame_time(), 30.0);
+        assert_eq!(stats.fps(), 50.0);
+    }
+
+    #[wasm_bindgen_test]
+    fn percentiles_pick_a_recorded_frame() {
+        let frame_times: Vec<f64> = (1..=100).map(|i| i as f64).collect();
+        let stats = stats_with(&frame_times);
+
+        assert_eq!(stats.p95_frame_time(), 95.0);
+        assert_eq!(stats.percentile_frame_time(100.0), 100.0);
+        assert_eq!(stats.percentile_frame_time(0.0), 1.0);
+    }
+
+    #[wasm_bindgen_test]
+    fn history_wraps_at_its_length() {
+        let mut stats = stats_with(&[1000.0]);
+        for _ in 0..HISTORY_LENGTH {
+            stats.record_frame(10.0, 2, 0);
+        }
+
+        //The slow first frame has dropped out of the history, but still counts as a frame
+        assert_eq!(stats.average_frame_time(), 10.0);
+        assert_eq!(stats.percentile_frame_time(100.0), 10.0);
+        assert_eq!(stats.frames, HISTORY_LENGTH as u64 + 1);
+        assert_eq!(stats.total_events, 2 * HISTORY_LENGTH as u64);
+        assert_eq!(stats.last_events, 2);
+    }
+
+    #[wasm_bindgen_test]
+    fn delta_filter_ignores_a_single_hitch() {
+        let mut filter = DeltaFilter::new(5, 1.0).with_warmup(0);
+        let deltas: Vec<f64> = [0.016, 0.017, 0.016, 0.4, 0.016].iter().map(|&dt| filter.filter(dt)).collect();
+
+        assert_eq!(deltas[3], 0.0165);
+        assert_eq!(deltas[4], 0.016);
+    }
+
+    #[wasm_bindgen_test]
+    fn delta_filter_clamps_pathological_deltas() {
+        let mut filter = DeltaFilter::new(1, 0.1).with_warmup(0);
+
+        assert_eq!(filter.filter(1234.0), 0.1);
+        assert_eq!(filter.filter(-5.0), 0.0);
+        assert_eq!(filter.filter(f64::NAN), 0.0);
+    }
+
+    #[wasm_bindgen_test]
+    fn delta_filter_follows_a_lasting_change() {
+        let mut filter = DeltaFilter::new(3, 1.0).with_warmup(0);
+        for _ in 0..3 {
+            filter.filter(0.016);
+        }
+
+        //Once the slower frames fill most of the window the median moves over to them
+        assert_eq!(filter.filter(0.033), 0.016);
+        assert_eq!(filter.filter(0.033), 0.033);
+    }
+
+    #[wasm_bindgen_test]
+    fn first_frame_has_no_delta() {
+        let mut filter = DeltaFilter::default();
+
+        //The first raw delta is measured from page load
+        assert_eq!(filter.filter(12.5), 0.0);
+        assert_eq!(filter.filter(0.016), 0.016);
+    }
+
+    #[wasm_bindgen_test]
+    fn warmup_skips_frames_and_restarts_on_reset() {
+        let mut filter = DeltaFilter::new(3, 1.0).with_warmup(2);
+        assert_eq!(filter.filter(0.5), 0.0);
+        assert_eq!(filter.filter(0.5), 0.0);
+        assert_eq!(filter.filter(0.02), 0.02);
+
+        filter.reset();
+        assert_eq!(filter.filter(0.02), 0.0);
+    }
+
+    fn render_stats(chunks_drawn: u32, triangles: u64, texture_bytes: u64) -> RenderStats {
+        RenderStats { chunks_drawn, triangles, texture_bytes, ..RenderStats::default() }
+    }
+
+    #[wasm_bindgen_test]
+    fn render_max_is_per_counter() {
+        let max = render_stats(9, 100, 0).max(&render_stats(4, 500, 64));
+
+        assert_eq!(max, render_stats(9, 500, 64));
+    }
+
+    #[wasm_bindgen_test]
+    fn render_stats_keep_the_last_frame_and_rolling_maxima() {
+        let mut stats = FrameStats::new();
+        assert_eq!(stats.last_render(), RenderStats::default());
+
+        stats.record_render(render_stats(20, 1000, 4096));
+        stats.record_render(render_stats(25, 800, 0));
+        assert_eq!(stats.last_render(), render_stats(25, 800, 0));
+        assert_eq!(stats.max_render(), render_stats(25, 1000, 4096));
+    }
+
+    #[wasm_bindgen_test]
+    fn render_maxima_forget_frames_past_the_history() {
+        let mut stats = FrameStats::new();
+        stats.record_render(render_stats(100, 0, 1 << 20));
+
+        for _ in 0..HISTORY_LENGTH {
+            stats.record_render(render_stats(10, 50, 0));
+        }
+        assert_eq!(stats.max_render(), render_stats(10, 50, 0));
+    }
+}
@@ -0,0 +1,308 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::noise::source::NoiseSource;
+
+use super::chunk::{noise_buffer, Chunk, ChunkPool, ChunkSampler, TextureFilter};
+use super::upload::Uploader;
+
+//Rows of a chunk's height texture filled per frame while streaming. A chunk takes a few frames to
+//appear, but no single frame pays for a whole texture.
+pub const STREAM_ROWS_PER_FRAME: u32 = 64;
+
+//A chunk whose texture is still being filled, it's drawn once every row is done
+struct PendingChunk {
+    coord: (i32, i32),
+    chunk: Chunk,
+    heights: Vec<f32>,
+    stride: usize,
+    next_row: u32
+}
+
+//Keeps the chunks within radius of the camera loaded. As the camera moves, chunks it leaves behind go
+//back to the pool and the nearest missing ones are filled in, one at a time, a slice of rows per frame.
+//When the noise changes, loaded chunks are refilled the same way and drawn as they were until then.
+pub struct ChunkStreamer {
+    pub pool: ChunkPool,
+    sampler: ChunkSampler,
+    radius: i32,
+    //Chunk size in world units
+    extent: f32,
+    //The camera's floating origin, chunks are placed relative to it
+    origin: [f64; 2],
+
+    center: (i32, i32),
+    resident: HashMap<(i32, i32), Chunk>,
+    //Resident chunks filled with an old sampler, replaced once their refill is done
+    stale: HashSet<(i32, i32)>,
+    pending: Option<PendingChunk>,
+    //Height texture bytes uploaded since take_uploaded_bytes
+    uploaded_bytes: u64,
+    //Grass instance bytes, the same
+    grass_bytes: u64
+}
+
+impl ChunkStreamer {
+    pub fn new(pool: ChunkPool, sampler: ChunkSampler, radius: i32, extent: f32, origin: [f64; 2], position: (f64, f64)) -> Self {
+        let mut streamer = ChunkStreamer {
+            pool,
+            sampler,
+            radius,
+            extent,
+            origin,
+
+            center: (0, 0),
+            resident: HashMap::new(),
+            stale: HashSet::new(),
+            pending: None,
+            uploaded_bytes: 0,
+            grass_bytes: 0
+        };
+        streamer.center = streamer.coord_at(position);
+
+        streamer
+    }
+
+    pub fn sampler(&self) -> ChunkSampler {
+        self.sampler
+    }
+
+    pub fn extent(&self) -> f32 {
+        self.extent
+    }
+
+    //Chunk containing a world position on the xz plane, absolute rather than relative to the origin
+    pub fn coord_at(&self, position: (f64, f64)) -> (i32, i32) {
+        let extent = self.extent as f64;
+        ((position.0 / extent).floor() as i32, (position.1 / extent).floor() as i32)
+    }
+
+    //Moves every chunk, loaded or not, to be drawn relative to a new floating origin
+    pub fn set_origin(&mut self, origin: [f64; 2]) {
+        self.origin = origin;
+
+        for chunk in self.resident.values_mut() {
+            chunk.set_origin(self.extent, origin);
+        }
+    }
+
+    fn in_range(&self, coord: (i32, i32)) -> bool {
+        (coord.0 - self.center.0).abs() <= self.radius && (coord.1 - self.center.1).abs() <= self.radius
+    }
+
+    //Chunks ready to draw
+    pub fn chunks(&self) -> impl Iterator<Item = &Chunk> {
+        self.resident.values()
+    }
+
+    //See ChunkUniforms::stage
+    pub fn stage_uniforms(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, uploader: &mut Uploader) {
+        self.pool.uniforms.stage(device, encoder, uploader, self.resident.values());
+    }
+
+    pub fn chunks_mut(&mut self) -> impl Iterator<Item = &mut Chunk> {
+        self.resident.values_mut()
+    }
+
+    //Bytes written to textures and to buffers
+    pub fn take_uploaded_bytes(&mut self) -> (u64, u64) {
+        (std::mem::take(&mut self.uploaded_bytes), std::mem::take(&mut self.grass_bytes))
+    }
+
+    //The chunk the camera was last over
+    pub fn center_chunk(&self) -> Option<&Chunk> {
+        self.resident.get(&self.center)
+    }
+
+    //Loaded and up to date out of all the chunks wanted, for progress reports
+    pub fn progress(&self) -> f64 {
+        let side = 2 * self.radius as usize + 1;
+        (self.resident.len() - self.stale.len()) as f64 / (side * side) as f64
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.pending.is_none() && self.next_to_fill().is_none()
+    }
+
+    //Whether every loaded chunk has been filled with the current sampler
+    pub fn is_refreshed(&self) -> bool {
+        self.stale.is_empty()
+    }
+
+    fn next_to_fill(&self) -> Option<(i32, i32)> {
+        next_to_fill(self.center, self.radius, &self.resident, &self.stale, self.pending.as_ref().map(|pending| pending.coord))
+    }
+
+    //Moves the loaded area to the chunk containing position, returning chunks left outside it to the pool
+    pub fn center_on(&mut self, position: (f64, f64)) {
+        let center = self.coord_at(position);
+        if center != self.center {
+            self.center = center;
+            self.drop_out_of_range();
+        }
+    }
+
+    //Fewer chunks are dropped straight away, more are streamed in like any others
+    pub fn set_radius(&mut self, radius: i32) {
+        self.radius = radius.max(0);
+        self.drop_out_of_range();
+    }
+
+    fn drop_out_of_range(&mut self) {
+        let dropped: Vec<(i32, i32)> = self.resident.keys().copied().filter(|&coord| !self.in_range(coord)).collect();
+        for coord in dropped {
+            if let Some(chunk) = self.resident.remove(&coord) {
+                self.pool.give_back(chunk);
+            }
+            self.stale.remove(&coord);
+        }
+
+        if self.pending.as_ref().is_some_and(|pending| !self.in_range(pending.coord)) {
+            if let Some(pending) = self.pending.take() {
+                self.pool.give_back(pending.chunk);
+            }
+        }
+    }
+
+    //Fills up to `rows` rows of the pending chunk, starting the next missing one if there isn't one.
+    //Returns whether a chunk finished and became drawable.
+    pub fn step(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, source: &dyn NoiseSource, rows: u32) -> bool {
+        if self.pending.is_none() {
+            let Some(coord) = self.next_to_fill() else {
+                return false;
+            };
+
+            let (heights, stride) = noise_buffer(self.sampler.texture_size());
+            self.pending = Some(PendingChunk {
+                coord,
+                chunk: self.pool.take(device, queue),
+                heights,
+                stride,
+                next_row: 0
+            });
+        }
+
+        let Some(pending) = &mut self.pending else {
+            return false;
+        };
+
+        let size = self.sampler.texture_size();
+        let end = (pending.next_row + rows).min(size);
+        self.sampler.fill_rows(source, pending.coord, &mut pending.heights, pending.stride, pending.next_row..end);
+        pending.next_row = end;
+
+        if end < size {
+            return false;
+        }
+
+        let Some(PendingChunk { coord, mut chunk, heights, stride, .. }) = self.pending.take() else {
+            return false;
+        };
+        let grass = self.pool.scatter_grass(coord, self.sampler.seed, &heights, stride);
+        self.grass_bytes += chunk.grass.write(queue, &grass);
+        self.uploaded_bytes += chunk.upload(device, queue, coord, self.sampler.seed, heights, stride);
+        chunk.set_origin(self.extent, self.origin);
+        if let Some(replaced) = self.resident.insert(coord, chunk) {
+            self.pool.give_back(replaced);
+        }
+        self.stale.remove(&coord);
+
+        true
+    }
+
+    //Per-frame streaming around the camera, returns whether a new chunk needs drawing
+    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, source: &dyn NoiseSource, position: (f64, f64)) -> bool {
+        self.center_on(position);
+        self.step(device, queue, source, STREAM_ROWS_PER_FRAME)
+    }
+
+    //For when the noise itself changes, see restream
+    pub fn set_sampler(&mut self, sampler: ChunkSampler) {
+        self.sampler = sampler;
+        self.restream();
+    }
+
+    //Refills every loaded chunk through step, after any missing ones. Their old textures stay bound until
+    //each replacement is complete, so the terrain never has holes and no frame fills more than its rows.
+    pub fn restream(&mut self) {
+        self.stale = self.resident.keys().copied().collect();
+
+        if let Some(pending) = &mut self.pending {
+            pending.next_row = 0;
+        }
+    }
+
+    pub fn set_height_scale(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, height_scale: f32) {
+        for chunk in self.resident.values_mut() {
+            chunk.set_height_scale(device, queue, height_scale);
+        }
+        if let Some(pending) = &mut self.pending {
+            pending.chunk.set_height_scale(device, queue, height_scale);
+        }
+
+        self.pool.set_height_scale(device, queue, height_scale);
+    }
+
+    pub fn set_filter(&mut self, device: &wgpu::Device, filter: TextureFilter) {
+        self.pool.set_filter(device, filter);
+
+        for chunk in self.resident.values_mut() {
+            self.pool.rebind(device, chunk);
+        }
+        if let Some(pending) = &mut self.pending {
+            self.pool.rebind(device, &mut pending.chunk);
+        }
+    }
+}
+
+//Nearest chunk in range that isn't loaded, or failing that the nearest stale one. The pending chunk is skipped.
+fn next_to_fill<V>(center: (i32, i32), radius: i32, resident: &HashMap<(i32, i32), V>, stale: &HashSet<(i32, i32)>, pending: Option<(i32, i32)>) -> Option<(i32, i32)> {
+    let (cx, cz) = center;
+    let distance = |&(x, z): &(i32, i32)| (x - cx).pow(2) + (z - cz).pow(2);
+
+    (-radius..=radius)
+        .flat_map(|z| (-radius..=radius).map(move |x| (cx + x, cz + z)))
+        .filter(|coord| !resident.contains_key(coord) && pending != Some(*coord))
+        .min_by_key(distance)
+        .or_else(|| stale.iter().copied().filter(|&coord| pending != Some(coord)).min_by_key(distance))
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    fn loaded(radius: i32) -> HashMap<(i32, i32), ()> {
+        (-radius..=radius).flat_map(|z| (-radius..=radius).map(move |x| ((x, z), ()))).collect()
+    }
+
+    #[wasm_bindgen_test]
+    fn missing_chunks_come_before_stale_ones() {
+        let mut resident = loaded(1);
+        resident.remove(&(1, 1));
+        let stale: HashSet<_> = [(0, 0), (1, 0)].iter().copied().collect();
+
+        assert_eq!(next_to_fill((0, 0), 1, &resident, &stale, None), Some((1, 1)));
+        assert_eq!(next_to_fill((0, 0), 1, &resident, &stale, Some((1, 1))), Some((0, 0)));
+
+        resident.insert((1, 1), ());
+        assert_eq!(next_to_fill((0, 0), 1, &resident, &stale, None), Some((0, 0)));
+        assert_eq!(next_to_fill((0, 0), 1, &resident, &stale, Some((0, 0))), Some((1, 0)));
+    }
+
+    #[wasm_bindgen_test]
+    fn nothing_to_fill_once_refreshed() {
+        let resident = loaded(2);
+
+        assert_eq!(next_to_fill((0, 0), 2, &resident, &HashSet::new(), None), None);
+        assert_eq!(next_to_fill((0, 0), 2, &resident, &[(2, 2)].iter().copied().collect(), None), Some((2, 2)));
+    }
+
+    #[wasm_bindgen_test]
+    fn nearest_missing_chunk_first() {
+        let resident: HashMap<_, ()> = HashMap::new();
+
+        assert_eq!(next_to_fill((3, -2), 2, &resident, &HashSet::new(), None), Some((3, -2)));
+        assert_eq!(next_to_fill((3, -2), 0, &resident, &HashSet::new(), Some((3, -2))), None);
+    }
+}
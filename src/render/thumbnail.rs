@@ -0,0 +1,56 @@
+use std::{collections::VecDeque, rc::Rc};
+
+use cgmath::Matrix4;
+
+use crate::noise::source::Seed;
+
+use super::camera::OPENGL_TO_WGPU_MATRIX;
+
+pub const DEFAULT_THUMBNAIL_SIZE: u32 = 128;
+
+pub struct Thumbnail {
+    pub seed: Seed,
+    pub size: u32,
+    //size * size pixels, RGBA8, rows top to bottom
+    pub rgba: Vec<u8>
+}
+
+pub type ThumbnailCallback = Rc<dyn Fn(Thumbnail)>;
+
+//Looks straight down at a square of terrain from x, z = 0 to extent. Heights up to max_height fit between the planes.
+pub fn top_down_view_proj(extent: f32, max_height: f32) -> Matrix4<f32> {
+    let half = extent / 2.0;
+    let eye = cgmath::Point3::new(half, max_height + 1.0, half);
+
+    //-z is up in the image, so the thumbnail has the same orientation as a map with +x to the right
+    let view = Matrix4::look_to_rh(eye, -cgmath::Vector3::unit_y(), -cgmath::Vector3::unit_z());
+    let proj = cgmath::ortho(-half, half, -half, half, 0.0, max_height + 2.0);
+
+    OPENGL_TO_WGPU_MATRIX * proj * view
+}
+
+//Seeds waiting for a thumbnail. One is rendered per frame, each switches the terrain to its seed and
+//back, so a long list doesn't hold up the page.
+pub struct ThumbnailQueue {
+    seeds: VecDeque<Seed>,
+    pub size: u32,
+    pub callback: ThumbnailCallback
+}
+
+impl ThumbnailQueue {
+    pub fn new(seeds: impl IntoIterator<Item = Seed>, size: u32, callback: ThumbnailCallback) -> Self {
+        ThumbnailQueue {
+            seeds: seeds.into_iter().collect(),
+            size: size.max(1),
+            callback
+        }
+    }
+
+    pub fn next_seed(&mut self) -> Option<Seed> {
+        self.seeds.pop_front()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.seeds.is_empty()
+    }
+}
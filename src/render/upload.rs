@@ -0,0 +1,93 @@
+use wgpu::util::StagingBelt;
+
+//Enough for a frame's uniforms in one staging buffer, bigger writes get a buffer of their own
+pub const STAGING_CHUNK_SIZE: wgpu::BufferAddress = 16 * 1024;
+
+//Buffer writes go through a ring of staging buffers kept between frames (wgpu's StagingBelt), rather than
+//queue.write_buffer, which can allocate a fresh staging copy on every call. Each write is a copy recorded
+//into the encoder it's given, so it lands before any pass recorded after it in the same submit.
+//Texture uploads still go through queue.write_texture, and a few buffers that are written rarely and in
+//bulk go through queue.write_buffer, they're only counted here.
+pub struct Uploader {
+    belt: StagingBelt,
+    frame_buffer_bytes: u64,
+    frame_texture_bytes: u64,
+    last_frame_bytes: u64
+}
+
+impl Uploader {
+    pub fn new(chunk_size: wgpu::BufferAddress) -> Self {
+        Uploader {
+            belt: StagingBelt::new(chunk_size),
+            frame_buffer_bytes: 0,
+            frame_texture_bytes: 0,
+            last_frame_bytes: 0
+        }
+    }
+
+    //data's length and offset have to be multiples of wgpu::COPY_BUFFER_ALIGNMENT
+    pub fn write_buffer(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, target: &wgpu::Buffer, offset: wgpu::BufferAddress, data: &[u8]) {
+        let Some(size) = wgpu::BufferSize::new(data.len() as u64) else {
+            return;
+        };
+
+        self.belt.write_buffer(encoder, target, offset, size, device).copy_from_slice(data);
+        self.frame_buffer_bytes += data.len() as u64;
+    }
+
+    //For texture uploads, so the frame's total covers them
+    pub fn count(&mut self, bytes: u64) {
+        self.frame_texture_bytes += bytes;
+    }
+
+    //For buffers written without going through write_buffer
+    pub fn count_buffer(&mut self, bytes: u64) {
+        self.frame_buffer_bytes += bytes;
+    }
+
+    //Once every write for an encoder is recorded, before it's submitted
+    pub fn finish(&mut self) {
+        self.belt.finish();
+    }
+
+    //After the submit. The staging buffers are reused once the GPU is done with them.
+    pub fn recall(&mut self) {
+        self.belt.recall();
+    }
+
+    //Closes the frame's byte count, see last_frame_bytes. Returns the bytes written to buffers and to
+    //textures separately.
+    pub fn end_frame(&mut self) -> (u64, u64) {
+        let buffer_bytes = std::mem::take(&mut self.frame_buffer_bytes);
+        let texture_bytes = std::mem::take(&mut self.frame_texture_bytes);
+        self.last_frame_bytes = buffer_bytes + texture_bytes;
+
+        (buffer_bytes, texture_bytes)
+    }
+
+    //Bytes uploaded for the last frame rendered, including anything written between frames
+    pub fn last_frame_bytes(&self) -> u64 {
+        self.last_frame_bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    fn byte_counts_reset_every_frame() {
+        let mut uploader = Uploader::new(STAGING_CHUNK_SIZE);
+
+        uploader.count(1024);
+        uploader.count(512);
+        uploader.count_buffer(256);
+        assert_eq!(uploader.end_frame(), (256, 1536));
+        assert_eq!(uploader.last_frame_bytes(), 1792);
+
+        assert_eq!(uploader.end_frame(), (0, 0));
+        assert_eq!(uploader.last_frame_bytes(), 0);
+    }
+}
@@ -0,0 +1,168 @@
+use wgpu::util::DeviceExt;
+
+use super::wgpu_context::BlendMode;
+
+//Normalised like the colour ramp's stops, so it sits just under the sand band at the default ramp.
+//The water is drawn at level * height_scale, the same scale as the terrain.
+pub const DEFAULT_WATER_LEVEL: f32 = 0.32;
+//Linear RGB, alpha is how much of the ground shows through when looking straight down
+pub const DEFAULT_WATER_COLOR: [f32; 4] = [0.02, 0.08, 0.15, 0.7];
+
+//Half the width of the quad, centred under the camera. Past the fog at any usable density.
+const WATER_EXTENT: f32 = 2000.0;
+//The waves repeat every this many world units, see water.wgsl
+const WAVE_TILE: f64 = 64.0;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct WaterUniform {
+    color: [f32; 4],
+    //World height of the surface
+    height: f32,
+    extent: f32,
+    //The camera's floating origin, wrapped to the wave tile so the waves stay put across recenters
+    wave_offset: [f32; 2]
+}
+
+//A flat sheet of water at a fixed level, drawn after the terrain with alpha blending. It tests against
+//the terrain's depth, so the shoreline is wherever the ground comes out of it.
+pub struct WaterRenderer {
+    pipeline: wgpu::RenderPipeline,
+    uniform: WaterUniform,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+
+    level: f32,
+    height_scale: f32
+}
+
+impl WaterRenderer {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, depth_format: wgpu::TextureFormat, settings_layout: &wgpu::BindGroupLayout) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Water shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/water.wgsl").into())
+        });
+
+        let uniform = WaterUniform {
+            color: DEFAULT_WATER_COLOR,
+            height: DEFAULT_WATER_LEVEL,
+            extent: WATER_EXTENT,
+            wave_offset: [0.0; 2]
+        };
+
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Water uniform buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("Water bind group layout"),
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("Water bind group"),
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Water Pipeline Layout"),
+            bind_group_layouts: &[settings_layout, &bind_group_layout],
+            push_constant_ranges: &[]
+        });
+
+        let blend = BlendMode::Transparent;
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Water Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(blend.color_target(format))],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                //Seen from below too, when the camera dips under the surface
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false
+            },
+            depth_stencil: Some(blend.depth_stencil(depth_format, wgpu::CompareFunction::Less)),
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false
+            },
+            multiview: None
+        });
+
+        Self {
+            pipeline,
+            uniform,
+            uniform_buffer,
+            bind_group,
+
+            level: DEFAULT_WATER_LEVEL,
+            height_scale: 1.0
+        }
+    }
+
+    //0 puts it under all the terrain, which is as good as turning it off
+    pub fn set_level(&mut self, queue: &wgpu::Queue, level: f32) {
+        self.level = level;
+        self.write(queue);
+    }
+
+    pub fn set_height_scale(&mut self, queue: &wgpu::Queue, height_scale: f32) {
+        self.height_scale = height_scale;
+        self.write(queue);
+    }
+
+    pub fn set_color(&mut self, queue: &wgpu::Queue, color: [f32; 4]) {
+        self.uniform.color = color;
+        self.write(queue);
+    }
+
+    pub fn set_origin(&mut self, queue: &wgpu::Queue, origin: [f64; 2]) {
+        self.uniform.wave_offset = origin.map(|value| value.rem_euclid(WAVE_TILE) as f32);
+        self.write(queue);
+    }
+
+    fn write(&mut self, queue: &wgpu::Queue) {
+        self.uniform.height = self.level * self.height_scale;
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, settings_bind_group: &'a wgpu::BindGroup) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, settings_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.bind_group, &[]);
+        render_pass.draw(0..6, 0..1);
+    }
+}
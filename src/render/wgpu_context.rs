@@ -1,514 +1,2703 @@
-use wasm_bindgen::prelude::{Closure, wasm_bindgen};
-use web_sys::HtmlCanvasElement;
-use wgpu::Device;
-use wgpu::util::DeviceExt;
-use winit::dpi::PhysicalSize;
-
-use crate::console_log;
-use crate::noise::source::{TestSource, NoiseSource, Coord};
-use crate::util::get_expected_size;
-
-use super::camera::Camera;
-
-#[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-struct Vertex {
-    position: [f32; 2],
-    uv: [f32; 2]
-}
-
-impl Vertex {
-    const ATTRIBS: [wgpu::VertexAttribute; 2] = wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2];
-
-    fn desc() -> wgpu::VertexBufferLayout<'static> {
-        wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &Self::ATTRIBS
-        }
-    }
-}
-
-const VERTICES: &[Vertex] = &[
-    Vertex { position: [-1.0, -1.0], uv: [0.0, 0.0] },
-    Vertex { position: [ 1.0, -1.0], uv: [1.0, 0.0] },
-    Vertex { position: [ 1.0,  1.0], uv: [1.0, 1.0] },
-    Vertex { position: [-1.0,  1.0], uv: [0.0, 1.0] }
-];
-
-const INDICES: &[u16] = &[
-    0, 1, 2,
-    2, 3, 0,
-];
-
-const TEX_SIZE: u32 = 512;
-
-#[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-struct RenderSettings {
-    view_proj: [[f32; 4]; 4],
-    height_scale: f32,
-    tex_size: u32,
-    _padding: [u8; 8]
-}
-
-impl RenderSettings {
-    fn new() -> Self {
-        use cgmath::SquareMatrix;
-        Self {
-            view_proj: cgmath::Matrix4::identity().into(),
-            height_scale: 1.0,
-            tex_size: TEX_SIZE,
-            _padding: [0; 8]
-        }
-    }
-
-    fn update_view_proj(&mut self, camera: &Camera) {
-        self.view_proj = camera.build_view_projection_matrix().into();
-    }
-}
-
-struct ChunkBuffers {
-    vertex_buffer: wgpu::Buffer,
-    index_buffer: wgpu::Buffer,
-    num_indices: u32
-}
-
-impl ChunkBuffers {
-    pub fn generate(device: &wgpu::Device, size: u32, density: f32) -> Self {
-        let mut points = vec![];
-
-        //Add border
-        for i in 0..size {
-            points.push(
-                delaunator::Point {
-                    x: i as f64,
-                    y: 0.0
-                }
-            );
-
-            points.push(
-                delaunator::Point {
-                    x: i as f64,
-                    y: size as f64 - 1.0
-                }
-            );
-        }
-
-        for i in 1..size-1 {
-            points.push(
-                delaunator::Point {
-                    x: 0.0,
-                    y: i as f64
-                }
-            );
-
-            points.push(
-                delaunator::Point {
-                    x: size as f64 - 1.0,
-                    y: i as f64
-                }
-            );
-        }
-
-        let inner_size = size - 2;
-        let num_inner_points = (inner_size as f32 * density).ceil() as u32;
-
-        for i in  0..num_inner_points {
-            for j in 0..num_inner_points {
-                let ti = (i + 1) as f64 / (size as f64 - 1.0);
-                let tj = (j + 1) as f64 / (size as f64 - 1.0);
-
-                let x = ti * (size + 1) as f64;
-                let y = tj * (size + 1) as f64;
-
-                points.push(
-                    delaunator::Point { x, y }
-                );
-            }
-        }
-
-        let indices: Vec<_> = delaunator::triangulate(&points).triangles.into_iter().map(|i| i as u32).collect();
-        let num_indices = indices.len() as u32;
-        let vertices: Vec<_> = points.into_iter().map(|p| {
-            Vertex {
-                position: [p.x as f32, p.y as f32],
-                uv: [p.x as f32 / (size - 1) as f32, p.y as f32 / (size - 1) as f32]
-            }
-        }).collect();
-
-        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Vertex buffer"),
-            contents: bytemuck::cast_slice(&vertices),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
-
-        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Index buffer"),
-            contents: bytemuck::cast_slice(&indices),
-            usage: wgpu::BufferUsages::INDEX,
-        });
-
-        console_log!("Generated {} vertices and {} indices", vertices.len(), indices.len());
-
-        Self {
-            vertex_buffer,
-            index_buffer,
-            num_indices
-        }
-    }
-}
-
-pub struct WgpuContext {
-    pub surface: wgpu::Surface,
-    pub device: wgpu::Device,
-    pub queue: wgpu::Queue,
-    pub config: wgpu::SurfaceConfiguration,
-    pub size: winit::dpi::PhysicalSize<u32>,
-
-    render_pipeline: wgpu::RenderPipeline,
-
-    chunk_buffers: ChunkBuffers,
-
-    render_settings_uniform: RenderSettings,
-    render_settings_uniform_buffer: wgpu::Buffer,
-    render_settings_uniform_bind_group: wgpu::BindGroup,
-
-    noise_texture_bind_group: wgpu::BindGroup,
-}
-
-impl WgpuContext {
-    pub async fn new(canvas: &HtmlCanvasElement, camera: &Camera)-> Self {
-        let (width, height) = get_expected_size(canvas);
-        console_log!("Surface size: {} {}", width, height);
-        canvas.set_width(width);
-        canvas.set_height(height);
-
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
-        let surface = instance.create_surface_from_canvas(canvas.clone()).expect("Could not create surface :(");
-
-        let adpater = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::HighPerformance,
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            })
-            .await
-            .unwrap();
-
-        console_log!("Adapter: {:?}", adpater.get_info());
-
-        let (device, queue) = adpater
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    features: wgpu::Features::empty(),
-                    limits: wgpu::Limits::downlevel_webgl2_defaults(),
-                    label: None
-                },
-                None,
-            )
-            .await
-            .unwrap();
-
-        let surface_caps = surface.get_capabilities(&adpater);
-        let surface_format = surface_caps.formats.iter().copied()
-            .find(|f| f.is_srgb())
-            .unwrap_or(surface_caps.formats[0]);
-
-        let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface_format,
-            width,
-            height,
-            present_mode: surface_caps.present_modes[0],
-            alpha_mode: surface_caps.alpha_modes[0],
-            view_formats: vec![]
-        };
-        surface.configure(&device, &config);
-
-        let (render_settings_uniform, render_settings_uniform_buffer, render_settings_uniform_bind_group, render_settings_bind_group_layout) = Self::create_render_settings_uniform(camera, &device);
-
-        let chunk_buffers = ChunkBuffers::generate(&device, 100, 1.0);
-
-        let noise_texture_size = TEX_SIZE;
-        let noise_res = 0.1;
-        let src = TestSource;
-
-        let noise_texture_desc = wgpu::TextureDescriptor {
-            size: wgpu::Extent3d {
-                width: noise_texture_size,
-                height: noise_texture_size,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::R32Float,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-            label: Some("Noise texture"),
-            view_formats: &[]
-        };
-        let noise_texture = device.create_texture(&noise_texture_desc);
-        
-        let pixel_size = std::mem::size_of::<f32>() as u32;
-        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
-        let unpadded_bytes_per_row = pixel_size * noise_texture_size;
-        let padding = (align - unpadded_bytes_per_row % align) % align;
-        let padded_bytes_per_row = unpadded_bytes_per_row + padding;
-
-        if padded_bytes_per_row % pixel_size != 0 {
-            panic!("Padded bytes per row is not a multiple of pixel size");
-        }
-
-        let padded_pixels_per_row = padded_bytes_per_row / pixel_size;
-
-        let mut noise_texture_data = vec![0.0; padded_pixels_per_row as usize * noise_texture_size as usize];
-
-        for x in 0..noise_texture_size {
-            for y in 0..noise_texture_size {
-                let noise = src.sample(x as Coord * noise_res, y as Coord * noise_res, 0);
-                let normed = noise * 0.5 + 0.5;
-
-                let idx = padded_pixels_per_row as usize * y as usize + x as usize;
-                noise_texture_data[idx] = normed;
-            }
-        }
-
-        queue.write_texture(
-            wgpu::ImageCopyTexture {
-                texture: &noise_texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            bytemuck::cast_slice(&noise_texture_data),
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(padded_bytes_per_row),
-                rows_per_image: Some(noise_texture_size),
-            },
-            noise_texture_desc.size
-        );
-
-        let noise_texture_view = noise_texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let noise_texture_sampler = device.create_sampler(
-            &wgpu::SamplerDescriptor {
-                address_mode_u: wgpu::AddressMode::ClampToEdge,
-                address_mode_v: wgpu::AddressMode::ClampToEdge,
-                address_mode_w: wgpu::AddressMode::ClampToEdge,
-                mag_filter: wgpu::FilterMode::Linear,
-                min_filter: wgpu::FilterMode::Nearest,
-                mipmap_filter: wgpu::FilterMode::Nearest,
-                ..Default::default()
-            }
-        );
-
-        let noise_texture_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Texture { 
-                        sample_type: wgpu::TextureSampleType::Float { filterable: true },  
-                        view_dimension: wgpu::TextureViewDimension::D2, 
-                        multisampled: false 
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                    count: None,
-                }
-            ],
-            label: Some("Noise texture bind group layout"),
-        });
-
-        let noise_texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &noise_texture_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&noise_texture_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&noise_texture_sampler),
-                },
-            ],
-            label: Some("Noise texture bind group"),
-        });
-
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Test shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/shader.wgsl").into())
-        });
-
-        let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("Render Pipeline Layout"),
-            bind_group_layouts: &[
-                &render_settings_bind_group_layout,
-                &noise_texture_bind_group_layout,
-            ],
-            push_constant_ranges: &[]
-        });
-
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[
-                    Vertex::desc()
-                ],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false
-            },
-            multiview: None
-        });
-
-        Self {
-            surface,
-            device,
-            queue,
-            config,
-            size: PhysicalSize::new(width, height),
-
-            render_pipeline,
-
-            chunk_buffers,
-
-            render_settings_uniform,
-            render_settings_uniform_buffer,
-            render_settings_uniform_bind_group,
-
-            noise_texture_bind_group
-        }
-    }
-
-    fn create_render_settings_uniform(camera: &Camera, device: &Device) -> (RenderSettings, wgpu::Buffer, wgpu::BindGroup, wgpu::BindGroupLayout) {
-        let mut render_settings_uniform = RenderSettings::new();
-        render_settings_uniform.update_view_proj(camera);
-
-        let render_settings_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Camera uniform buffer"),
-            contents: bytemuck::cast_slice(&[render_settings_uniform]),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
-
-        let render_settings_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-            ],
-            label: Some("Camera uniform bind group layout"),
-        });
-
-        let render_settings_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &render_settings_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: render_settings_buffer.as_entire_binding(),
-                },
-            ],
-            label: Some("Camera uniform bind group"),
-        });
-
-        (render_settings_uniform, render_settings_buffer, render_settings_bind_group, render_settings_bind_group_layout)
-    }
-
-    pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
-        if new_size.width > 0 && new_size.height > 0 {
-            self.size = new_size;
-            self.config.width = new_size.width;
-            self.config.height = new_size.height;
-
-            self.surface.configure(&self.device, &self.config);
-
-            console_log!("Resized canvas to {}x{}", new_size.width, new_size.height);
-        }
-    }
-
-    pub fn render(&mut self, delay: f64, camera: &Camera) -> Result<(), wgpu::SurfaceError>{
-        self.render_settings_uniform.update_view_proj(camera);
-        self.queue.write_buffer(&self.render_settings_uniform_buffer, 0, bytemuck::cast_slice(&[self.render_settings_uniform]));
-
-        let output = self.surface.get_current_texture()?;
-        let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("Render Encoder")
-        });
-
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[
-                    Some(wgpu::RenderPassColorAttachment {
-                        view: &view,
-                        resolve_target: None,
-                        ops: wgpu::Operations {
-                            load: wgpu::LoadOp::Clear(
-                                wgpu::Color {
-                                    r: 0.1,
-                                    g: 0.2,
-                                    b: 0.3,
-                                    a: 1.0
-                                }
-                            ),
-                            store: true
-                        }
-                    })
-                ],
-                depth_stencil_attachment: None
-            });
-
-            render_pass.set_pipeline(&self.render_pipeline);
-
-            render_pass.set_vertex_buffer(0, self.chunk_buffers.vertex_buffer.slice(..));
-            render_pass.set_index_buffer(self.chunk_buffers.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-
-            render_pass.set_bind_group(0, &self.render_settings_uniform_bind_group, &[]);
-            render_pass.set_bind_group(1, &self.noise_texture_bind_group, &[]);
-
-            render_pass.draw_indexed(0..self.chunk_buffers.num_indices, 0, 0..1);
-        }
-
-        self.queue.submit(Some(encoder.finish()));
-        output.present();
-
-        Ok(())
-    }
-}
\ No newline at end of file
+use web_sys::HtmlCanvasElement;
+use std::cell::Cell;
+use std::future::Future;
+use std::rc::Rc;
+
+use wgpu::Device;
+use wgpu::util::DeviceExt;
+use winit::dpi::PhysicalSize;
+
+use crate::console_log;
+use crate::noise::source::{TestSource, NoiseSource, Coord, Seed, sample_hash};
+use crate::util::{get_expected_size, now, yield_now, Profiler, DEFAULT_MIN_CANVAS_SIZE};
+
+use super::camera::Camera;
+use super::grid::{GridConfig, GridRenderer};
+use super::sky::SkyRenderer;
+use super::water::WaterRenderer;
+use super::grass::{GrassRenderer, BLADE_TRIANGLES};
+use super::color_ramp::{ColorRamp, ColorRampTexture};
+use super::splat::{SplatSettings, SplatUniform};
+use super::shadow::{self, ShadowMap};
+use super::event::{KeyOptions, DEFAULT_EVENT_CAP};
+use super::height_field::{HeightField, Heightmap};
+use super::chunk::{self, Chunk, ChunkPool, ChunkSampler, HeightFormat, TextureFilter, CHUNK_APRON, CHUNK_GRID_RADIUS};
+use super::streaming::ChunkStreamer;
+use super::thumbnail::{self, Thumbnail, ThumbnailCallback};
+use super::frame_graph::{RenderPassNode, ColorLoad, DepthLoad, PassTarget, Viewport, ViewRect};
+use super::gpu_timer::GpuTimer;
+use super::scene::Scene;
+use super::render_scale;
+use super::post::{self, PostProcessor, SceneTarget};
+use super::displacement::Displacement;
+use super::screenshot;
+use super::stats::{MeshStats, RenderStats};
+use super::poisson;
+use super::baked_mesh::{BakedVertex, MeshShape};
+use super::upload::{Uploader, STAGING_CHUNK_SIZE};
+use super::xr::XrView;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct Vertex {
+    position: [f32; 2],
+    uv: [f32; 2],
+    //1 for the copies of the border pushed down into a skirt, 0 for the surface
+    skirt: f32
+}
+
+impl Vertex {
+    const ATTRIBS: [wgpu::VertexAttribute; 3] = wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Float32];
+
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBS
+        }
+    }
+}
+
+//Height texture texels and mesh units along each side of a chunk, unless WgpuContextBuilder says otherwise
+pub const DEFAULT_TEX_SIZE: u32 = 512;
+pub const DEFAULT_CHUNK_SIZE: u32 = 100;
+//Rows of the height textures filled between yields during startup, each slice should stay well under a frame
+const NOISE_ROWS_PER_SLICE: u32 = 32;
+//Nodes along each side of the cached height grid, one every few world units
+const HEIGHT_FIELD_RESOLUTION: usize = 34;
+//Samples along each side of the grid terrain_hash hashes
+const TERRAIN_HASH_RESOLUTION: u32 = 64;
+
+pub const DEFAULT_NOISE_FREQUENCY: Coord = 0.1;
+//Bounds on TessellationConfig::density. Meshes built for one chunk's noise get up to max_subdivisions
+//squared extra points in steep cells on top, so much more than this and triangulating takes long enough to hang the tab.
+pub const MIN_MESH_DENSITY: f32 = 0.1;
+pub const MAX_MESH_DENSITY: f32 = 2.0;
+//Normalised height, see RenderSettings::skirt_depth
+pub const DEFAULT_SKIRT_DEPTH: f32 = 0.05;
+
+pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+//Linear RGB
+pub const DEFAULT_AMBIENT_COLOR: [f32; 3] = [0.3, 0.3, 0.3];
+pub const DEFAULT_GROUND_COLOR: [f32; 3] = [0.15, 0.12, 0.1];
+pub const DEFAULT_SUN_COLOR: [f32; 3] = [0.7, 0.7, 0.7];
+//Degrees. Azimuth is measured from +x towards +z, elevation up from the horizon.
+pub const DEFAULT_SUN_AZIMUTH: f32 = 27.0;
+pub const DEFAULT_SUN_ELEVATION: f32 = 61.0;
+
+pub fn sun_direction(azimuth: f32, elevation: f32) -> [f32; 3] {
+    let (azimuth, elevation) = (azimuth.to_radians(), elevation.to_radians());
+    [elevation.cos() * azimuth.cos(), elevation.sin(), elevation.cos() * azimuth.sin()]
+}
+
+pub const DEFAULT_FOG_COLOR: [f32; 3] = [0.1, 0.2, 0.3];
+pub const DEFAULT_FOG_DENSITY: f32 = 0.005;
+//Linear RGB, the background when neither the sky nor fog is on
+pub const DEFAULT_CLEAR_COLOR: [f32; 3] = [0.1, 0.2, 0.3];
+//Nothing in the terrain is this colour, so any gap the debug clear shows through stands out
+const DEBUG_CLEAR_COLOR: wgpu::Color = wgpu::Color { r: 1.0, g: 0.0, b: 1.0, a: 1.0 };
+
+//Same curve as output_color in the shaders
+pub fn encode_srgb(linear: f32) -> f64 {
+    let c = linear.clamp(0.0, 1.0) as f64;
+
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct RenderSettings {
+    view_proj: [[f32; 4]; 4],
+    height_scale: f32,
+    tex_size: u32,
+    //Procedural detail added on top of the baked heightmap, a strength of 0 disables it
+    detail_strength: f32,
+    detail_frequency: f32,
+    //Shaders always compute linear colour. Set this to have them encode it themselves, for a target that
+    //isn't sRGB and doesn't keep linear colour. The scene target never needs it, see post::choose_scene_format.
+    encode_srgb: u32,
+    //One of the DEBUG_* constants in shader.wgsl, see DebugMode::shader_mode
+    debug_mode: u32,
+    //World time in seconds, see WorldClock
+    time: f32,
+    //How far the chunk skirts hang below the border, in normalised height so it follows height_scale
+    skirt_depth: f32,
+    //Light reaching surfaces the sun doesn't, so they aren't black. With hemisphere_ambient set it's
+    //the sky colour for surfaces facing up, fading to ground_color for surfaces facing down.
+    ambient_color: [f32; 3],
+    hemisphere_ambient: u32,
+    ground_color: [f32; 3],
+    _padding2: [u32; 1],
+    //Unit vector towards the sun
+    sun_direction: [f32; 3],
+    _padding3: [u32; 1],
+    sun_color: [f32; 3],
+    _padding4: [u32; 1],
+    camera_position: [f32; 3],
+    //Exponential squared fog, 0 turns it off
+    fog_density: f32,
+    //Linear RGB, also the sky's colour at the horizon so distant terrain blends into it
+    fog_color: [f32; 3],
+    //Opacity of the sky and the clear behind it, below 1 the page shows through. Colours drawn with it
+    //are premultiplied, see ContextOptions::alpha_mode.
+    background_alpha: f32,
+    //The camera's floating origin times detail_frequency, split into a whole lattice cell and what's left
+    //over, so the detail noise lines up across recenters without losing precision far from zero
+    detail_cell: [i32; 2],
+    detail_offset: [f32; 2],
+    //For the sky, which turns screen positions back into view directions
+    inverse_view_proj: [[f32; 4]; 4],
+    //Scale and bias from a height texel to a height, see HeightFormat::decode
+    height_decode: [f32; 2],
+    _padding6: [u32; 2]
+}
+
+impl RenderSettings {
+    fn new(tex_size: u32) -> Self {
+        use cgmath::SquareMatrix;
+        Self {
+            view_proj: cgmath::Matrix4::identity().into(),
+            height_scale: 1.0,
+            tex_size,
+            detail_strength: 0.02,
+            detail_frequency: 4.0,
+            encode_srgb: 0,
+            debug_mode: 0,
+            time: 0.0,
+            skirt_depth: DEFAULT_SKIRT_DEPTH,
+            ambient_color: DEFAULT_AMBIENT_COLOR,
+            hemisphere_ambient: 0,
+            ground_color: DEFAULT_GROUND_COLOR,
+            _padding2: [0; 1],
+            sun_direction: sun_direction(DEFAULT_SUN_AZIMUTH, DEFAULT_SUN_ELEVATION),
+            _padding3: [0; 1],
+            sun_color: DEFAULT_SUN_COLOR,
+            _padding4: [0; 1],
+            camera_position: [0.0; 3],
+            fog_density: DEFAULT_FOG_DENSITY,
+            fog_color: DEFAULT_FOG_COLOR,
+            background_alpha: 1.0,
+            detail_cell: [0; 2],
+            detail_offset: [0.0; 2],
+            inverse_view_proj: cgmath::Matrix4::identity().into(),
+            height_decode: [1.0, 0.0],
+            _padding6: [0; 2]
+        }
+    }
+
+    fn update_view_proj(&mut self, camera: &Camera) {
+        self.set_view(camera.build_view_projection_matrix(), camera.eye);
+    }
+
+    fn set_view(&mut self, view_proj: cgmath::Matrix4<f32>, position: cgmath::Point3<f32>) {
+        use cgmath::SquareMatrix;
+        self.view_proj = view_proj.into();
+        self.inverse_view_proj = view_proj.invert().unwrap_or_else(cgmath::Matrix4::identity).into();
+        self.camera_position = position.into();
+    }
+
+    fn set_detail_origin(&mut self, origin: [f64; 2]) {
+        let scaled = origin.map(|value| value * self.detail_frequency as f64);
+        self.detail_cell = scaled.map(|value| value.floor() as i32);
+        self.detail_offset = scaled.map(|value| (value - value.floor()) as f32);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CullMode {
+    Back,
+    Front,
+    None
+}
+
+impl CullMode {
+    pub fn face(self) -> Option<wgpu::Face> {
+        match self {
+            CullMode::Back => Some(wgpu::Face::Back),
+            CullMode::Front => Some(wgpu::Face::Front),
+            CullMode::None => None
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            CullMode::Back => CullMode::Front,
+            CullMode::Front => CullMode::None,
+            CullMode::None => CullMode::Back
+        }
+    }
+}
+
+//How a pipeline's output combines with what's already in the frame. Transparent pipelines draw after
+//everything opaque, test against its depth but don't write their own, so overlapping overlays all show.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlendMode {
+    Opaque,
+    Transparent
+}
+
+impl BlendMode {
+    pub fn state(self) -> wgpu::BlendState {
+        match self {
+            BlendMode::Opaque => wgpu::BlendState::REPLACE,
+            BlendMode::Transparent => wgpu::BlendState::ALPHA_BLENDING
+        }
+    }
+
+    pub fn writes_depth(self) -> bool {
+        self == BlendMode::Opaque
+    }
+
+    pub fn color_target(self, format: wgpu::TextureFormat) -> wgpu::ColorTargetState {
+        wgpu::ColorTargetState {
+            format,
+            blend: Some(self.state()),
+            write_mask: wgpu::ColorWrites::ALL
+        }
+    }
+
+    pub fn depth_stencil(self, format: wgpu::TextureFormat, compare: wgpu::CompareFunction) -> wgpu::DepthStencilState {
+        wgpu::DepthStencilState {
+            format,
+            depth_write_enabled: self.writes_depth(),
+            depth_compare: compare,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default()
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MeshStrategy {
+    //delaunay_mesh, extra points where the terrain is steep. Only for meshes built for one chunk's
+    //noise, the shared chunk mesh gets the uniform points alone (see TessellationConfig::shared).
+    DelaunayScatter,
+    //grid_mesh, much quicker to build but the same density everywhere
+    RegularGrid,
+    //poisson_mesh, evenly but irregularly spaced points so the triangles don't line up in rows
+    PoissonScatter
+}
+
+//Where the terrain shader gets heights and normals from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VertexHeights {
+    //The vertex stage reads the chunk's height texture and the fragment stage its normal texture
+    Texture,
+    //Baked on the CPU into a vertex stream per chunk, with normals averaged from the triangles around
+    //each vertex. Lighting follows the actual mesh, and there's no texture fetch in the vertex stage.
+    Baked
+}
+
+impl VertexHeights {
+    fn entry_points(self) -> (&'static str, &'static str) {
+        match self {
+            VertexHeights::Texture => ("vs_main", "fs_main"),
+            VertexHeights::Baked => ("vs_baked", "fs_baked")
+        }
+    }
+
+    fn shadow_entry_point(self) -> &'static str {
+        match self {
+            VertexHeights::Texture => "vs_shadow",
+            VertexHeights::Baked => "vs_shadow_baked"
+        }
+    }
+
+    fn buffers(self) -> Vec<wgpu::VertexBufferLayout<'static>> {
+        match self {
+            VertexHeights::Texture => vec![Vertex::desc()],
+            VertexHeights::Baked => vec![Vertex::desc(), BakedVertex::desc()]
+        }
+    }
+}
+
+//Controls how many extra points steep parts of the chunk get. Slopes are in normalised height
+//(0..1 before height_scale) per mesh unit. The slope terms only apply to a mesh built for one chunk,
+//every streamed chunk draws the same mesh so the renderer meshes with shared().
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TessellationConfig {
+    pub strategy: MeshStrategy,
+    //Closest two inner points can be with PoissonScatter, in mesh units. density doesn't apply to it.
+    pub min_distance: f32,
+    //Inner points per mesh unit, every cell gets at least this many
+    pub density: f32,
+    //Cells flatter than this get no extra points
+    pub flat_slope: f32,
+    //Cells this steep or steeper get max_subdivisions extra points along each axis
+    pub steep_slope: f32,
+    pub max_subdivisions: u32
+}
+
+impl Default for TessellationConfig {
+    fn default() -> Self {
+        TessellationConfig {
+            strategy: MeshStrategy::DelaunayScatter,
+            min_distance: 1.0,
+            density: 1.0,
+            flat_slope: 0.05,
+            steep_slope: 0.25,
+            max_subdivisions: 3
+        }
+    }
+}
+
+impl TessellationConfig {
+    //The config for a mesh every chunk draws. Extra points placed from any one chunk's slopes would land on
+    //that chunk's ridges and cost triangles everywhere else, so it keeps only the uniform points.
+    pub fn shared(&self) -> Self {
+        TessellationConfig {
+            max_subdivisions: 0,
+            ..*self
+        }
+    }
+
+    pub fn subdivisions(&self, slope: f32) -> u32 {
+        let range = (self.steep_slope - self.flat_slope).max(f32::EPSILON);
+        let t = ((slope - self.flat_slope) / range).clamp(0.0, 1.0);
+
+        (t * self.max_subdivisions as f32).round() as u32
+    }
+}
+
+//Gradient magnitude of the normalised height at a mesh position. noise_scale converts mesh units to noise coordinates.
+fn sample_slope(source: &dyn NoiseSource, seed: Seed, x: f64, y: f64, noise_scale: Coord) -> f32 {
+    let h = 0.5;
+    let height = |x: f64, y: f64| source.sample(x as Coord * noise_scale, y as Coord * noise_scale, seed) * 0.5;
+
+    let dx = (height(x + h, y) - height(x - h, y)) / (2.0 * h as f32);
+    let dy = (height(x, y + h) - height(x, y - h)) / (2.0 * h as f32);
+
+    (dx * dx + dy * dy).sqrt()
+}
+
+//One point per mesh unit around the edge, shared with the neighbouring chunks
+fn border_points(size: u32) -> Vec<delaunator::Point> {
+    let mut points = vec![];
+
+    //Add border
+    for i in 0..size {
+        points.push(
+            delaunator::Point {
+                x: i as f64,
+                y: 0.0
+            }
+        );
+
+        points.push(
+            delaunator::Point {
+                x: i as f64,
+                y: size as f64 - 1.0
+            }
+        );
+    }
+
+    for i in 1..size-1 {
+        points.push(
+            delaunator::Point {
+                x: 0.0,
+                y: i as f64
+            }
+        );
+
+        points.push(
+            delaunator::Point {
+                x: size as f64 - 1.0,
+                y: i as f64
+            }
+        );
+    }
+
+    points
+}
+
+//Border points, a uniform grid of inner points, and then extra points inside every inner cell steep enough to need them
+pub fn chunk_points(size: u32, source: &dyn NoiseSource, seed: Seed, noise_scale: Coord, config: &TessellationConfig) -> Vec<delaunator::Point> {
+    let mut points = border_points(size);
+
+    //The inner points split the chunk into num_inner_points + 1 equal cells along each axis, so they
+    //stay strictly inside the border and the last cell ends exactly on it
+    let inner_size = size - 2;
+    let num_inner_points = (inner_size as f32 * config.density).ceil() as u32;
+    let max = size as f64 - 1.0;
+    let step = max / (num_inner_points + 1) as f64;
+
+    for i in  0..num_inner_points {
+        for j in 0..num_inner_points {
+            let x = (i + 1) as f64 * step;
+            let y = (j + 1) as f64 * step;
+
+            points.push(
+                delaunator::Point { x, y }
+            );
+
+            let subdivisions = config.subdivisions(sample_slope(source, seed, x + step * 0.5, y + step * 0.5, noise_scale));
+
+            for a in 0..subdivisions {
+                for b in 0..subdivisions {
+                    let px = x + (a as f64 + 0.5) / subdivisions as f64 * step;
+                    let py = y + (b as f64 + 0.5) / subdivisions as f64 * step;
+
+                    if px < max && py < max {
+                        points.push(delaunator::Point { x: px, y: py });
+                    }
+                }
+            }
+        }
+    }
+
+    points
+}
+
+//Twice the signed area of a triangle in mesh coordinates, positive when the corners go anticlockwise with y up
+fn signed_area(a: &delaunator::Point, b: &delaunator::Point, c: &delaunator::Point) -> f64 {
+    (b.x - a.x) * (c.y - a.y) - (c.x - a.x) * (b.y - a.y)
+}
+
+//Mesh y becomes world z, so seen from above (looking down -y) a triangle that's anticlockwise in mesh
+//coordinates is clockwise on screen. Every triangle is turned to negative signed area, which is
+//FrontFace::Ccw from above, instead of trusting the triangulator's ordering. Degenerate triangles are left alone.
+fn orient_triangles(points: &[delaunator::Point], indices: &mut [u32]) {
+    for triangle in indices.chunks_exact_mut(3) {
+        let area = signed_area(&points[triangle[0] as usize], &points[triangle[1] as usize], &points[triangle[2] as usize]);
+
+        if area > 0.0 {
+            triangle.swap(1, 2);
+        }
+    }
+}
+
+fn triangulate(points: Vec<delaunator::Point>) -> (Vec<delaunator::Point>, Vec<u32>) {
+    let mut indices: Vec<_> = delaunator::triangulate(&points).triangles.into_iter().map(|i| i as u32).collect();
+    orient_triangles(&points, &mut indices);
+
+    (points, indices)
+}
+
+//Points and oriented triangle indices of the chunk mesh, triangulated from chunk_points
+pub fn delaunay_mesh(size: u32, source: &dyn NoiseSource, seed: Seed, noise_scale: Coord, config: &TessellationConfig) -> (Vec<delaunator::Point>, Vec<u32>) {
+    triangulate(chunk_points(size, source, seed, noise_scale, config))
+}
+
+//Smallest min_distance poisson_mesh uses, anything less would take too many points to fill the chunk
+pub const MIN_POISSON_DISTANCE: f32 = 0.25;
+
+//The border points with Poisson disk samples inside them, kept half a min_distance in from the border
+//so there are no slivers along it. Different seeds give different (but always the same) meshes.
+pub fn poisson_mesh(size: u32, seed: Seed, config: &TessellationConfig) -> (Vec<delaunator::Point>, Vec<u32>) {
+    let max = size as f64 - 1.0;
+    let min_distance = config.min_distance.max(MIN_POISSON_DISTANCE) as f64;
+    let margin = min_distance * 0.5;
+
+    let mut points = border_points(size);
+    points.extend(poisson::poisson_disk(max - 2.0 * margin, max - 2.0 * margin, min_distance, seed).into_iter().map(|[x, y]| {
+        delaunator::Point { x: x + margin, y: y + margin }
+    }));
+
+    triangulate(points)
+}
+
+//A regular grid over the same area with config.density cells per mesh unit and two triangles per cell.
+//Cheaper to build than delaunay_mesh but it can't add detail where the terrain is steep.
+pub fn grid_mesh(size: u32, config: &TessellationConfig) -> (Vec<delaunator::Point>, Vec<u32>) {
+    let max = size as f64 - 1.0;
+    let cells = ((max as f32 * config.density).ceil() as u32).max(1);
+    let row = cells + 1;
+
+    let points: Vec<_> = (0..row * row).map(|i| delaunator::Point {
+        x: (i % row) as f64 / cells as f64 * max,
+        y: (i / row) as f64 / cells as f64 * max
+    }).collect();
+
+    let mut indices = Vec::with_capacity((cells * cells * 6) as usize);
+    for y in 0..cells {
+        for x in 0..cells {
+            let corner = y * row + x;
+            indices.extend_from_slice(&[corner, corner + 1, corner + row + 1, corner, corner + row + 1, corner + row]);
+        }
+    }
+    orient_triangles(&points, &mut indices);
+
+    (points, indices)
+}
+
+//Builds the chunk mesh the way config.strategy says
+pub fn chunk_mesh(size: u32, source: &dyn NoiseSource, seed: Seed, noise_scale: Coord, config: &TessellationConfig) -> (Vec<delaunator::Point>, Vec<u32>) {
+    match config.strategy {
+        MeshStrategy::DelaunayScatter => delaunay_mesh(size, source, seed, noise_scale, config),
+        MeshStrategy::RegularGrid => grid_mesh(size, config),
+        MeshStrategy::PoissonScatter => poisson_mesh(size, seed, config)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugMode {
+    Shaded,
+    Wireframe,
+    Uv,
+    Height
+}
+
+impl DebugMode {
+    pub fn next(self) -> Self {
+        match self {
+            DebugMode::Shaded => DebugMode::Wireframe,
+            DebugMode::Wireframe => DebugMode::Uv,
+            DebugMode::Uv => DebugMode::Height,
+            DebugMode::Height => DebugMode::Shaded
+        }
+    }
+
+    //Value of settings.debug_mode. A line pipeline draws the wireframe itself, so it's shaded
+    //normally, otherwise the shader draws edges from barycentric coordinates.
+    fn shader_mode(self, line_pipeline: bool) -> u32 {
+        match self {
+            DebugMode::Shaded => 0,
+            DebugMode::Wireframe if line_pipeline => 0,
+            DebugMode::Wireframe => 1,
+            DebugMode::Uv => 2,
+            DebugMode::Height => 3
+        }
+    }
+}
+
+//Hangs a skirt off the edge of the mesh: every border vertex gets a copy flagged as skirt, which the
+//shader pushes down, and each border edge becomes a wall down to the copies. Where neighbouring chunks
+//don't quite meet the gap shows the walls instead of the sky. Border edges are the ones only one
+//triangle uses, and walls are wound from their triangle so they face outwards like it faces up.
+//Returns the surface vertex each new vertex copies, in order.
+fn add_skirt(vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>) -> Vec<u32> {
+    use std::collections::{HashMap, HashSet};
+
+    let edges: HashSet<(u32, u32)> = indices.chunks_exact(3)
+        .flat_map(|t| [(t[0], t[1]), (t[1], t[2]), (t[2], t[0])])
+        .collect();
+
+    let mut border: Vec<_> = edges.iter().copied().filter(|&(a, b)| !edges.contains(&(b, a))).collect();
+    //HashSet order changes from run to run, keep the buffers the same
+    border.sort_unstable();
+
+    let mut skirt_vertices = HashMap::new();
+    let mut sources = Vec::new();
+    let mut skirt_vertex = |vertices: &mut Vec<Vertex>, i: u32| *skirt_vertices.entry(i).or_insert_with(|| {
+        vertices.push(Vertex { skirt: 1.0, ..vertices[i as usize] });
+        sources.push(i);
+        (vertices.len() - 1) as u32
+    });
+
+    for (a, b) in border {
+        let (skirt_a, skirt_b) = (skirt_vertex(vertices, a), skirt_vertex(vertices, b));
+        indices.extend_from_slice(&[a, skirt_a, skirt_b, a, skirt_b, b]);
+    }
+
+    sources
+}
+
+//Uint16 whenever every vertex fits, half the memory and friendlier to downlevel devices
+fn index_data(num_vertices: usize, indices: &[u32]) -> (wgpu::IndexFormat, Vec<u8>) {
+    if num_vertices <= u16::MAX as usize {
+        let short: Vec<u16> = indices.iter().map(|&i| i as u16).collect();
+        (wgpu::IndexFormat::Uint16, bytemuck::cast_slice(&short).to_vec())
+    } else {
+        (wgpu::IndexFormat::Uint32, bytemuck::cast_slice(indices).to_vec())
+    }
+}
+
+struct ChunkBuffers {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    //See index_data
+    index_format: wgpu::IndexFormat,
+    num_indices: u32,
+    num_vertices: usize,
+    //Every triangle's vertices written out separately, so the vertex index picks a barycentric
+    //corner. Only built when the device can't draw lines with PolygonMode::Line (WebGL2).
+    unindexed_buffer: Option<wgpu::Buffer>,
+    //Kept for baking chunks, see VertexHeights::Baked
+    mesh: MeshShape
+}
+
+impl ChunkBuffers {
+    //GPU memory held by the buffers, baked chunk streams not included
+    fn byte_size(&self) -> u64 {
+        self.vertex_buffer.size() + self.index_buffer.size() + self.unindexed_buffer.as_ref().map_or(0, |buffer| buffer.size())
+    }
+
+    pub fn generate(device: &wgpu::Device, size: u32, source: &dyn NoiseSource, seed: Seed, noise_scale: Coord, config: &TessellationConfig, unindexed: bool) -> Self {
+        let start = now();
+        let (points, indices) = chunk_mesh(size, source, seed, noise_scale, config);
+        let mesh_time = now() - start;
+        //Border points sit exactly on 0 and size - 1, so their uvs are exactly 0 and 1 and hit the edge texels
+        let extent = (size - 1) as f32;
+        let mut vertices: Vec<_> = points.into_iter().map(|p| {
+            Vertex {
+                position: [p.x as f32, p.y as f32],
+                uv: [(p.x as f32 / extent).clamp(0.0, 1.0), (p.y as f32 / extent).clamp(0.0, 1.0)],
+                skirt: 0.0
+            }
+        }).collect();
+        let mut indices = indices;
+        let surface_indices = indices.len();
+        let skirt_sources = add_skirt(&mut vertices, &mut indices);
+        let num_indices = indices.len() as u32;
+
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Vertex buffer"),
+            contents: bytemuck::cast_slice(&vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let (index_format, index_bytes) = index_data(vertices.len(), &indices);
+
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Index buffer"),
+            contents: &index_bytes,
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        let unindexed_buffer = unindexed.then(|| {
+            let unindexed_vertices: Vec<_> = indices.iter().map(|&i| vertices[i as usize]).collect();
+
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Unindexed vertex buffer"),
+                contents: bytemuck::cast_slice(&unindexed_vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            })
+        });
+
+        console_log!("Generated {} vertices and {} triangles with {:?} in {:.1}ms", vertices.len(), indices.len() / 3, config.strategy, mesh_time);
+
+        Self {
+            num_vertices: vertices.len(),
+            vertex_buffer,
+            index_buffer,
+            index_format,
+            num_indices,
+            unindexed_buffer,
+            mesh: MeshShape {
+                positions: vertices.iter().map(|vertex| vertex.position).collect(),
+                uvs: vertices.iter().map(|vertex| vertex.uv).collect(),
+                indices,
+                surface_indices,
+                skirt_sources
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ContextError {
+    //No WebGPU or WebGL2 adapter, usually the browser doesn't support either or has them disabled
+    NoAdapter,
+    DeviceRequestFailed(String),
+    SurfaceCreation(String),
+    //The surface offered no formats to render to
+    UnsupportedTextureFormat,
+    //ContextOptions::alpha_mode asked for a mode the surface can't composite with
+    UnsupportedAlphaMode(wgpu::CompositeAlphaMode),
+    //WgpuContextBuilder was given parameters that don't work together or on this device
+    InvalidConfig(String)
+}
+
+impl std::fmt::Display for ContextError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ContextError::NoAdapter => write!(f, "No graphics adapter was found. This browser may not support WebGPU or WebGL2, or they may be disabled."),
+            ContextError::DeviceRequestFailed(e) => write!(f, "The graphics device couldn't be created: {}", e),
+            ContextError::SurfaceCreation(e) => write!(f, "The canvas couldn't be used for rendering: {}", e),
+            ContextError::UnsupportedTextureFormat => write!(f, "The canvas doesn't support any texture format that can be rendered to."),
+            ContextError::UnsupportedAlphaMode(mode) => write!(f, "The canvas can't be composited with alpha mode {:?}.", mode),
+            ContextError::InvalidConfig(e) => write!(f, "The renderer was configured wrongly: {}", e)
+        }
+    }
+}
+
+impl std::error::Error for ContextError {}
+
+impl From<ContextError> for wasm_bindgen::JsValue {
+    fn from(error: ContextError) -> Self {
+        wasm_bindgen::JsValue::from_str(&error.to_string())
+    }
+}
+
+//What the surface should present with. The Auto preferences pick whatever the surface has, an explicit mode
+//the surface doesn't have falls back to AutoVsync.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PresentPreference {
+    AutoVsync,
+    AutoNoVsync,
+    Mode(wgpu::PresentMode)
+}
+
+impl PresentPreference {
+    //Fifo is the only mode every surface has to support, so it's the last resort for all of them
+    pub fn choose(self, modes: &[wgpu::PresentMode]) -> wgpu::PresentMode {
+        use wgpu::PresentMode::*;
+
+        let candidates: &[wgpu::PresentMode] = match self {
+            PresentPreference::AutoVsync => &[FifoRelaxed, Fifo],
+            PresentPreference::AutoNoVsync => &[Immediate, Mailbox, Fifo],
+            PresentPreference::Mode(mode) if modes.contains(&mode) => return mode,
+            PresentPreference::Mode(_) => return PresentPreference::AutoVsync.choose(modes)
+        };
+
+        candidates.iter().copied().find(|mode| modes.contains(mode)).unwrap_or(Fifo)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ContextOptions {
+    //Render to an sRGB surface if there is one, see the colour convention in WgpuContext::new
+    pub prefer_srgb: bool,
+    //Smallest canvas size in device pixels, used by the initial sizing and every resize
+    pub min_canvas_size: u32,
+    //Where keyboard input is taken from
+    pub keys: KeyOptions,
+    //Present mode to start with, see WgpuContext::set_present_mode
+    pub present: PresentPreference,
+    //Most input events queued between frames, see event::enforce_cap
+    pub event_cap: usize,
+    //How the canvas is composited with the page. None takes the first mode the surface lists, which is
+    //usually opaque. PreMultiplied with a background alpha below 1 lets the page show through the sky.
+    pub alpha_mode: Option<wgpu::CompositeAlphaMode>
+}
+
+impl Default for ContextOptions {
+    fn default() -> Self {
+        ContextOptions {
+            prefer_srgb: true,
+            min_canvas_size: DEFAULT_MIN_CANVAS_SIZE,
+            keys: KeyOptions::default(),
+            present: PresentPreference::AutoVsync,
+            event_cap: DEFAULT_EVENT_CAP,
+            alpha_mode: None
+        }
+    }
+}
+
+//Everything WgpuContext is built from. Anything not set keeps the defaults the renderer has always used.
+pub struct WgpuContextBuilder {
+    options: ContextOptions,
+    tex_size: u32,
+    chunk_size: u32,
+    tessellation: TessellationConfig,
+    source: Rc<dyn NoiseSource>,
+    power_preference: wgpu::PowerPreference,
+    //None asks for the downlevel defaults the device can manage, see build
+    limits: Option<wgpu::Limits>
+}
+
+impl WgpuContextBuilder {
+    pub fn new() -> Self {
+        WgpuContextBuilder {
+            options: ContextOptions::default(),
+            tex_size: DEFAULT_TEX_SIZE,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            tessellation: TessellationConfig::default(),
+            source: Rc::new(TestSource),
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            limits: None
+        }
+    }
+
+    pub fn with_options(mut self, options: ContextOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    //Height texture texels along each side of a chunk, not counting the apron
+    pub fn with_tex_size(mut self, tex_size: u32) -> Self {
+        self.tex_size = tex_size;
+        self
+    }
+
+    //Mesh units along each side of a chunk, plus one
+    pub fn with_chunk_size(mut self, chunk_size: u32) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    pub fn with_tessellation(mut self, tessellation: TessellationConfig) -> Self {
+        self.tessellation = tessellation;
+        self
+    }
+
+    //What the terrain is sampled from, shared by the chunks, the height field and thumbnails
+    pub fn with_source(mut self, source: Rc<dyn NoiseSource>) -> Self {
+        self.source = source;
+        self
+    }
+
+    pub fn with_power_preference(mut self, power_preference: wgpu::PowerPreference) -> Self {
+        self.power_preference = power_preference;
+        self
+    }
+
+    pub fn with_limits(mut self, limits: wgpu::Limits) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    pub fn with_present(mut self, present: PresentPreference) -> Self {
+        self.options.present = present;
+        self
+    }
+
+    pub fn with_alpha_mode(mut self, alpha_mode: wgpu::CompositeAlphaMode) -> Self {
+        self.options.alpha_mode = Some(alpha_mode);
+        self
+    }
+
+    //Checks the sizes against each other and against the limits the device was created with
+    pub fn validate(&self, limits: &wgpu::Limits) -> Result<(), ContextError> {
+        let texture_size = self.tex_size.saturating_add(2 * CHUNK_APRON);
+
+        if self.tex_size < 2 {
+            Err(ContextError::InvalidConfig(format!("texture size {} leaves nothing to sample between", self.tex_size)))
+        } else if self.chunk_size < 3 {
+            Err(ContextError::InvalidConfig(format!("chunk size {} has no inner points", self.chunk_size)))
+        } else if texture_size > limits.max_texture_dimension_2d {
+            Err(ContextError::InvalidConfig(format!("texture size {} with its apron is over the device's limit of {}", self.tex_size, limits.max_texture_dimension_2d)))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Default for WgpuContextBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct WgpuContext {
+    pub surface: wgpu::Surface,
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+    pub config: wgpu::SurfaceConfiguration,
+    pub size: winit::dpi::PhysicalSize<u32>,
+    pub options: ContextOptions,
+
+    tex_size: u32,
+    chunk_size: u32,
+    source: Rc<dyn NoiseSource>,
+    //What the surface reported it can present with, set_present_mode picks from these
+    present_modes: Vec<wgpu::PresentMode>,
+    render_pipeline: wgpu::RenderPipeline,
+    //Only exists when the device supports PolygonMode::Line
+    wireframe_pipeline: Option<wgpu::RenderPipeline>,
+    shadow_pipeline: wgpu::RenderPipeline,
+    debug_mode: DebugMode,
+    render_pipeline_layout: wgpu::PipelineLayout,
+    terrain_pipeline_layout: wgpu::PipelineLayout,
+    shader: wgpu::ShaderModule,
+    cull_mode: CullMode,
+    vertex_heights: VertexHeights,
+    //Baked heights wait for every chunk to be refilled with its heights kept, see set_vertex_heights
+    pending_vertex_heights: Option<VertexHeights>,
+    pending_pipeline: Option<(wgpu::ShaderModule, wgpu::RenderPipeline)>,
+    //Sized to the scaled resolution, like everything the scene draws into
+    depth_view: wgpu::TextureView,
+    render_scale: f32,
+    //Every scene pass draws here, the post pass takes it to the surface
+    scene_target: SceneTarget,
+    //Width / height the scene is drawn at, letterboxed within the canvas. None fills the canvas.
+    aspect_lock: Option<f32>,
+    post: PostProcessor,
+
+    chunk_buffers: ChunkBuffers,
+    //The chunks copies of chunk_buffers are drawn at, with their height textures
+    streamer: ChunkStreamer,
+    //Only set while a thumbnail is being drawn
+    thumbnail_chunk: Option<Chunk>,
+    //Camera::origin as of the last prepare, the chunks and detail noise are placed relative to it
+    origin: [f64; 2],
+    tessellation: TessellationConfig,
+    //Bumped whenever baked chunks go stale: the mesh or the height scale changed
+    mesh_version: u64,
+
+    grid: GridRenderer,
+    sky: SkyRenderer,
+    water: WaterRenderer,
+    grass: GrassRenderer,
+    pub show_grid: bool,
+    show_grass: bool,
+    show_sky: bool,
+    clear_color: [f32; 3],
+    //Clears to DEBUG_CLEAR_COLOR with the sky off, so holes from culling or skirts show
+    debug_clear: bool,
+    passes: Vec<RenderPassNode>,
+    //Set while the canvas is out of the DOM, nothing is drawn until resume_surface
+    suspended: bool,
+
+    render_settings_uniform: RenderSettings,
+    render_settings_uniform_buffer: wgpu::Buffer,
+    render_settings_uniform_bind_group: wgpu::BindGroup,
+    uploader: Uploader,
+    //Bound next to the render settings, the terrain's colour by height
+    color_ramp: ColorRampTexture,
+    splat: SplatUniform,
+    shadow: ShadowMap,
+    //The render settings with the shadow placeholder bound, for the shadow pass (which can't read the
+    //map it draws) and for thumbnails, which the map doesn't cover
+    unshadowed_bind_group: wgpu::BindGroup,
+
+    noise_frequency: Coord,
+    seed: Seed,
+    chunk_variation: f32,
+    height_field: HeightField,
+
+    gpu_timer: Option<GpuTimer>,
+    //Each pass's GPU time by label, filled whenever the timer has a frame's times
+    gpu_passes: Profiler,
+    //Counted by the record functions, which only get &self. Reset when a frame starts, see render.
+    render_stats: Cell<RenderStats>
+}
+
+impl WgpuContextBuilder {
+    //progress is called with a stage description and the fraction of startup done. Every stage
+    //yields to the browser afterwards so the page can show it.
+    pub async fn build(self, canvas: &HtmlCanvasElement, camera: &Camera, progress: &mut dyn FnMut(&str, f64)) -> Result<WgpuContext, ContextError> {
+        progress("Requesting adapter", 0.0);
+        let (width, height) = get_expected_size(canvas, self.options.min_canvas_size);
+        console_log!("Surface size: {} {}", width, height);
+        canvas.set_width(width);
+        canvas.set_height(height);
+
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+        let surface = instance.create_surface_from_canvas(canvas.clone()).map_err(|e| ContextError::SurfaceCreation(e.to_string()))?;
+
+        let adpater = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: self.power_preference,
+                compatible_surface: Some(&surface),
+                force_fallback_adapter: false,
+            })
+            .await
+            .ok_or(ContextError::NoAdapter)?;
+
+        progress("Requesting device", 0.1);
+
+        //WebGL2 can't run compute shaders, the normal map is generated on the CPU there
+        let compute = adpater.get_downlevel_capabilities().flags.contains(wgpu::DownlevelFlags::COMPUTE_SHADERS);
+        console_log!("Compute shaders: {}", if compute { "available" } else { "unavailable" });
+
+        let (device, queue) = adpater
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    //The last two widen the choice of height texture format, see HeightFormat
+                    features: adpater.features() & (wgpu::Features::TIMESTAMP_QUERY | wgpu::Features::POLYGON_MODE_LINE | wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES | wgpu::Features::TEXTURE_FORMAT_16BIT_NORM),
+                    limits: self.limits.clone().unwrap_or_else(|| if compute { wgpu::Limits::downlevel_defaults() } else { wgpu::Limits::downlevel_webgl2_defaults() }),
+                    label: None
+                },
+                None,
+            )
+            .await
+            .map_err(|e| ContextError::DeviceRequestFailed(e.to_string()))?;
+
+        #[cfg(feature = "hot-shaders")]
+        device.on_uncaptured_error(Box::new(|error| {
+            console_log!("wgpu error: {}", error);
+        }));
+
+        self.validate(&device.limits())?;
+        let WgpuContextBuilder { options, tex_size, chunk_size, tessellation, source, .. } = self;
+
+        //Colour convention: shaders always work in linear space and draw into the scene target, which
+        //keeps it that way. The post pass tonemaps it and encodes for the surface, on write for an sRGB
+        //one and in post.wgsl otherwise, so either way the terrain looks the same.
+        let surface_caps = surface.get_capabilities(&adpater);
+        WgpuContext::log_capabilities(&adpater, &device, &surface_caps);
+
+        let surface_format = surface_caps.formats.iter().copied()
+            .find(|f| f.is_srgb() == options.prefer_srgb)
+            .or_else(|| surface_caps.formats.first().copied())
+            .ok_or(ContextError::UnsupportedTextureFormat)?;
+        console_log!("Surface format: {:?}", surface_format);
+
+        let alpha_mode = match options.alpha_mode {
+            Some(mode) if surface_caps.alpha_modes.contains(&mode) => mode,
+            Some(mode) => return Err(ContextError::UnsupportedAlphaMode(mode)),
+            None => surface_caps.alpha_modes.first().copied().unwrap_or(wgpu::CompositeAlphaMode::Auto)
+        };
+        console_log!("Alpha mode: {:?}", alpha_mode);
+
+        let scene_format = post::choose_scene_format(&adpater, &device);
+        console_log!("Scene format: {:?}", scene_format);
+
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: surface_format,
+            width,
+            height,
+            present_mode: options.present.choose(&surface_caps.present_modes),
+            alpha_mode,
+            view_formats: vec![]
+        };
+        surface.configure(&device, &config);
+        console_log!("Present mode: {:?}", config.present_mode);
+
+        let color_ramp = ColorRampTexture::new(&device, &queue, &ColorRamp::default());
+        let splat = SplatUniform::new(&device, camera.origin);
+        let shadow = ShadowMap::new(&device, &queue, ShadowMap::supported(&adpater, &device));
+        console_log!("Shadows: {}", if shadow.is_supported() { "available" } else { "unavailable" });
+        let (mut render_settings_uniform, render_settings_uniform_buffer, render_settings_uniform_bind_group, render_settings_bind_group_layout) = WgpuContext::create_render_settings_uniform(camera, tex_size, &device, &color_ramp, &splat, &shadow);
+        let unshadowed_bind_group = WgpuContext::create_render_settings_bind_group(&device, &render_settings_bind_group_layout, &render_settings_uniform_buffer, &color_ramp, &splat, &shadow, &shadow.placeholder);
+
+        let noise_frequency = DEFAULT_NOISE_FREQUENCY;
+        let seed = 0;
+
+        let line_polygons = device.features().contains(wgpu::Features::POLYGON_MODE_LINE);
+        console_log!("Wireframe: {}", if line_polygons { "line polygon mode" } else { "barycentric fallback" });
+
+        progress("Building terrain mesh", 0.2);
+        yield_now().await;
+        let chunk_buffers = ChunkBuffers::generate(&device, chunk_size, source.as_ref(), seed, WgpuContext::chunk_noise_scale(tex_size, chunk_size, noise_frequency), &tessellation.shared(), !line_polygons);
+
+        //Chunks are filled a slice of rows at a time, as the slowest part of startup
+        let height_format = HeightFormat::choose(&adpater, &device);
+        console_log!("Height texture format: {:?}", height_format);
+        render_settings_uniform.height_decode = height_format.decode();
+        let pool = ChunkPool::new(&device, tex_size + 2 * CHUNK_APRON, height_format, (chunk_size - 1) as f32 / (tex_size - 1) as f32, compute);
+        let sampler = ChunkSampler { tex_size, noise_frequency, seed, variation: 0.0 };
+        let [x, _, z] = camera.world_position();
+        let mut streamer = ChunkStreamer::new(pool, sampler, CHUNK_GRID_RADIUS, (chunk_size - 1) as f32, camera.origin, (x, z));
+        while !streamer.is_done() {
+            progress("Filling height textures", 0.3 + 0.6 * streamer.progress());
+            yield_now().await;
+
+            streamer.step(&device, &queue, source.as_ref(), NOISE_ROWS_PER_SLICE);
+        }
+
+        progress("Creating pipelines", 0.9);
+        yield_now().await;
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Test shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/shader.wgsl").into())
+        });
+
+        let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Render Pipeline Layout"),
+            bind_group_layouts: &[
+                &render_settings_bind_group_layout,
+                &streamer.pool.texture_layout,
+            ],
+            push_constant_ranges: &[]
+        });
+
+        //The terrain also takes a per-chunk offset, scenes that draw other things only need the first two groups
+        let terrain_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Terrain Pipeline Layout"),
+            bind_group_layouts: &[
+                &render_settings_bind_group_layout,
+                &streamer.pool.texture_layout,
+                &streamer.pool.uniform_layout,
+            ],
+            push_constant_ranges: &[]
+        });
+
+        let cull_mode = CullMode::Back;
+        let render_pipeline = WgpuContext::create_terrain_pipeline(&device, &terrain_pipeline_layout, &shader, scene_format, cull_mode, wgpu::PolygonMode::Fill, BlendMode::Opaque, VertexHeights::Texture);
+        let wireframe_pipeline = line_polygons.then(|| WgpuContext::create_terrain_pipeline(&device, &terrain_pipeline_layout, &shader, scene_format, cull_mode, wgpu::PolygonMode::Line, BlendMode::Opaque, VertexHeights::Texture));
+        let shadow_pipeline = shadow::create_pipeline(&device, &terrain_pipeline_layout, &shader, VertexHeights::Texture.shadow_entry_point(), &VertexHeights::Texture.buffers());
+
+        let depth_view = WgpuContext::create_depth_view(&device, config.width, config.height);
+        let post = PostProcessor::new(&device, scene_format, config.format);
+        let scene_target = post.create_target(&device, config.width, config.height);
+        let grid = GridRenderer::new(&device, scene_format, DEPTH_FORMAT, &render_settings_bind_group_layout, GridConfig::default(), BlendMode::Transparent);
+        let sky = SkyRenderer::new(&device, scene_format, &render_settings_bind_group_layout);
+        let water = WaterRenderer::new(&device, scene_format, DEPTH_FORMAT, &render_settings_bind_group_layout);
+        let grass = GrassRenderer::new(&device, &shader, scene_format, DEPTH_FORMAT, [&render_settings_bind_group_layout, &streamer.pool.texture_layout, &streamer.pool.uniform_layout], streamer.extent());
+
+        let gpu_timer = GpuTimer::new(&device, &queue);
+        console_log!("GPU timestamp queries: {}", if gpu_timer.is_some() { "available" } else { "unavailable" });
+
+        Ok(WgpuContext {
+            surface,
+            device,
+            queue,
+            config,
+            size: PhysicalSize::new(width, height),
+            options,
+
+            tex_size,
+            chunk_size,
+            source: source.clone(),
+            present_modes: surface_caps.present_modes,
+            render_pipeline,
+            wireframe_pipeline,
+            shadow_pipeline,
+            debug_mode: DebugMode::Shaded,
+            render_pipeline_layout,
+            terrain_pipeline_layout,
+            shader,
+            cull_mode,
+            vertex_heights: VertexHeights::Texture,
+            pending_vertex_heights: None,
+            pending_pipeline: None,
+            depth_view,
+            render_scale: 1.0,
+            scene_target,
+            aspect_lock: None,
+            post,
+
+            chunk_buffers,
+            streamer,
+            thumbnail_chunk: None,
+            origin: camera.origin,
+            tessellation,
+            mesh_version: 0,
+
+            grid,
+            sky,
+            water,
+            grass,
+            show_grid: false,
+            show_grass: false,
+            show_sky: true,
+            clear_color: DEFAULT_CLEAR_COLOR,
+            debug_clear: false,
+            passes: WgpuContext::default_passes(),
+            suspended: false,
+
+            render_settings_uniform,
+            render_settings_uniform_buffer,
+            render_settings_uniform_bind_group,
+            uploader: Uploader::new(STAGING_CHUNK_SIZE),
+            color_ramp,
+            splat,
+            shadow,
+            unshadowed_bind_group,
+
+            noise_frequency,
+            seed,
+            chunk_variation: 0.0,
+            height_field: HeightField::new(source, HEIGHT_FIELD_RESOLUTION),
+
+            gpu_timer,
+            gpu_passes: Profiler::new(),
+            render_stats: Cell::new(RenderStats::default())
+        })
+    }
+}
+
+impl WgpuContext {
+
+    fn log_capabilities(adapter: &wgpu::Adapter, device: &wgpu::Device, surface_caps: &wgpu::SurfaceCapabilities) {
+        let info = adapter.get_info();
+        console_log!("Adapter: {} ({:?}, {:?})", info.name, info.backend, info.device_type);
+        console_log!("Features: {:?}", device.features());
+        console_log!("Limits: {:?}", device.limits());
+        console_log!("Surface formats: {:?}", surface_caps.formats);
+        console_log!("Present modes: {:?}", surface_caps.present_modes);
+        console_log!("Alpha modes: {:?}", surface_caps.alpha_modes);
+    }
+
+    pub fn set_noise_frequency(&mut self, frequency: Coord) {
+        if frequency != self.noise_frequency {
+            self.noise_frequency = frequency;
+            self.resample();
+        }
+    }
+
+    pub fn seed(&self) -> Seed {
+        self.seed
+    }
+
+    pub fn set_seed(&mut self, seed: Seed) {
+        if seed != self.seed {
+            self.seed = seed;
+            self.resample();
+        }
+    }
+
+    pub fn chunk_variation(&self) -> f32 {
+        self.chunk_variation
+    }
+
+    //How much each chunk's own pattern shows over the global one, from 0 to 1. See displacement::chunk_texel_value.
+    pub fn set_chunk_variation(&mut self, variation: f32) {
+        let variation = variation.clamp(0.0, 1.0);
+        if variation != self.chunk_variation {
+            self.chunk_variation = variation;
+            self.resample();
+        }
+    }
+
+    fn chunk_sampler(&self) -> ChunkSampler {
+        ChunkSampler {
+            tex_size: self.tex_size,
+            noise_frequency: self.noise_frequency,
+            seed: self.seed,
+            variation: self.chunk_variation
+        }
+    }
+
+    //Rebuilds everything derived from the noise: the chunk mesh now, and the height textures and their
+    //normals as they're restreamed over the next frames
+    fn resample(&mut self) {
+        self.streamer.set_sampler(self.chunk_sampler());
+        self.regenerate_chunk();
+    }
+
+    //Loads chunks around the camera as it moves and refills stale ones, returns whether one was added
+    //and the frame needs redrawing
+    pub fn stream_chunks(&mut self, camera: &Camera) -> bool {
+        let [x, _, z] = camera.world_position();
+        let streamed = self.streamer.update(&self.device, &self.queue, self.source.as_ref(), (x, z));
+
+        if self.pending_vertex_heights.is_some() && self.streamer.is_refreshed() {
+            if let Some(heights) = self.pending_vertex_heights.take() {
+                self.use_vertex_heights(heights);
+            }
+            return true;
+        }
+
+        streamed
+    }
+
+    //Chunk size in world units, the camera's origin moves in steps of it so chunk offsets stay exact
+    pub fn chunk_extent(&self) -> f32 {
+        self.streamer.extent()
+    }
+
+    fn set_origin(&mut self, origin: [f64; 2]) {
+        self.origin = origin;
+        self.streamer.set_origin(origin);
+        self.water.set_origin(&self.queue, origin);
+        self.splat.set_origin(&self.queue, origin);
+        self.render_settings_uniform.set_detail_origin(origin);
+    }
+
+    //The chunk's uv spans its texture, first texel to last not counting the apron, which samples the source once per texel
+    pub fn chunk_noise_scale(tex_size: u32, chunk_size: u32, noise_frequency: Coord) -> Coord {
+        (tex_size - 1) as Coord / (chunk_size - 1) as Coord * noise_frequency
+    }
+
+    fn noise_scale(&self) -> Coord {
+        Self::chunk_noise_scale(self.tex_size, self.chunk_size, self.noise_frequency)
+    }
+
+    fn regenerate_chunk(&mut self) {
+        self.chunk_buffers = ChunkBuffers::generate(&self.device, self.chunk_size, self.source.as_ref(), self.seed, self.noise_scale(), &self.tessellation.shared(), self.wireframe_pipeline.is_none());
+        self.mesh_version += 1;
+    }
+
+    pub fn mesh_stats(&self) -> MeshStats {
+        MeshStats {
+            vertices: self.chunk_buffers.num_vertices,
+            indices: self.chunk_buffers.num_indices,
+            index_format: self.chunk_buffers.index_format,
+            bytes: self.chunk_buffers.byte_size(),
+            draw_calls: self.streamer.chunks().count()
+        }
+    }
+
+    //What's in use, or about to be once the chunks are refilled
+    pub fn vertex_heights(&self) -> VertexHeights {
+        self.pending_vertex_heights.unwrap_or(self.vertex_heights)
+    }
+
+    //Baking needs the heights and chunks only keep them from their next upload, so switching to it restreams
+    //every chunk and keeps drawing from the textures until they're all done. Textures are always uploaded,
+    //switching back to them is immediate.
+    pub fn set_vertex_heights(&mut self, heights: VertexHeights) {
+        if heights == self.vertex_heights() {
+            return;
+        }
+
+        self.streamer.pool.set_keep_heights(heights == VertexHeights::Baked);
+        self.pending_vertex_heights = None;
+
+        match heights {
+            VertexHeights::Baked => {
+                self.pending_vertex_heights = Some(heights);
+                self.streamer.restream();
+            },
+            VertexHeights::Texture if self.vertex_heights != heights => self.use_vertex_heights(heights),
+            VertexHeights::Texture => {}
+        }
+    }
+
+    fn use_vertex_heights(&mut self, heights: VertexHeights) {
+        self.vertex_heights = heights;
+        self.rebuild_pipelines();
+
+        console_log!("Vertex heights: {:?}", heights);
+    }
+
+    //Brings chunks baked for an old mesh or height scale up to date, and bakes newly loaded ones
+    fn bake_chunks(&mut self) {
+        if self.vertex_heights != VertexHeights::Baked {
+            return;
+        }
+
+        let unindexed = self.chunk_buffers.unindexed_buffer.is_some();
+        let height_scale = self.render_settings_uniform.height_scale;
+        for chunk in self.streamer.chunks_mut().chain(self.thumbnail_chunk.iter_mut()) {
+            chunk.bake(&self.device, &self.chunk_buffers.mesh, self.mesh_version, height_scale, unindexed);
+        }
+    }
+
+    pub fn set_chunk_radius(&mut self, radius: i32) {
+        self.streamer.set_radius(radius);
+    }
+
+    pub fn tessellation(&self) -> &TessellationConfig {
+        &self.tessellation
+    }
+
+    pub fn texture_filter(&self) -> TextureFilter {
+        self.streamer.pool.filter()
+    }
+
+    pub fn set_texture_filter(&mut self, filter: TextureFilter) {
+        if filter != self.texture_filter() {
+            self.streamer.set_filter(&self.device, filter);
+        }
+    }
+
+    pub fn set_tessellation(&mut self, config: TessellationConfig) {
+        if config != self.tessellation {
+            let rebuild = config.shared() != self.tessellation.shared();
+            self.tessellation = config;
+
+            if rebuild {
+                self.regenerate_chunk();
+            }
+        }
+    }
+
+    //CPU copy of the terrain height function with the current settings
+    pub fn displacement(&self) -> Displacement {
+        Displacement {
+            tex_size: self.tex_size,
+            chunk_size: self.chunk_size,
+            noise_frequency: self.noise_frequency,
+            seed: self.seed,
+            chunk_variation: self.chunk_variation,
+            height_scale: self.render_settings_uniform.height_scale,
+            detail_strength: self.render_settings_uniform.detail_strength,
+            detail_frequency: self.render_settings_uniform.detail_frequency
+        }
+    }
+
+    //The heights a chunk is drawn with, as the GPU has them. See Chunk::read_heights.
+    pub fn read_height_texture(&self, chunk: &Chunk) -> impl Future<Output = Result<Heightmap, wgpu::BufferAsyncError>> {
+        chunk.read_heights(&self.device, &self.queue)
+    }
+
+    //read_height_texture for the chunk under the camera, None if it isn't loaded yet
+    pub fn read_center_heights(&self) -> Option<impl Future<Output = Result<Heightmap, wgpu::BufferAsyncError>>> {
+        self.streamer.center_chunk().map(|chunk| self.read_height_texture(chunk))
+    }
+
+    //sample_hash of the terrain noise over one chunk at the current seed and frequency, for checking the
+    //same seed gives the same terrain across runs and browsers
+    pub fn terrain_hash(&self) -> u64 {
+        let extent = (self.chunk_size - 1) as Coord * self.noise_scale();
+        sample_hash(self.source.as_ref(), self.seed, (extent, extent), TERRAIN_HASH_RESOLUTION)
+    }
+
+    //Shared height queries, brought up to date with the current displacement first
+    pub fn height_field(&mut self) -> &HeightField {
+        let displacement = self.displacement();
+        self.height_field.update(displacement);
+        &self.height_field
+    }
+
+    pub fn set_ambient_color(&mut self, color: [f32; 3]) {
+        self.render_settings_uniform.ambient_color = color;
+    }
+
+    //Some((sky, ground)) lights by how much a surface faces up, None goes back to a flat ambient_color
+    pub fn set_hemisphere_ambient(&mut self, colors: Option<([f32; 3], [f32; 3])>) {
+        match colors {
+            Some((sky, ground)) => {
+                self.render_settings_uniform.ambient_color = sky;
+                self.render_settings_uniform.ground_color = ground;
+                self.render_settings_uniform.hemisphere_ambient = 1;
+            },
+            None => self.render_settings_uniform.hemisphere_ambient = 0
+        }
+    }
+
+    //Angles in degrees, see sun_direction
+    pub fn set_sun_angles(&mut self, azimuth: f32, elevation: f32) {
+        self.render_settings_uniform.sun_direction = sun_direction(azimuth, elevation);
+    }
+
+    pub fn set_sun_color(&mut self, color: [f32; 3]) {
+        self.render_settings_uniform.sun_color = color;
+    }
+
+    pub fn set_fog_density(&mut self, density: f32) {
+        self.render_settings_uniform.fog_density = density.max(0.0);
+    }
+
+    pub fn set_fog_color(&mut self, color: [f32; 3]) {
+        self.render_settings_uniform.fog_color = color;
+    }
+
+    //What clears show, premultiplied by the background alpha like the sky. That's the fog colour while
+    //the sky or fog is on, so the background matches the horizon, and the clear colour otherwise. Clears
+    //skip the shaders, so if they encode their output it's encoded here the way output_color would.
+    pub fn background_color(&self) -> wgpu::Color {
+        if self.debug_clear {
+            return DEBUG_CLEAR_COLOR;
+        }
+
+        let [r, g, b] = if self.show_sky || self.render_settings_uniform.fog_density > 0.0 {
+            self.render_settings_uniform.fog_color
+        } else {
+            self.clear_color
+        };
+        let a = self.render_settings_uniform.background_alpha as f64;
+
+        if self.render_settings_uniform.encode_srgb != 0 {
+            wgpu::Color { r: encode_srgb(r) * a, g: encode_srgb(g) * a, b: encode_srgb(b) * a, a }
+        } else {
+            wgpu::Color { r: r as f64 * a, g: g as f64 * a, b: b as f64 * a, a }
+        }
+    }
+
+    pub fn alpha_mode(&self) -> wgpu::CompositeAlphaMode {
+        self.config.alpha_mode
+    }
+
+    pub fn background_alpha(&self) -> f32 {
+        self.render_settings_uniform.background_alpha
+    }
+
+    //Only shows with an alpha mode that composites, see ContextOptions::alpha_mode
+    pub fn set_background_alpha(&mut self, alpha: f32) {
+        self.render_settings_uniform.background_alpha = alpha.clamp(0.0, 1.0);
+    }
+
+    pub fn set_time(&mut self, time: f32) {
+        self.render_settings_uniform.time = time;
+    }
+
+    //Inner points per mesh unit, clamped to MIN_MESH_DENSITY..=MAX_MESH_DENSITY. Regenerates the chunk mesh.
+    pub fn set_mesh_density(&mut self, density: f32) {
+        self.set_tessellation(TessellationConfig {
+            density: density.clamp(MIN_MESH_DENSITY, MAX_MESH_DENSITY),
+            ..self.tessellation
+        });
+    }
+
+    //Normalised height, scaled by height_scale like the terrain
+    pub fn set_skirt_depth(&mut self, depth: f32) {
+        self.render_settings_uniform.skirt_depth = depth.max(0.0);
+    }
+
+    pub fn set_height_scale(&mut self, height_scale: f32) {
+        self.render_settings_uniform.height_scale = height_scale;
+        self.mesh_version += 1;
+        self.streamer.set_height_scale(&self.device, &self.queue, height_scale);
+        self.water.set_height_scale(&self.queue, height_scale);
+    }
+
+    //Normalised height, like the colour ramp, see WaterRenderer
+    pub fn set_water_level(&mut self, level: f32) {
+        self.water.set_level(&self.queue, level.clamp(0.0, 1.0));
+    }
+
+    pub fn clear_color(&self) -> [f32; 3] {
+        self.clear_color
+    }
+
+    //Linear RGB, only seen with both the sky and fog off, see background_color
+    pub fn set_clear_color(&mut self, color: [f32; 3]) {
+        self.clear_color = color;
+    }
+
+    pub fn show_sky(&self) -> bool {
+        self.show_sky
+    }
+
+    pub fn set_show_sky(&mut self, show: bool) {
+        self.show_sky = show;
+    }
+
+    pub fn debug_clear(&self) -> bool {
+        self.debug_clear
+    }
+
+    pub fn set_debug_clear(&mut self, enabled: bool) {
+        self.debug_clear = enabled;
+    }
+
+    //The debug clear hides the sky too, it'd cover the clear everywhere the terrain doesn't
+    fn draws_sky(&self) -> bool {
+        self.show_sky && !self.debug_clear
+    }
+
+    pub fn show_grass(&self) -> bool {
+        self.show_grass
+    }
+
+    pub fn set_show_grass(&mut self, show: bool) {
+        self.show_grass = show;
+    }
+
+    pub fn grass_density(&self) -> f32 {
+        self.grass.density()
+    }
+
+    //Fraction of each chunk's blades drawn, see GrassRenderer::set_density
+    pub fn set_grass_density(&mut self, density: f32) {
+        self.grass.set_density(density);
+    }
+
+    pub fn grass_distance(&self) -> f32 {
+        self.grass.distance()
+    }
+
+    //World units from the camera, past it no grass is drawn
+    pub fn set_grass_distance(&mut self, distance: f32) {
+        self.grass.set_distance(&self.queue, distance);
+    }
+
+    //Linear RGB and how opaque the water is looking straight down
+    pub fn set_water_color(&mut self, color: [f32; 4]) {
+        self.water.set_color(&self.queue, color);
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create_terrain_pipeline(device: &wgpu::Device, layout: &wgpu::PipelineLayout, shader: &wgpu::ShaderModule, format: wgpu::TextureFormat, cull_mode: CullMode, polygon_mode: wgpu::PolygonMode, blend: BlendMode, heights: VertexHeights) -> wgpu::RenderPipeline {
+        let (vertex_entry, fragment_entry) = heights.entry_points();
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Render Pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: vertex_entry,
+                buffers: &heights.buffers(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: fragment_entry,
+                targets: &[Some(blend.color_target(format))],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: cull_mode.face(),
+                polygon_mode,
+                unclipped_depth: false,
+                conservative: false
+            },
+            depth_stencil: Some(blend.depth_stencil(DEPTH_FORMAT, wgpu::CompareFunction::Less)),
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false
+            },
+            multiview: None
+        })
+    }
+
+    //Layout shared by every scene pipeline: group 0 is the render settings, group 1 a chunk's textures
+    pub fn scene_pipeline_layout(&self) -> &wgpu::PipelineLayout {
+        &self.render_pipeline_layout
+    }
+
+    //What scene pipelines have to target, they draw into the scene target rather than the surface
+    pub fn scene_format(&self) -> wgpu::TextureFormat {
+        self.post.scene_format()
+    }
+
+    pub fn render_settings_bind_group(&self) -> &wgpu::BindGroup {
+        &self.render_settings_uniform_bind_group
+    }
+
+    //Textures of the chunk under the camera, None while it's still loading
+    pub fn noise_texture_bind_group(&self) -> Option<&wgpu::BindGroup> {
+        self.streamer.center_chunk().map(|chunk| &chunk.texture_bind_group)
+    }
+
+    pub fn cull_mode(&self) -> CullMode {
+        self.cull_mode
+    }
+
+    pub fn set_cull_mode(&mut self, cull_mode: CullMode) {
+        if cull_mode != self.cull_mode {
+            self.cull_mode = cull_mode;
+            self.rebuild_pipelines();
+
+            console_log!("Cull mode: {:?}", cull_mode);
+        }
+    }
+
+    fn rebuild_pipelines(&mut self) {
+        self.render_pipeline = Self::create_terrain_pipeline(&self.device, &self.terrain_pipeline_layout, &self.shader, self.post.scene_format(), self.cull_mode, wgpu::PolygonMode::Fill, BlendMode::Opaque, self.vertex_heights);
+
+        if self.wireframe_pipeline.is_some() {
+            self.wireframe_pipeline = Some(Self::create_terrain_pipeline(&self.device, &self.terrain_pipeline_layout, &self.shader, self.post.scene_format(), self.cull_mode, wgpu::PolygonMode::Line, BlendMode::Opaque, self.vertex_heights));
+        }
+        self.shadow_pipeline = shadow::create_pipeline(&self.device, &self.terrain_pipeline_layout, &self.shader, self.vertex_heights.shadow_entry_point(), &self.vertex_heights.buffers());
+    }
+
+    pub fn debug_mode(&self) -> DebugMode {
+        self.debug_mode
+    }
+
+    pub fn set_debug_mode(&mut self, mode: DebugMode) {
+        self.debug_mode = mode;
+        self.render_settings_uniform.debug_mode = mode.shader_mode(self.wireframe_pipeline.is_some());
+
+        console_log!("Debug mode: {:?}", mode);
+    }
+
+    //Builds the terrain pipeline from new shader source. It only replaces the current pipeline once
+    //the returned future (the validation result) is passed to finish_rebuild, so a broken shader
+    //leaves the old one running.
+    pub fn rebuild_pipeline(&mut self, source: &str) -> impl Future<Output = Option<wgpu::Error>> {
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+
+        let shader = self.device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Test shader"),
+            source: wgpu::ShaderSource::Wgsl(source.to_owned().into())
+        });
+        let pipeline = Self::create_terrain_pipeline(&self.device, &self.terrain_pipeline_layout, &shader, self.post.scene_format(), self.cull_mode, wgpu::PolygonMode::Fill, BlendMode::Opaque, self.vertex_heights);
+
+        self.pending_pipeline = Some((shader, pipeline));
+
+        self.device.pop_error_scope()
+    }
+
+    pub fn finish_rebuild(&mut self, error: Option<wgpu::Error>) {
+        let pending = self.pending_pipeline.take();
+
+        match (pending, error) {
+            (_, Some(error)) => console_log!("Shader rebuild failed, keeping the previous pipeline: {}", error),
+            (Some((shader, pipeline)), None) => {
+                self.shader = shader;
+                self.render_pipeline = pipeline;
+                if self.wireframe_pipeline.is_some() {
+                    self.wireframe_pipeline = Some(Self::create_terrain_pipeline(&self.device, &self.terrain_pipeline_layout, &self.shader, self.post.scene_format(), self.cull_mode, wgpu::PolygonMode::Line, BlendMode::Opaque, self.vertex_heights));
+                }
+                self.shadow_pipeline = shadow::create_pipeline(&self.device, &self.terrain_pipeline_layout, &self.shader, self.vertex_heights.shadow_entry_point(), &self.vertex_heights.buffers());
+                self.grass.rebuild(&self.device, &self.shader, self.post.scene_format(), DEPTH_FORMAT);
+                console_log!("Rebuilt terrain pipeline");
+            },
+            (None, None) => {}
+        }
+    }
+
+    fn create_depth_view(device: &wgpu::Device, width: u32, height: u32) -> wgpu::TextureView {
+        let depth_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Depth texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[]
+        });
+
+        depth_texture.create_view(&wgpu::TextureViewDescriptor::default())
+    }
+
+    fn create_render_settings_uniform(camera: &Camera, tex_size: u32, device: &Device, color_ramp: &ColorRampTexture, splat: &SplatUniform, shadow: &ShadowMap) -> (RenderSettings, wgpu::Buffer, wgpu::BindGroup, wgpu::BindGroupLayout) {
+        let mut render_settings_uniform = RenderSettings::new(tex_size);
+        render_settings_uniform.update_view_proj(camera);
+
+        let render_settings_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Camera uniform buffer"),
+            contents: bytemuck::cast_slice(&[render_settings_uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let render_settings_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Depth,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                    count: None,
+                },
+                //The shadow pass places the terrain with the light's matrix from here
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("Camera uniform bind group layout"),
+        });
+
+        let render_settings_bind_group = Self::create_render_settings_bind_group(device, &render_settings_bind_group_layout, &render_settings_buffer, color_ramp, splat, shadow, &shadow.view);
+
+        (render_settings_uniform, render_settings_buffer, render_settings_bind_group, render_settings_bind_group_layout)
+    }
+
+    //shadow_view is the map the terrain is shaded with, either the shadow map or its placeholder
+    fn create_render_settings_bind_group(device: &Device, layout: &wgpu::BindGroupLayout, buffer: &wgpu::Buffer, color_ramp: &ColorRampTexture, splat: &SplatUniform, shadow: &ShadowMap, shadow_view: &wgpu::TextureView) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&color_ramp.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&color_ramp.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: splat.buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(shadow_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::Sampler(&shadow.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: shadow.buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("Camera uniform bind group"),
+        })
+    }
+
+    //Rewrites the ramp texture in place, nothing has to be rebuilt
+    pub fn set_color_ramp(&mut self, ramp: &ColorRamp) {
+        self.color_ramp.upload(&self.queue, ramp);
+    }
+
+    pub fn splat_settings(&self) -> SplatSettings {
+        self.splat.settings()
+    }
+
+    pub fn set_splat_settings(&mut self, settings: SplatSettings) {
+        self.splat.set(&self.queue, settings);
+    }
+
+    //World units, see ShadowMap::set_bias
+    pub fn shadow_bias(&self) -> (f32, f32) {
+        self.shadow.bias()
+    }
+
+    pub fn set_shadow_bias(&mut self, bias: f32, slope_bias: f32) {
+        self.shadow.set_bias(&self.queue, bias, slope_bias);
+    }
+
+    pub fn exposure(&self) -> f32 {
+        self.post.exposure()
+    }
+
+    pub fn set_exposure(&mut self, exposure: f32) {
+        self.post.set_exposure(&self.queue, exposure);
+    }
+
+    pub fn vignette(&self) -> f32 {
+        self.post.vignette()
+    }
+
+    pub fn set_vignette(&mut self, vignette: f32) {
+        self.post.set_vignette(&self.queue, vignette);
+    }
+
+    pub fn set_detail(&mut self, strength: f32, frequency: f32) {
+        self.render_settings_uniform.detail_strength = strength.max(0.0);
+        self.render_settings_uniform.detail_frequency = frequency.max(0.0);
+        self.render_settings_uniform.set_detail_origin(self.origin);
+    }
+
+    pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
+        if new_size.width > 0 && new_size.height > 0 {
+            self.size = new_size;
+            self.config.width = new_size.width;
+            self.config.height = new_size.height;
+
+            self.surface.configure(&self.device, &self.config);
+            self.recreate_render_targets();
+
+            console_log!("Resized canvas to {}x{}", new_size.width, new_size.height);
+        }
+    }
+
+    pub fn present_mode(&self) -> wgpu::PresentMode {
+        self.config.present_mode
+    }
+
+    //Reconfigures the surface, the next frame is presented with the new mode. Returns the mode picked.
+    pub fn set_present_mode(&mut self, preference: PresentPreference) -> wgpu::PresentMode {
+        self.options.present = preference;
+        let mode = preference.choose(&self.present_modes);
+
+        if mode != self.config.present_mode {
+            self.config.present_mode = mode;
+            self.surface.configure(&self.device, &self.config);
+            console_log!("Present mode: {:?}", mode);
+        }
+
+        mode
+    }
+
+    pub fn suspend(&mut self) {
+        if !self.suspended {
+            self.suspended = true;
+            console_log!("Surface suspended");
+        }
+    }
+
+    pub fn is_suspended(&self) -> bool {
+        self.suspended
+    }
+
+    //Reconfigures the surface for the canvas's current size, the old configuration may be gone after a reattach
+    pub fn resume_surface(&mut self, size: PhysicalSize<u32>) {
+        self.suspended = false;
+        self.resize(size);
+    }
+
+    pub fn render_scale(&self) -> f32 {
+        self.render_scale
+    }
+
+    //Fraction of the surface resolution the scene is drawn at before being upsampled
+    pub fn set_render_scale(&mut self, scale: f32) {
+        let scale = scale.clamp(render_scale::MIN_RENDER_SCALE, render_scale::MAX_RENDER_SCALE);
+
+        if scale != self.render_scale {
+            self.render_scale = scale;
+            self.recreate_render_targets();
+
+            console_log!("Render scale: {:.2}", scale);
+        }
+    }
+
+    fn recreate_render_targets(&mut self) {
+        let (width, height) = render_scale::scaled_size(self.config.width, self.config.height, self.render_scale);
+
+        self.depth_view = Self::create_depth_view(&self.device, width, height);
+        self.scene_target = self.post.create_target(&self.device, width, height);
+    }
+
+    //Draws the scene into the scene target, then the output passes take it to the view
+    fn draw_frame(&self, scene: &dyn Scene, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        scene.render(self, encoder, &self.scene_target.view);
+        self.encode_output(encoder, view);
+    }
+
+    pub fn prepare(&mut self, camera: &Camera) {
+        if camera.origin != self.origin {
+            self.set_origin(camera.origin);
+        }
+        self.render_settings_uniform.update_view_proj(camera);
+        self.bake_chunks();
+
+        let caster_height = self.render_settings_uniform.height_scale + self.render_settings_uniform.detail_strength;
+        self.shadow.fit(&self.queue, camera, self.render_settings_uniform.sun_direction, caster_height);
+
+        if self.show_grid {
+            self.grid.update(&self.queue, camera);
+        }
+    }
+
+    //The copy is recorded before any of the encoder's passes, so they all draw with settings
+    fn stage_render_settings(&mut self, encoder: &mut wgpu::CommandEncoder, settings: RenderSettings) {
+        self.uploader.write_buffer(&self.device, encoder, &self.render_settings_uniform_buffer, 0, bytemuck::cast_slice(&[settings]));
+    }
+
+    //Bytes sent to the GPU for the last frame rendered, staged writes and chunk textures
+    pub fn uploaded_bytes(&self) -> u64 {
+        self.uploader.last_frame_bytes()
+    }
+
+    pub fn add_pass(&mut self, node: RenderPassNode) {
+        self.passes.push(node);
+    }
+
+    fn default_passes() -> Vec<RenderPassNode> {
+        vec![
+            //Only one of these runs: the sky covers the whole view, so it's the clear when it's drawn
+            RenderPassNode::new("Background pass", ColorLoad::Background, |_, _| {}).when(|context| !context.draws_sky()),
+            RenderPassNode::new("Sky pass", ColorLoad::Covered, Self::record_sky).when(Self::draws_sky),
+            RenderPassNode::new("Terrain pass", ColorLoad::Keep, Self::record_terrain).with_depth(DepthLoad::Clear(1.0)),
+            RenderPassNode::new("Grass pass", ColorLoad::Keep, Self::record_grass).with_depth(DepthLoad::Keep).when(|context| context.show_grass),
+            //Transparent overlays go after every opaque pass, testing against the depth they left
+            RenderPassNode::new("Water pass", ColorLoad::Keep, Self::record_water).with_depth(DepthLoad::Keep),
+            RenderPassNode::new("Grid pass", ColorLoad::Keep, Self::record_grid).with_depth(DepthLoad::Keep).when(|context| context.show_grid),
+            RenderPassNode::new("Post pass", ColorLoad::Covered, Self::record_post).to_output()
+        ]
+    }
+
+    fn active_passes(&self, target: PassTarget) -> impl Iterator<Item = &RenderPassNode> {
+        self.passes.iter().filter(move |node| node.target == target && node.is_active(self))
+    }
+
+    pub fn encode_scene(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        let viewport = self.scene_viewport();
+
+        self.encode_shadows(encoder);
+
+        for node in self.active_passes(PassTarget::Scene) {
+            node.encode(self, encoder, view, &self.depth_view, viewport);
+        }
+    }
+
+    //The letterbox bars are part of the scene target, output passes always cover the whole view
+    fn encode_output(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        for node in self.active_passes(PassTarget::Output) {
+            node.encode(self, encoder, view, &self.depth_view, None);
+        }
+    }
+
+    pub fn aspect_lock(&self) -> Option<f32> {
+        self.aspect_lock
+    }
+
+    //Ratios that aren't positive and finite unlock it
+    pub fn set_aspect_lock(&mut self, aspect: Option<f32>) {
+        self.aspect_lock = aspect.filter(|aspect| aspect.is_finite() && *aspect > 0.0);
+    }
+
+    //What Camera::aspect should be, the locked ratio if there is one
+    pub fn camera_aspect(&self) -> f32 {
+        self.aspect_lock.unwrap_or(self.config.width as f32 / self.config.height.max(1) as f32)
+    }
+
+    //Where in the scene's target (scaled or not) the scene is drawn
+    fn scene_viewport(&self) -> Option<Viewport> {
+        let aspect = self.aspect_lock?;
+        let (width, height) = render_scale::scaled_size(self.config.width, self.config.height, self.render_scale);
+
+        Viewport::letterbox(width, height, aspect)
+    }
+
+    //Depth of the streamed chunks from the sun, before any scene pass reads it. Not part of the pass list,
+    //it has no colour target and every view shares it.
+    fn encode_shadows(&self, encoder: &mut wgpu::CommandEncoder) {
+        if !self.shadow.is_supported() {
+            return;
+        }
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Shadow pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(ShadowMap::depth_attachment(&self.shadow.view))
+            });
+
+            render_pass.set_pipeline(&self.shadow_pipeline);
+            render_pass.set_bind_group(0, &self.unshadowed_bind_group, &[]);
+            self.count(|stats| stats.bind_group_switches += 1);
+            self.draw_chunks(&mut render_pass, self.streamer.chunks(), false);
+        }
+
+        self.time_pass(encoder, "Shadow pass");
+    }
+
+    //Times everything recorded into encoder since the last pass timed as label, when the device has
+    //timestamp queries and the frame is being measured. Passes from the frame graph are timed already.
+    pub fn time_pass(&self, encoder: &mut wgpu::CommandEncoder, label: &'static str) {
+        if let Some(timer) = &self.gpu_timer {
+            timer.mark(encoder, label);
+        }
+    }
+
+    //Average, last and max GPU time of each pass by label, empty without timestamp queries
+    pub fn gpu_passes(&self) -> &Profiler {
+        &self.gpu_passes
+    }
+
+    //GPU times arrive a few frames late, they're recorded whenever one is ready
+    fn read_gpu_times(&mut self, profiler: &mut Profiler) {
+        let Some(timer) = &mut self.gpu_timer else { return };
+        timer.read_back();
+
+        if let Some(times) = timer.take_result() {
+            profiler.record("gpu", times.total);
+
+            for (label, time) in times.passes {
+                self.gpu_passes.record(label, time);
+            }
+        }
+    }
+
+    fn record_terrain<'pass>(&'pass self, render_pass: &mut wgpu::RenderPass<'pass>) {
+        self.record_chunks(render_pass, self.streamer.chunks(), &self.render_settings_uniform_bind_group);
+    }
+
+    fn record_thumbnail<'pass>(&'pass self, render_pass: &mut wgpu::RenderPass<'pass>) {
+        self.record_chunks(render_pass, self.thumbnail_chunk.iter(), &self.unshadowed_bind_group);
+    }
+
+    fn record_chunks<'pass>(&'pass self, render_pass: &mut wgpu::RenderPass<'pass>, chunks: impl Iterator<Item = &'pass Chunk>, settings: &'pass wgpu::BindGroup) {
+        let wireframe = self.debug_mode == DebugMode::Wireframe;
+
+        match &self.wireframe_pipeline {
+            Some(pipeline) if wireframe => render_pass.set_pipeline(pipeline),
+            _ => render_pass.set_pipeline(&self.render_pipeline)
+        }
+
+        render_pass.set_bind_group(0, settings, &[]);
+        self.count(|stats| stats.bind_group_switches += 1);
+        self.draw_chunks(render_pass, chunks, wireframe);
+    }
+
+    //Binds the chunk buffers and draws every chunk with whatever pipeline and settings are set
+    fn draw_chunks<'pass>(&'pass self, render_pass: &mut wgpu::RenderPass<'pass>, chunks: impl Iterator<Item = &'pass Chunk>, wireframe: bool) {
+        let unindexed = self.chunk_buffers.unindexed_buffer.as_ref().filter(|_| wireframe);
+        match unindexed {
+            Some(unindexed) => render_pass.set_vertex_buffer(0, unindexed.slice(..)),
+            None => {
+                render_pass.set_vertex_buffer(0, self.chunk_buffers.vertex_buffer.slice(..));
+                render_pass.set_index_buffer(self.chunk_buffers.index_buffer.slice(..), self.chunk_buffers.index_format);
+            }
+        }
+
+        for chunk in chunks {
+            if self.vertex_heights == VertexHeights::Baked {
+                //Chunks are baked in prepare, one that isn't yet was loaded since and waits a frame
+                let baked = chunk.baked.as_ref().filter(|baked| baked.version == self.mesh_version);
+                let buffer = match baked {
+                    Some(baked) if unindexed.is_some() => baked.unindexed.as_ref(),
+                    Some(baked) => Some(&baked.vertices),
+                    None => None
+                };
+                let Some(buffer) = buffer else {
+                    self.count(|stats| stats.chunks_culled += 1);
+                    continue;
+                };
+
+                render_pass.set_vertex_buffer(1, buffer.slice(..));
+            }
+
+            render_pass.set_bind_group(1, &chunk.texture_bind_group, &[]);
+            render_pass.set_bind_group(2, &self.streamer.pool.uniforms.bind_group, &[self.streamer.pool.uniforms.offset(chunk)]);
+
+            if unindexed.is_some() {
+                render_pass.draw(0..self.chunk_buffers.num_indices, 0..1);
+            } else {
+                render_pass.draw_indexed(0..self.chunk_buffers.num_indices, 0, 0..1);
+            }
+
+            self.count(|stats| {
+                stats.chunks_drawn += 1;
+                stats.triangles += self.chunk_buffers.num_indices as u64 / 3;
+                stats.bind_group_switches += 2;
+            });
+        }
+    }
+
+    fn count(&self, update: impl FnOnce(&mut RenderStats)) {
+        let mut stats = self.render_stats.get();
+        update(&mut stats);
+        self.render_stats.set(stats);
+    }
+
+    //Closes the frame's counts once it's submitted, along with the uploader's
+    fn end_frame(&mut self) -> RenderStats {
+        let (buffer_bytes, texture_bytes) = self.uploader.end_frame();
+
+        RenderStats {
+            buffer_bytes,
+            texture_bytes,
+            ..self.render_stats.take()
+        }
+    }
+
+    fn record_sky<'pass>(&'pass self, render_pass: &mut wgpu::RenderPass<'pass>) {
+        self.sky.draw(render_pass, &self.render_settings_uniform_bind_group);
+    }
+
+    fn record_post<'pass>(&'pass self, render_pass: &mut wgpu::RenderPass<'pass>) {
+        self.post.draw(render_pass, &self.scene_target);
+    }
+
+    //Chunks out of the grass distance are skipped, the rest get one instanced draw each
+    fn record_grass<'pass>(&'pass self, render_pass: &mut wgpu::RenderPass<'pass>) {
+        self.grass.bind(render_pass, &self.render_settings_uniform_bind_group);
+        self.count(|stats| stats.bind_group_switches += 2);
+
+        for chunk in self.streamer.chunks() {
+            if !self.grass.in_range(chunk.offset(), self.render_settings_uniform.camera_position) {
+                continue;
+            }
+
+            render_pass.set_bind_group(1, &chunk.texture_bind_group, &[]);
+            render_pass.set_bind_group(2, &self.streamer.pool.uniforms.bind_group, &[self.streamer.pool.uniforms.offset(chunk)]);
+            let blades = self.grass.draw(render_pass, &chunk.grass);
+
+            self.count(|stats| {
+                stats.triangles += blades as u64 * BLADE_TRIANGLES as u64;
+                stats.bind_group_switches += 2;
+            });
+        }
+    }
+
+    fn record_water<'pass>(&'pass self, render_pass: &mut wgpu::RenderPass<'pass>) {
+        self.water.draw(render_pass, &self.render_settings_uniform_bind_group);
+    }
+
+    fn record_grid<'pass>(&'pass self, render_pass: &mut wgpu::RenderPass<'pass>) {
+        self.grid.draw(render_pass, &self.render_settings_uniform_bind_group);
+    }
+
+    //Returns what was drawn and uploaded for the frame, or nothing counted while suspended
+    pub fn render(&mut self, scene: &dyn Scene, profiler: &mut Profiler) -> Result<RenderStats, wgpu::SurfaceError>{
+        if self.suspended {
+            return Ok(RenderStats::default());
+        }
+
+        let start = now();
+        //Anything drawn between frames, like thumbnails, isn't part of this one
+        self.render_stats.take();
+
+        let output = self.surface.get_current_texture()?;
+        let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Render Encoder")
+        });
+        self.stage_render_settings(&mut encoder, self.render_settings_uniform);
+        self.streamer.stage_uniforms(&self.device, &mut encoder, &mut self.uploader);
+
+        if let Some(timer) = &mut self.gpu_timer {
+            timer.begin(&mut encoder);
+        }
+        self.draw_frame(scene, &mut encoder, &view);
+        if let Some(timer) = &mut self.gpu_timer {
+            timer.end(&mut encoder);
+        }
+        let start = profiler.span("encode", start);
+
+        let (texture_bytes, buffer_bytes) = self.streamer.take_uploaded_bytes();
+        self.uploader.count(texture_bytes);
+        self.uploader.count_buffer(buffer_bytes);
+        self.uploader.finish();
+        self.queue.submit(Some(encoder.finish()));
+        self.uploader.recall();
+        let stats = self.end_frame();
+        output.present();
+        profiler.span("submit", start);
+        self.read_gpu_times(profiler);
+
+        Ok(stats)
+    }
+
+    //render with a view per eye instead of the camera's, each drawn into its own viewport with its own
+    //matrices, see render_viewports
+    pub fn render_xr(&mut self, views: &[XrView], profiler: &mut Profiler) -> Result<RenderStats, wgpu::SurfaceError> {
+        let (width, height) = render_scale::scaled_size(self.config.width, self.config.height, self.render_scale);
+        let views: Vec<_> = views.iter().filter_map(|eye| {
+            let viewport = eye.pixel_viewport(width, height)?;
+            let mut settings = self.render_settings_uniform;
+            settings.set_view(eye.view_proj, eye.position);
+
+            Some((settings, viewport))
+        }).collect();
+
+        self.render_viewports("XR Encoder", &views, profiler)
+    }
+
+    //render with several cameras, each drawn into its own part of the canvas, for split screen or picture
+    //in picture. The chunks are placed around the origin prepare was last given, so every camera should
+    //share that origin (Camera::top_down does). The aspect lock doesn't apply, each rect is its own.
+    pub fn render_views(&mut self, views: &[(Camera, ViewRect)], profiler: &mut Profiler) -> Result<RenderStats, wgpu::SurfaceError> {
+        let (width, height) = render_scale::scaled_size(self.config.width, self.config.height, self.render_scale);
+        let views: Vec<_> = views.iter().filter_map(|(camera, rect)| {
+            let viewport = rect.to_pixels(width, height)?;
+            let mut settings = self.render_settings_uniform;
+            settings.update_view_proj(camera);
+
+            Some((settings, viewport))
+        }).collect();
+
+        self.render_viewports("Views Encoder", &views, profiler)
+    }
+
+    //Every view runs the whole pass list with its own settings, limited to its viewport. Only the first
+    //clears the colour. The scene's own render isn't used, views always draw the terrain passes.
+    fn render_viewports(&mut self, label: &'static str, views: &[(RenderSettings, Viewport)], profiler: &mut Profiler) -> Result<RenderStats, wgpu::SurfaceError> {
+        if self.suspended || views.is_empty() {
+            return Ok(RenderStats::default());
+        }
+
+        let start = now();
+        self.render_stats.take();
+
+        let output = self.surface.get_current_texture()?;
+        let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some(label)
+        });
+        self.streamer.stage_uniforms(&self.device, &mut encoder, &mut self.uploader);
+        self.stage_render_settings(&mut encoder, self.render_settings_uniform);
+
+        if let Some(timer) = &mut self.gpu_timer {
+            timer.begin(&mut encoder);
+        }
+        self.encode_shadows(&mut encoder);
+
+        for (i, &(settings, viewport)) in views.iter().enumerate() {
+            //Copies and passes run in the order they're recorded, so each view's passes see its own settings
+            self.stage_render_settings(&mut encoder, settings);
+
+            for node in self.active_passes(PassTarget::Scene) {
+                let node = if i == 0 { *node } else { node.keeping_color() };
+                node.encode(self, &mut encoder, &self.scene_target.view, &self.depth_view, Some(viewport));
+            }
+        }
+
+        self.encode_output(&mut encoder, &view);
+        if let Some(timer) = &mut self.gpu_timer {
+            timer.end(&mut encoder);
+        }
+        let start = profiler.span("encode", start);
+
+        let (texture_bytes, buffer_bytes) = self.streamer.take_uploaded_bytes();
+        self.uploader.count(texture_bytes);
+        self.uploader.count_buffer(buffer_bytes);
+        self.uploader.finish();
+        self.queue.submit(Some(encoder.finish()));
+        self.uploader.recall();
+        let stats = self.end_frame();
+        output.present();
+        profiler.span("submit", start);
+        self.read_gpu_times(profiler);
+
+        Ok(stats)
+    }
+
+    //Renders the origin chunk for another seed from above into a size x size image and passes it to done
+    //once it's been read back. The chunk is filled from the pool, the streamed chunks aren't touched.
+    pub fn render_thumbnail(&mut self, seed: Seed, size: u32, done: ThumbnailCallback) {
+        let format = self.config.format;
+
+        let sampler = ChunkSampler { seed, ..self.chunk_sampler() };
+        let (mut heights, stride) = chunk::noise_buffer(sampler.texture_size());
+        sampler.fill_rows(self.source.as_ref(), (0, 0), &mut heights, stride, 0..sampler.texture_size());
+
+        let mut chunk = self.streamer.pool.take(&self.device, &self.queue);
+        chunk.upload(&self.device, &self.queue, (0, 0), seed, heights, stride);
+        chunk.set_origin((self.chunk_size - 1) as f32, [0.0; 2]);
+        self.thumbnail_chunk = Some(chunk);
+        self.bake_chunks();
+
+        let mut uniform = self.render_settings_uniform;
+        let max_height = uniform.height_scale + uniform.detail_strength;
+        uniform.view_proj = thumbnail::top_down_view_proj((self.chunk_size - 1) as f32, max_height).into();
+        //From above the whole chunk is far away, fog would only wash it out
+        uniform.fog_density = 0.0;
+        uniform.set_detail_origin([0.0; 2]);
+
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Thumbnail texture"),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[]
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let scene_target = self.post.create_target(&self.device, size, size);
+        let depth_view = Self::create_depth_view(&self.device, size, size);
+
+        let bytes_per_row = screenshot::padded_bytes_per_row(size, 4);
+        let buffer = Rc::new(self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Thumbnail buffer"),
+            size: (bytes_per_row * size) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false
+        }));
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Thumbnail Encoder")
+        });
+        //Only this encoder sees the thumbnail's settings, the next frame stages its own
+        self.stage_render_settings(&mut encoder, uniform);
+        self.streamer.pool.uniforms.stage(&self.device, &mut encoder, &mut self.uploader, self.thumbnail_chunk.iter());
+
+        RenderPassNode::new("Thumbnail pass", ColorLoad::Background, Self::record_thumbnail)
+            .with_depth(DepthLoad::Clear(1.0))
+            .encode(self, &mut encoder, &scene_target.view, &depth_view, None);
+        self.post.apply(&mut encoder, &scene_target, &view);
+
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(bytes_per_row),
+                    rows_per_image: Some(size),
+                },
+            },
+            wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 1,
+            }
+        );
+
+        self.uploader.finish();
+        self.queue.submit(Some(encoder.finish()));
+        self.uploader.recall();
+
+        if let Some(chunk) = self.thumbnail_chunk.take() {
+            self.streamer.pool.give_back(chunk);
+        }
+
+        let mapped_buffer = buffer.clone();
+        buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+            if let Err(e) = result {
+                console_log!("Thumbnail readback failed: {:?}", e);
+                return;
+            }
+
+            let pixels = {
+                let data = mapped_buffer.slice(..).get_mapped_range();
+                screenshot::to_rgba8(format, &data, size, size, bytes_per_row)
+            };
+            mapped_buffer.unmap();
+
+            match pixels {
+                Some(rgba) => done(Thumbnail { seed, size, rgba }),
+                None => console_log!("Can't make thumbnails from a {:?} surface", format)
+            }
+        });
+    }
+
+    //Renders a frame offscreen and downloads it as a PNG once the readback completes
+    pub fn screenshot(&mut self, scene: &dyn Scene) {
+        let (width, height) = (self.config.width, self.config.height);
+        let format = self.config.format;
+
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Screenshot texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[]
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let bytes_per_row = screenshot::padded_bytes_per_row(width, 4);
+        let buffer = Rc::new(self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Screenshot buffer"),
+            size: (bytes_per_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false
+        }));
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Screenshot Encoder")
+        });
+        self.stage_render_settings(&mut encoder, self.render_settings_uniform);
+        self.streamer.stage_uniforms(&self.device, &mut encoder, &mut self.uploader);
+
+        self.draw_frame(scene, &mut encoder, &view);
+
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            }
+        );
+
+        self.uploader.finish();
+        self.queue.submit(Some(encoder.finish()));
+        self.uploader.recall();
+
+        //The callback runs from the browser's event loop once the GPU is done, so rendering carries on meanwhile
+        let mapped_buffer = buffer.clone();
+        buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+            if let Err(e) = result {
+                console_log!("Screenshot readback failed: {:?}", e);
+                return;
+            }
+
+            let pixels = {
+                let data = mapped_buffer.slice(..).get_mapped_range();
+                screenshot::to_rgba8(format, &data, width, height, bytes_per_row)
+            };
+            mapped_buffer.unmap();
+
+            let Some(pixels) = pixels else {
+                console_log!("Can't take a screenshot of a {:?} surface", format);
+                return;
+            };
+
+            match screenshot::encode_png(&pixels, width, height) {
+                Ok(png) => {
+                    if let Err(e) = screenshot::download(&png, "image/png", "cacophony.png") {
+                        console_log!("Couldn't download screenshot: {:?}", e);
+                    }
+                },
+                Err(e) => console_log!("Couldn't encode screenshot: {}", e)
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use crate::noise::source::{TableSource, TableFilter};
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    fn flat_source_gets_the_minimum_points() {
+        let flat = TableSource::new(1, 1, vec![0.3], TableFilter::Nearest).unwrap();
+        let points = chunk_points(17, &flat, 0, 1.0, &TessellationConfig::default());
+
+        //The border plus one inner point per mesh unit
+        assert_eq!(points.len(), 2 * 17 + 2 * 15 + 15 * 15);
+    }
+
+    #[wasm_bindgen_test]
+    fn rough_source_gets_more_points() {
+        let config = TessellationConfig::default();
+        let flat = TableSource::new(1, 1, vec![0.3], TableFilter::Nearest).unwrap();
+
+        let flat_points = chunk_points(17, &flat, 0, 1.0, &config).len();
+        let rough_points = chunk_points(17, &TestSource, 0, 1.0, &config).len();
+
+        assert!(rough_points > flat_points, "{} <= {}", rough_points, flat_points);
+    }
+
+    #[wasm_bindgen_test]
+    fn shared_mesh_ignores_slope() {
+        let config = TessellationConfig::default().shared();
+        let flat = TableSource::new(1, 1, vec![0.3], TableFilter::Nearest).unwrap();
+
+        assert_eq!(chunk_points(17, &TestSource, 0, 1.0, &config).len(), chunk_points(17, &flat, 0, 1.0, &config).len());
+        assert_eq!(config.shared(), config);
+    }
+
+    //Negative in mesh coordinates is anticlockwise seen from above, see orient_triangles
+    fn assert_front_facing(points: &[delaunator::Point], indices: &[u32]) {
+        assert!(!indices.is_empty());
+
+        for triangle in indices.chunks_exact(3) {
+            let area = signed_area(&points[triangle[0] as usize], &points[triangle[1] as usize], &points[triangle[2] as usize]);
+            assert!(area < 0.0, "triangle {:?} has signed area {}", triangle, area);
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn every_mesh_strategy_winds_the_same_way() {
+        for strategy in [MeshStrategy::DelaunayScatter, MeshStrategy::RegularGrid, MeshStrategy::PoissonScatter] {
+            let config = TessellationConfig { strategy, ..TessellationConfig::default() };
+            let (points, indices) = chunk_mesh(17, &TestSource, 3, 1.0, &config);
+
+            assert_front_facing(&points, &indices);
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn orienting_flips_only_the_wrong_way_round() {
+        let points = [
+            delaunator::Point { x: 0.0, y: 0.0 },
+            delaunator::Point { x: 1.0, y: 0.0 },
+            delaunator::Point { x: 0.0, y: 1.0 }
+        ];
+        let mut indices = [0, 1, 2, 0, 2, 1];
+        orient_triangles(&points, &mut indices);
+
+        assert_eq!(indices, [0, 2, 1, 0, 2, 1]);
+    }
+
+    //Offsets of RenderSettings in shader.wgsl (and the other shaders' copies). A field added in the wrong
+    //place would otherwise quietly shift everything after it.
+    #[wasm_bindgen_test]
+    fn render_settings_matches_wgsl_layout() {
+        use std::mem::{offset_of, size_of};
+
+        let fields = [
+            (offset_of!(RenderSettings, view_proj), 0, "view_proj"),
+            (offset_of!(RenderSettings, height_scale), 64, "height_scale"),
+            (offset_of!(RenderSettings, tex_size), 68, "tex_size"),
+            (offset_of!(RenderSettings, detail_strength), 72, "detail_strength"),
+            (offset_of!(RenderSettings, detail_frequency), 76, "detail_frequency"),
+            (offset_of!(RenderSettings, encode_srgb), 80, "encode_srgb"),
+            (offset_of!(RenderSettings, debug_mode), 84, "debug_mode"),
+            (offset_of!(RenderSettings, time), 88, "time"),
+            (offset_of!(RenderSettings, skirt_depth), 92, "skirt_depth"),
+            (offset_of!(RenderSettings, ambient_color), 96, "ambient_color"),
+            (offset_of!(RenderSettings, hemisphere_ambient), 108, "hemisphere_ambient"),
+            (offset_of!(RenderSettings, ground_color), 112, "ground_color"),
+            (offset_of!(RenderSettings, sun_direction), 128, "sun_direction"),
+            (offset_of!(RenderSettings, sun_color), 144, "sun_color"),
+            (offset_of!(RenderSettings, camera_position), 160, "camera_position"),
+            (offset_of!(RenderSettings, fog_density), 172, "fog_density"),
+            (offset_of!(RenderSettings, fog_color), 176, "fog_color"),
+            (offset_of!(RenderSettings, background_alpha), 188, "background_alpha"),
+            (offset_of!(RenderSettings, detail_cell), 192, "detail_cell"),
+            (offset_of!(RenderSettings, detail_offset), 200, "detail_offset"),
+            (offset_of!(RenderSettings, inverse_view_proj), 208, "inverse_view_proj"),
+            (offset_of!(RenderSettings, height_decode), 272, "height_decode")
+        ];
+
+        for (offset, expected, name) in fields {
+            assert_eq!(offset, expected, "RenderSettings::{} is at the wrong offset", name);
+        }
+        assert_eq!(size_of::<RenderSettings>(), 288);
+    }
+
+    #[wasm_bindgen_test]
+    fn mesh_points_stay_inside_the_chunk() {
+        let size = 17;
+        let max = (size - 1) as f64;
+
+        for strategy in [MeshStrategy::DelaunayScatter, MeshStrategy::RegularGrid, MeshStrategy::PoissonScatter] {
+            for density in [0.5, 1.0, 1.7] {
+                let config = TessellationConfig { strategy, density, ..TessellationConfig::default() };
+                let (points, _) = chunk_mesh(size, &TestSource, 1, 1.0, &config);
+
+                for point in &points {
+                    assert!((0.0..=max).contains(&point.x) && (0.0..=max).contains(&point.y), "{:?} at density {}: {:?}", strategy, density, point);
+                }
+            }
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn grid_mesh_sizes_follow_the_closed_form() {
+        for (size, density) in [(17, 1.0), (33, 0.5), (9, 1.5), (2, 1.0)] {
+            let (points, indices) = grid_mesh(size, &TessellationConfig { density, ..TessellationConfig::default() });
+            let cells = (((size - 1) as f32 * density).ceil() as usize).max(1);
+
+            assert_eq!(points.len(), (cells + 1) * (cells + 1), "size {} density {}", size, density);
+            assert_eq!(indices.len(), cells * cells * 6, "size {} density {}", size, density);
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn small_chunks_get_16_bit_indices() {
+        let (points, indices) = grid_mesh(17, &TessellationConfig::default());
+        let (format, bytes) = index_data(points.len(), &indices);
+
+        assert_eq!(format, wgpu::IndexFormat::Uint16);
+        assert_eq!(bytes.len(), indices.len() * 2);
+        assert_eq!(&bytes[..4], &[indices[0] as u8, (indices[0] >> 8) as u8, indices[1] as u8, (indices[1] >> 8) as u8]);
+    }
+
+    #[wasm_bindgen_test]
+    fn large_chunks_get_32_bit_indices() {
+        let (points, indices) = grid_mesh(300, &TessellationConfig::default());
+        assert!(points.len() > u16::MAX as usize);
+
+        let (format, bytes) = index_data(points.len(), &indices);
+        assert_eq!(format, wgpu::IndexFormat::Uint32);
+        assert_eq!(bytes, bytemuck::cast_slice::<u32, u8>(&indices));
+    }
+
+    #[wasm_bindgen_test]
+    fn index_format_switches_past_65535_vertices() {
+        assert_eq!(index_data(65535, &[65534]).0, wgpu::IndexFormat::Uint16);
+        assert_eq!(index_data(65536, &[65535]).0, wgpu::IndexFormat::Uint32);
+    }
+}
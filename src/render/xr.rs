@@ -0,0 +1,59 @@
+use wasm_bindgen::JsValue;
+
+use super::camera::{Camera, OPENGL_TO_WGPU_MATRIX};
+use super::frame_graph::{Viewport, ViewRect};
+
+//Numbers per eye in what the host page passes from an XRViewerPose, see XrView::from_pose
+pub const XR_VIEW_LENGTH: usize = 36;
+
+//Whether the browser has WebXR at all. Says nothing about whether a session can be started.
+pub fn xr_available() -> bool {
+    web_sys::window()
+        .and_then(|window| js_sys::Reflect::has(&window.navigator(), &JsValue::from_str("xr")).ok())
+        .unwrap_or(false)
+}
+
+//One eye of a stereo frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct XrView {
+    pub view_proj: cgmath::Matrix4<f32>,
+    //Relative to the floating origin, like Camera::eye
+    pub position: cgmath::Point3<f32>,
+    //Part of the canvas the eye is drawn to, as fractions of it: x, y, width, height, from the bottom left
+    //like WebXR's viewports
+    pub viewport: [f32; 4]
+}
+
+impl XrView {
+    //From an XRView's transform.inverse.matrix, its projectionMatrix (both column major) and its viewport
+    //as fractions, 36 numbers in all. The pose is taken relative to the camera's position, so head
+    //movement adds to where the camera is rather than replacing it. The camera's rotation is ignored,
+    //the headset's is the only one that counts.
+    pub fn from_pose(camera: &Camera, data: &[f32]) -> Option<Self> {
+        use cgmath::SquareMatrix;
+
+        if data.len() != XR_VIEW_LENGTH {
+            return None;
+        }
+
+        let matrix = |values: &[f32]| cgmath::Matrix4::from(std::array::from_fn::<[f32; 4], 4, _>(|column| std::array::from_fn(|row| values[column * 4 + row])));
+        let view = matrix(&data[0..16]);
+        let projection = matrix(&data[16..32]);
+
+        let head = view.invert()?.w;
+        let to_camera = cgmath::Matrix4::from_translation(-cgmath::Vector3::new(camera.eye.x, camera.eye.y, camera.eye.z));
+
+        Some(XrView {
+            view_proj: OPENGL_TO_WGPU_MATRIX * projection * view * to_camera,
+            position: cgmath::Point3::new(camera.eye.x + head.x, camera.eye.y + head.y, camera.eye.z + head.z),
+            viewport: [data[32], data[33], data[34], data[35]]
+        })
+    }
+
+    //The viewport in pixels of a width x height target, from the top left like wgpu's. None if it has
+    //no area there.
+    pub fn pixel_viewport(&self, width: u32, height: u32) -> Option<Viewport> {
+        let [x, y, view_width, view_height] = self.viewport;
+        ViewRect { x, y: 1.0 - y - view_height, width: view_width, height: view_height }.to_pixels(width, height)
+    }
+}
@@ -1,61 +1,367 @@
-use wasm_bindgen::prelude::{wasm_bindgen, Closure};
-use web_sys::HtmlCanvasElement;
-
-#[wasm_bindgen]
-extern "C" {
-    fn setInterval(closure: &Closure<dyn FnMut()>, millis: u32) -> f64;
-    fn clearInterval(token: f64);
-
-    // Use `js_namespace` here to bind `console.log(..)` instead of just
-    // `log(..)`
-    #[wasm_bindgen(js_namespace = console)]
-    pub fn log(s: &str);
-}
-
-#[macro_export]
-macro_rules! console_log {
-    // Note that this is using the `log` function imported above during
-    // `bare_bones`
-    ($($t:tt)*) => ($crate::util::log(&format_args!($($t)*).to_string()))
-}
-
-pub struct Interval {
-    closure: Closure<dyn FnMut()>,
-    token: f64,
-}
-
-impl Interval {
-    pub fn new<F: 'static>(f: F, millis: u32) -> Interval
-    where
-        F: FnMut()
-    {
-        // Construct a new closure.
-        let closure = Closure::new(f);
-
-        // Pass the closure to JS, to run every n milliseconds.
-        let token = setInterval(&closure, millis);
-
-        Interval { closure, token }
-    }
-
-    pub fn leak(self) {
-        Box::leak(Box::new(self));
-    }
-}
-
-// When the Interval is destroyed, clear its `setInterval` timer.
-impl Drop for Interval {
-    fn drop(&mut self) {
-        clearInterval(self.token);
-    }
-}
-
-pub fn get_expected_size(canvas: &HtmlCanvasElement) -> (u32, u32) {
-    let width = canvas.client_width();
-    let height = canvas.client_height();
-
-    let width = width.max(150);
-    let height = height.max(150);
-
-    (width as u32, height as u32)
-}
\ No newline at end of file
+use wasm_bindgen::{JsValue, prelude::{wasm_bindgen, Closure}};
+use web_sys::{Element, HtmlCanvasElement, HtmlElement};
+
+#[wasm_bindgen]
+extern "C" {
+    fn setInterval(closure: &Closure<dyn FnMut()>, millis: u32) -> f64;
+    fn clearInterval(token: f64);
+    fn setTimeout(callback: &js_sys::Function, millis: u32) -> f64;
+
+    // Use `js_namespace` here to bind `console.log(..)` instead of just
+    // `log(..)`
+    #[wasm_bindgen(js_namespace = console)]
+    pub fn log(s: &str);
+}
+
+#[macro_export]
+macro_rules! console_log {
+    // Note that this is using the `log` function imported above during
+    // `bare_bones`
+    ($($t:tt)*) => ($crate::util::log(&format_args!($($t)*).to_string()))
+}
+
+pub struct Interval {
+    #[allow(dead_code)]
+    closure: Closure<dyn FnMut()>,
+    token: f64,
+}
+
+impl Interval {
+    pub fn new<F>(f: F, millis: u32) -> Interval
+    where
+        F: FnMut() + 'static
+    {
+        // Construct a new closure.
+        let closure = Closure::new(f);
+
+        // Pass the closure to JS, to run every n milliseconds.
+        let token = setInterval(&closure, millis);
+
+        Interval { closure, token }
+    }
+
+    pub fn leak(self) {
+        Box::leak(Box::new(self));
+    }
+}
+
+// When the Interval is destroyed, clear its `setInterval` timer.
+impl Drop for Interval {
+    fn drop(&mut self) {
+        clearInterval(self.token);
+    }
+}
+
+//Lets the browser handle events and paint before the future carries on
+pub async fn yield_now() {
+    sleep(0).await;
+}
+
+pub async fn sleep(millis: u32) {
+    let promise = js_sys::Promise::new(&mut |resolve, _reject| {
+        setTimeout(&resolve, millis);
+    });
+
+    let _ = wasm_bindgen_futures::JsFuture::from(promise).await;
+}
+
+#[cfg(feature = "hot-shaders")]
+pub async fn fetch_text(url: &str) -> Result<String, wasm_bindgen::JsValue> {
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen_futures::JsFuture;
+
+    let window = web_sys::window().ok_or("No window")?;
+    let response: web_sys::Response = JsFuture::from(window.fetch_with_str(url)).await?.unchecked_into();
+
+    if !response.ok() {
+        return Err(format!("Fetching {} failed with status {}", url, response.status()).into());
+    }
+
+    let text = JsFuture::from(response.text()?).await?;
+    text.as_string().ok_or_else(|| "Response wasn't text".into())
+}
+
+pub fn now() -> f64 {
+    web_sys::window()
+        .and_then(|window| window.performance())
+        .map(|performance| performance.now())
+        .unwrap_or(0.0)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimingBucket {
+    pub name: &'static str,
+    pub count: u64,
+    pub total: f64,
+    pub last: f64,
+    pub max: f64
+}
+
+impl TimingBucket {
+    pub fn average(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.total / self.count as f64
+        }
+    }
+}
+
+//Collects timing spans (in milliseconds) into named buckets, kept in the order they were first recorded
+pub struct Profiler {
+    buckets: Vec<TimingBucket>
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler {
+            buckets: Vec::new()
+        }
+    }
+
+    pub fn record(&mut self, name: &'static str, time: f64) {
+        let index = match self.buckets.iter().position(|bucket| bucket.name == name) {
+            Some(index) => index,
+            None => {
+                self.buckets.push(TimingBucket { name, count: 0, total: 0.0, last: 0.0, max: 0.0 });
+                self.buckets.len() - 1
+            }
+        };
+
+        let bucket = &mut self.buckets[index];
+        bucket.count += 1;
+        bucket.total += time;
+        bucket.last = time;
+        bucket.max = bucket.max.max(time);
+    }
+
+    //Records the time since start and returns the current time, so spans can be chained
+    pub fn span(&mut self, name: &'static str, start: f64) -> f64 {
+        let end = now();
+        self.record(name, end - start);
+        end
+    }
+
+    pub fn bucket(&self, name: &str) -> Option<&TimingBucket> {
+        self.buckets.iter().find(|bucket| bucket.name == name)
+    }
+
+    pub fn buckets(&self) -> &[TimingBucket] {
+        &self.buckets
+    }
+
+    pub fn reset(&mut self) {
+        self.buckets.clear();
+    }
+
+    pub fn summary(&self) -> String {
+        self.buckets.iter()
+            .map(|bucket| format!("{} {:.2}", bucket.name, bucket.average()))
+            .collect::<Vec<_>>()
+            .join(" | ")
+    }
+
+    pub fn table(&self) -> String {
+        let mut table = format!("{:<10} {:>8} {:>10} {:>10} {:>10}", "stage", "count", "avg ms", "last ms", "max ms");
+
+        for bucket in &self.buckets {
+            table.push_str(&format!("\n{:<10} {:>8} {:>10.3} {:>10.3} {:>10.3}", bucket.name, bucket.count, bucket.average(), bucket.last, bucket.max));
+        }
+
+        table
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub const DEFAULT_MIN_CANVAS_SIZE: u32 = 150;
+
+pub const DEFAULT_CANVAS_ID: &str = "wgpu-canvas";
+
+//Ids of page elements belonging to one canvas, so several instances don't share an overlay or controls.
+//The demo canvas keeps the plain names.
+pub fn scoped_id(canvas_id: &str, name: &str) -> String {
+    if canvas_id.is_empty() || canvas_id == DEFAULT_CANVAS_ID {
+        name.to_string()
+    } else {
+        format!("{}-{}", canvas_id, name)
+    }
+}
+
+//A corner of the canvas to pin an element over
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Corner {
+    TopLeft,
+    TopRight
+}
+
+impl Corner {
+    //CSS left and top of an element pinned here, from the canvas's offset box. TopRight elements are moved
+    //back by their own width with transform().
+    pub fn position(self, canvas_left: i32, canvas_top: i32, canvas_width: i32) -> (i32, i32) {
+        match self {
+            Corner::TopLeft => (canvas_left, canvas_top),
+            Corner::TopRight => (canvas_left + canvas_width, canvas_top)
+        }
+    }
+
+    fn transform(self) -> &'static str {
+        match self {
+            Corner::TopLeft => "none",
+            Corner::TopRight => "translateX(-100%)"
+        }
+    }
+}
+
+//Puts an element created for a canvas next to it, so it shares the canvas's offset parent and pin_to_canvas lines up
+pub fn insert_after_canvas(element: &Element, canvas: &HtmlCanvasElement) -> Result<(), JsValue> {
+    if canvas.parent_node().is_some() {
+        return canvas.after_with_node_1(element);
+    }
+
+    let body = canvas.owner_document().and_then(|document| document.body()).ok_or("Document has no body")?;
+    body.append_child(element).map(|_| ())
+}
+
+//Positions an element over a corner of the canvas instead of the window's, so each instance on a page
+//keeps its own overlays. Needs calling again when the canvas moves or resizes.
+pub fn pin_to_canvas(element: &HtmlElement, canvas: &HtmlCanvasElement, corner: Corner) {
+    let (left, top) = corner.position(canvas.offset_left(), canvas.offset_top(), canvas.offset_width());
+    let style = element.style();
+
+    let _ = style.set_property("position", "absolute");
+    let _ = style.set_property("left", &format!("{}px", left));
+    let _ = style.set_property("top", &format!("{}px", top));
+    let _ = style.set_property("transform", corner.transform());
+}
+
+pub fn device_pixel_ratio() -> f64 {
+    web_sys::window().map(|window| window.device_pixel_ratio()).unwrap_or(1.0)
+}
+
+//Converts a CSS pixel size to device pixels so high-DPI screens render at native resolution.
+//min_size is in device pixels.
+pub fn device_pixel_size(client_width: i32, client_height: i32, device_pixel_ratio: f64, min_size: u32) -> (u32, u32) {
+    let ratio = if device_pixel_ratio.is_finite() && device_pixel_ratio > 0.0 { device_pixel_ratio } else { 1.0 };
+    let scale = |client: i32| ((client.max(0) as f64 * ratio).round() as u32).max(min_size);
+
+    (scale(client_width), scale(client_height))
+}
+
+pub fn get_expected_size(canvas: &HtmlCanvasElement, min_size: u32) -> (u32, u32) {
+    device_pixel_size(canvas.client_width(), canvas.client_height(), device_pixel_ratio(), min_size)
+}
+
+//A canvas with no layout box (display: none, or inside a hidden tab) reports a zero client size.
+//get_expected_size would clamp that up to min_size and size the surface for something nobody sees.
+pub fn has_layout_size(client_width: i32, client_height: i32) -> bool {
+    client_width > 0 && client_height > 0
+}
+
+//In the DOM and laid out, so there's something to size a surface for
+pub fn canvas_visible(canvas: &HtmlCanvasElement) -> bool {
+    canvas.is_connected() && has_layout_size(canvas.client_width(), canvas.client_height())
+}
+
+//How often a hidden canvas is checked while startup waits for it
+const VISIBILITY_POLL_INTERVAL: u32 = 250;
+
+//Resolves once canvas_visible, checking every VISIBILITY_POLL_INTERVAL ms
+pub async fn wait_until_visible(canvas: &HtmlCanvasElement) {
+    if canvas_visible(canvas) {
+        return;
+    }
+
+    console_log!("Canvas is hidden or detached, waiting for it to be shown");
+    while !canvas_visible(canvas) {
+        sleep(VISIBILITY_POLL_INTERVAL).await;
+    }
+    console_log!("Canvas shown");
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    fn profiler_aggregates_per_bucket() {
+        let mut profiler = Profiler::new();
+        profiler.record("encode", 2.0);
+        profiler.record("submit", 1.0);
+        profiler.record("encode", 6.0);
+        profiler.record("encode", 4.0);
+
+        let encode = profiler.bucket("encode").unwrap();
+        assert_eq!(encode.count, 3);
+        assert_eq!(encode.total, 12.0);
+        assert_eq!(encode.average(), 4.0);
+        assert_eq!(encode.last, 4.0);
+        assert_eq!(encode.max, 6.0);
+
+        assert_eq!(profiler.bucket("submit").unwrap().count, 1);
+        assert!(profiler.bucket("present").is_none());
+    }
+
+    #[wasm_bindgen_test]
+    fn profiler_keeps_first_recorded_order() {
+        let mut profiler = Profiler::new();
+        for name in ["b", "a", "b", "c"] {
+            profiler.record(name, 1.0);
+        }
+
+        let names: Vec<_> = profiler.buckets().iter().map(|bucket| bucket.name).collect();
+        assert_eq!(names, ["b", "a", "c"]);
+        assert_eq!(profiler.summary(), "b 1.00 | a 1.00 | c 1.00");
+
+        profiler.reset();
+        assert!(profiler.buckets().is_empty());
+    }
+
+    #[wasm_bindgen_test]
+    fn empty_bucket_averages_zero() {
+        let bucket = TimingBucket { name: "idle", count: 0, total: 0.0, last: 0.0, max: 0.0 };
+        assert_eq!(bucket.average(), 0.0);
+    }
+
+    #[wasm_bindgen_test]
+    fn device_pixels_at_2x_ratio() {
+        assert_eq!(device_pixel_size(800, 600, 2.0, 150), (1600, 1200));
+        assert_eq!(device_pixel_size(101, 50, 1.5, 1), (152, 75));
+    }
+
+    #[wasm_bindgen_test]
+    fn min_size_applies_after_scaling() {
+        //100 CSS pixels are 200 device pixels, already past the minimum
+        assert_eq!(device_pixel_size(100, 60, 2.0, 150), (200, 150));
+        assert_eq!(device_pixel_size(0, 0, 2.0, 32), (32, 32));
+    }
+
+    #[wasm_bindgen_test]
+    fn bad_ratios_fall_back_to_one() {
+        assert_eq!(device_pixel_size(300, 200, 0.0, 1), (300, 200));
+        assert_eq!(device_pixel_size(300, 200, f64::NAN, 1), (300, 200));
+    }
+
+    #[wasm_bindgen_test]
+    fn zero_size_canvases_have_no_layout() {
+        assert!(!has_layout_size(0, 0));
+        assert!(!has_layout_size(0, 300));
+        assert!(!has_layout_size(300, 0));
+        assert!(!has_layout_size(-1, 300));
+        assert!(has_layout_size(1, 1));
+        assert!(has_layout_size(640, 480));
+    }
+
+    #[wasm_bindgen_test]
+    fn corners_pin_to_the_canvas_box() {
+        assert_eq!(Corner::TopLeft.position(30, 40, 200), (30, 40));
+        assert_eq!(Corner::TopRight.position(30, 40, 200), (230, 40));
+        assert_eq!(Corner::TopRight.transform(), "translateX(-100%)");
+    }
+}